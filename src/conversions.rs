@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use crate::embed::KaulinError;
+use crate::runtime::values::*;
+
+// Rust types that can be read out of a Kaulin value
+pub trait FromKaulin: Sized {
+    fn from_kaulin(value: RuntimeVal) -> Result<Self, KaulinError>;
+
+    // Optional host function parameters may be left out by the caller
+    fn is_optional() -> bool {
+        false
+    }
+}
+
+// Rust types that can be handed to Kaulin code
+pub trait IntoKaulin {
+    fn into_kaulin(self) -> RuntimeVal;
+}
+
+fn mismatch(expected: &str, value: &RuntimeVal) -> KaulinError {
+    KaulinError::Conversion(format!("Odotettiin {}, saatiin {} {}", expected, value.type_name(), value))
+}
+
+impl FromKaulin for RuntimeVal {
+    fn from_kaulin(value: RuntimeVal) -> Result<Self, KaulinError> {
+        Ok(value)
+    }
+}
+
+impl IntoKaulin for RuntimeVal {
+    fn into_kaulin(self) -> RuntimeVal {
+        self
+    }
+}
+
+impl FromKaulin for () {
+    fn from_kaulin(value: RuntimeVal) -> Result<Self, KaulinError> {
+        match value {
+            RuntimeVal::Null => Ok(()),
+            other => Err(mismatch("tyhjää", &other)),
+        }
+    }
+}
+
+impl IntoKaulin for () {
+    fn into_kaulin(self) -> RuntimeVal {
+        MK_NULL()
+    }
+}
+
+impl FromKaulin for bool {
+    fn from_kaulin(value: RuntimeVal) -> Result<Self, KaulinError> {
+        match value {
+            RuntimeVal::Bool(b) => Ok(b),
+            other => Err(mismatch("totuusarvoa", &other)),
+        }
+    }
+}
+
+impl IntoKaulin for bool {
+    fn into_kaulin(self) -> RuntimeVal {
+        MK_BOOL(self)
+    }
+}
+
+// Number literals evaluate to liukuluvut, so whole ones are accepted too
+impl FromKaulin for i64 {
+    fn from_kaulin(value: RuntimeVal) -> Result<Self, KaulinError> {
+        match value {
+            RuntimeVal::Integer(i) => Ok(i),
+            RuntimeVal::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Ok(n as i64),
+            other => Err(mismatch("kokonaislukua", &other)),
+        }
+    }
+}
+
+impl IntoKaulin for i64 {
+    fn into_kaulin(self) -> RuntimeVal {
+        MK_INTEGER(self)
+    }
+}
+
+// Kokonaisluvut are accepted where a liukuluku is expected, as in arithmetic
+impl FromKaulin for f64 {
+    fn from_kaulin(value: RuntimeVal) -> Result<Self, KaulinError> {
+        match value {
+            RuntimeVal::Number(n) => Ok(n),
+            RuntimeVal::Integer(i) => Ok(i as f64),
+            other => Err(mismatch("liukulukua", &other)),
+        }
+    }
+}
+
+impl IntoKaulin for f64 {
+    fn into_kaulin(self) -> RuntimeVal {
+        MK_NUMBER(self)
+    }
+}
+
+impl FromKaulin for String {
+    fn from_kaulin(value: RuntimeVal) -> Result<Self, KaulinError> {
+        match value {
+            RuntimeVal::String(s) => Ok(s),
+            other => Err(mismatch("merkkijonoa", &other)),
+        }
+    }
+}
+
+impl IntoKaulin for String {
+    fn into_kaulin(self) -> RuntimeVal {
+        MK_STRING(self)
+    }
+}
+
+impl IntoKaulin for &str {
+    fn into_kaulin(self) -> RuntimeVal {
+        MK_STRING(self.to_string())
+    }
+}
+
+// `tyhjä` becomes None
+impl<T: FromKaulin> FromKaulin for Option<T> {
+    fn from_kaulin(value: RuntimeVal) -> Result<Self, KaulinError> {
+        match value {
+            RuntimeVal::Null => Ok(None),
+            other => T::from_kaulin(other).map(Some),
+        }
+    }
+
+    fn is_optional() -> bool {
+        true
+    }
+}
+
+impl<T: IntoKaulin> IntoKaulin for Option<T> {
+    fn into_kaulin(self) -> RuntimeVal {
+        match self {
+            Some(value) => value.into_kaulin(),
+            None => MK_NULL(),
+        }
+    }
+}
+
+impl<T: FromKaulin> FromKaulin for Vec<T> {
+    fn from_kaulin(value: RuntimeVal) -> Result<Self, KaulinError> {
+        match value {
+            RuntimeVal::Array(elements) => elements.into_iter().map(T::from_kaulin).collect(),
+            other => Err(mismatch("taulukkoa", &other)),
+        }
+    }
+}
+
+impl<T: IntoKaulin> IntoKaulin for Vec<T> {
+    fn into_kaulin(self) -> RuntimeVal {
+        MK_ARRAY(self.into_iter().map(IntoKaulin::into_kaulin).collect())
+    }
+}
+
+impl<T: FromKaulin> FromKaulin for HashMap<String, T> {
+    fn from_kaulin(value: RuntimeVal) -> Result<Self, KaulinError> {
+        match value {
+            RuntimeVal::Object(properties) => properties
+                .into_iter()
+                .map(|(key, value)| Ok((key, T::from_kaulin(value)?)))
+                .collect(),
+            other => Err(mismatch("objektia", &other)),
+        }
+    }
+}
+
+// Keys are sorted so the object prints the same way on every run
+impl<T: IntoKaulin> IntoKaulin for HashMap<String, T> {
+    fn into_kaulin(self) -> RuntimeVal {
+        let mut properties: Vec<(String, RuntimeVal)> = self
+            .into_iter()
+            .map(|(key, value)| (key, value.into_kaulin()))
+            .collect();
+        properties.sort_by(|(a, _), (b, _)| a.cmp(b));
+        MK_OBJECT(properties)
+    }
+}
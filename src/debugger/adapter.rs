@@ -0,0 +1,313 @@
+use std::cell::Cell;
+use std::io;
+use std::path::{ Path, PathBuf };
+use std::rc::Rc;
+use crate::debugger::*;
+use crate::json::*;
+use crate::runtime::assertions::shown;
+
+// A Debug Adapter Protocol server over stdin and stdout, started with `kaulin debug --dap`. The
+// program runs on the same thread as the protocol, so requests are read only while it is stopped;
+// a pause request cannot stop a running program. tulosta is sent to the editor as output events.
+
+// Kaulin has a single thread
+const THREAD: usize = 1;
+
+#[derive(Clone)]
+struct Connection {
+    seq: Rc<Cell<usize>>,
+}
+
+impl Connection {
+    // None when the editor has closed the connection
+    fn receive(&self) -> Option<Json> {
+        read_message(&mut io::stdin().lock()).ok().flatten()
+    }
+
+    fn send(&self, kind: &str, mut fields: Vec<(&str, Json)>) {
+        let seq = self.seq.get() + 1;
+        self.seq.set(seq);
+        fields.insert(0, ("seq", Json::from(seq)));
+        fields.insert(1, ("type", Json::from(kind)));
+        // A closed connection shows up as the end of the input
+        let _ = write_message(&mut io::stdout().lock(), &Json::object(fields));
+    }
+
+    fn respond(&self, request: &Json, body: Result<Json, String>) {
+        let command = request.get("command").clone();
+        let request_seq = request.get("seq").clone();
+        match body {
+            Ok(body) => self.send("response", vec![
+                ("request_seq", request_seq), ("success", Json::from(true)), ("command", command), ("body", body),
+            ]),
+            Err(message) => self.send("response", vec![
+                ("request_seq", request_seq), ("success", Json::from(false)), ("command", command), ("message", Json::from(message)),
+            ]),
+        }
+    }
+
+    fn event(&self, event: &str, body: Json) {
+        self.send("event", vec![("event", Json::from(event)), ("body", body)]);
+    }
+
+    fn output(&self, category: &str, text: &str) {
+        self.event("output", Json::object(vec![("category", Json::from(category)), ("output", Json::from(text))]));
+    }
+}
+
+// What a variablesReference stands for; the references are valid until the program goes on
+enum Handle {
+    Scope(Environment),
+    Value(RuntimeVal),
+}
+
+struct Adapter {
+    connection: Connection,
+    handles: Vec<Handle>,
+    stopped_before: bool,
+}
+
+impl Frontend for Adapter {
+    fn paused(&mut self, reason: StopReason, frames: &[Frame], breakpoints: &mut Breakpoints) -> Resume {
+        let (reason, text) = match reason {
+            StopReason::Step if !self.stopped_before => ("entry", None),
+            StopReason::Step => ("step", None),
+            StopReason::Breakpoint => ("breakpoint", None),
+            StopReason::ConditionError(error) => ("exception", Some(format!("Katkopisteen ehto epäonnistui: {}", error))),
+            StopReason::Error(error) => ("exception", Some(error)),
+        };
+        self.stopped_before = true;
+        let mut body = vec![("reason", Json::from(reason)), ("threadId", Json::from(THREAD)), ("allThreadsStopped", Json::from(true))];
+        if let Some(text) = text {
+            body.push(("text", Json::from(text)));
+        }
+        self.connection.event("stopped", Json::object(body));
+
+        let resume = loop {
+            let Some(request) = self.connection.receive() else {
+                break Resume::Terminate;
+            };
+            let resume = match request.get("command").as_str().unwrap_or_default() {
+                "continue" => Some(Resume::Continue),
+                "next" => Some(Resume::StepOver),
+                "stepIn" => Some(Resume::StepIn),
+                "stepOut" => Some(Resume::StepOut),
+                "disconnect" | "terminate" => Some(Resume::Terminate),
+                _ => None,
+            };
+            match resume {
+                Some(resume) => {
+                    let body = match resume {
+                        Resume::Continue => Json::object(vec![("allThreadsContinued", Json::from(true))]),
+                        _ => Json::object(Vec::new()),
+                    };
+                    self.connection.respond(&request, Ok(body));
+                    break resume;
+                }
+                None => {
+                    let body = self.request(&request, frames, breakpoints);
+                    self.connection.respond(&request, body);
+                }
+            }
+        };
+        self.handles.clear();
+        resume
+    }
+}
+
+impl Adapter {
+    // Requests that do not make the program go on
+    fn request(&mut self, request: &Json, frames: &[Frame], breakpoints: &mut Breakpoints) -> Result<Json, String> {
+        let arguments = request.get("arguments");
+        match request.get("command").as_str().unwrap_or_default() {
+            "setBreakpoints" => Ok(set_breakpoints(arguments, breakpoints)),
+            "threads" => Ok(threads()),
+            "stackTrace" => Ok(stack_trace(frames)),
+            "scopes" => {
+                let frame = frame(arguments, frames)?;
+                let scopes: Vec<Json> = scope_chain(&frame.env).into_iter().map(|(name, scope)| {
+                    let variables = scope.local_variables().len();
+                    Json::object(vec![
+                        ("name", Json::from(name)),
+                        ("variablesReference", Json::from(self.handle(Handle::Scope(scope)))),
+                        ("namedVariables", Json::from(variables)),
+                        ("expensive", Json::from(name == "Sisäänrakennetut")),
+                    ])
+                }).collect();
+                Ok(Json::object(vec![("scopes", Json::from(scopes))]))
+            }
+            "variables" => {
+                let reference = arguments.get("variablesReference").as_u64().unwrap_or(0) as usize;
+                let variables = match self.handles.get(reference.wrapping_sub(1)) {
+                    Some(Handle::Scope(scope)) => scope.local_variables(),
+                    Some(Handle::Value(value)) => children(value),
+                    None => return Err(format!("Tuntematon muuttujaviite {}", reference)),
+                };
+                let variables: Vec<Json> = variables.into_iter().map(|(name, value)| {
+                    let mut fields = self.value(value);
+                    fields.insert(0, ("name", Json::from(name)));
+                    Json::object(fields)
+                }).collect();
+                Ok(Json::object(vec![("variables", Json::from(variables))]))
+            }
+            "evaluate" => {
+                let frame = frame(arguments, frames)?;
+                let expression = arguments.get("expression").as_str().unwrap_or_default();
+                let value = evaluate_in(&frame.env, expression)?;
+                let mut fields = self.value(value);
+                fields[0].0 = "result";
+                Ok(Json::object(fields))
+            }
+            // Errors stop the program in any case
+            "setExceptionBreakpoints" => Ok(Json::object(Vec::new())),
+            // Requests are read only while the program is stopped, so it already is
+            "pause" => Ok(Json::object(Vec::new())),
+            command => Err(format!("Tuntematon pyyntö '{}'", command)),
+        }
+    }
+
+    fn handle(&mut self, handle: Handle) -> usize {
+        self.handles.push(handle);
+        self.handles.len()
+    }
+
+    // The fields shared by variables and evaluate results; the first is the value
+    fn value(&mut self, value: RuntimeVal) -> Vec<(&'static str, Json)> {
+        let text = shown(&value);
+        let kind = value.type_name();
+        let reference = if children(&value).is_empty() { 0 } else { self.handle(Handle::Value(value)) };
+        vec![("value", Json::from(text)), ("type", Json::from(kind)), ("variablesReference", Json::from(reference))]
+    }
+}
+
+fn set_breakpoints(arguments: &Json, breakpoints: &mut Breakpoints) -> Json {
+    let path = Path::new(arguments.get("source").get("path").as_str().unwrap_or_default());
+    let requested: Vec<Breakpoint> = arguments.get("breakpoints").as_array().iter().map(|breakpoint| Breakpoint {
+        line: breakpoint.get("line").as_u64().unwrap_or(0) as usize,
+        condition: breakpoint.get("condition").as_str().filter(|condition| !condition.trim().is_empty()).map(str::to_string),
+    }).collect();
+    let verified: Vec<Json> = requested.iter().map(|breakpoint| Json::object(vec![
+        ("verified", Json::from(true)),
+        ("line", Json::from(breakpoint.line)),
+    ])).collect();
+    breakpoints.set(path, requested);
+    Json::object(vec![("breakpoints", Json::from(verified))])
+}
+
+fn threads() -> Json {
+    let thread = Json::object(vec![("id", Json::from(THREAD)), ("name", Json::from(MAIN_FRAME))]);
+    Json::object(vec![("threads", Json::from(vec![thread]))])
+}
+
+// Frame ids are indices from the outermost frame; the editor wants the innermost first
+fn stack_trace(frames: &[Frame]) -> Json {
+    let frames: Vec<Json> = frames.iter().enumerate().rev().map(|(id, frame)| {
+        let mut fields = vec![
+            ("id", Json::from(id)),
+            ("name", Json::from(frame.name.as_str())),
+            ("line", Json::from(frame.line)),
+            ("column", Json::from(1usize)),
+        ];
+        if let Some(path) = frame.path() {
+            let name = path.file_name().map(|name| name.to_string_lossy().to_string());
+            fields.push(("source", Json::object(vec![
+                ("name", Json::from(name)),
+                ("path", Json::from(path.display().to_string())),
+            ])));
+        }
+        Json::object(fields)
+    }).collect();
+    let count = frames.len();
+    Json::object(vec![("stackFrames", Json::from(frames)), ("totalFrames", Json::from(count))])
+}
+
+// The frame named by frameId, or the innermost one
+fn frame<'a>(arguments: &Json, frames: &'a [Frame]) -> Result<&'a Frame, String> {
+    match arguments.get("frameId").as_u64() {
+        Some(id) => frames.get(id as usize).ok_or_else(|| format!("Tuntematon kehys {}", id)),
+        None => frames.last().ok_or_else(|| "Ohjelma ei ole pysähtynyt".to_string()),
+    }
+}
+
+fn initialize() -> Json {
+    Json::object(vec![
+        ("supportsConfigurationDoneRequest", Json::from(true)),
+        ("supportsConditionalBreakpoints", Json::from(true)),
+        ("supportsEvaluateForHovers", Json::from(true)),
+        ("supportsTerminateRequest", Json::from(true)),
+    ])
+}
+
+// Returns the exit code: 0 when the editor disconnected after the program ended
+pub fn run() -> i32 {
+    let connection = Connection { seq: Rc::new(Cell::new(0)) };
+    let mut breakpoints = Breakpoints::default();
+    let mut program: Option<(PathBuf, bool)> = None;
+    let mut configured = false;
+
+    // The program starts once it is launched and the editor has sent its breakpoints
+    while program.is_none() || !configured {
+        let Some(request) = connection.receive() else {
+            return 1;
+        };
+        let arguments = request.get("arguments");
+        let body = match request.get("command").as_str().unwrap_or_default() {
+            "initialize" => {
+                connection.respond(&request, Ok(initialize()));
+                connection.event("initialized", Json::object(Vec::new()));
+                continue;
+            }
+            "launch" => match arguments.get("program").as_str() {
+                Some(path) if Path::new(path).exists() => {
+                    let stop_on_entry = arguments.get("stopOnEntry").as_bool().unwrap_or(false);
+                    program = Some((PathBuf::from(path), stop_on_entry));
+                    Ok(Json::object(Vec::new()))
+                }
+                Some(path) => Err(format!("Tiedostoa {} ei ole olemassa", path)),
+                None => Err("launch-pyyntö odottaa ajettavan tiedoston polkua kentässä program".to_string()),
+            },
+            "configurationDone" => {
+                configured = true;
+                Ok(Json::object(Vec::new()))
+            }
+            "setBreakpoints" => Ok(set_breakpoints(arguments, &mut breakpoints)),
+            "setExceptionBreakpoints" => Ok(Json::object(Vec::new())),
+            "threads" => Ok(threads()),
+            "disconnect" => {
+                connection.respond(&request, Ok(Json::object(Vec::new())));
+                return 0;
+            }
+            command => Err(format!("Tuntematon pyyntö '{}'", command)),
+        };
+        connection.respond(&request, body);
+    }
+
+    let (path, stop_on_entry) = program.unwrap();
+    let console = connection.clone();
+    set_console(Some(Box::new(move |text| console.output("stdout", &format!("{}\n", text)))));
+    let adapter = Adapter { connection: connection.clone(), handles: Vec::new(), stopped_before: false };
+    let resume = if stop_on_entry { Resume::StepIn } else { Resume::Continue };
+    let exit_code: usize = match debug_file(&path, Box::new(adapter), breakpoints, resume) {
+        Outcome::Finished(_) | Outcome::Terminated => 0,
+        Outcome::Failed(error) => {
+            connection.output("stderr", &format!("Virhe: {}\n", error));
+            1
+        }
+    };
+    set_console(None);
+    connection.event("exited", Json::object(vec![("exitCode", Json::from(exit_code))]));
+    connection.event("terminated", Json::object(Vec::new()));
+
+    // The editor still asks for threads and the like until it disconnects
+    while let Some(request) = connection.receive() {
+        match request.get("command").as_str().unwrap_or_default() {
+            "disconnect" => {
+                connection.respond(&request, Ok(Json::object(Vec::new())));
+                return 0;
+            }
+            "threads" => connection.respond(&request, Ok(threads())),
+            command => connection.respond(&request, Err(format!("Ohjelma on päättynyt; pyyntöä '{}' ei voida käsitellä", command))),
+        }
+    }
+    1
+}
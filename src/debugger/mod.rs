@@ -0,0 +1,361 @@
+use std::cell::{ Cell, RefCell };
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use crate::embed::{ catch_panic, Interpreter, KaulinError };
+use crate::frontend::ast::Stmt;
+use crate::frontend::lexer::tokenize;
+use crate::frontend::parser::Parser;
+use crate::runtime::environment::Environment;
+use crate::runtime::interpreter::evaluate;
+use crate::runtime::values::*;
+
+mod adapter;
+mod terminal;
+
+// `kaulin debug tiedosto.ka` runs a program under the debugger in the terminal, and `kaulin debug --dap`
+// serves the Debug Adapter Protocol over stdin and stdout for editors. While a session is attached,
+// the tree-walker calls `statement` before each statement and `enter_function` on each call; when the
+// program stops, the frontend gets the call stack and decides how the program goes on.
+
+// The name of the frame running the top level of the file
+pub const MAIN_FRAME: &str = "pääohjelma";
+
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub line: usize,
+    // Code evaluated in the stopped frame; the program stops only if the value is truthy
+    pub condition: Option<String>,
+}
+
+// Breakpoints by the canonical path of their file
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoints {
+    files: HashMap<PathBuf, Vec<Breakpoint>>,
+}
+
+impl Breakpoints {
+    // Replaces the breakpoints of a file
+    pub fn set(&mut self, path: &Path, breakpoints: Vec<Breakpoint>) {
+        self.files.insert(canonical(path), breakpoints);
+    }
+
+    // Replaces a breakpoint on the same line
+    pub fn add(&mut self, path: &Path, breakpoint: Breakpoint) {
+        let breakpoints = self.files.entry(canonical(path)).or_default();
+        breakpoints.retain(|existing| existing.line != breakpoint.line);
+        breakpoints.push(breakpoint);
+    }
+
+    // Whether there was a breakpoint to remove
+    pub fn remove(&mut self, path: &Path, line: usize) -> bool {
+        let Some(breakpoints) = self.files.get_mut(&canonical(path)) else {
+            return false;
+        };
+        let count = breakpoints.len();
+        breakpoints.retain(|breakpoint| breakpoint.line != line);
+        breakpoints.len() < count
+    }
+
+    // Every breakpoint, by file and line
+    pub fn all(&self) -> Vec<(&Path, &Breakpoint)> {
+        let mut all: Vec<(&Path, &Breakpoint)> = self.files
+            .iter()
+            .flat_map(|(path, breakpoints)| breakpoints.iter().map(move |breakpoint| (path.as_path(), breakpoint)))
+            .collect();
+        all.sort_by_key(|(path, breakpoint)| (*path, breakpoint.line));
+        all
+    }
+
+    pub fn at(&self, path: &Path, line: usize) -> Option<&Breakpoint> {
+        self.files.get(path)?.iter().find(|breakpoint| breakpoint.line == line)
+    }
+
+    // Checked before looking up the file, which needs a walk up the scopes
+    fn on_line(&self, line: usize) -> bool {
+        self.files.values().any(|breakpoints| breakpoints.iter().any(|breakpoint| breakpoint.line == line))
+    }
+}
+
+// How the program goes on after a stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    // Stops at the next statement, also inside a called function
+    StepIn,
+    // Stops at the next statement of this function or its callers
+    StepOver,
+    // Stops at the next statement of a caller
+    StepOut,
+    Terminate,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    Step,
+    Breakpoint,
+    // The condition of a breakpoint failed, so the program stops to show the error
+    ConditionError(String),
+    // The program failed; the frames are the call stack at the error and the program cannot go on
+    Error(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub name: String,
+    // The line of the statement running in this frame
+    pub line: usize,
+    // The innermost scope of that statement
+    pub env: Environment,
+}
+
+impl Frame {
+    pub fn path(&self) -> Option<PathBuf> {
+        self.env.module_path()
+    }
+}
+
+pub trait Frontend {
+    // Called when the program stops, with the call stack outermost first. Runs code in the frames
+    // with `evaluate_in` and may change the breakpoints before the program goes on.
+    fn paused(&mut self, reason: StopReason, frames: &[Frame], breakpoints: &mut Breakpoints) -> Resume;
+}
+
+pub enum Outcome {
+    Finished(RuntimeVal),
+    Failed(KaulinError),
+    Terminated,
+}
+
+struct Session {
+    frontend: Box<dyn Frontend>,
+    breakpoints: Breakpoints,
+    frames: Vec<Frame>,
+    resume: Resume,
+    // The call depth when the program went on, for stepping over and out
+    depth: usize,
+    // The call stack when an error started unwinding it; cleared by the next statement, since
+    // väitä_virhe and the like catch errors
+    failure: Option<Vec<Frame>>,
+    terminated: bool,
+}
+
+// Receives what tulosta writes
+type Console = Box<dyn FnMut(&str)>;
+
+thread_local! {
+    // Checked before every statement, so it is kept apart from the session
+    static ATTACHED: Cell<bool> = const { Cell::new(false) };
+    static SESSION: RefCell<Option<Session>> = const { RefCell::new(None) };
+    // Set when a debug adapter uses stdin and stdout for the protocol
+    static CONSOLE: RefCell<Option<Console>> = const { RefCell::new(None) };
+}
+
+pub fn is_attached() -> bool {
+    ATTACHED.with(Cell::get)
+}
+
+// Runs `f` with the hooks off, so code run by the debugger itself never stops
+fn suspended<R>(f: impl FnOnce() -> R) -> R {
+    ATTACHED.with(|attached| attached.set(false));
+    let result = f();
+    ATTACHED.with(|attached| attached.set(true));
+    result
+}
+
+// Runs a file with the debugger attached. A failed program stops once more at the error, so the
+// frontend can show its call stack and variables.
+pub fn debug_file(path: &Path, frontend: Box<dyn Frontend>, breakpoints: Breakpoints, resume: Resume) -> Outcome {
+    let main = Frame { name: MAIN_FRAME.to_string(), line: 0, env: Environment::new(None) };
+    let session = Session { frontend, breakpoints, frames: vec![main], resume, depth: 1, failure: None, terminated: false };
+    SESSION.with(|current| *current.borrow_mut() = Some(session));
+
+    let mut interpreter = Interpreter::new();
+    interpreter.set_warning_handler(|warning| eprintln!("Varoitus: {}", warning));
+    ATTACHED.with(|attached| attached.set(true));
+    let result = interpreter.eval_file(path);
+    ATTACHED.with(|attached| attached.set(false));
+
+    let mut session = SESSION.with(|current| current.borrow_mut().take()).expect("Virheenjäljitysistunto puuttuu");
+    match result {
+        Ok(value) => Outcome::Finished(value),
+        Err(_) if session.terminated => Outcome::Terminated,
+        Err(error) => {
+            let frames = session.failure.take().unwrap_or(session.frames);
+            // An error before the first statement is a parse or resolve error, with nothing to inspect
+            if frames.iter().any(|frame| frame.line > 0) {
+                session.frontend.paused(StopReason::Error(error.to_string()), &frames, &mut session.breakpoints);
+            }
+            Outcome::Failed(error)
+        }
+    }
+}
+
+// The hook before each statement
+pub fn statement(stmt: &Stmt, env: &Environment) {
+    let line = stmt.line();
+    if line == 0 {
+        return;
+    }
+    let terminated = SESSION.with(|current| {
+        let mut current = current.borrow_mut();
+        let Some(session) = current.as_mut() else {
+            return false;
+        };
+        session.failure = None;
+        if let Some(frame) = session.frames.last_mut() {
+            frame.line = line;
+            frame.env = env.clone();
+        }
+
+        let depth = session.frames.len();
+        let stepped = match session.resume {
+            Resume::StepIn => true,
+            Resume::StepOver => depth <= session.depth,
+            Resume::StepOut => depth < session.depth,
+            Resume::Continue | Resume::Terminate => false,
+        };
+        let reason = match session.breakpoint(line, env) {
+            Some(reason) => reason,
+            None if stepped => StopReason::Step,
+            None => return false,
+        };
+
+        let Session { frontend, frames, breakpoints, .. } = &mut *session;
+        session.resume = suspended(|| frontend.paused(reason, frames, breakpoints));
+        session.depth = depth;
+        session.terminated = session.resume == Resume::Terminate;
+        session.terminated
+    });
+    if terminated {
+        panic!("Ohjelma lopetettiin virheenjäljittimestä");
+    }
+}
+
+impl Session {
+    fn breakpoint(&self, line: usize, env: &Environment) -> Option<StopReason> {
+        if !self.breakpoints.on_line(line) {
+            return None;
+        }
+        let breakpoint = self.breakpoints.at(&env.module_path()?, line)?;
+        let Some(condition) = &breakpoint.condition else {
+            return Some(StopReason::Breakpoint);
+        };
+        match suspended(|| evaluate_in(env, condition)) {
+            Ok(value) => value.is_truthy().then_some(StopReason::Breakpoint),
+            Err(error) => Some(StopReason::ConditionError(error)),
+        }
+    }
+}
+
+// Pops the frame of a call when dropped, also when an error unwinds the call
+pub struct FrameGuard;
+
+impl Drop for FrameGuard {
+    fn drop(&mut self) {
+        SESSION.with(|current| {
+            if let Some(session) = current.borrow_mut().as_mut() {
+                if std::thread::panicking() && session.failure.is_none() {
+                    session.failure = Some(session.frames.clone());
+                }
+                session.frames.pop();
+            }
+        });
+    }
+}
+
+// The hook on each call of a user function, with the scope of its parameters
+pub fn enter_function(name: &str, env: &Environment) -> Option<FrameGuard> {
+    if !is_attached() {
+        return None;
+    }
+    SESSION.with(|current| {
+        let session = &mut *current.borrow_mut();
+        session.as_mut().map(|session| {
+            session.frames.push(Frame { name: name.to_string(), line: 0, env: env.clone() });
+            FrameGuard
+        })
+    })
+}
+
+// Runs code in the scope of a stopped frame; declarations and assignments change the program's variables
+pub fn evaluate_in(env: &Environment, source: &str) -> Result<RuntimeVal, String> {
+    catch_panic(|| {
+        let program = Parser::new(tokenize(source)).produce_ast();
+        let mut env = env.clone();
+        program.body.iter().fold(MK_NULL(), |_, stmt| evaluate(stmt, &mut env))
+    }).map_err(|error| error.to_string())
+}
+
+// The scopes visible in a frame, innermost first, with a name for each
+pub fn scope_chain(env: &Environment) -> Vec<(&'static str, Environment)> {
+    let mut scopes = Vec::new();
+    let mut current = Some(env.clone());
+    while let Some(env) = current {
+        let parent = env.parent();
+        let name = match &parent {
+            None => "Sisäänrakennetut",
+            Some(parent) if parent.parent().is_none() => "Tiedosto",
+            Some(_) if scopes.is_empty() => "Paikalliset",
+            Some(_) => "Ympäröivä",
+        };
+        scopes.push((name, env));
+        current = parent;
+    }
+    scopes
+}
+
+// The parts of a value that can be expanded: elements, properties, fields and payloads
+pub fn children(value: &RuntimeVal) -> Vec<(String, RuntimeVal)> {
+    match value {
+        RuntimeVal::Array(elements) => elements.iter().enumerate().map(|(index, element)| (index.to_string(), element.clone())).collect(),
+        RuntimeVal::Object(properties) => properties.clone(),
+        RuntimeVal::Instance(instance) => instance.borrow().fields.clone(),
+        RuntimeVal::EnumValue(value) => value.variant().fields.iter().cloned().zip(value.values.iter().cloned()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Where tulosta writes; a debug adapter sends the output to the editor instead of stdout
+pub fn print(text: &str) {
+    CONSOLE.with(|console| match console.borrow_mut().as_mut() {
+        Some(console) => console(text),
+        None => println!("{}", text),
+    })
+}
+
+// Whether stdin and stdout carry the debug protocol, so syöte cannot read them
+pub fn console_taken() -> bool {
+    CONSOLE.with(|console| console.borrow().is_some())
+}
+
+fn set_console(console: Option<Console>) {
+    CONSOLE.with(|current| *current.borrow_mut() = console);
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// A path relative to the working directory when it is below it
+pub fn display_path(path: &Path) -> String {
+    let relative = env::current_dir().ok().and_then(|dir| path.strip_prefix(dir).ok().map(Path::to_path_buf));
+    relative.unwrap_or_else(|| path.to_path_buf()).display().to_string()
+}
+
+// kaulin debug tiedosto.ka or kaulin debug --dap; returns the exit code
+pub fn run(args: &[String]) -> i32 {
+    match args {
+        [flag] if flag == "--dap" => adapter::run(),
+        [flag] if flag.starts_with("--") => {
+            eprintln!("Virhe: Tuntematon valitsin {}", flag);
+            2
+        }
+        [file] => terminal::run(Path::new(file)),
+        _ => {
+            eprintln!("Käyttö: kaulin debug tiedosto.ka | kaulin debug --dap");
+            2
+        }
+    }
+}
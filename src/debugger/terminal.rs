@@ -0,0 +1,206 @@
+use std::fs;
+use std::io::{ self, Write };
+use std::path::{ Path, PathBuf };
+use crate::debugger::*;
+use crate::runtime::assertions::shown;
+
+// The debugger in the terminal: the program stops before its first statement, and commands are read
+// from stdin whenever it stops. An empty line repeats the previous command.
+
+const HELP: &str = "\
+Komennot:
+  jatka, j                       jatka seuraavaan katkopisteeseen
+  askel, a                       seuraavaan lauseeseen, myös kutsutun funktion sisälle
+  yli, y                         seuraavaan lauseeseen tässä funktiossa
+  ulos, u                        palaa kutsuneeseen funktioon
+  katko [tiedosto:]rivi [jos ehto], k   lisää katkopiste, ehdolla pysähtyy vain kun ehto on tosi
+  poista [tiedosto:]rivi         poista katkopiste
+  katkot                         näytä katkopisteet
+  pino, p                        näytä kutsupino
+  kehys n                        valitse pinon kehys muuttujia ja laskemista varten
+  muuttujat [kaikki], m          näytä valitun kehyksen näkyvyysalueet ja muuttujat
+  laske lauseke, l               laske lauseke valitussa kehyksessä
+  lähde                          näytä koodia rivin ympäriltä
+  lopeta, q                      lopeta ohjelma
+  apua, ?                        näytä tämä ohje";
+
+struct Terminal {
+    program: PathBuf,
+    // The frame used by muuttujat and laske, counted from the outermost
+    selected: usize,
+    previous: String,
+}
+
+impl Frontend for Terminal {
+    fn paused(&mut self, reason: StopReason, frames: &[Frame], breakpoints: &mut Breakpoints) -> Resume {
+        self.selected = frames.len() - 1;
+        let frame = &frames[self.selected];
+        match &reason {
+            StopReason::Step => (),
+            StopReason::Breakpoint => println!("Pysähtyi katkopisteeseen {}:{}", self.file_of(frame), frame.line),
+            StopReason::ConditionError(error) => println!("Katkopisteen ehto epäonnistui rivillä {}: {}", frame.line, error),
+            StopReason::Error(error) => {
+                println!("Virhe: {}", error);
+                self.stack(frames);
+                println!("Ohjelma pysähtyi virheeseen; tutki muuttujia tai lopeta komennolla 'jatka'.");
+            }
+        }
+        self.source(frame, breakpoints, 0);
+
+        loop {
+            print!("(kaulin) ");
+            io::stdout().flush().unwrap();
+            let mut input = String::new();
+            if io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+                return Resume::Terminate;
+            }
+            let input = match input.trim() {
+                "" => self.previous.clone(),
+                input => input.to_string(),
+            };
+            self.previous = input.clone();
+            let (command, rest) = input.split_once(' ').map_or((input.as_str(), ""), |(command, rest)| (command, rest.trim()));
+
+            match command {
+                "jatka" | "j" => return Resume::Continue,
+                "askel" | "a" => return Resume::StepIn,
+                "yli" | "y" => return Resume::StepOver,
+                "ulos" | "u" => return Resume::StepOut,
+                "lopeta" | "q" => return Resume::Terminate,
+                "katko" | "k" => match self.location(rest, frames) {
+                    Ok((path, line, condition)) => {
+                        println!("Katkopiste lisätty {}:{}", display_path(&path), line);
+                        breakpoints.add(&path, Breakpoint { line, condition });
+                    }
+                    Err(error) => println!("{}", error),
+                },
+                "poista" => match self.location(rest, frames) {
+                    Ok((path, line, _)) if breakpoints.remove(&path, line) => println!("Katkopiste {}:{} poistettu", display_path(&path), line),
+                    Ok((path, line, _)) => println!("Rivillä {}:{} ei ole katkopistettä", display_path(&path), line),
+                    Err(error) => println!("{}", error),
+                },
+                "katkot" => {
+                    let all = breakpoints.all();
+                    if all.is_empty() {
+                        println!("Ei katkopisteitä");
+                    }
+                    for (path, breakpoint) in all {
+                        match &breakpoint.condition {
+                            Some(condition) => println!("  {}:{} jos {}", display_path(path), breakpoint.line, condition),
+                            None => println!("  {}:{}", display_path(path), breakpoint.line),
+                        }
+                    }
+                }
+                "pino" | "p" => self.stack(frames),
+                "kehys" => match rest.parse::<usize>() {
+                    // Numbered as in pino, the innermost frame first
+                    Ok(number) if number < frames.len() => {
+                        self.selected = frames.len() - 1 - number;
+                        self.source(&frames[self.selected], breakpoints, 0);
+                    }
+                    _ => println!("Anna kehyksen numero väliltä 0–{}", frames.len() - 1),
+                },
+                "muuttujat" | "m" => self.variables(&frames[self.selected], rest == "kaikki"),
+                "laske" | "l" if rest.is_empty() => println!("Anna laskettava lauseke, esimerkiksi: laske x + 1"),
+                "laske" | "l" => match evaluate_in(&frames[self.selected].env, rest) {
+                    Ok(value) => println!("{}", shown(&value)),
+                    Err(error) => println!("Virhe: {}", error),
+                },
+                "lähde" => self.source(&frames[self.selected], breakpoints, 5),
+                "apua" | "?" => println!("{}", HELP),
+                _ => println!("Tuntematon komento '{}'; kirjoita 'apua' nähdäksesi komennot", command),
+            }
+        }
+    }
+}
+
+impl Terminal {
+    fn file_of(&self, frame: &Frame) -> String {
+        display_path(&frame.path().unwrap_or_else(|| self.program.clone()))
+    }
+
+    // The current line with `context` lines around it
+    fn source(&self, frame: &Frame, breakpoints: &Breakpoints, context: usize) {
+        let path = frame.path().unwrap_or_else(|| self.program.clone());
+        let Ok(source) = fs::read_to_string(&path) else {
+            println!("{}:{}", display_path(&path), frame.line);
+            return;
+        };
+        let first = frame.line.saturating_sub(context).max(1);
+        for (index, text) in source.lines().enumerate().skip(first - 1).take(2 * context + 1) {
+            let line = index + 1;
+            let current = if line == frame.line { "->" } else { "  " };
+            let breakpoint = if breakpoints.at(&path, line).is_some() { "●" } else { " " };
+            println!("{}{} {:>4} | {}", current, breakpoint, line, text);
+        }
+    }
+
+    // Innermost first, like an error trace
+    fn stack(&self, frames: &[Frame]) {
+        for (number, (index, frame)) in frames.iter().enumerate().rev().enumerate() {
+            let marker = if index == self.selected { ">" } else { " " };
+            println!("{} #{} {} ({}:{})", marker, number, frame.name, self.file_of(frame), frame.line);
+        }
+    }
+
+    // The built-in functions are left out unless all scopes are asked for
+    fn variables(&self, frame: &Frame, all: bool) {
+        for (name, scope) in scope_chain(&frame.env) {
+            if scope.parent().is_none() && !all {
+                continue;
+            }
+            let variables = scope.local_variables();
+            if variables.is_empty() {
+                continue;
+            }
+            println!("{}:", name);
+            for (name, value) in variables {
+                println!("  {} = {}", name, shown(&value));
+            }
+        }
+    }
+
+    // [tiedosto:]rivi [jos ehto]; the file defaults to the one of the selected frame
+    fn location(&self, text: &str, frames: &[Frame]) -> Result<(PathBuf, usize, Option<String>), String> {
+        let (place, condition) = match text.split_once(" jos ") {
+            Some((place, condition)) => (place.trim(), Some(condition.trim().to_string())),
+            None => (text.trim(), None),
+        };
+        let (file, line) = match place.rsplit_once(':') {
+            Some((file, line)) => (Some(PathBuf::from(file)), line),
+            None => (None, place),
+        };
+        let line = match line.parse::<usize>() {
+            Ok(line) if line > 0 => line,
+            _ => return Err(format!("Virheellinen rivinumero '{}'", line)),
+        };
+        let path = file.unwrap_or_else(|| frames[self.selected].path().unwrap_or_else(|| self.program.clone()));
+        if !path.exists() {
+            return Err(format!("Tiedostoa {} ei ole olemassa", path.display()));
+        }
+        Ok((path, line, condition))
+    }
+}
+
+pub fn run(path: &Path) -> i32 {
+    if !path.exists() {
+        eprintln!("Virhe: Tiedostoa {} ei ole olemassa", path.display());
+        return 2;
+    }
+    println!("Kaulin-virheenjäljitin: {}. Kirjoita 'apua' nähdäksesi komennot.", display_path(path));
+    let terminal = Terminal { program: path.to_path_buf(), selected: 0, previous: String::new() };
+    match debug_file(path, Box::new(terminal), Breakpoints::default(), Resume::StepIn) {
+        Outcome::Finished(_) => {
+            println!("Ohjelma päättyi");
+            0
+        }
+        Outcome::Terminated => {
+            println!("Ohjelma lopetettiin");
+            0
+        }
+        Outcome::Failed(error) => {
+            eprintln!("Virhe: {}", error);
+            1
+        }
+    }
+}
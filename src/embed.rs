@@ -0,0 +1,282 @@
+use std::cell::Cell;
+use std::fmt;
+use std::fs;
+use std::panic::{ self, AssertUnwindSafe };
+use std::path::Path;
+use std::sync::Once;
+use std::rc::Rc;
+use crate::conversions::*;
+use crate::host::HostFunction;
+use crate::frontend::ast::*;
+use crate::frontend::cache::parse_cached;
+use crate::frontend::lexer::*;
+use crate::frontend::optimizer::optimize_program;
+use crate::frontend::parser::*;
+use crate::frontend::resolver::*;
+use crate::runtime::environment::*;
+use crate::runtime::eval::expressions::call_value;
+use crate::runtime::interpreter::*;
+use crate::runtime::modules::*;
+use crate::runtime::values::*;
+use crate::vm::chunk::Proto;
+use crate::vm::compiler::compile_program;
+use crate::vm::machine::run_script;
+
+#[derive(Debug)]
+pub enum KaulinError {
+    // The script failed while parsing or running
+    Runtime(String),
+    // A file could not be read
+    Io(String),
+    // A value did not have the Rust type asked for
+    Conversion(String),
+}
+
+impl fmt::Display for KaulinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KaulinError::Runtime(message) | KaulinError::Io(message) | KaulinError::Conversion(message) =>
+                write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for KaulinError {}
+
+pub type WarningHandler = Box<dyn Fn(&str)>;
+
+// How scripts are run; the bytecode VM falls back to the tree-walker for what it cannot compile yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    #[default]
+    TreeWalker,
+    Bytecode,
+}
+
+// An interpreter with its own global scope; variables persist between calls
+pub struct Interpreter {
+    env: Environment,
+    warnings: Vec<String>,
+    warning_handler: Option<WarningHandler>,
+    backend: Backend,
+    optimization: u8,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self {
+            env: Environment::new(Some(create_global_env())),
+            warnings: Vec::new(),
+            warning_handler: None,
+            backend: Backend::default(),
+            optimization: 0,
+        }
+    }
+
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    // 0 runs the code as written; see `optimize_program` for what 1 and 2 do
+    pub fn set_optimization_level(&mut self, level: u8) {
+        self.optimization = level;
+    }
+
+    // Parser warnings from the latest eval_str or eval_file
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    // Called with each parser warning before the code runs
+    pub fn set_warning_handler(&mut self, handler: impl Fn(&str) + 'static) {
+        self.warning_handler = Some(Box::new(handler));
+    }
+
+    pub fn eval_str(&mut self, source: &str) -> Result<RuntimeVal, KaulinError> {
+        let mut program = self.parse(source, None)?;
+        let resolution = self.resolve(&program)?;
+        let resolution = self.optimize(&mut program, resolution)?;
+        if let Some(proto) = self.compile(&program, &resolution) {
+            return self.guard(|env| run_script(proto, env));
+        }
+        self.guard(|env| {
+            let mut result = MK_NULL();
+            for stmt in &program.body {
+                result = evaluate(stmt, env);
+            }
+            result
+        })
+    }
+
+    // Runs a file; its imports resolve relative to the file's directory
+    pub fn eval_file(&mut self, path: impl AsRef<Path>) -> Result<RuntimeVal, KaulinError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .map_err(|error| KaulinError::Io(format!("Tiedostoa {} ei voitu lukea: {}", path.display(), error)))?;
+        let mut program = self.parse(&source, Some(path))?;
+        let resolution = self.resolve(&program)?;
+        let resolution = self.optimize(&mut program, resolution)?;
+        if let Some(proto) = self.compile(&program, &resolution) {
+            return self.guard(|env| run_file(path, env, |env| run_script(proto, env)));
+        }
+        self.guard(|env| eval_file_program(&program, path, env))
+    }
+
+    // Calls a function, method or class visible in the global scope
+    pub fn call_function(&mut self, name: &str, args: Vec<RuntimeVal>) -> Result<RuntimeVal, KaulinError> {
+        let function = self.lookup(name)?;
+        self.guard(|env| call_value(function, args, Vec::new(), env))
+    }
+
+    pub fn get_global<T: FromKaulin>(&mut self, name: &str) -> Result<T, KaulinError> {
+        T::from_kaulin(self.lookup(name)?)
+    }
+
+    // Declares the variable, or assigns it if the script already declared it
+    pub fn set_global<T: IntoKaulin>(&mut self, name: &str, value: T) -> Result<(), KaulinError> {
+        let value = value.into_kaulin();
+        let name = name.to_string();
+        self.guard(|env| {
+            if env.has_var(&name) {
+                env.assign_var(&name, &value);
+            } else {
+                env.declare_var(name, value, false);
+            }
+        })
+    }
+
+    // Makes a Rust closure callable from Kaulin; see `HostFunction`
+    pub fn register_function<M>(&mut self, name: &str, function: impl HostFunction<M>) -> Result<(), KaulinError> {
+        self.guard(|env| env.global().register_function(name, function))
+    }
+
+    fn lookup(&mut self, name: &str) -> Result<RuntimeVal, KaulinError> {
+        if !self.env.has_var(name) {
+            return Err(KaulinError::Runtime(format!("Nimeä '{}' ei ole määritelty", name)));
+        }
+        Ok(self.env.lookup_var(name))
+    }
+
+    // Files go through the .kac cache; a program that fails to parse is never cached
+    fn parse(&mut self, source: &str, path: Option<&Path>) -> Result<Program, KaulinError> {
+        let (program, warnings) = catch_panic(|| {
+            let parse = || {
+                let mut parser = Parser::new(tokenize(source));
+                let program = parser.produce_ast();
+                (program, parser.warnings)
+            };
+            match path {
+                Some(path) => parse_cached(path, source, parse),
+                None => parse(),
+            }
+        })?;
+        if let Some(handler) = &self.warning_handler {
+            warnings.iter().for_each(|warning| handler(warning));
+        }
+        self.warnings = warnings;
+        Ok(program)
+    }
+
+    // Undefined names and assignments to vakio are errors before any code runs
+    fn resolve(&self, program: &Program) -> Result<Resolution, KaulinError> {
+        let env = &self.env;
+        let resolution = resolve_program(program, &|name| env.constness(name));
+        if !resolution.errors.is_empty() {
+            return Err(KaulinError::Runtime(resolution.errors.join("\n")));
+        }
+        Ok(resolution)
+    }
+
+    // The original program is resolved first, so errors in branches that get dropped are still reported
+    fn optimize(&self, program: &mut Program, resolution: Resolution) -> Result<Resolution, KaulinError> {
+        if self.optimization == 0 {
+            return Ok(resolution);
+        }
+        optimize_program(program, self.optimization, &self.env);
+        self.resolve(program)
+    }
+
+    // None means the tree-walker runs the whole program
+    fn compile(&mut self, program: &Program, resolution: &Resolution) -> Option<Rc<Proto>> {
+        if self.backend != Backend::Bytecode {
+            return None;
+        }
+        let (proto, mut unsupported) = compile_program(program, resolution);
+        unsupported.sort();
+        unsupported.dedup();
+        for feature in unsupported {
+            self.warn(format!("Tavukoodikääntäjä ei tue vielä {}, joten niitä käyttävät lauseet ajetaan puutulkilla", feature));
+        }
+        Some(proto)
+    }
+
+    fn warn(&mut self, warning: String) {
+        if let Some(handler) = &self.warning_handler {
+            handler(&warning);
+        }
+        self.warnings.push(warning);
+    }
+
+    // Runs `f` on a handle to the global scope, turning a panic into an error.
+    // Scopes opened by the failed code are dropped with the handle.
+    fn guard<R>(&mut self, f: impl FnOnce(&mut Environment) -> R) -> Result<R, KaulinError> {
+        let mut env = self.env.clone();
+        let result = catch_panic(|| f(&mut env));
+        if result.is_err() {
+            self.env.module_registry().borrow_mut().clear_loading();
+        }
+        result
+    }
+}
+
+thread_local! {
+    // How many calls of `quietly` this thread is inside
+    static QUIET: Cell<usize> = const { Cell::new(0) };
+}
+
+// Runtime errors are panics with a Finnish message, which the default hook would print to stderr.
+// One hook, installed once for the whole process, skips the panics of threads running `f`
+// and hands every other panic to the hook that was there before.
+pub(crate) fn quietly<R>(f: impl FnOnce() -> R) -> R {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if QUIET.with(Cell::get) == 0 {
+                previous(info);
+            }
+        }));
+    });
+
+    // Decremented on the way out even if `f` panics
+    struct Quiet;
+    impl Drop for Quiet {
+        fn drop(&mut self) {
+            QUIET.with(|quiet| quiet.set(quiet.get() - 1));
+        }
+    }
+    QUIET.with(|quiet| quiet.set(quiet.get() + 1));
+    let _quiet = Quiet;
+    f()
+}
+
+pub(crate) fn catch_panic<R>(f: impl FnOnce() -> R) -> Result<R, KaulinError> {
+    let result = quietly(|| panic::catch_unwind(AssertUnwindSafe(f)));
+
+    result.map_err(|payload| {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "Tuntematon virhe".to_string(),
+            },
+        };
+        KaulinError::Runtime(message)
+    })
+}
@@ -0,0 +1,158 @@
+use std::fs;
+use std::io::{ self, Read };
+use std::path::{ Path, PathBuf };
+use crate::embed::catch_panic;
+use crate::frontend::lexer::tokenize;
+use crate::frontend::parser::Parser;
+use crate::frontend::syntax;
+
+mod printer;
+
+// `kaulin muotoile` prints .ka files in one canonical style: four-space indentation, spaces around
+// binary operators, one statement per line, at most one blank line in a row, and brackets broken
+// one item per line when the line would get longer than the width. Comments stay where they were.
+
+pub const DEFAULT_WIDTH: usize = 100;
+
+// Fails with the parse error if the source does not parse
+pub fn format_source(source: &str, width: usize) -> Result<String, String> {
+    let program = parse(source)?;
+    let items = syntax::items(source);
+    let tree = syntax::TreeParser::new(&items).program()?;
+    let formatted = printer::print(&tree, &items, width);
+
+    // Formatting must never change what the program does
+    if parse(&formatted).ok() != Some(program) {
+        return Err("muotoiltu koodi ei vastaisi alkuperäistä, joten tiedostoa ei muotoiltu".to_string());
+    }
+    Ok(formatted)
+}
+
+// The syntax tree as text for comparing two sources, without the line numbers some nodes keep
+fn parse(source: &str) -> Result<String, String> {
+    let program = catch_panic(|| format!("{:?}", Parser::new(tokenize(source)).produce_ast())).map_err(|error| error.to_string())?;
+    let mut parts = program.split("line: ");
+    let mut result = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        result.push_str(part.trim_start_matches(|c: char| c.is_ascii_digit()));
+    }
+    Ok(result)
+}
+
+// kaulin muotoile [--check] [--width N] [polut...]; returns the exit code
+pub fn run(args: &[String]) -> i32 {
+    let mut check = false;
+    let mut width = DEFAULT_WIDTH;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // Reports the files that are not formatted instead of rewriting them
+            "--check" => check = true,
+            "--width" => match args.next().and_then(|value| value.parse().ok()) {
+                Some(value) if value > 0 => width = value,
+                _ => {
+                    eprintln!("Virhe: --width odottaa positiivisen kokonaisluvun");
+                    return 2;
+                }
+            },
+            flag if flag.starts_with("--") => {
+                eprintln!("Virhe: Tuntematon valitsin {}", flag);
+                return 2;
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    // - formats standard input to standard output, for editors
+    if paths.len() == 1 && paths[0] == Path::new("-") {
+        let mut source = String::new();
+        if let Err(error) = io::stdin().read_to_string(&mut source) {
+            eprintln!("Virhe: syötettä ei voitu lukea: {}", error);
+            return 2;
+        }
+        return match format_source(&source, width) {
+            Ok(formatted) if check => (formatted != source) as i32,
+            Ok(formatted) => {
+                print!("{}", formatted);
+                0
+            }
+            Err(error) => {
+                eprintln!("Virhe: {}", error);
+                2
+            }
+        };
+    }
+
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+    let mut files = Vec::new();
+    for path in &paths {
+        collect_files(path, &mut files);
+    }
+
+    let (mut unformatted, mut failed) = (0, 0);
+    for file in &files {
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("Virhe: {}: {}", file.display(), error);
+                failed += 1;
+                continue;
+            }
+        };
+        let formatted = match format_source(&source, width) {
+            Ok(formatted) => formatted,
+            Err(error) => {
+                eprintln!("Virhe: {}: {}", file.display(), error);
+                failed += 1;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        unformatted += 1;
+        if check {
+            let line = source.lines().zip(formatted.lines()).position(|(old, new)| old != new)
+                .unwrap_or_else(|| source.lines().count().min(formatted.lines().count()));
+            println!("{}:{}: muotoilu eroaa", file.display(), line + 1);
+        } else if let Err(error) = fs::write(file, &formatted) {
+            eprintln!("Virhe: {}: {}", file.display(), error);
+            failed += 1;
+        } else {
+            println!("Muotoiltu {}", file.display());
+        }
+    }
+
+    if check && unformatted > 0 {
+        eprintln!("{} tiedostoa {}:sta ei ole muotoiltu", unformatted, files.len());
+    }
+    match (failed, check && unformatted > 0) {
+        (0, false) => 0,
+        (0, true) => 1,
+        _ => 2,
+    }
+}
+
+// .ka files under the path; hidden directories and build output are skipped
+pub(crate) fn collect_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return;
+    }
+    let Ok(entries) = fs::read_dir(path) else { return };
+    let mut entries: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    entries.sort();
+    for entry in entries {
+        let name = entry.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        if entry.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                collect_files(&entry, files);
+            }
+        } else if name.ends_with(".ka") {
+            files.push(entry);
+        }
+    }
+}
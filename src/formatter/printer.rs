@@ -0,0 +1,471 @@
+use std::cell::Cell;
+use crate::frontend::lexer::TokenType;
+use crate::frontend::syntax::*;
+
+const INDENT: usize = 4;
+
+// The layout of the output before the line width is known. A group is written on one line when it
+// fits, otherwise its lines break; comments that end a line force the groups around them to break.
+enum Doc {
+    Text(String),
+    // A space, or a line break when the group breaks
+    Line,
+    // Nothing, or a line break when the group breaks
+    SoftLine,
+    HardLine,
+    // A line break unless the line is still empty
+    FreshLine,
+    // A comment at the end of a line; the next text starts a new line
+    Trailing(String),
+    Indent(Box<Doc>),
+    Group(Box<Doc>, bool),
+    Concat(Vec<Doc>),
+}
+
+fn text(text: &str) -> Doc {
+    Doc::Text(text.to_string())
+}
+
+fn indent(docs: Vec<Doc>) -> Doc {
+    Doc::Indent(Box::new(Doc::Concat(docs)))
+}
+
+fn group(docs: Vec<Doc>) -> Doc {
+    let doc = Doc::Concat(docs);
+    let broken = breaks(&doc);
+    Doc::Group(Box::new(doc), broken)
+}
+
+// A comment that ends the doc, e.g. after the ')' of a call, does not break it
+fn breaks(doc: &Doc) -> bool {
+    breaks_after(doc, &mut false)
+}
+
+fn breaks_after(doc: &Doc, trailing: &mut bool) -> bool {
+    match doc {
+        Doc::HardLine | Doc::FreshLine => true,
+        Doc::Trailing(_) => {
+            *trailing = true;
+            false
+        }
+        Doc::Text(text) => *trailing && !text.trim().is_empty(),
+        Doc::Indent(doc) => breaks_after(doc, trailing),
+        Doc::Group(doc, broken) => *broken || breaks_after(doc, trailing),
+        Doc::Concat(docs) => docs.iter().any(|doc| breaks_after(doc, trailing)),
+        Doc::Line | Doc::SoftLine => false,
+    }
+}
+
+// The code in ${ } is formatted like any other; code that would need more than one line, or has
+// comments, is kept as written
+fn template(source: &str) -> String {
+    let mut result = String::new();
+    let mut written = 0;
+    for range in interpolations(source) {
+        result.push_str(&source[written..range.start]);
+        let code = &source[range.clone()];
+        result.push_str(&expression(code).unwrap_or_else(|| code.to_string()));
+        written = range.end;
+    }
+    result.push_str(&source[written..]);
+    result
+}
+
+fn expression(source: &str) -> Option<String> {
+    let items = items(source);
+    if items.iter().any(|item| !item.leading.is_empty() || !item.attached.is_empty() || item.trailing.is_some()) {
+        return None;
+    }
+    let tree = TreeParser::new(&items).program().ok()?;
+    let Node::Tree(_, children) = &tree else { return None };
+    if children.len() != 2 {
+        return None;
+    }
+    let formatted = print(&tree, &items, isize::MAX as usize);
+    let formatted = formatted.trim_end();
+    (!formatted.contains('\n')).then(|| formatted.to_string())
+}
+
+pub fn print(tree: &Node, items: &[Item], width: usize) -> String {
+    let doc = Printer { items, hoisted: Cell::new(None) }.node(tree);
+    let mut layout = Layout { width, output: String::new(), column: 0, indent: 0, line_start: true, trailing: false };
+    layout.write(&doc);
+    layout.output
+}
+
+struct Layout {
+    width: usize,
+    output: String,
+    column: usize,
+    // Indentation is written with the first text of a line, so blank lines stay empty
+    indent: usize,
+    line_start: bool,
+    trailing: bool,
+}
+
+impl Layout {
+    fn write(&mut self, doc: &Doc) {
+        let mut stack = vec![(0, false, doc)];
+        while let Some((indent, flat, doc)) = stack.pop() {
+            match doc {
+                // A space is not needed where a line ends or begins
+                Doc::Text(text) if text == " " && (self.trailing || self.line_start) => (),
+                Doc::Text(text) => {
+                    if self.trailing {
+                        self.newline(indent);
+                    }
+                    if self.line_start {
+                        self.output.extend(std::iter::repeat_n(' ', self.indent));
+                        self.column = self.indent;
+                        self.line_start = false;
+                    }
+                    self.output.push_str(text);
+                    match text.rfind('\n') {
+                        Some(at) => self.column = text[at + 1..].chars().count(),
+                        None => self.column += text.chars().count(),
+                    }
+                }
+                Doc::Line if flat && !self.trailing => {
+                    self.output.push(' ');
+                    self.column += 1;
+                }
+                Doc::SoftLine if flat && !self.trailing => (),
+                Doc::Line | Doc::SoftLine | Doc::HardLine => self.newline(indent),
+                Doc::FreshLine if self.line_start => (),
+                Doc::FreshLine => self.newline(indent),
+                Doc::Trailing(comment) => {
+                    self.output.push(' ');
+                    self.output.push_str(comment);
+                    self.trailing = true;
+                }
+                Doc::Indent(doc) => stack.push((indent + INDENT, flat, doc)),
+                Doc::Group(doc, broken) => {
+                    let flat = flat || (!broken && self.fits(doc, &stack));
+                    stack.push((indent, flat, doc));
+                }
+                Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, flat, doc))),
+            }
+        }
+    }
+
+    fn newline(&mut self, indent: usize) {
+        let end = self.output.trim_end_matches(' ').len();
+        self.output.truncate(end);
+        self.output.push('\n');
+        self.column = 0;
+        self.indent = indent;
+        self.line_start = true;
+        self.trailing = false;
+    }
+
+    // Whether the group fits on the rest of the line, together with what follows it up to the next line break
+    fn fits(&self, doc: &Doc, rest: &[(usize, bool, &Doc)]) -> bool {
+        let mut remaining = self.width as isize - self.column.max(if self.line_start { self.indent } else { 0 }) as isize;
+        let mut stack = vec![(true, doc)];
+        let mut rest = rest.iter().rev();
+        loop {
+            let (flat, doc) = match stack.pop() {
+                Some(next) => next,
+                None => match rest.next() {
+                    Some(&(_, flat, doc)) => (flat, doc),
+                    None => return true,
+                },
+            };
+            match doc {
+                Doc::Text(text) => match text.split_once('\n') {
+                    Some((first, _)) => return remaining >= first.chars().count() as isize,
+                    None => remaining -= text.chars().count() as isize,
+                },
+                Doc::Line if flat => remaining -= 1,
+                Doc::SoftLine if flat => (),
+                Doc::Line | Doc::SoftLine | Doc::HardLine | Doc::FreshLine | Doc::Trailing(_) => return remaining >= 0,
+                Doc::Indent(doc) => stack.push((flat, doc)),
+                Doc::Group(doc, broken) => stack.push((flat && !broken, doc)),
+                Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (flat, doc))),
+            }
+            if remaining < 0 {
+                return false;
+            }
+        }
+    }
+}
+
+struct Printer<'i> {
+    items: &'i [Item],
+    // The first token of the statement being printed, whose comments are already written above it
+    hoisted: Cell<Option<usize>>,
+}
+
+impl Printer<'_> {
+    fn node(&self, node: &Node) -> Doc {
+        let (kind, children) = match node {
+            Node::Token(index) => return self.token(*index),
+            Node::Tree(kind, children) => (*kind, children),
+        };
+        match kind {
+            Kind::Program => {
+                let (end, statements) = children.split_last().unwrap();
+                let mut docs = vec![self.statements(statements)];
+                let end = self.item(end);
+                for (index, comment) in end.leading.iter().enumerate() {
+                    if !statements.is_empty() || index > 0 {
+                        docs.push(Doc::HardLine);
+                        if comment.blank_before {
+                            docs.push(Doc::HardLine);
+                        }
+                    }
+                    docs.push(text(&comment.text));
+                }
+                if !statements.is_empty() || !end.leading.is_empty() {
+                    docs.push(Doc::HardLine);
+                }
+                Doc::Concat(docs)
+            }
+            Kind::Block | Kind::Arms => self.block(children),
+            Kind::Arm => {
+                let mut docs = vec![self.node(&children[0]), text(" "), self.node(&children[1])];
+                let block = matches!(&children[1], Node::Tree(Kind::Block, _));
+                match children.get(2) {
+                    Some(comma) if block => docs.push(self.comments_only(comma)),
+                    Some(comma) => docs.push(self.node(comma)),
+                    None if block => (),
+                    None => docs.push(text(",")),
+                }
+                Doc::Concat(docs)
+            }
+            Kind::Seq => {
+                let mut docs = Vec::new();
+                for (index, child) in children.iter().enumerate() {
+                    if index > 0 {
+                        docs.push(text(" "));
+                    }
+                    docs.push(self.node(child));
+                }
+                Doc::Concat(docs)
+            }
+            Kind::Tight => {
+                let mut docs = Vec::new();
+                for (index, child) in children.iter().enumerate() {
+                    // - -x must not become --x
+                    if index > 0 && self.last_text(&children[index - 1]).ends_with('-') && self.first_text(child).starts_with('-') {
+                        docs.push(text(" "));
+                    }
+                    docs.push(self.node(child));
+                }
+                Doc::Concat(docs)
+            }
+            Kind::List(broken) => self.list(children, broken),
+            Kind::Binary(level) => {
+                let mut operands = Vec::new();
+                self.flatten(node, level, &mut operands);
+                let mut rest = Vec::new();
+                for pair in operands[1..].chunks(2) {
+                    rest.extend([text(" "), self.node(pair[0]), Doc::Line, self.node(pair[1])]);
+                }
+                group(vec![self.node(operands[0]), indent(rest)])
+            }
+            Kind::Conditional => {
+                let [condition, question, consequent, colon, alternate] = &children[..] else { unreachable!() };
+                let rest = vec![Doc::Line, self.node(question), text(" "), self.node(consequent), Doc::Line, self.node(colon), text(" "), self.node(alternate)];
+                group(vec![self.node(condition), indent(rest)])
+            }
+        }
+    }
+
+    fn item(&self, node: &Node) -> &Item {
+        &self.items[token_index(node)]
+    }
+
+    // The comments before a token, the token and the comment after it
+    fn token(&self, index: usize) -> Doc {
+        let item = &self.items[index];
+        let mut docs = match self.hoisted.get() == Some(index) {
+            true => Vec::new(),
+            false => self.leading(item),
+        };
+        match item.token_type {
+            TokenType::Backtick => docs.push(Doc::Text(template(&item.text))),
+            _ => docs.push(text(&item.text)),
+        }
+        docs.extend(self.after(index));
+        Doc::Concat(docs)
+    }
+
+    // The comments after a token. `jos a // ehto` before a '{' on the next line is written after the '{'.
+    fn after(&self, index: usize) -> Vec<Doc> {
+        let item = &self.items[index];
+        let mut docs = Vec::new();
+        if !item.attached.is_empty() {
+            docs.push(text(&item.attached));
+        }
+        if let Some(comment) = index.checked_sub(1).and_then(|previous| self.moved_trailing(previous)) {
+            docs.push(Doc::Trailing(comment.to_string()));
+        }
+        if let Some(comment) = &item.trailing {
+            if self.moved_trailing(index).is_none() {
+                docs.push(Doc::Trailing(comment.clone()));
+            }
+        }
+        docs
+    }
+
+    // The comment after the token, if it is written after the next token instead
+    fn moved_trailing(&self, index: usize) -> Option<&str> {
+        let next = self.items.get(index + 1)?;
+        let movable = next.token_type == TokenType::OpenBrace && next.leading.is_empty() && next.trailing.is_none();
+        self.items[index].trailing.as_deref().filter(|_| movable)
+    }
+
+    fn leading(&self, item: &Item) -> Vec<Doc> {
+        let mut docs = Vec::new();
+        for (index, comment) in item.leading.iter().enumerate() {
+            if index > 0 && comment.blank_before {
+                docs.push(Doc::HardLine);
+            }
+            if !comment.inline {
+                docs.push(Doc::FreshLine);
+            }
+            docs.push(text(&comment.text));
+            docs.push(if comment.inline { text(" ") } else { Doc::HardLine });
+        }
+        if item.blank_before && item.leading.last().is_some_and(|comment| !comment.inline) {
+            docs.push(Doc::HardLine);
+        }
+        docs
+    }
+
+    // A token that is left out, e.g. a comma before a closing bracket, keeps its comments
+    fn comments_only(&self, node: &Node) -> Doc {
+        let item = self.item(node);
+        let mut docs: Vec<Doc> = item.leading.iter().map(|comment| Doc::Trailing(comment.text.clone())).collect();
+        docs.extend(self.after(token_index(node)));
+        Doc::Concat(docs)
+    }
+
+    // Comments before a closing bracket, written inside the brackets
+    fn closing_comments(&self, close: &Item, after_items: bool) -> Vec<Doc> {
+        let mut docs = Vec::new();
+        for (index, comment) in close.leading.iter().enumerate() {
+            docs.push(Doc::HardLine);
+            if comment.blank_before && (after_items || index > 0) {
+                docs.push(Doc::HardLine);
+            }
+            docs.push(text(&comment.text));
+        }
+        docs
+    }
+
+    // The closing bracket without the comments before it
+    fn close(&self, node: &Node) -> Doc {
+        let mut docs = vec![text(&self.item(node).text)];
+        docs.extend(self.after(token_index(node)));
+        Doc::Concat(docs)
+    }
+
+    // Statements on their own lines, keeping one blank line where the source had any
+    fn statements(&self, statements: &[Node]) -> Doc {
+        let mut docs = Vec::new();
+        for (index, statement) in statements.iter().enumerate() {
+            if index > 0 {
+                docs.push(Doc::HardLine);
+                if self.blank_before(statement) {
+                    docs.push(Doc::HardLine);
+                }
+            }
+            // Comments above a statement do not break the lines of its expressions
+            let first = self.first(statement);
+            docs.extend(self.leading(&self.items[first]));
+            self.hoisted.set(Some(first));
+            docs.push(self.node(statement));
+        }
+        Doc::Concat(docs)
+    }
+
+    fn block(&self, children: &[Node]) -> Doc {
+        let (open, rest) = children.split_first().unwrap();
+        let (close, statements) = rest.split_last().unwrap();
+        let close_item = self.item(close);
+        if statements.is_empty() && close_item.leading.is_empty() {
+            return Doc::Concat(vec![self.node(open), self.close(close)]);
+        }
+
+        let mut body = Vec::new();
+        if !statements.is_empty() {
+            body.push(Doc::HardLine);
+            body.push(self.statements(statements));
+        }
+        body.extend(self.closing_comments(close_item, !statements.is_empty()));
+        Doc::Concat(vec![self.node(open), indent(body), Doc::HardLine, self.close(close)])
+    }
+
+    fn list(&self, children: &[Node], broken: bool) -> Doc {
+        let (open, rest) = children.split_first().unwrap();
+        let (close, rest) = rest.split_last().unwrap();
+        let close_item = self.item(close);
+        let braces = self.item(open).token_type == TokenType::OpenBrace;
+        let line = || if braces { Doc::Line } else { Doc::SoftLine };
+        if rest.is_empty() && close_item.leading.is_empty() {
+            return Doc::Concat(vec![self.node(open), self.close(close)]);
+        }
+
+        let mut body = vec![line()];
+        for (index, child) in rest.iter().enumerate() {
+            let is_comma = index % 2 == 1;
+            if !is_comma {
+                body.push(self.node(child));
+            } else if index + 1 == rest.len() {
+                body.push(self.comments_only(child));
+            } else {
+                body.extend([self.token(token_index(child)), Doc::Line]);
+            }
+        }
+        body.extend(self.closing_comments(close_item, !rest.is_empty()));
+        let doc = Doc::Concat(vec![self.node(open), indent(body), line(), self.close(close)]);
+        let broken = broken || breaks(&doc);
+        Doc::Group(Box::new(doc), broken)
+    }
+
+    fn flatten<'n>(&self, node: &'n Node, level: u8, operands: &mut Vec<&'n Node>) {
+        match node {
+            Node::Tree(Kind::Binary(inner), children) if *inner == level => {
+                self.flatten(&children[0], level, operands);
+                operands.extend([&children[1], &children[2]]);
+            }
+            _ => operands.push(node),
+        }
+    }
+
+    fn blank_before(&self, node: &Node) -> bool {
+        let item = &self.items[self.first(node)];
+        item.leading.first().map_or(item.blank_before, |comment| comment.blank_before)
+    }
+
+    fn first(&self, node: &Node) -> usize {
+        match node {
+            Node::Token(index) => *index,
+            Node::Tree(_, children) => self.first(&children[0]),
+        }
+    }
+
+    fn last(&self, node: &Node) -> usize {
+        match node {
+            Node::Token(index) => *index,
+            Node::Tree(_, children) => self.last(children.last().unwrap()),
+        }
+    }
+
+    fn first_text(&self, node: &Node) -> &str {
+        &self.items[self.first(node)].text
+    }
+
+    fn last_text(&self, node: &Node) -> &str {
+        &self.items[self.last(node)].text
+    }
+}
+
+fn token_index(node: &Node) -> usize {
+    match node {
+        Node::Token(index) => *index,
+        Node::Tree(..) => unreachable!(),
+    }
+}
@@ -3,35 +3,130 @@ pub enum Stmt {
     Program(Program),
     VarDeclaration(VarDeclaration),
     FunctionDeclaration(FunctionDeclaration),
-    Expr(Expr),
+    Expr(ExprStmt),
     WhileLoop(WhileLoop),
-    ForLoop(ForLoop)
+    ForLoop(ForLoop),
+    ForEachLoop(ForEachLoop),
+    ClassDeclaration(ClassDeclaration),
+    EnumDeclaration(EnumDeclaration),
+    Import(ImportDeclaration),
+    Export(ExportDeclaration),
 
 }
 
+impl Stmt {
+    // The line the statement starts on, where the debugger stops
+    pub fn line(&self) -> usize {
+        match self {
+            Stmt::Program(_) => 0,
+            Stmt::VarDeclaration(declaration) => declaration.line,
+            Stmt::FunctionDeclaration(declaration) => declaration.line,
+            Stmt::Expr(statement) => statement.line,
+            Stmt::WhileLoop(while_loop) => while_loop.line,
+            Stmt::ForLoop(for_loop) => for_loop.line,
+            Stmt::ForEachLoop(for_each) => for_each.line,
+            Stmt::ClassDeclaration(declaration) => declaration.line,
+            Stmt::EnumDeclaration(declaration) => declaration.line,
+            Stmt::Import(import) => import.line,
+            Stmt::Export(export) => export.line,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Program {
     pub body: Vec<Stmt>,
 }
 
+// An expression used as a statement
+#[derive(Debug, Clone)]
+pub struct ExprStmt {
+    pub expr: Expr,
+    pub line: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct VarDeclaration {
     pub constant: bool,
-    pub identifier: Identifier,
+    pub target: Pattern,
     pub value: Option<Expr>,
+    pub line: usize,
 }
 
 #[derive(Debug, Clone)]
 pub struct FunctionDeclaration {
-    pub parameters: Vec<String>,
+    pub parameters: Vec<Pattern>,
+    pub rest: Option<String>,
     pub name: String,
     pub body: Vec<Stmt>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClassDeclaration {
+    pub name: String,
+    pub parent: Option<String>,
+    pub fields: Vec<FieldDeclaration>,
+    pub constructor: Option<FunctionDeclaration>,
+    pub methods: Vec<FunctionDeclaration>,
+    pub static_fields: Vec<FieldDeclaration>,
+    pub static_methods: Vec<FunctionDeclaration>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldDeclaration {
+    pub name: String,
+    pub value: Option<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumDeclaration {
+    pub name: String,
+    pub variants: Vec<VariantDeclaration>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct VariantDeclaration {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+// tuo "polku.ka" [nimellä m] or tuo { a, b: c } moduulista "polku.ka"
+#[derive(Debug, Clone)]
+pub struct ImportDeclaration {
+    pub path: String,
+    pub alias: Option<String>,
+    pub names: Option<ObjectPattern>,
+    pub line: usize,
+}
+
+// vie <määrittely> or vie { a, b }
+#[derive(Debug, Clone)]
+pub struct ExportDeclaration {
+    pub declaration: Option<Box<Stmt>>,
+    pub names: Vec<String>,
+    pub line: usize,
+}
+
+impl ExportDeclaration {
+    pub fn exported_names(&self) -> Vec<String> {
+        match self.declaration.as_deref() {
+            Some(Stmt::VarDeclaration(declaration)) => declaration.target.bound_names(),
+            Some(Stmt::FunctionDeclaration(declaration)) => vec![declaration.name.clone()],
+            Some(Stmt::ClassDeclaration(declaration)) => vec![declaration.name.clone()],
+            Some(Stmt::EnumDeclaration(declaration)) => vec![declaration.name.clone()],
+            _ => self.names.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct WhileLoop {
     pub condition: Box<Expr>,
     pub body: Block,
+    pub line: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -40,20 +135,36 @@ pub struct ForLoop {
     pub condition: Box<Expr>,
     pub increment: Box<Expr>,
     pub body: Block,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ForEachLoop {
+    pub constant: bool,
+    pub target: Pattern,
+    pub iterable: Box<Expr>,
+    pub body: Block,
+    pub line: usize,
 }
 
 
 #[derive(Debug, Clone)]
 pub enum Expr {
     AssignmentExpr(AssignmentExpr),
+    CompoundAssignmentExpr(CompoundAssignmentExpr),
+    UpdateExpr(UpdateExpr),
     MemberExpr(MemberExpr),
     CallExpr(CallExpr),
     IfElseExpr(IfElseExpr),
+    ConditionalExpr(ConditionalExpr),
+    MatchExpr(MatchExpr),
     Property(Property),
     ObjectLiteral(ObjectLiteral),
     ArrayLiteral(ArrayLiteral),
     NumericLiteral(NumericLiteral),
+    IntegerLiteral(IntegerLiteral),
     StringLiteral(StringLiteral),
+    TemplateLiteral(TemplateLiteral),
     FloatLiteral(FloatLiteral),
     Identifier(Identifier),
     BinaryExpr(BinaryExpr),
@@ -61,6 +172,17 @@ pub enum Expr {
     LogicalExpr(LogicalExpr),
 }
 
+impl Expr {
+    // A member or call chain with a ?. or ?[] link, which tyhjä short-circuits as a whole
+    pub fn is_optional_chain(&self) -> bool {
+        match self {
+            Expr::MemberExpr(member) => member.optional || member.object.is_optional_chain(),
+            Expr::CallExpr(call) => call.caller.is_optional_chain(),
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum BinaryOperator {
     Add,
@@ -69,8 +191,8 @@ pub enum BinaryOperator {
     Divide,
     Exponent,
     Modulus,
-    AddEqual,
-    SubtractEqual,
+    IntegerDivide,
+    NullishCoalescing,
 
     // TODO: Refactor these to be comparison operators
     Equal,
@@ -83,6 +205,12 @@ pub enum BinaryOperator {
     //TODO: Refactor these to be logical operators
     And,
     Or,
+
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
 }
 
 #[derive(Debug, Clone)]
@@ -98,6 +226,20 @@ pub struct AssignmentExpr {
     pub value: Box<Expr>,
 }
 
+#[derive(Debug, Clone)]
+pub struct CompoundAssignmentExpr {
+    pub assignee: Box<Expr>,
+    pub operator: BinaryOperator,
+    pub value: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UpdateExpr {
+    pub operator: String,
+    pub prefix: bool,
+    pub assignee: Box<Expr>,
+}
+
 #[derive(Debug, Clone)]
 pub struct IfElseExpr {
     pub condition: Box<Expr>,
@@ -105,6 +247,181 @@ pub struct IfElseExpr {
     pub else_branch: Option<Block>,
 }
 
+#[derive(Debug, Clone)]
+pub struct ConditionalExpr {
+    pub condition: Box<Expr>,
+    pub consequent: Box<Expr>,
+    pub alternate: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchExpr {
+    pub subject: Box<Expr>,
+    pub arms: Vec<MatchArm>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub pattern: Option<Pattern>,
+    pub guard: Option<Expr>,
+    pub body: Block,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Wildcard,
+    Binding(String),
+    Literal(Box<Expr>),
+    Range(RangePattern),
+    Array(ArrayPattern),
+    Object(ObjectPattern),
+    Alternatives(Vec<Pattern>),
+    Default(DefaultPattern),
+    Variant(VariantPattern),
+}
+
+#[derive(Debug, Clone)]
+pub struct RangePattern {
+    pub start: Box<Expr>,
+    pub end: Box<Expr>,
+    pub inclusive: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArrayPattern {
+    pub elements: Vec<Pattern>,
+    pub rest: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectPattern {
+    pub properties: Vec<PropertyPattern>,
+}
+
+// Muoto.Ympyrä(r); without parentheses the payload is not inspected
+#[derive(Debug, Clone)]
+pub struct VariantPattern {
+    pub enum_name: String,
+    pub variant: String,
+    pub fields: Option<Vec<Pattern>>,
+}
+
+// A pattern with a fallback value used when the element or key is missing
+#[derive(Debug, Clone)]
+pub struct DefaultPattern {
+    pub pattern: Box<Pattern>,
+    pub default: Box<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PropertyPattern {
+    pub key: String,
+    pub pattern: Pattern,
+}
+
+impl Pattern {
+    // A pattern that matches every value
+    pub fn is_irrefutable(&self) -> bool {
+        match self {
+            Pattern::Wildcard | Pattern::Binding(_) => true,
+            Pattern::Alternatives(alternatives) => alternatives.iter().any(|p| p.is_irrefutable()),
+            Pattern::Default(default) => default.pattern.is_irrefutable(),
+            _ => false,
+        }
+    }
+
+    // Every variable name the pattern declares, in source order
+    pub fn bound_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        self.collect_names(&mut names);
+        names
+    }
+
+    fn collect_names(&self, names: &mut Vec<String>) {
+        match self {
+            Pattern::Binding(name) => names.push(name.clone()),
+            Pattern::Array(array) => {
+                array.elements.iter().for_each(|p| p.collect_names(names));
+                if let Some(rest) = array.rest.as_ref().filter(|rest| *rest != "_") {
+                    names.push(rest.clone());
+                }
+            }
+            Pattern::Object(object) => object.properties.iter().for_each(|p| p.pattern.collect_names(names)),
+            Pattern::Default(default) => default.pattern.collect_names(names),
+            Pattern::Alternatives(alternatives) => {
+                if let Some(first) = alternatives.first() {
+                    first.collect_names(names);
+                }
+            }
+            Pattern::Variant(variant) => variant.fields.iter().flatten().for_each(|p| p.collect_names(names)),
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Range(_) => (),
+        }
+    }
+
+    // The name a parameter can be passed by: a or a = 1
+    pub fn parameter_name(&self) -> Option<&String> {
+        match self {
+            Pattern::Binding(name) => Some(name),
+            Pattern::Default(default) => default.pattern.parameter_name(),
+            _ => None,
+        }
+    }
+
+    // Declarations and parameters may only bind names, not test values
+    pub fn is_binding_only(&self) -> bool {
+        match self {
+            Pattern::Wildcard | Pattern::Binding(_) => true,
+            Pattern::Array(array) => array.elements.iter().all(|p| p.is_binding_only()),
+            Pattern::Object(object) => object.properties.iter().all(|p| p.pattern.is_binding_only()),
+            Pattern::Default(default) => default.pattern.is_binding_only(),
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Binding(name) => write!(f, "{}", name),
+            Pattern::Array(array) => {
+                let mut parts: Vec<String> = array.elements.iter().map(|p| p.to_string()).collect();
+                if let Some(rest) = &array.rest {
+                    parts.push(format!("...{}", rest));
+                }
+                write!(f, "[{}]", parts.join(", "))
+            }
+            Pattern::Object(object) => {
+                let parts: Vec<String> = object.properties
+                    .iter()
+                    .map(|p| match &p.pattern {
+                        Pattern::Binding(name) if name == &p.key => p.key.clone(),
+                        Pattern::Default(default) if matches!(&*default.pattern, Pattern::Binding(name) if name == &p.key) =>
+                            format!("{} = ...", p.key),
+                        pattern => format!("{}: {}", p.key, pattern),
+                    })
+                    .collect();
+                write!(f, "{{ {} }}", parts.join(", "))
+            }
+            Pattern::Default(default) => write!(f, "{} = ...", default.pattern),
+            Pattern::Alternatives(alternatives) => {
+                let parts: Vec<String> = alternatives.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}", parts.join(" | "))
+            }
+            Pattern::Variant(variant) => {
+                write!(f, "{}.{}", variant.enum_name, variant.variant)?;
+                if let Some(fields) = &variant.fields {
+                    let parts: Vec<String> = fields.iter().map(|p| p.to_string()).collect();
+                    write!(f, "({})", parts.join(", "))?;
+                }
+                Ok(())
+            }
+            Pattern::Literal(_) | Pattern::Range(_) => write!(f, "..."),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Block {
     pub statements: Vec<Stmt>,
@@ -115,24 +432,45 @@ pub struct MemberExpr {
     pub object: Box<Expr>,
     pub property: Box<Expr>,
     pub computed: bool,
+    pub optional: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct CallExpr {
-    pub args: Vec<Expr>,
+    pub args: Vec<Argument>,
     pub caller: Box<Expr>,
 }
 
+#[derive(Debug, Clone)]
+pub enum Argument {
+    Positional(Expr),
+    // f(nimi: arvo)
+    Named(String, Expr),
+    // f(...lista)
+    Spread(Expr),
+}
+
 #[derive(Debug, Clone)]
 pub struct NumericLiteral {
     pub value: f64,
 }
 
+#[derive(Debug, Clone)]
+pub struct IntegerLiteral {
+    pub value: i64,
+}
+
 #[derive(Debug, Clone)]
 pub struct StringLiteral {
     pub value: String,
 }
 
+// `teksti ${lauseke}`: the parts are joined as strings
+#[derive(Debug, Clone)]
+pub struct TemplateLiteral {
+    pub parts: Vec<Expr>,
+}
+
 #[derive(Debug, Clone)]
 pub struct FloatLiteral {
     pub value: f64,
@@ -0,0 +1,456 @@
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use crate::frontend::ast::*;
+
+// Parsed programs are saved in .kac files, next to the .ka file or in the directory KAULIN_CACHE.
+// A cache file is used only when its header matches this build and the source it was made from;
+// anything else, including a damaged file, is parsed again and the cache rewritten.
+//
+//   "KAC\0"  format version (u32)  Kaulin version (str)  source hash (u64)
+//   payload length (u64)  payload hash (u64)  payload: warnings, then the program
+
+const MAGIC: &[u8; 4] = b"KAC\0";
+// Bump when the AST or its encoding below changes
+const FORMAT_VERSION: u32 = 3;
+
+// Returns the program and parser warnings for `source`, read from the cache when possible
+pub fn parse_cached(
+    path: &Path,
+    source: &str,
+    parse: impl FnOnce() -> (Program, Vec<String>)
+) -> (Program, Vec<String>) {
+    let cache = cache_path(path);
+    let source_hash = hash(source.as_bytes());
+
+    if let Some(cached) = fs::read(&cache).ok().and_then(|bytes| decode(&bytes, source_hash)) {
+        return cached;
+    }
+
+    let (program, warnings) = parse();
+    // The cache is only a speed-up, so a file that cannot be written is skipped
+    let _ = write_atomically(&cache, &encode(&program, &warnings, source_hash));
+    (program, warnings)
+}
+
+pub fn cache_path(path: &Path) -> PathBuf {
+    match env::var_os("KAULIN_CACHE").filter(|dir| !dir.is_empty()) {
+        // Files with the same name in different directories must not share an entry
+        Some(dir) => {
+            let absolute = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+            let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+            PathBuf::from(dir).join(format!("{}-{:016x}.kac", stem, hash(absolute.to_string_lossy().as_bytes())))
+        }
+        None => path.with_extension("kac"),
+    }
+}
+
+// Another run may read the file while this one writes it
+fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let temporary = path.with_extension(format!("kac.{}", std::process::id()));
+    fs::write(&temporary, bytes)?;
+    fs::rename(&temporary, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temporary);
+    })
+}
+
+// FNV-1a; std's hashers are not guaranteed to give the same result in every build
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+fn encode(program: &Program, warnings: &Vec<String>, source_hash: u64) -> Vec<u8> {
+    let mut payload = Vec::new();
+    warnings.write(&mut payload);
+    program.write(&mut payload);
+
+    let mut bytes = MAGIC.to_vec();
+    FORMAT_VERSION.write(&mut bytes);
+    env!("CARGO_PKG_VERSION").to_string().write(&mut bytes);
+    source_hash.write(&mut bytes);
+    (payload.len() as u64).write(&mut bytes);
+    hash(&payload).write(&mut bytes);
+    bytes.extend(payload);
+    bytes
+}
+
+fn decode(bytes: &[u8], source_hash: u64) -> Option<(Program, Vec<String>)> {
+    let mut input = Input { bytes, position: 0 };
+    if input.take(MAGIC.len())? != MAGIC
+        || u32::read(&mut input)? != FORMAT_VERSION
+        || String::read(&mut input)? != env!("CARGO_PKG_VERSION")
+        || u64::read(&mut input)? != source_hash
+    {
+        return None;
+    }
+    let length = u64::read(&mut input)?;
+    let payload_hash = u64::read(&mut input)?;
+    let payload = input.take(usize::try_from(length).ok()?)?;
+    if input.position != bytes.len() || hash(payload) != payload_hash {
+        return None;
+    }
+
+    let mut input = Input { bytes: payload, position: 0 };
+    let warnings = Vec::read(&mut input)?;
+    let program = Program::read(&mut input)?;
+    (input.position == payload.len()).then_some((program, warnings))
+}
+
+struct Input<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Input<'a> {
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        let end = self.position.checked_add(count)?;
+        let bytes = self.bytes.get(self.position..end)?;
+        self.position = end;
+        Some(bytes)
+    }
+
+    fn tag(&mut self) -> Option<u8> {
+        u8::read(self)
+    }
+}
+
+// Reading never panics: a value that does not fit the input gives None
+trait Cached: Sized {
+    fn write(&self, out: &mut Vec<u8>);
+    fn read(input: &mut Input) -> Option<Self>;
+}
+
+impl Cached for u8 {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+
+    fn read(input: &mut Input) -> Option<Self> {
+        Some(input.take(1)?[0])
+    }
+}
+
+impl Cached for bool {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn read(input: &mut Input) -> Option<Self> {
+        match input.tag()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! cached_number {
+    ($($number:ty),*) => {$(
+        impl Cached for $number {
+            fn write(&self, out: &mut Vec<u8>) {
+                out.extend(self.to_le_bytes());
+            }
+
+            fn read(input: &mut Input) -> Option<Self> {
+                Some(<$number>::from_le_bytes(input.take(std::mem::size_of::<$number>())?.try_into().ok()?))
+            }
+        }
+    )*};
+}
+
+cached_number!(u32, u64, i64, f64);
+
+impl Cached for usize {
+    fn write(&self, out: &mut Vec<u8>) {
+        (*self as u64).write(out);
+    }
+
+    fn read(input: &mut Input) -> Option<Self> {
+        usize::try_from(u64::read(input)?).ok()
+    }
+}
+
+impl Cached for String {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.len().write(out);
+        out.extend(self.as_bytes());
+    }
+
+    fn read(input: &mut Input) -> Option<Self> {
+        let length = usize::read(input)?;
+        String::from_utf8(input.take(length)?.to_vec()).ok()
+    }
+}
+
+impl<T: Cached> Cached for Vec<T> {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.len().write(out);
+        self.iter().for_each(|item| item.write(out));
+    }
+
+    // The length is not trusted for the allocation, since a damaged file may claim anything
+    fn read(input: &mut Input) -> Option<Self> {
+        let length = usize::read(input)?;
+        let mut items = Vec::with_capacity(length.min(input.bytes.len() - input.position));
+        for _ in 0..length {
+            items.push(T::read(input)?);
+        }
+        Some(items)
+    }
+}
+
+impl<T: Cached> Cached for Option<T> {
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Some(value) => {
+                out.push(1);
+                value.write(out);
+            }
+            None => out.push(0),
+        }
+    }
+
+    fn read(input: &mut Input) -> Option<Self> {
+        match input.tag()? {
+            0 => Some(None),
+            1 => Some(Some(T::read(input)?)),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Cached> Cached for Box<T> {
+    fn write(&self, out: &mut Vec<u8>) {
+        (**self).write(out);
+    }
+
+    fn read(input: &mut Input) -> Option<Self> {
+        T::read(input).map(Box::new)
+    }
+}
+
+// Structs are their fields in order
+macro_rules! cached_struct {
+    ($($name:ident { $($field:ident),* })*) => {$(
+        impl Cached for $name {
+            fn write(&self, out: &mut Vec<u8>) {
+                $(self.$field.write(out);)*
+            }
+
+            fn read(input: &mut Input) -> Option<Self> {
+                Some($name { $($field: Cached::read(input)?),* })
+            }
+        }
+    )*};
+}
+
+cached_struct! {
+    Program { body }
+    ExprStmt { expr, line }
+    VarDeclaration { constant, target, value, line }
+    FunctionDeclaration { parameters, rest, name, body, line }
+    ClassDeclaration { name, parent, fields, constructor, methods, static_fields, static_methods, line }
+    FieldDeclaration { name, value }
+    EnumDeclaration { name, variants, line }
+    VariantDeclaration { name, fields }
+    ImportDeclaration { path, alias, names, line }
+    ExportDeclaration { declaration, names, line }
+    WhileLoop { condition, body, line }
+    ForLoop { initializer, condition, increment, body, line }
+    ForEachLoop { constant, target, iterable, body, line }
+    LogicalExpr { left, right, operator }
+    AssignmentExpr { assignee, value }
+    CompoundAssignmentExpr { assignee, operator, value }
+    UpdateExpr { operator, prefix, assignee }
+    IfElseExpr { condition, if_branch, else_branch }
+    ConditionalExpr { condition, consequent, alternate }
+    MatchExpr { subject, arms }
+    MatchArm { pattern, guard, body, line }
+    RangePattern { start, end, inclusive }
+    ArrayPattern { elements, rest }
+    ObjectPattern { properties }
+    VariantPattern { enum_name, variant, fields }
+    DefaultPattern { pattern, default }
+    PropertyPattern { key, pattern }
+    Block { statements }
+    MemberExpr { object, property, computed, optional }
+    CallExpr { args, caller }
+    NumericLiteral { value }
+    IntegerLiteral { value }
+    StringLiteral { value }
+    TemplateLiteral { parts }
+    FloatLiteral { value }
+    Identifier { symbol }
+    Property { key, value }
+    ObjectLiteral { properties }
+    ArrayLiteral { elements }
+    BinaryExpr { left, right, operator }
+    UnaryExpr { operator, operand }
+}
+
+// Enums are a tag, then the fields of the variant; the tags are the variants in order
+macro_rules! cached_enum {
+    ($($name:ident { $($variant:ident),* })*) => {$(
+        impl Cached for $name {
+            fn write(&self, out: &mut Vec<u8>) {
+                let mut tag = 0u8;
+                $(
+                    if let $name::$variant(value) = self {
+                        out.push(tag);
+                        value.write(out);
+                        return;
+                    }
+                    tag += 1;
+                )*
+                let _ = tag;
+            }
+
+            fn read(input: &mut Input) -> Option<Self> {
+                let tag = input.tag()?;
+                let mut expected = 0u8;
+                $(
+                    if tag == expected {
+                        return Some($name::$variant(Cached::read(input)?));
+                    }
+                    expected += 1;
+                )*
+                let _ = expected;
+                None
+            }
+        }
+    )*};
+}
+
+cached_enum! {
+    Stmt {
+        Program, VarDeclaration, FunctionDeclaration, Expr, WhileLoop, ForLoop, ForEachLoop,
+        ClassDeclaration, EnumDeclaration, Import, Export
+    }
+    Expr {
+        AssignmentExpr, CompoundAssignmentExpr, UpdateExpr, MemberExpr, CallExpr, IfElseExpr,
+        ConditionalExpr, MatchExpr, Property, ObjectLiteral, ArrayLiteral, NumericLiteral,
+        IntegerLiteral, StringLiteral, FloatLiteral, Identifier, BinaryExpr, UnaryExpr, LogicalExpr, TemplateLiteral
+    }
+}
+
+impl Cached for Argument {
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Argument::Positional(value) => {
+                out.push(0);
+                value.write(out);
+            }
+            Argument::Named(name, value) => {
+                out.push(1);
+                name.write(out);
+                value.write(out);
+            }
+            Argument::Spread(value) => {
+                out.push(2);
+                value.write(out);
+            }
+        }
+    }
+
+    fn read(input: &mut Input) -> Option<Self> {
+        match input.tag()? {
+            0 => Some(Argument::Positional(Expr::read(input)?)),
+            1 => Some(Argument::Named(String::read(input)?, Expr::read(input)?)),
+            2 => Some(Argument::Spread(Expr::read(input)?)),
+            _ => None,
+        }
+    }
+}
+
+impl Cached for Pattern {
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Pattern::Wildcard => out.push(0),
+            Pattern::Binding(name) => {
+                out.push(1);
+                name.write(out);
+            }
+            Pattern::Literal(value) => {
+                out.push(2);
+                value.write(out);
+            }
+            Pattern::Range(range) => {
+                out.push(3);
+                range.write(out);
+            }
+            Pattern::Array(array) => {
+                out.push(4);
+                array.write(out);
+            }
+            Pattern::Object(object) => {
+                out.push(5);
+                object.write(out);
+            }
+            Pattern::Alternatives(alternatives) => {
+                out.push(6);
+                alternatives.write(out);
+            }
+            Pattern::Default(default) => {
+                out.push(7);
+                default.write(out);
+            }
+            Pattern::Variant(variant) => {
+                out.push(8);
+                variant.write(out);
+            }
+        }
+    }
+
+    fn read(input: &mut Input) -> Option<Self> {
+        Some(match input.tag()? {
+            0 => Pattern::Wildcard,
+            1 => Pattern::Binding(Cached::read(input)?),
+            2 => Pattern::Literal(Cached::read(input)?),
+            3 => Pattern::Range(Cached::read(input)?),
+            4 => Pattern::Array(Cached::read(input)?),
+            5 => Pattern::Object(Cached::read(input)?),
+            6 => Pattern::Alternatives(Cached::read(input)?),
+            7 => Pattern::Default(Cached::read(input)?),
+            8 => Pattern::Variant(Cached::read(input)?),
+            _ => return None,
+        })
+    }
+}
+
+const OPERATORS: [BinaryOperator; 21] = [
+    BinaryOperator::Add,
+    BinaryOperator::Subtract,
+    BinaryOperator::Multiply,
+    BinaryOperator::Divide,
+    BinaryOperator::Exponent,
+    BinaryOperator::Modulus,
+    BinaryOperator::IntegerDivide,
+    BinaryOperator::NullishCoalescing,
+    BinaryOperator::Equal,
+    BinaryOperator::NotEqual,
+    BinaryOperator::LessThan,
+    BinaryOperator::GreaterThan,
+    BinaryOperator::LessThanOrEqual,
+    BinaryOperator::GreaterThanOrEqual,
+    BinaryOperator::And,
+    BinaryOperator::Or,
+    BinaryOperator::BitwiseAnd,
+    BinaryOperator::BitwiseOr,
+    BinaryOperator::BitwiseXor,
+    BinaryOperator::ShiftLeft,
+    BinaryOperator::ShiftRight,
+];
+
+impl Cached for BinaryOperator {
+    fn write(&self, out: &mut Vec<u8>) {
+        let index = OPERATORS.iter().position(|operator| operator == self).unwrap();
+        out.push(index as u8);
+    }
+
+    fn read(input: &mut Input) -> Option<Self> {
+        OPERATORS.get(input.tag()? as usize).cloned()
+    }
+}
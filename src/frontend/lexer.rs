@@ -1,15 +1,27 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::iter::Peekable;
 use std::str::Chars;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum TokenType {
     Integer,
     Float,
+    TypedInteger, // 0xff, 0b1010, 0o17, 10k
     Identifier,
     StringLiteral,
+    Backtick, // `
+    InterpolationStart, // ${
 
     PlusEqual, // +=
     MinusEqual, // -=
+    TimesEqual, // *=
+    DivideEqual, // /=
+    ModulusEqual, // %=
+    ExponentEqual, // **=
+    NullishEqual, // ??=
+    Increment, // ++
+    Decrement, // --
     Equal, // ==
     NotEqual, // !=
     LessThan, // <
@@ -27,11 +39,31 @@ pub enum TokenType {
     Else,
     While,
     For,
+    Match,
+    Case,
+    In,
+    Class,
+    Extends,
+    Constructor,
+    Static,
+    Enum,
+    Import,
+    From,
+    Export,
+    As,
     Absolute,
     BinaryOperator,
+    UnaryOperator, // ! ~
     Assign,
     Comma,
     Dot,
+    DotDot, // ..
+    DotDotEqual, // ..=
+    Ellipsis, // ...
+    FatArrow, // =>
+    QuestionMark, // ?
+    OptionalDot, // ?.
+    OptionalBracket, // ?[
     Exponent,
     Colon,
     SemiColon,
@@ -44,18 +76,144 @@ pub enum TokenType {
     EOF,
 }
 
-#[derive(Debug)]
-pub struct Token {
-    pub value: String,
+// Values borrow from the source, except where the lexer rewrites the text (escapes, 0xff -> 255)
+#[derive(Clone)]
+pub struct Token<'a> {
+    pub value: Cow<'a, str>,
     pub token_type: TokenType,
+    // Identifiers and keywords; the same name always gets the same symbol within one tokenize call
+    pub symbol: Option<Symbol>,
+    pub line: usize,
+    pub column: usize,
 }
 
-impl Token {
-    pub fn new(value: String, token_type: TokenType) -> Self {
-        Self { value, token_type }
+impl<'a> Token<'a> {
+    pub fn new(value: impl Into<Cow<'a, str>>, token_type: TokenType) -> Self {
+        Self { value: value.into(), token_type, symbol: None, line: 0, column: 0 }
     }
 }
 
+// Parse errors print tokens, so the symbol is left out
+impl std::fmt::Debug for Token<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Token")
+            .field("value", &self.value)
+            .field("token_type", &self.token_type)
+            .field("line", &self.line)
+            .field("column", &self.column)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+const KEYWORDS: [(&str, TokenType); 19] = [
+    ("olkoon", TokenType::Let),
+    ("vakio", TokenType::Const),
+    ("funktio", TokenType::Fn),
+    ("jos", TokenType::If),
+    ("muuten", TokenType::Else),
+    ("kun", TokenType::While),
+    ("toista", TokenType::For),
+    ("valitse", TokenType::Match),
+    ("tapaus", TokenType::Case),
+    ("joukosta", TokenType::In),
+    ("luokka", TokenType::Class),
+    ("laajentaa", TokenType::Extends),
+    ("rakentaja", TokenType::Constructor),
+    ("staattinen", TokenType::Static),
+    ("luettelo", TokenType::Enum),
+    ("tuo", TokenType::Import),
+    ("moduulista", TokenType::From),
+    ("vie", TokenType::Export),
+    ("nimellä", TokenType::As),
+];
+
+// Maps each distinct name to a symbol; keywords are interned first, so their symbols are their indices
+pub struct Interner<'a> {
+    symbols: HashMap<&'a str, Symbol>,
+    names: Vec<&'a str>,
+}
+
+impl<'a> Interner<'a> {
+    pub fn new() -> Self {
+        let mut interner = Self { symbols: HashMap::new(), names: Vec::new() };
+        for (keyword, _) in KEYWORDS {
+            interner.intern(keyword);
+        }
+        interner
+    }
+
+    pub fn intern(&mut self, name: &'a str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        self.symbols.insert(name, symbol);
+        self.names.push(name);
+        symbol
+    }
+
+    pub fn name(&self, symbol: Symbol) -> &'a str {
+        self.names[symbol.0 as usize]
+    }
+
+    fn keyword(symbol: Symbol) -> Option<TokenType> {
+        KEYWORDS.get(symbol.0 as usize).map(|&(_, token_type)| token_type)
+    }
+}
+
+impl Default for Interner<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Character iterator that keeps track of the current line, column and byte offset
+struct SourceChars<'a> {
+    source: &'a str,
+    chars: Peekable<Chars<'a>>,
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> SourceChars<'a> {
+    fn new(source: &'a str) -> Self {
+        Self { source, chars: source.chars().peekable(), offset: 0, line: 1, column: 1 }
+    }
+
+    // The source from `start` up to the current position
+    fn slice_from(&self, start: usize) -> &'a str {
+        &self.source[start..self.offset]
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.chars.clone().nth(1)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+}
+
+fn lexer_error(line: usize, column: usize, message: &str) -> ! {
+    panic!("Rivi {}, sarake {}: {}", line, column, message)
+}
+
 fn is_alpha(c: char) -> bool {
     c.is_alphabetic() || c == '_'
 }
@@ -65,66 +223,407 @@ fn is_skippable(c: char) -> bool {
 }
 
 fn is_float_or_int(c: char) -> bool {
-    c.is_digit(10) || c == '.'
+    c.is_ascii_digit()
+}
+
+// Reads digits of the given radix, allowing single '_' separators between them; returns them as written
+fn read_digits<'a>(chars: &mut SourceChars<'a>, radix: u32, line: usize, column: usize) -> &'a str {
+    let start = chars.offset;
+    let mut last_was_separator = false;
+    while let Some(&c) = chars.peek() {
+        if c == '_' {
+            if chars.offset == start || last_was_separator {
+                lexer_error(line, column, "Numeroerotin '_' on sallittu vain numeroiden välissä");
+            }
+            last_was_separator = true;
+            chars.next();
+        } else if c.is_digit(radix) {
+            chars.next();
+            last_was_separator = false;
+        } else if c.is_ascii_alphanumeric() && radix != 10 {
+            lexer_error(line, column, &format!("Virheellinen numero '{}' {}-kantaisessa luvussa", c, radix));
+        } else {
+            break;
+        }
+    }
+    if last_was_separator {
+        lexer_error(line, column, "Numeroerotin '_' on sallittu vain numeroiden välissä");
+    }
+    chars.slice_from(start)
+}
+
+// Reads a numeric literal and returns its normalized value, e.g. 0xff -> "255", 1_000.5 -> "1000.5"
+//
+//   123  1_000  1.5  1.5e-3  0xff  0b1010  0o17
+//
+// A trailing type hint forces the type: 10k is a kokonaisluku and 10l a liukuluku.
+fn read_number<'a>(chars: &mut SourceChars<'a>) -> (Cow<'a, str>, TokenType) {
+    let (line, column) = (chars.line, chars.column);
+    let start = chars.offset;
+
+    let radix = if chars.peek() == Some(&'0') {
+        match chars.peek_second() {
+            Some('x') | Some('X') => 16,
+            Some('b') | Some('B') => 2,
+            Some('o') | Some('O') => 8,
+            _ => 10,
+        }
+    } else {
+        10
+    };
+
+    let (num, mut token_type) = if radix != 10 {
+        chars.next();
+        chars.next();
+        let digits = read_digits(chars, radix, line, column);
+        if digits.is_empty() {
+            lexer_error(line, column, "Lukujärjestelmän etuliitteen jälkeen puuttuu numerot");
+        }
+        match i64::from_str_radix(&digits.replace('_', ""), radix) {
+            Ok(value) => (Cow::Owned(value.to_string()), TokenType::TypedInteger),
+            Err(_) => lexer_error(line, column, "Kokonaisluku on liian suuri"),
+        }
+    } else {
+        read_digits(chars, 10, line, column);
+        let mut token_type = TokenType::Integer;
+
+        // A '.' belongs to the number unless another '.' follows, so 1..5 stays a range and 5. is 5.0
+        if chars.peek() == Some(&'.') && chars.peek_second() != Some('.') {
+            chars.next();
+            read_digits(chars, 10, line, column);
+            token_type = TokenType::Float;
+            if chars.peek() == Some(&'.') && chars.peek_second().is_some_and(|c| c.is_ascii_digit()) {
+                lexer_error(chars.line, chars.column, "Odottamaton '.' numerossa");
+            }
+        }
+
+        if chars.peek() == Some(&'e') || chars.peek() == Some(&'E') {
+            let exponent_ok = match chars.peek_second() {
+                Some(c) if c.is_ascii_digit() => true,
+                Some('+') | Some('-') => true,
+                _ => false,
+            };
+            if exponent_ok {
+                chars.next();
+                if chars.peek() == Some(&'+') || chars.peek() == Some(&'-') {
+                    chars.next();
+                }
+                if read_digits(chars, 10, line, column).is_empty() {
+                    lexer_error(line, column, "Eksponentista puuttuu numerot");
+                }
+                token_type = TokenType::Float;
+            }
+        }
+
+        let text = chars.slice_from(start);
+        if token_type == TokenType::Integer && text.replace('_', "").parse::<i64>().is_err() {
+            lexer_error(line, column, "Kokonaisluku on liian suuri");
+        }
+        if text.contains(['_', 'E']) {
+            (Cow::Owned(text.replace('_', "").replace('E', "e")), token_type)
+        } else {
+            (Cow::Borrowed(text), token_type)
+        }
+    };
+
+    // Trailing type hint
+    let hint = chars.peek().copied();
+    let hint_stands_alone = !chars.peek_second().is_some_and(|c| is_alpha(c) || c.is_ascii_digit());
+    match hint {
+        Some('k') if hint_stands_alone => {
+            chars.next();
+            if token_type == TokenType::Float {
+                lexer_error(line, column, "Tyyppivihje 'k' vaatii kokonaisluvun");
+            }
+            token_type = TokenType::TypedInteger;
+        }
+        Some('l') if hint_stands_alone => {
+            chars.next();
+            token_type = TokenType::Float;
+        }
+        _ => (),
+    }
+
+    if let Some(&c) = chars.peek() {
+        if is_alpha(c) || c.is_ascii_digit() {
+            lexer_error(line, column, &format!("Virheellinen numeroliteraali: odottamaton '{}'", c));
+        }
+    }
+
+    (num, token_type)
+}
+
+fn read_escape(chars: &mut SourceChars) -> Option<char> {
+    let (line, column) = (chars.line, chars.column);
+    chars.next(); // consume the '\\'
+    let c = match chars.next() {
+        Some(c) => c,
+        None => lexer_error(line, column, "Merkkijono päättyi kesken ohjausmerkin"),
+    };
+    let escaped = match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        '\\' | '"' | '\'' | '`' | '$' => c,
+        'u' => {
+            if chars.next() != Some('{') {
+                lexer_error(line, column, "Unicode-ohjausmerkin muoto on \\u{XXXX}");
+            }
+            let mut hex = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == '}' {
+                    break;
+                }
+                hex.push(chars.next().unwrap());
+            }
+            if chars.next() != Some('}') {
+                lexer_error(line, column, "Unicode-ohjausmerkistä puuttuu sulkeva '}'");
+            }
+            match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                Some(c) => c,
+                None => lexer_error(line, column, &format!("Virheellinen Unicode-koodipiste '{}'", hex)),
+            }
+        }
+        // A backslash at the end of a line continues the string on the next line
+        '\n' | '\r' => {
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+            while let Some(&c) = chars.peek() {
+                if c == ' ' || c == '\t' {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            return None;
+        }
+        _ => lexer_error(line, column, &format!("Tuntematon ohjausmerkki '\\{}'", c)),
+    };
+    Some(escaped)
+}
+
+fn read_string<'a>(chars: &mut SourceChars<'a>) -> Cow<'a, str> {
+    let (line, column) = (chars.line, chars.column);
+    let quote_type = chars.next().unwrap();
+
+    // """...""" strings may contain unescaped quotes, and a newline right after the opening quotes is skipped
+    let mut triple = false;
+    if quote_type == '"' && chars.peek() == Some(&'"') {
+        chars.next();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            triple = true;
+            if chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+        } else {
+            return Cow::Borrowed("");
+        }
+    }
+
+    // Without escapes the literal is a slice of the source as is
+    if !triple {
+        let rest = &chars.source[chars.offset..];
+        if let Some(end) = rest.find([quote_type, '\\']).filter(|&end| rest[end..].starts_with(quote_type)) {
+            let close = chars.offset + end;
+            while chars.offset < close {
+                chars.next();
+            }
+            chars.next();
+            return Cow::Borrowed(&rest[..end]);
+        }
+    }
+
+    let mut string_literal = String::new();
+    let mut closing_quotes = 0;
+    loop {
+        match chars.peek() {
+            None => lexer_error(line, column, "Merkkijonosta puuttuu lopetusmerkki"),
+            Some(&'\\') => {
+                if let Some(c) = read_escape(chars) {
+                    string_literal.push(c);
+                }
+                closing_quotes = 0;
+            }
+            Some(&c) if c == quote_type => {
+                chars.next();
+                if !triple {
+                    break;
+                }
+                closing_quotes += 1;
+                string_literal.push(c);
+                if closing_quotes == 3 {
+                    string_literal.truncate(string_literal.len() - 3);
+                    break;
+                }
+            }
+            Some(_) => {
+                string_literal.push(chars.next().unwrap());
+                closing_quotes = 0;
+            }
+        }
+    }
+    Cow::Owned(string_literal)
 }
 
-fn get_keywords() -> Vec<(&'static str, TokenType)> {
-    vec![
-        ("olkoon", TokenType::Let), 
-        ("vakio", TokenType::Const), 
-        ("funktio", TokenType::Fn),
-        ("jos", TokenType::If),
-        ("muuten", TokenType::Else),
-        ("kun", TokenType::While),
-        ("toista", TokenType::For),
-        ]
+// Reads the source of a `${ ... }` interpolation up to its matching closing brace
+fn read_interpolation<'a>(chars: &mut SourceChars<'a>, line: usize, column: usize) -> &'a str {
+    let start = chars.offset;
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    loop {
+        let c = match chars.next() {
+            Some(c) => c,
+            None => lexer_error(line, column, "Lausekkeesta '${' puuttuu sulkeva '}'"),
+        };
+        match quote {
+            Some(q) => {
+                if c == '\\' {
+                    chars.next();
+                    continue;
+                }
+                if c == q {
+                    quote = None;
+                }
+            }
+            None => match c {
+                '"' | '\'' | '`' => quote = Some(c),
+                '{' => depth += 1,
+                '}' if depth == 0 => return &chars.source[start..chars.offset - 1],
+                '}' => depth -= 1,
+                _ => (),
+            },
+        }
+    }
 }
 
-pub fn tokenize(source_code: &str) -> Vec<Token> {
-    let mut tokens = Vec::new();
-    let mut chars = source_code.chars().peekable();
-    let keywords = get_keywords();
+fn read_template<'a>(chars: &mut SourceChars<'a>, tokens: &mut Vec<Token<'a>>, interner: &mut Interner<'a>) {
+    let (line, column) = (chars.line, chars.column);
+    chars.next(); // consume the opening '`'
+    tokens.push(Token::new("`", TokenType::Backtick));
+
+    let mut part = String::new();
+    loop {
+        match chars.peek() {
+            None => lexer_error(line, column, "Mallimerkkijonosta puuttuu lopetusmerkki '`'"),
+            Some(&'`') => {
+                chars.next();
+                break;
+            }
+            Some(&'\\') => {
+                if let Some(c) = read_escape(chars) {
+                    part.push(c);
+                }
+            }
+            Some(&'$') => {
+                let (expr_line, expr_column) = (chars.line, chars.column);
+                chars.next();
+                if chars.peek() != Some(&'{') {
+                    part.push('$');
+                    continue;
+                }
+                chars.next();
+
+                tokens.push(Token::new(std::mem::take(&mut part), TokenType::StringLiteral));
+
+                let mut start = Token::new("${", TokenType::InterpolationStart);
+                start.line = expr_line;
+                start.column = expr_column;
+                tokens.push(start);
+
+                let (body_line, body_column) = (chars.line, chars.column);
+                let source = read_interpolation(chars, expr_line, expr_column);
+                let mut inner = tokenize_with(source, interner);
+                inner.pop(); // drop the inner EndOfFile
+                for token in &mut inner {
+                    if token.line == 1 {
+                        token.column += body_column - 1;
+                    }
+                    token.line += body_line - 1;
+                }
+                tokens.extend(inner);
+
+                let mut end = Token::new("}", TokenType::CloseBrace);
+                end.line = chars.line;
+                end.column = chars.column - 1;
+                tokens.push(end);
+            }
+            Some(_) => part.push(chars.next().unwrap()),
+        }
+    }
+
+    tokens.push(Token::new(part, TokenType::StringLiteral));
+    tokens.push(Token::new("`", TokenType::Backtick));
+}
+
+pub fn tokenize(source_code: &str) -> Vec<Token<'_>> {
+    tokenize_with(source_code, &mut Interner::new())
+}
+
+// Keeps comments as SingleLineComment and MultiLineComment tokens, for tools that print the source back
+pub fn tokenize_with_comments(source_code: &str) -> Vec<Token<'_>> {
+    lex(source_code, &mut Interner::new(), true)
+}
+
+// Interpolations are tokenized with the interner of the surrounding source, so names keep their symbols
+pub fn tokenize_with<'a>(source_code: &'a str, interner: &mut Interner<'a>) -> Vec<Token<'a>> {
+    lex(source_code, interner, false)
+}
+
+fn lex<'a>(source_code: &'a str, interner: &mut Interner<'a>, comments: bool) -> Vec<Token<'a>> {
+    let mut tokens: Vec<Token<'a>> = Vec::new();
+    let mut chars = SourceChars::new(source_code);
 
     while let Some(&c) = chars.peek() {
+        let (line, column) = (chars.line, chars.column);
+        let first_new = tokens.len();
+
         match c {
             '*' => {
                 chars.next();
-                if let Some(&next_c) = chars.peek() {
-                    if next_c == '*' {
-                        chars.next(); // consume the second '*'
-                        tokens.push(Token::new("**".to_string(), TokenType::BinaryOperator));
+                if chars.peek() == Some(&'*') {
+                    chars.next(); // consume the second '*'
+                    if chars.peek() == Some(&'=') {
+                        chars.next();
+                        tokens.push(Token::new("**=", TokenType::ExponentEqual));
                     } else {
-                        tokens.push(Token::new("*".to_string(), TokenType::BinaryOperator));
+                        tokens.push(Token::new("**", TokenType::BinaryOperator));
                     }
+                } else if chars.peek() == Some(&'=') {
+                    chars.next();
+                    tokens.push(Token::new("*=", TokenType::TimesEqual));
                 } else {
-                    tokens.push(Token::new("*".to_string(), TokenType::BinaryOperator));
+                    tokens.push(Token::new("*", TokenType::BinaryOperator));
                 }
             }
             '(' => {
                 chars.next();
-                tokens.push(Token::new("(".to_string(), TokenType::OpenParen));
+                tokens.push(Token::new("(", TokenType::OpenParen));
             }
             ')' => {
                 chars.next();
-                tokens.push(Token::new(")".to_string(), TokenType::CloseParen));
+                tokens.push(Token::new(")", TokenType::CloseParen));
             } 
             '{' => {
                 chars.next();
-                tokens.push(Token::new("{".to_string(), TokenType::OpenBrace));
+                tokens.push(Token::new("{", TokenType::OpenBrace));
             } 
             '}' => {
                 chars.next();
-                tokens.push(Token::new("}".to_string(), TokenType::CloseBrace));
+                tokens.push(Token::new("}", TokenType::CloseBrace));
             } 
             '[' => {
                 chars.next();
-                tokens.push(Token::new("[".to_string(), TokenType::OpenBracket));
+                tokens.push(Token::new("[", TokenType::OpenBracket));
             } 
             ']' => {
                 chars.next();
-                tokens.push(Token::new("]".to_string(), TokenType::CloseBracket));
+                tokens.push(Token::new("]", TokenType::CloseBracket));
             }
             '/' => {
+                let start = chars.offset;
                 chars.next();
                 match chars.peek() {
                     Some(&'/') => {
@@ -136,6 +635,9 @@ pub fn tokenize(source_code: &str) -> Vec<Token> {
                                 chars.next();
                             }
                         }
+                        if comments {
+                            tokens.push(Token::new(chars.slice_from(start).trim_end(), TokenType::SingleLineComment));
+                        }
                     }
                     Some(&'*') => {
                         // Multi-line comment, consume until '*/'
@@ -153,161 +655,211 @@ pub fn tokenize(source_code: &str) -> Vec<Token> {
                                 chars.next();
                             }
                         }
+                        if comments {
+                            tokens.push(Token::new(chars.slice_from(start), TokenType::MultiLineComment));
+                        }
+                    }
+                    Some(&'=') => {
+                        chars.next();
+                        tokens.push(Token::new("/=", TokenType::DivideEqual));
                     }
                     _ => {
-                        tokens.push(Token::new("/".to_string(), TokenType::BinaryOperator));
+                        tokens.push(Token::new("/", TokenType::BinaryOperator));
                     }
                 }
             }
-            '+' | '-' | '*' | '%' | '&' | '|' => {
-                let mut operator = chars.next().unwrap().to_string();
-                if (c == '&' && chars.peek() == Some(&'&')) || (c == '|' && chars.peek() == Some(&'|')) {
-                    operator.push(chars.next().unwrap());
-                } else if (c == '+' || c == '-') && chars.peek() == Some(&'=') {
-                    operator.push(chars.next().unwrap());
+            '+' | '-' | '%' => {
+                let start = chars.offset;
+                chars.next();
+                if chars.peek() == Some(&'=') || ((c == '+' || c == '-') && chars.peek() == Some(&c)) {
+                    chars.next();
                 }
-                let token_type = match operator.as_str() {
+                let operator = chars.slice_from(start);
+                let token_type = match operator {
                     "+=" => TokenType::PlusEqual,
                     "-=" => TokenType::MinusEqual,
+                    "%=" => TokenType::ModulusEqual,
+                    "++" => TokenType::Increment,
+                    "--" => TokenType::Decrement,
                     _ => TokenType::BinaryOperator,
                 };
                 tokens.push(Token::new(operator, token_type));
             }
             ';' => {
                 chars.next();
-                tokens.push(Token::new(";".to_string(), TokenType::SemiColon));
+                tokens.push(Token::new(";", TokenType::SemiColon));
             }
             '=' => {
                 chars.next();
                 if chars.peek() == Some(&'=') {
                     chars.next();
-                    tokens.push(Token::new("==".to_string(), TokenType::Equal));
+                    tokens.push(Token::new("==", TokenType::Equal));
+                } else if chars.peek() == Some(&'>') {
+                    chars.next();
+                    tokens.push(Token::new("=>", TokenType::FatArrow));
                 } else {
-                    tokens.push(Token::new("=".to_string(), TokenType::Assign));
+                    tokens.push(Token::new("=", TokenType::Assign));
                 }
             }
             '!' => {
                 chars.next();
                 if chars.peek() == Some(&'=') {
                     chars.next();
-                    tokens.push(Token::new("!=".to_string(), TokenType::NotEqual));
+                    tokens.push(Token::new("!=", TokenType::NotEqual));
                 } else {
-                    panic!("Odottamaton '!' lähteessä");
+                    tokens.push(Token::new("!", TokenType::UnaryOperator));
                 }
-            } 
+            }
+            '~' => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    tokens.push(Token::new("~/", TokenType::BinaryOperator));
+                } else {
+                    tokens.push(Token::new("~", TokenType::UnaryOperator));
+                }
+            }
+            '?' => {
+                chars.next();
+                match (chars.peek().copied(), chars.peek_second()) {
+                    (Some('?'), Some('=')) => {
+                        chars.next();
+                        chars.next();
+                        tokens.push(Token::new("??=", TokenType::NullishEqual));
+                    }
+                    (Some('?'), _) => {
+                        chars.next();
+                        tokens.push(Token::new("??", TokenType::BinaryOperator));
+                    }
+                    // `ehto ?.5 : 1` is still a conditional
+                    (Some('.'), next) if !next.is_some_and(|c| c.is_ascii_digit()) => {
+                        chars.next();
+                        tokens.push(Token::new("?.", TokenType::OptionalDot));
+                    }
+                    // Written without a space, `?[` is always an optional index
+                    (Some('['), _) => {
+                        chars.next();
+                        tokens.push(Token::new("?[", TokenType::OptionalBracket));
+                    }
+                    _ => {
+                        tokens.push(Token::new("?", TokenType::QuestionMark));
+                    }
+                }
+            }
+            '^' => {
+                chars.next();
+                tokens.push(Token::new("^", TokenType::BinaryOperator));
+            }
             '<' => {
                 chars.next();
                 if chars.peek() == Some(&'=') {
                     chars.next();
-                    tokens.push(Token::new("<=".to_string(), TokenType::LessThanOrEqual));
+                    tokens.push(Token::new("<=", TokenType::LessThanOrEqual));
+                } else if chars.peek() == Some(&'<') {
+                    chars.next();
+                    tokens.push(Token::new("<<", TokenType::BinaryOperator));
                 } else {
-                    tokens.push(Token::new("<".to_string(), TokenType::LessThan));
+                    tokens.push(Token::new("<", TokenType::LessThan));
                 }
             }
             '>' => {
                 chars.next();
                 if chars.peek() == Some(&'=') {
                     chars.next();
-                    tokens.push(Token::new(">=".to_string(), TokenType::GreaterThanOrEqual));
+                    tokens.push(Token::new(">=", TokenType::GreaterThanOrEqual));
+                } else if chars.peek() == Some(&'>') {
+                    chars.next();
+                    tokens.push(Token::new(">>", TokenType::BinaryOperator));
                 } else {
-                    tokens.push(Token::new(">".to_string(), TokenType::GreaterThan));
+                    tokens.push(Token::new(">", TokenType::GreaterThan));
                 }
             }
             '&' => {
                 chars.next();
                 if chars.peek() == Some(&'&') {
                     chars.next();
-                    tokens.push(Token::new("&&".to_string(), TokenType::LogicalAnd));
+                    tokens.push(Token::new("&&", TokenType::LogicalAnd));
                 } else {
-                    panic!("Odottamaton '&' lähteessä");
+                    tokens.push(Token::new("&", TokenType::BinaryOperator));
                 }
             }
             '|' => {
                 chars.next();
                 if chars.peek() == Some(&'|') {
                     chars.next();
-                    tokens.push(Token::new("||".to_string(), TokenType::LogicalOr));
+                    tokens.push(Token::new("||", TokenType::LogicalOr));
                 } else {
-                    panic!("Odottamaton '|' lähteessä");
+                    tokens.push(Token::new("|", TokenType::BinaryOperator));
                 }
             }
             ':' => {
                 chars.next();
-                tokens.push(Token::new(":".to_string(), TokenType::Colon));
+                tokens.push(Token::new(":", TokenType::Colon));
             }
             ',' => {
                 chars.next();
-                tokens.push(Token::new(",".to_string(), TokenType::Comma));
+                tokens.push(Token::new(",", TokenType::Comma));
             }
             '.' => {
                 chars.next();
-                tokens.push(Token::new(".".to_string(), TokenType::Dot));
-            }
-            '"' | '\'' => {
-                let quote_type = chars.next().unwrap();
-                let mut string_literal = String::new();
-                while let Some(&c) = chars.peek() {
-                    if c != quote_type {
-                        string_literal.push(chars.next().unwrap());
-                    } else {
+                if chars.peek() == Some(&'.') {
+                    chars.next();
+                    if chars.peek() == Some(&'.') {
                         chars.next();
-                        if !string_literal.is_empty() {
-                            tokens.push(Token::new(string_literal.clone(), TokenType::StringLiteral));
-                            string_literal.clear();
-                        }
-                        break;
-                    }
-                }
-                if !string_literal.is_empty() {
-                    tokens.push(Token::new(string_literal, TokenType::StringLiteral));
-                }
-            }
-            _ if is_float_or_int(c) => {
-                let mut num = String::new();
-                let mut has_dot = false;
-                while let Some(&c) = chars.peek() {
-                    if c == '.' {
-                        if has_dot {
-                            panic!("Odottamaton '.' numerossa");
-                        } else {
-                            has_dot = true;
-                            num.push(chars.next().unwrap());
-                        }
-                    } else if c.is_digit(10) {
-                        num.push(chars.next().unwrap());
+                        tokens.push(Token::new("...", TokenType::Ellipsis));
+                    } else if chars.peek() == Some(&'=') {
+                        chars.next();
+                        tokens.push(Token::new("..=", TokenType::DotDotEqual));
                     } else {
-                        break;
+                        tokens.push(Token::new("..", TokenType::DotDot));
                     }
-                }
-                if has_dot {
-                    tokens.push(Token::new(num, TokenType::Float));
                 } else {
-                    tokens.push(Token::new(num, TokenType::Integer));
+                    tokens.push(Token::new(".", TokenType::Dot));
                 }
             }
+            '"' | '\'' => {
+                let string_literal = read_string(&mut chars);
+                tokens.push(Token::new(string_literal, TokenType::StringLiteral));
+            }
+            '`' => {
+                read_template(&mut chars, &mut tokens, interner);
+            }
+            _ if is_float_or_int(c) => {
+                let (num, token_type) = read_number(&mut chars);
+                tokens.push(Token::new(num, token_type));
+            }
             _ if is_alpha(c) => {
-                let mut ident = String::new();
-                while let Some(&c) = chars.peek() {
-                    if is_alpha(c) || c.is_digit(10) {
-                        ident.push(chars.next().unwrap());
-                    } else {
-                        break;
-                    }
-                }
-                match keywords.iter().find(|&&(kw, _)| kw == ident.as_str()) {
-                    Some((_, token_type)) => tokens.push(Token::new(ident.clone(), token_type.clone())),
-                    None => tokens.push(Token::new(ident, TokenType::Identifier)),
+                let start = chars.offset;
+                while chars.peek().is_some_and(|&c| is_alpha(c) || c.is_ascii_digit()) {
+                    chars.next();
                 }
+                let ident = chars.slice_from(start);
+                let symbol = interner.intern(ident);
+                let token_type = Interner::keyword(symbol).unwrap_or(TokenType::Identifier);
+                let mut token = Token::new(ident, token_type);
+                token.symbol = Some(symbol);
+                tokens.push(token);
             }
             _ if is_skippable(c) => {
                 chars.next();
             }
             _ => {
-                panic!("Lähteestä löytyi tuntematon merkki: {}", c);
+                lexer_error(line, column, &format!("Lähteestä löytyi tuntematon merkki: {}", c));
+            }
+        }
+
+        // Tokens produced by this iteration start where it started, unless they already know better
+        for token in &mut tokens[first_new..] {
+            if token.line == 0 {
+                token.line = line;
+                token.column = column;
             }
         }
     }
 
-    tokens.push(Token::new("EndOfFile".to_string(), TokenType::EOF));
+    let mut eof = Token::new("EndOfFile", TokenType::EOF);
+    eof.line = chars.line;
+    eof.column = chars.column;
+    tokens.push(eof);
     tokens
 }
\ No newline at end of file
@@ -1,3 +1,8 @@
 pub mod parser;
 pub mod ast;
 pub mod lexer;
+pub mod resolver;
+pub mod optimizer;
+pub mod cache;
+pub mod symbols;
+pub mod syntax;
@@ -0,0 +1,700 @@
+use std::collections::{ HashMap, HashSet };
+use std::panic::{ self, AssertUnwindSafe };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use crate::embed::quietly;
+use crate::frontend::ast::*;
+use crate::frontend::resolver::module_stem;
+use crate::runtime::environment::Environment;
+use crate::runtime::eval::expressions::{ apply_binary_operator, apply_unary_operator };
+use crate::runtime::values::*;
+
+// Runs after the resolver when asked for with -O. A rewrite must not change what the program
+// prints or the errors it fails with, so anything that might behave differently is left alone.
+//
+//   1: folds constant expressions, drops dead jos-branches and inlines vakio scalars
+//   2: also hoists loop-invariant expressions out of kun- and toista-loops
+
+// Names for hoisted values; '@' cannot appear in source, and the REPL keeps globals between lines
+static HOISTED: AtomicUsize = AtomicUsize::new(0);
+
+pub fn optimize_program(program: &mut Program, level: u8, env: &Environment) {
+    if level == 0 {
+        return;
+    }
+
+    let mut names = Names::default();
+    program.body.iter().for_each(|stmt| names.stmt(stmt));
+    // Declared in every method call
+    names.declare("tämä");
+    names.declare("yli");
+
+    let mut optimizer = Optimizer { level, names, env, constants: HashMap::new() };
+
+    // Folding runs the operators, which report errors by panicking; those are left for runtime
+    quietly(|| optimizer.statements(&mut program.body, true));
+}
+
+// Every name the code declares and every name it assigns to, anywhere
+#[derive(Default)]
+struct Names {
+    declared: HashMap<String, usize>,
+    assigned: HashSet<String>,
+}
+
+impl Names {
+    fn declare(&mut self, name: &str) {
+        *self.declared.entry(name.to_string()).or_default() += 1;
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Program(program) => program.body.iter().for_each(|stmt| self.stmt(stmt)),
+            Stmt::VarDeclaration(declaration) => {
+                if let Some(value) = &declaration.value {
+                    self.expr(value);
+                }
+                self.pattern(&declaration.target);
+            }
+            Stmt::FunctionDeclaration(declaration) => {
+                self.declare(&declaration.name);
+                self.function(declaration);
+            }
+            Stmt::Expr(statement) => self.expr(&statement.expr),
+            Stmt::WhileLoop(while_loop) => {
+                self.expr(&while_loop.condition);
+                while_loop.body.statements.iter().for_each(|stmt| self.stmt(stmt));
+            }
+            Stmt::ForLoop(for_loop) => {
+                self.stmt(&for_loop.initializer);
+                self.expr(&for_loop.condition);
+                self.expr(&for_loop.increment);
+                for_loop.body.statements.iter().for_each(|stmt| self.stmt(stmt));
+            }
+            Stmt::ForEachLoop(for_each) => {
+                self.expr(&for_each.iterable);
+                self.pattern(&for_each.target);
+                for_each.body.statements.iter().for_each(|stmt| self.stmt(stmt));
+            }
+            Stmt::ClassDeclaration(declaration) => {
+                self.declare(&declaration.name);
+                for field in declaration.static_fields.iter().chain(&declaration.fields) {
+                    if let Some(value) = &field.value {
+                        self.expr(value);
+                    }
+                }
+                let methods = declaration.constructor.iter().chain(&declaration.methods).chain(&declaration.static_methods);
+                methods.for_each(|method| self.function(method));
+            }
+            Stmt::EnumDeclaration(declaration) => self.declare(&declaration.name),
+            Stmt::Import(import) => match (&import.names, &import.alias) {
+                (Some(names), _) => names.properties.iter().for_each(|property| self.pattern(&property.pattern)),
+                (None, Some(alias)) => self.declare(alias),
+                (None, None) => self.declare(&module_stem(&import.path)),
+            },
+            Stmt::Export(export) => {
+                if let Some(declaration) = &export.declaration {
+                    self.stmt(declaration);
+                }
+            }
+        }
+    }
+
+    fn function(&mut self, declaration: &FunctionDeclaration) {
+        declaration.parameters.iter().for_each(|parameter| self.pattern(parameter));
+        if let Some(rest) = &declaration.rest {
+            self.declare(rest);
+        }
+        declaration.body.iter().for_each(|stmt| self.stmt(stmt));
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        pattern.bound_names().iter().for_each(|name| self.declare(name));
+        self.pattern_exprs(pattern);
+    }
+
+    fn pattern_exprs(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Literal(expr) => self.expr(expr),
+            Pattern::Range(range) => {
+                self.expr(&range.start);
+                self.expr(&range.end);
+            }
+            Pattern::Array(array) => array.elements.iter().for_each(|element| self.pattern_exprs(element)),
+            Pattern::Object(object) => object.properties.iter().for_each(|property| self.pattern_exprs(&property.pattern)),
+            Pattern::Alternatives(alternatives) => alternatives.iter().for_each(|alternative| self.pattern_exprs(alternative)),
+            Pattern::Default(default) => {
+                self.pattern_exprs(&default.pattern);
+                self.expr(&default.default);
+            }
+            Pattern::Variant(variant) => variant.fields.iter().flatten().for_each(|field| self.pattern_exprs(field)),
+            Pattern::Wildcard | Pattern::Binding(_) => (),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::AssignmentExpr(assignment) => {
+                self.assignee(&assignment.assignee);
+                self.expr(&assignment.value);
+            }
+            Expr::CompoundAssignmentExpr(assignment) => {
+                self.assignee(&assignment.assignee);
+                self.expr(&assignment.value);
+            }
+            Expr::UpdateExpr(update) => self.assignee(&update.assignee),
+            Expr::MemberExpr(member) => {
+                self.expr(&member.object);
+                self.expr(&member.property);
+            }
+            Expr::CallExpr(call) => {
+                self.expr(&call.caller);
+                for argument in &call.args {
+                    match argument {
+                        Argument::Positional(expr) | Argument::Named(_, expr) | Argument::Spread(expr) => self.expr(expr),
+                    }
+                }
+            }
+            Expr::IfElseExpr(if_else) => {
+                self.expr(&if_else.condition);
+                if_else.if_branch.statements.iter().for_each(|stmt| self.stmt(stmt));
+                if let Some(else_branch) = &if_else.else_branch {
+                    else_branch.statements.iter().for_each(|stmt| self.stmt(stmt));
+                }
+            }
+            Expr::ConditionalExpr(conditional) => {
+                self.expr(&conditional.condition);
+                self.expr(&conditional.consequent);
+                self.expr(&conditional.alternate);
+            }
+            Expr::MatchExpr(match_expr) => {
+                self.expr(&match_expr.subject);
+                for arm in &match_expr.arms {
+                    if let Some(pattern) = &arm.pattern {
+                        self.pattern(pattern);
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.expr(guard);
+                    }
+                    arm.body.statements.iter().for_each(|stmt| self.stmt(stmt));
+                }
+            }
+            Expr::Property(property) => {
+                if let Some(value) = &property.value {
+                    self.expr(value);
+                }
+            }
+            Expr::ObjectLiteral(object) => object.properties.iter().for_each(|property| {
+                if let Some(value) = &property.value {
+                    self.expr(value);
+                }
+            }),
+            Expr::ArrayLiteral(array) => array.elements.iter().for_each(|element| self.expr(element)),
+            Expr::TemplateLiteral(template) => template.parts.iter().for_each(|part| self.expr(part)),
+            Expr::BinaryExpr(binary) => {
+                self.expr(&binary.left);
+                self.expr(&binary.right);
+            }
+            Expr::LogicalExpr(logical) => {
+                self.expr(&logical.left);
+                self.expr(&logical.right);
+            }
+            Expr::UnaryExpr(unary) => self.expr(&unary.operand),
+            Expr::Identifier(_) | Expr::NumericLiteral(_) | Expr::IntegerLiteral(_) | Expr::StringLiteral(_) | Expr::FloatLiteral(_) => (),
+        }
+    }
+
+    // Every name in an assignment target counts, including the roots of member chains
+    fn assignee(&mut self, assignee: &Expr) {
+        match assignee {
+            Expr::Identifier(identifier) => {
+                self.assigned.insert(identifier.symbol.clone());
+            }
+            Expr::MemberExpr(member) => {
+                self.assignee(&member.object);
+                self.expr(&member.property);
+            }
+            other => self.expr(other),
+        }
+    }
+}
+
+struct Optimizer<'a> {
+    level: u8,
+    names: Names,
+    env: &'a Environment,
+    // Top-level vakio scalars, from their declaration on
+    constants: HashMap<String, RuntimeVal>,
+}
+
+impl Optimizer<'_> {
+    // The value of a name that cannot change: a vakio of this program, or one declared before it ran
+    fn constant(&self, name: &str) -> Option<RuntimeVal> {
+        if let Some(value) = self.constants.get(name) {
+            return Some(value.clone());
+        }
+        if self.names.declared.contains_key(name) || self.env.constness(name) != Some(true) {
+            return None;
+        }
+        Some(self.env.clone().lookup_var(name)).filter(is_scalar)
+    }
+
+    fn value(&self, expr: &Expr) -> Option<RuntimeVal> {
+        match expr {
+            Expr::NumericLiteral(literal) => Some(MK_NUMBER(literal.value)),
+            Expr::FloatLiteral(literal) => Some(MK_NUMBER(literal.value)),
+            Expr::IntegerLiteral(literal) => Some(MK_INTEGER(literal.value)),
+            Expr::StringLiteral(literal) => Some(MK_STRING(literal.value.clone())),
+            Expr::Identifier(identifier) => self.constant(&identifier.symbol),
+            _ => None,
+        }
+    }
+
+    // Totuusarvot and tyhjä are written with the built-in names, as long as nothing hides them
+    fn literal(&self, value: RuntimeVal) -> Option<Expr> {
+        let name = match value {
+            RuntimeVal::Integer(value) => return Some(Expr::IntegerLiteral(IntegerLiteral { value })),
+            RuntimeVal::Number(value) => return Some(Expr::NumericLiteral(NumericLiteral { value })),
+            RuntimeVal::String(value) => return Some(Expr::StringLiteral(StringLiteral { value })),
+            RuntimeVal::Bool(true) => "tosi",
+            RuntimeVal::Bool(false) => "epätosi",
+            RuntimeVal::Null => "tyhjä",
+            _ => return None,
+        };
+        match self.constant(name) {
+            Some(builtin) if builtin.equals(&value) && is_scalar(&builtin) =>
+                Some(Expr::Identifier(Identifier { symbol: name.to_string() })),
+            _ => None,
+        }
+    }
+
+    fn statements(&mut self, statements: &mut Vec<Stmt>, top_level: bool) {
+        let input = std::mem::take(statements);
+        let last = input.len().saturating_sub(1);
+        for (index, mut statement) in input.into_iter().enumerate() {
+            // A jos with a constant condition becomes the branch it takes; the branches share the
+            // enclosing scope. The value of the last statement is the value of the block, so an
+            // empty branch there keeps its jos.
+            if let Some(mut branch) = self.live_branch(&mut statement) {
+                self.statements(&mut branch, top_level);
+                if !branch.is_empty() || index != last {
+                    statements.extend(branch);
+                    continue;
+                }
+            }
+
+            self.stmt(&mut statement, top_level);
+            if self.level >= 2 {
+                statements.extend(self.hoist(&mut statement));
+            }
+            statements.push(statement);
+        }
+    }
+
+    fn live_branch(&mut self, statement: &mut Stmt) -> Option<Vec<Stmt>> {
+        let Stmt::Expr(ExprStmt { expr: Expr::IfElseExpr(if_else), .. }) = statement else {
+            return None;
+        };
+        self.expr(&mut if_else.condition);
+        match self.value(&if_else.condition)? {
+            RuntimeVal::Bool(true) => Some(std::mem::take(&mut if_else.if_branch.statements)),
+            RuntimeVal::Bool(false) => Some(match &mut if_else.else_branch {
+                Some(else_branch) => std::mem::take(&mut else_branch.statements),
+                None => Vec::new(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt, top_level: bool) {
+        match stmt {
+            Stmt::Program(program) => self.statements(&mut program.body, top_level),
+            Stmt::VarDeclaration(declaration) => {
+                if let Some(value) = &mut declaration.value {
+                    self.expr(value);
+                }
+                // Declared once, so every later use in the program means this vakio
+                if let (true, true, Pattern::Binding(name), Some(value)) = (top_level, declaration.constant, &declaration.target, &declaration.value) {
+                    if self.names.declared.get(name) == Some(&1) {
+                        if let Some(value) = self.value(value).filter(is_scalar) {
+                            self.constants.insert(name.clone(), value);
+                        }
+                    }
+                }
+            }
+            Stmt::FunctionDeclaration(declaration) => self.function(declaration),
+            Stmt::Expr(statement) => self.expr(&mut statement.expr),
+            Stmt::WhileLoop(while_loop) => {
+                self.expr(&mut while_loop.condition);
+                self.statements(&mut while_loop.body.statements, false);
+            }
+            Stmt::ForLoop(for_loop) => {
+                self.stmt(&mut for_loop.initializer, false);
+                self.expr(&mut for_loop.condition);
+                self.expr(&mut for_loop.increment);
+                self.statements(&mut for_loop.body.statements, false);
+            }
+            Stmt::ForEachLoop(for_each) => {
+                self.expr(&mut for_each.iterable);
+                self.statements(&mut for_each.body.statements, false);
+            }
+            Stmt::ClassDeclaration(declaration) => {
+                for field in declaration.static_fields.iter_mut().chain(&mut declaration.fields) {
+                    if let Some(value) = &mut field.value {
+                        self.expr(value);
+                    }
+                }
+                let methods = declaration.constructor.iter_mut().chain(&mut declaration.methods).chain(&mut declaration.static_methods);
+                methods.for_each(|method| self.function(method));
+            }
+            Stmt::Export(export) => {
+                if let Some(declaration) = &mut export.declaration {
+                    self.stmt(declaration, top_level);
+                }
+            }
+            Stmt::EnumDeclaration(_) | Stmt::Import(_) => (),
+        }
+    }
+
+    fn function(&mut self, declaration: &mut FunctionDeclaration) {
+        self.statements(&mut declaration.body, false);
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Identifier(identifier) => {
+                if let Some(literal) = self.constant(&identifier.symbol).and_then(|value| self.literal(value)) {
+                    *expr = literal;
+                }
+            }
+            Expr::AssignmentExpr(assignment) => {
+                self.assignee(&mut assignment.assignee);
+                self.expr(&mut assignment.value);
+            }
+            Expr::CompoundAssignmentExpr(assignment) => {
+                self.assignee(&mut assignment.assignee);
+                self.expr(&mut assignment.value);
+            }
+            Expr::UpdateExpr(update) => self.assignee(&mut update.assignee),
+            // Members are looked up on a named object as written
+            Expr::MemberExpr(member) => {
+                if !matches!(*member.object, Expr::Identifier(_)) {
+                    self.expr(&mut member.object);
+                }
+                if member.computed {
+                    self.expr(&mut member.property);
+                }
+            }
+            Expr::CallExpr(call) => {
+                if !matches!(*call.caller, Expr::Identifier(_)) {
+                    self.expr(&mut call.caller);
+                }
+                for argument in &mut call.args {
+                    match argument {
+                        Argument::Positional(expr) | Argument::Named(_, expr) | Argument::Spread(expr) => self.expr(expr),
+                    }
+                }
+            }
+            Expr::IfElseExpr(if_else) => {
+                self.expr(&mut if_else.condition);
+                self.statements(&mut if_else.if_branch.statements, false);
+                if let Some(else_branch) = &mut if_else.else_branch {
+                    self.statements(&mut else_branch.statements, false);
+                }
+                let branch = match self.value(&if_else.condition) {
+                    Some(RuntimeVal::Bool(true)) => Some(&if_else.if_branch.statements[..]),
+                    Some(RuntimeVal::Bool(false)) => Some(if_else.else_branch.as_ref().map_or(&[][..], |branch| &branch.statements[..])),
+                    _ => None,
+                };
+                let replacement = match branch {
+                    Some([Stmt::Expr(statement)]) => Some(statement.expr.clone()),
+                    Some([]) => self.literal(MK_NULL()),
+                    _ => None,
+                };
+                if let Some(replacement) = replacement {
+                    *expr = replacement;
+                }
+            }
+            Expr::ConditionalExpr(conditional) => {
+                self.expr(&mut conditional.condition);
+                self.expr(&mut conditional.consequent);
+                self.expr(&mut conditional.alternate);
+                if let Some(condition) = self.value(&conditional.condition) {
+                    let taken = if condition.is_truthy() { &conditional.consequent } else { &conditional.alternate };
+                    *expr = (**taken).clone();
+                }
+            }
+            Expr::MatchExpr(match_expr) => {
+                self.expr(&mut match_expr.subject);
+                for arm in &mut match_expr.arms {
+                    if let Some(guard) = &mut arm.guard {
+                        self.expr(guard);
+                    }
+                    self.statements(&mut arm.body.statements, false);
+                }
+            }
+            Expr::Property(property) => {
+                if let Some(value) = &mut property.value {
+                    self.expr(value);
+                }
+            }
+            Expr::ObjectLiteral(object) => {
+                for property in &mut object.properties {
+                    if let Some(value) = &mut property.value {
+                        self.expr(value);
+                    }
+                }
+            }
+            Expr::ArrayLiteral(array) => array.elements.iter_mut().for_each(|element| self.expr(element)),
+            Expr::TemplateLiteral(template) => {
+                template.parts.iter_mut().for_each(|part| self.expr(part));
+                let values: Option<Vec<RuntimeVal>> = template.parts.iter().map(|part| self.value(part)).collect();
+                if let Some(values) = values {
+                    let value = values.iter().map(|value| value.to_string()).collect();
+                    *expr = Expr::StringLiteral(StringLiteral { value });
+                }
+            }
+            Expr::BinaryExpr(binary) => {
+                self.expr(&mut binary.left);
+                self.expr(&mut binary.right);
+                if let (Some(left), Some(right)) = (self.value(&binary.left), self.value(&binary.right)) {
+                    let operator = &binary.operator;
+                    if let Some(literal) = fold(|| apply_binary_operator(left, right, operator)).and_then(|value| self.literal(value)) {
+                        *expr = literal;
+                    }
+                }
+            }
+            Expr::LogicalExpr(logical) => {
+                self.expr(&mut logical.left);
+                self.expr(&mut logical.right);
+                if let Some(replacement) = self.fold_logical(logical) {
+                    *expr = replacement;
+                }
+            }
+            Expr::UnaryExpr(unary) => {
+                self.expr(&mut unary.operand);
+                if let Some(operand) = self.value(&unary.operand) {
+                    let operator = &unary.operator;
+                    if let Some(literal) = fold(|| apply_unary_operator(operator, operand)).and_then(|value| self.literal(value)) {
+                        *expr = literal;
+                    }
+                }
+            }
+            Expr::NumericLiteral(_) | Expr::IntegerLiteral(_) | Expr::StringLiteral(_) | Expr::FloatLiteral(_) => (),
+        }
+    }
+
+    // A constant left side decides whether the right side runs at all
+    fn fold_logical(&self, logical: &LogicalExpr) -> Option<Expr> {
+        let left = self.value(&logical.left)?;
+        match logical.operator {
+            BinaryOperator::And if !left.is_truthy() => self.literal(MK_BOOL(false)),
+            BinaryOperator::Or if left.is_truthy() => self.literal(MK_BOOL(true)),
+            BinaryOperator::And | BinaryOperator::Or => {
+                let right = self.value(&logical.right)?;
+                self.literal(MK_BOOL(right.is_truthy()))
+            }
+            BinaryOperator::NullishCoalescing => match left {
+                RuntimeVal::Null => Some((*logical.right).clone()),
+                left => self.literal(left),
+            },
+            _ => None,
+        }
+    }
+
+    // The root name stays, so the write goes to the variable as before
+    fn assignee(&mut self, assignee: &mut Expr) {
+        if let Expr::MemberExpr(member) = assignee {
+            self.assignee(&mut member.object);
+            if member.computed {
+                self.expr(&mut member.property);
+            }
+        }
+    }
+
+    // Replaces invariant expressions in a loop with `@n ?? (@n = lauseke)` and returns the
+    // declarations of the @n, which go right before the loop. The first use computes the value
+    // where the loop used to, so errors happen at the same point; later uses read it.
+    fn hoist(&self, statement: &mut Stmt) -> Vec<Stmt> {
+        let mut inside = Names::default();
+        inside.stmt(statement);
+        let mut hoister = Hoister { names: &self.names, inside, env: self.env, hoisted: Vec::new() };
+
+        match statement {
+            Stmt::WhileLoop(while_loop) => {
+                hoister.expr(&mut while_loop.condition);
+                hoister.statements(&mut while_loop.body.statements);
+            }
+            Stmt::ForLoop(for_loop) => {
+                hoister.expr(&mut for_loop.condition);
+                hoister.expr(&mut for_loop.increment);
+                hoister.statements(&mut for_loop.body.statements);
+            }
+            Stmt::ForEachLoop(for_each) => hoister.statements(&mut for_each.body.statements),
+            _ => (),
+        }
+
+        let line = statement.line();
+        hoister.hoisted
+            .into_iter()
+            .map(|name| Stmt::VarDeclaration(VarDeclaration { constant: false, target: Pattern::Binding(name), value: None, line }))
+            .collect()
+    }
+}
+
+struct Hoister<'a> {
+    names: &'a Names,
+    // Declared by the loop itself, so possibly different on each iteration
+    inside: Names,
+    env: &'a Environment,
+    hoisted: Vec<String>,
+}
+
+impl Hoister<'_> {
+    // Nothing the loop runs can change the value: no calls or members, and every name is
+    // one that no code assigns to
+    fn invariant(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Identifier(identifier) => {
+                let name = &identifier.symbol;
+                !self.inside.declared.contains_key(name)
+                    && !self.names.assigned.contains(name)
+                    && self.env.constness(name) != Some(false)
+            }
+            Expr::BinaryExpr(binary) => self.invariant(&binary.left) && self.invariant(&binary.right),
+            Expr::LogicalExpr(logical) => self.invariant(&logical.left) && self.invariant(&logical.right),
+            Expr::UnaryExpr(unary) => self.invariant(&unary.operand),
+            Expr::TemplateLiteral(template) => template.parts.iter().all(|part| self.invariant(part)),
+            Expr::ConditionalExpr(conditional) =>
+                self.invariant(&conditional.condition) && self.invariant(&conditional.consequent) && self.invariant(&conditional.alternate),
+            Expr::NumericLiteral(_) | Expr::IntegerLiteral(_) | Expr::StringLiteral(_) | Expr::FloatLiteral(_) => true,
+            _ => false,
+        }
+    }
+
+    fn statements(&mut self, statements: &mut [Stmt]) {
+        statements.iter_mut().for_each(|stmt| self.stmt(stmt));
+    }
+
+    // Functions and classes declared in the loop run later, if at all, so they are left alone
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Program(program) => self.statements(&mut program.body),
+            Stmt::VarDeclaration(declaration) => {
+                if let Some(value) = &mut declaration.value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Expr(statement) => self.expr(&mut statement.expr),
+            Stmt::WhileLoop(while_loop) => {
+                self.expr(&mut while_loop.condition);
+                self.statements(&mut while_loop.body.statements);
+            }
+            Stmt::ForLoop(for_loop) => {
+                self.stmt(&mut for_loop.initializer);
+                self.expr(&mut for_loop.condition);
+                self.expr(&mut for_loop.increment);
+                self.statements(&mut for_loop.body.statements);
+            }
+            Stmt::ForEachLoop(for_each) => {
+                self.expr(&mut for_each.iterable);
+                self.statements(&mut for_each.body.statements);
+            }
+            Stmt::Export(export) => {
+                if let Some(declaration) = &mut export.declaration {
+                    self.stmt(declaration);
+                }
+            }
+            Stmt::FunctionDeclaration(_) | Stmt::ClassDeclaration(_) | Stmt::EnumDeclaration(_) | Stmt::Import(_) => (),
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        let operation = matches!(expr, Expr::BinaryExpr(_) | Expr::LogicalExpr(_) | Expr::UnaryExpr(_) | Expr::ConditionalExpr(_));
+        if operation && self.invariant(expr) {
+            let name = format!("@{}", HOISTED.fetch_add(1, Ordering::Relaxed));
+            let value = std::mem::replace(expr, Expr::Identifier(Identifier { symbol: name.clone() }));
+            *expr = Expr::LogicalExpr(LogicalExpr {
+                left: Box::new(Expr::Identifier(Identifier { symbol: name.clone() })),
+                right: Box::new(Expr::AssignmentExpr(AssignmentExpr {
+                    assignee: Box::new(Expr::Identifier(Identifier { symbol: name.clone() })),
+                    value: Box::new(value),
+                })),
+                operator: BinaryOperator::NullishCoalescing,
+            });
+            self.hoisted.push(name);
+            return;
+        }
+
+        match expr {
+            Expr::AssignmentExpr(assignment) => self.expr(&mut assignment.value),
+            Expr::CompoundAssignmentExpr(assignment) => self.expr(&mut assignment.value),
+            Expr::MemberExpr(member) => {
+                if member.computed {
+                    self.expr(&mut member.property);
+                }
+            }
+            Expr::CallExpr(call) => {
+                for argument in &mut call.args {
+                    match argument {
+                        Argument::Positional(expr) | Argument::Named(_, expr) | Argument::Spread(expr) => self.expr(expr),
+                    }
+                }
+            }
+            Expr::IfElseExpr(if_else) => {
+                self.expr(&mut if_else.condition);
+                self.statements(&mut if_else.if_branch.statements);
+                if let Some(else_branch) = &mut if_else.else_branch {
+                    self.statements(&mut else_branch.statements);
+                }
+            }
+            Expr::ConditionalExpr(conditional) => {
+                self.expr(&mut conditional.condition);
+                self.expr(&mut conditional.consequent);
+                self.expr(&mut conditional.alternate);
+            }
+            Expr::MatchExpr(match_expr) => {
+                self.expr(&mut match_expr.subject);
+                for arm in &mut match_expr.arms {
+                    if let Some(guard) = &mut arm.guard {
+                        self.expr(guard);
+                    }
+                    self.statements(&mut arm.body.statements);
+                }
+            }
+            Expr::Property(property) => {
+                if let Some(value) = &mut property.value {
+                    self.expr(value);
+                }
+            }
+            Expr::ObjectLiteral(object) => {
+                for property in &mut object.properties {
+                    if let Some(value) = &mut property.value {
+                        self.expr(value);
+                    }
+                }
+            }
+            Expr::ArrayLiteral(array) => array.elements.iter_mut().for_each(|element| self.expr(element)),
+            Expr::TemplateLiteral(template) => template.parts.iter_mut().for_each(|part| self.expr(part)),
+            Expr::BinaryExpr(binary) => {
+                self.expr(&mut binary.left);
+                self.expr(&mut binary.right);
+            }
+            Expr::LogicalExpr(logical) => {
+                self.expr(&mut logical.left);
+                self.expr(&mut logical.right);
+            }
+            Expr::UnaryExpr(unary) => self.expr(&mut unary.operand),
+            Expr::UpdateExpr(_) | Expr::Identifier(_) | Expr::NumericLiteral(_) | Expr::IntegerLiteral(_)
+                | Expr::StringLiteral(_) | Expr::FloatLiteral(_) => (),
+        }
+    }
+}
+
+fn is_scalar(value: &RuntimeVal) -> bool {
+    matches!(value, RuntimeVal::Null | RuntimeVal::Bool(_) | RuntimeVal::Integer(_) | RuntimeVal::Number(_) | RuntimeVal::String(_))
+}
+
+// The value of a constant operation, or None if it fails and should fail when the program runs
+fn fold(operation: impl FnOnce() -> RuntimeVal) -> Option<RuntimeVal> {
+    panic::catch_unwind(AssertUnwindSafe(operation)).ok()
+}
@@ -2,26 +2,59 @@ use std::panic;
 use crate::frontend::lexer::*;
 use crate::frontend::ast::*;
 
-pub struct Parser {
-    tokens: Vec<Token>,
+// Walks the tokens with a cursor; eaten tokens are left behind, since the parser never looks back
+pub struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    position: usize,
+    last_line: usize,
+    pub warnings: Vec<String>,
+    // Every luettelo in the file by name, and the valitse expressions still waiting for them,
+    // since a luettelo may be declared after the valitse that matches it
+    enums: Vec<(String, Vec<String>)>,
+    unfinished_matches: Vec<(usize, Vec<(String, String)>)>,
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        //println!("{:?}", tokens);
-        Self { tokens }
+impl<'a> Parser<'a> {
+    pub fn new(tokens: Vec<Token<'a>>) -> Self {
+        Self { tokens, position: 0, last_line: 0, warnings: Vec::new(), enums: Vec::new(), unfinished_matches: Vec::new() }
     }
 
     fn not_eof(&self) -> bool {
-        !matches!(self.tokens.first(), Some(Token { token_type: TokenType::EOF, .. }))
+        !matches!(self.tokens.get(self.position), Some(Token { token_type: TokenType::EOF, .. }))
     }
 
-    fn at(&self) -> &Token {
-        &self.tokens[0]
+    fn at(&self) -> &Token<'a> {
+        match self.tokens.get(self.position) {
+            Some(token) => token,
+            None => panic!("Rivi {}: lähdekoodi päättyi kesken", self.last_line),
+        }
+    }
+
+    // The token `offset` places after the current one
+    fn peek(&self, offset: usize) -> Option<&Token<'a>> {
+        self.tokens.get(self.position + offset)
+    }
+
+    fn eat(&mut self) -> Token<'a> {
+        self.at();
+        let token = &mut self.tokens[self.position];
+        self.position += 1;
+        self.last_line = token.line;
+        Token { value: std::mem::take(&mut token.value), ..*token }
+    }
+
+    // The line and column of the last token read, for locating an error that stopped parsing
+    pub fn last_position(&self) -> (usize, usize) {
+        match self.position.checked_sub(1).and_then(|last| self.tokens.get(last)).or(self.tokens.last()) {
+            Some(token) => (token.line, token.column),
+            None => (1, 1),
+        }
     }
 
-    fn eat(&mut self) -> Token {
-        self.tokens.remove(0)
+    // Checks for an operator by its text, so that string literals with the same text don't match
+    fn at_operator(&self, operators: &[&str]) -> bool {
+        let token = self.at();
+        token.token_type != TokenType::StringLiteral && operators.contains(&token.value.as_ref())
     }
 
     fn parse_block(&mut self) -> Block {
@@ -40,7 +73,7 @@ impl Parser {
         Block { statements }
     }
 
-    fn expect(&mut self, token_type: TokenType) -> Option<Token> {
+    fn expect(&mut self, token_type: TokenType) -> Option<Token<'a>> {
         let token = self.eat();
         if token.token_type == token_type {
             Some(token)
@@ -56,28 +89,38 @@ impl Parser {
             body.push(self.parse_stmt());
         }
 
+        self.drop_exhaustive_match_warnings();
         Program { body }
     }
+
+    // A valitse without muuten is fine when its arms cover every variant of a luettelo in this file
+    fn drop_exhaustive_match_warnings(&mut self) {
+        let unfinished = std::mem::take(&mut self.unfinished_matches);
+        for (warning, covered) in unfinished.into_iter().rev() {
+            let exhaustive = self.enums.iter().any(|(name, variants)| {
+                variants.iter().all(|variant| covered.iter().any(|(e, v)| e == name && v == variant))
+            });
+            if exhaustive {
+                self.warnings.remove(warning);
+            }
+        }
+    }
     
     fn parse_stmt(&mut self) -> Stmt {
         let stmt = match self.at().token_type {
             TokenType::If => {
-                self.eat();
-                let expr = self.parse_if_else_expr();
-                Stmt::Expr(expr)
-            }
-            TokenType::Else => {
-                self.eat();
+                let line = self.at().line;
                 let expr = self.parse_if_else_expr();
-                Stmt::Expr(expr)
+                Stmt::Expr(ExprStmt { expr, line })
             }
             TokenType::While => {
-                self.eat();
+                let line = self.eat().line;
                 let condition = self.parse_expr();
                 let body = self.parse_block();
                 Stmt::WhileLoop(WhileLoop {
                     condition: Box::new(condition),
                     body,
+                    line,
                 })
             }
             TokenType::For => {
@@ -95,10 +138,17 @@ impl Parser {
                 stmt
             }
             TokenType::Fn => {
-                let stmt = self.parse_fn_declaration();
-                stmt
+                
+                self.parse_fn_declaration()
+            }
+            TokenType::Class => self.parse_class_declaration(),
+            TokenType::Enum => self.parse_enum_declaration(),
+            TokenType::Import => self.parse_import(),
+            TokenType::Export => self.parse_export(),
+            _ => {
+                let line = self.at().line;
+                Stmt::Expr(ExprStmt { expr: self.parse_expr(), line })
             }
-            _ => Stmt::Expr(self.parse_expr()),
         };
     
         stmt
@@ -114,19 +164,18 @@ impl Parser {
     fn parse_fn_declaration(&mut self) -> Stmt {
         self.eat();
         let name = match self.expect(TokenType::Identifier) {
-            Some(token) => token.value,
+            Some(token) => token.value.into_owned(),
             None => panic!("Odotettu funktion nimi funktio-avainsanan jälkeen"),
         };
 
-        let args = self.parse_args();
-        let mut params: Vec<String> = Vec::new();
-        for arg in args {
-            match arg {
-                Expr::Identifier(identifier) => params.push(identifier.symbol),
-                _ =>
-                    panic!("Funktiomäärityksen sisällä olevien parametrien odotetaan olevan tyyppiä merkkijono"),
-            }
-        }
+        Stmt::FunctionDeclaration(self.parse_function(name))
+    }
+
+    // Parses the parameters and body shared by functions, methods and constructors
+    // Calls parse_params and parse_stmt
+    fn parse_function(&mut self, name: String) -> FunctionDeclaration {
+        let line = self.last_line; // the line of the name
+        let (params, rest) = self.parse_params();
 
         match self.expect(TokenType::OpenBrace) {
             Some(_) => (),
@@ -143,22 +192,303 @@ impl Parser {
             None => panic!("Sulkevaa aaltosuljetta odotetaan funktion määrittelyssä"),
         }
 
-        Stmt::FunctionDeclaration(FunctionDeclaration {
+        FunctionDeclaration {
             name,
             parameters: params,
+            rest,
             body,
-        })
+            line,
+        }
     }
 
-    fn parse_if_else_expr(&mut self) -> Expr {
+    // Parses class declarations: luokka Nimi [laajentaa Isä] { kentät, rakentaja, metodit }
+    // Calls parse_function and parse_expr
+    fn parse_class_declaration(&mut self) -> Stmt {
+        let line = self.eat().line; // advance past luokka
+        let name = match self.expect(TokenType::Identifier) {
+            Some(token) => token.value.into_owned(),
+            None => panic!("Odotettu luokan nimi luokka-avainsanan jälkeen"),
+        };
+
+        let parent = if self.at().token_type == TokenType::Extends {
+            self.eat();
+            match self.expect(TokenType::Identifier) {
+                Some(token) => Some(token.value.into_owned()),
+                None => panic!("Odotettu yliluokan nimi laajentaa-avainsanan jälkeen"),
+            }
+        } else {
+            None
+        };
+
+        if self.expect(TokenType::OpenBrace).is_none() {
+            panic!("Odotettu avaava aaltosulje luokan {} määrittelyssä", name);
+        }
+
+        let mut class = ClassDeclaration {
+            name,
+            parent,
+            fields: Vec::new(),
+            constructor: None,
+            methods: Vec::new(),
+            static_fields: Vec::new(),
+            static_methods: Vec::new(),
+            line,
+        };
+
+        loop {
+            while matches!(self.at().token_type, TokenType::SingleLineComment | TokenType::MultiLineComment) {
+                self.eat();
+            }
+            if !self.not_eof() || self.at().token_type == TokenType::CloseBrace {
+                break;
+            }
+
+            let line = self.at().line;
+            let is_static = self.at().token_type == TokenType::Static;
+            if is_static {
+                self.eat();
+            }
+
+            match self.at().token_type {
+                TokenType::Let => {
+                    self.eat();
+                    let field_name = match self.expect(TokenType::Identifier) {
+                        Some(token) => token.value.into_owned(),
+                        None => panic!("Rivi {}: odotettu kentän nimi olkoon-avainsanan jälkeen", line),
+                    };
+                    let value = if self.at().token_type == TokenType::Assign {
+                        self.eat();
+                        Some(self.parse_expr())
+                    } else {
+                        None
+                    };
+                    self.expect_semicolon();
+                    let fields = if is_static { &mut class.static_fields } else { &mut class.fields };
+                    if fields.iter().any(|field| field.name == field_name) {
+                        panic!("Rivi {}: kenttä '{}' on määritelty useaan kertaan luokassa {}", line, field_name, class.name);
+                    }
+                    fields.push(FieldDeclaration { name: field_name, value });
+                }
+                TokenType::Fn => {
+                    self.eat();
+                    let method_name = match self.expect(TokenType::Identifier) {
+                        Some(token) => token.value.into_owned(),
+                        None => panic!("Rivi {}: odotettu metodin nimi funktio-avainsanan jälkeen", line),
+                    };
+                    let method = self.parse_function(method_name);
+                    let methods = if is_static { &mut class.static_methods } else { &mut class.methods };
+                    if methods.iter().any(|m| m.name == method.name) {
+                        panic!("Rivi {}: metodi '{}' on määritelty useaan kertaan luokassa {}", line, method.name, class.name);
+                    }
+                    methods.push(method);
+                }
+                TokenType::Constructor if !is_static => {
+                    self.eat();
+                    if class.constructor.is_some() {
+                        panic!("Rivi {}: luokalla {} voi olla vain yksi rakentaja", line, class.name);
+                    }
+                    class.constructor = Some(self.parse_function(class.name.clone()));
+                }
+                TokenType::Const => panic!("Rivi {}: luokan kentät määritellään olkoon-avainsanalla", line),
+                _ => panic!("Rivi {}: odottamaton tunnus luokan {} rungossa: {:?}", line, class.name, self.at().value),
+            }
+        }
+
+        if self.expect(TokenType::CloseBrace).is_none() {
+            panic!("Sulkevaa aaltosuljetta odotetaan luokan {} määrittelyssä", class.name);
+        }
+
+        Stmt::ClassDeclaration(class)
+    }
+
+    // Parses the parameter list of a function declaration: (a, b = 2, ...muut)
+    // Calls parse_binding_pattern and parse_pattern_default
+    fn parse_params(&mut self) -> (Vec<Pattern>, Option<String>) {
+        if self.expect(TokenType::OpenParen).is_none() {
+            panic!("Odotettu avaava sulku funktion nimen jälkeen");
+        }
+        let mut params: Vec<Pattern> = Vec::new();
+        let mut rest = None;
+        while self.not_eof() && self.at().token_type != TokenType::CloseParen {
+            if rest.is_some() {
+                panic!("Rivi {}: loput-parametrin '...' on oltava viimeinen parametri", self.at().line);
+            }
+            let line = self.at().line;
+            let param_name = if self.at().token_type == TokenType::Ellipsis {
+                self.eat();
+                let name = match self.expect(TokenType::Identifier) {
+                    Some(token) => token.value.into_owned(),
+                    None => panic!("Rivi {}: odotettu parametrin nimi '...'-merkinnän jälkeen", line),
+                };
+                rest = Some(name.clone());
+                Some(name)
+            } else {
+                let param = self.parse_binding_pattern("Funktion parametri");
+                let param = self.parse_pattern_default(param);
+                let name = param.parameter_name().cloned();
+                params.push(param);
+                name
+            };
+            if let Some(name) = param_name {
+                if params.iter().filter(|p| p.parameter_name() == Some(&name)).count() + (rest.as_ref() == Some(&name)) as usize > 1 {
+                    panic!("Rivi {}: parametri '{}' on määritelty useaan kertaan", line, name);
+                }
+            }
+            if self.at().token_type != TokenType::CloseParen && self.expect(TokenType::Comma).is_none() {
+                panic!("Odotettu pilkku tai sulkeva sulku parametrilistassa");
+            }
+        }
+        self.expect(TokenType::CloseParen);
+        (params, rest)
+    }
+
+    // Parses enum declarations: luettelo Nimi { Muunnelma(kenttä, ...), ... }
+    // Calls expect
+    fn parse_enum_declaration(&mut self) -> Stmt {
+        let declaration_line = self.eat().line; // advance past luettelo
+        let name = match self.expect(TokenType::Identifier) {
+            Some(token) => token.value.into_owned(),
+            None => panic!("Odotettu luettelon nimi luettelo-avainsanan jälkeen"),
+        };
+        if self.expect(TokenType::OpenBrace).is_none() {
+            panic!("Odotettu avaava aaltosulje luettelon {} määrittelyssä", name);
+        }
+
+        let mut variants: Vec<VariantDeclaration> = Vec::new();
+        loop {
+            while matches!(self.at().token_type, TokenType::SingleLineComment | TokenType::MultiLineComment) {
+                self.eat();
+            }
+            if !self.not_eof() || self.at().token_type == TokenType::CloseBrace {
+                break;
+            }
+
+            let line = self.at().line;
+            let variant_name = match self.expect(TokenType::Identifier) {
+                Some(token) => token.value.into_owned(),
+                None => panic!("Rivi {}: odotettu muunnelman nimi luettelossa {}", line, name),
+            };
+            if variants.iter().any(|v| v.name == variant_name) {
+                panic!("Rivi {}: muunnelma '{}' on määritelty useaan kertaan luettelossa {}", line, variant_name, name);
+            }
+
+            let mut fields: Vec<String> = Vec::new();
+            if self.at().token_type == TokenType::OpenParen {
+                self.eat();
+                while self.not_eof() && self.at().token_type != TokenType::CloseParen {
+                    let field = match self.expect(TokenType::Identifier) {
+                        Some(token) => token.value.into_owned(),
+                        None => panic!("Rivi {}: odotettu kentän nimi muunnelmassa {}", line, variant_name),
+                    };
+                    if fields.contains(&field) {
+                        panic!("Rivi {}: kenttä '{}' on määritelty useaan kertaan muunnelmassa {}", line, field, variant_name);
+                    }
+                    fields.push(field);
+                    if self.at().token_type != TokenType::CloseParen && self.expect(TokenType::Comma).is_none() {
+                        panic!("Rivi {}: odotettu pilkku tai sulkeva sulku muunnelmassa {}", line, variant_name);
+                    }
+                }
+                self.expect(TokenType::CloseParen);
+            }
+            variants.push(VariantDeclaration { name: variant_name, fields });
+
+            if self.at().token_type != TokenType::CloseBrace && self.expect(TokenType::Comma).is_none() {
+                panic!("Rivi {}: odotettu pilkku muunnelmien välissä luettelossa {}", line, name);
+            }
+        }
+
+        if self.expect(TokenType::CloseBrace).is_none() {
+            panic!("Sulkevaa aaltosuljetta odotetaan luettelon {} määrittelyssä", name);
+        }
+
+        self.enums.push((name.clone(), variants.iter().map(|variant| variant.name.clone()).collect()));
+        Stmt::EnumDeclaration(EnumDeclaration { name, variants, line: declaration_line })
+    }
+
+    // Parses imports: tuo "polku.ka" [nimellä m] or tuo { a, b: c } moduulista "polku.ka"
+    // Calls parse_single_pattern
+    fn parse_import(&mut self) -> Stmt {
+        let line = self.eat().line; // advance past tuo
+
+        let names = if self.at().token_type == TokenType::OpenBrace {
+            let names = match self.parse_single_pattern() {
+                Pattern::Object(object) if Pattern::Object(object.clone()).is_binding_only() => object,
+                _ => panic!("Rivi {}: tuo-lauseen nimilista voi sisältää vain tunnisteita", line),
+            };
+            if self.expect(TokenType::From).is_none() {
+                panic!("Rivi {}: odotettu 'moduulista' tuotavien nimien jälkeen", line);
+            }
+            Some(names)
+        } else {
+            None
+        };
+
+        let path = match self.expect(TokenType::StringLiteral) {
+            Some(token) => token.value.into_owned(),
+            None => panic!("Rivi {}: tuo-lause odottaa moduulin polun merkkijonona", line),
+        };
+
+        let alias = if names.is_none() && self.at().token_type == TokenType::As {
+            self.eat();
+            match self.expect(TokenType::Identifier) {
+                Some(token) => Some(token.value.into_owned()),
+                None => panic!("Rivi {}: odotettu nimi 'nimellä'-avainsanan jälkeen", line),
+            }
+        } else {
+            None
+        };
+
+        Stmt::Import(ImportDeclaration { path, alias, names, line })
+    }
+
+    // Parses exports: vie <määrittely> or vie { a, b }
+    // Calls parse_stmt
+    fn parse_export(&mut self) -> Stmt {
+        let line = self.eat().line; // advance past vie
 
+        if self.at().token_type == TokenType::OpenBrace {
+            self.eat();
+            let mut names = Vec::new();
+            while self.not_eof() && self.at().token_type != TokenType::CloseBrace {
+                match self.expect(TokenType::Identifier) {
+                    Some(token) => names.push(token.value.into_owned()),
+                    None => panic!("Rivi {}: vie-lauseen nimilista voi sisältää vain tunnisteita", line),
+                }
+                if self.at().token_type != TokenType::CloseBrace && self.expect(TokenType::Comma).is_none() {
+                    panic!("Rivi {}: odotettu pilkku tai sulkeva aaltosulje vie-lauseessa", line);
+                }
+            }
+            self.expect(TokenType::CloseBrace);
+            return Stmt::Export(ExportDeclaration { declaration: None, names, line });
+        }
+
+        match self.at().token_type {
+            TokenType::Let | TokenType::Const | TokenType::Fn | TokenType::Class | TokenType::Enum => {
+                let declaration = self.parse_stmt();
+                Stmt::Export(ExportDeclaration { declaration: Some(Box::new(declaration)), names: Vec::new(), line })
+            }
+            _ => panic!("Rivi {}: vie-lause odottaa määrittelyä tai nimilistaa", line),
+        }
+    }
+
+    // Parses `jos` expressions, which may also appear in expression position
+    // Calls parse_expr
+    fn parse_if_else_expr(&mut self) -> Expr {
+        self.eat(); // advance past jos
         let condition = Box::new(self.parse_expr());
     
         let if_branch = self.parse_block();
     
         let else_branch = if self.at().token_type == TokenType::Else {
             self.eat(); 
-            Some(self.parse_block())
+            if self.at().token_type == TokenType::If {
+                // muuten jos ...
+                let line = self.at().line;
+                let else_if = self.parse_if_else_expr();
+                Some(Block { statements: vec![Stmt::Expr(ExprStmt { expr: else_if, line })] })
+            } else {
+                Some(self.parse_block())
+            }
         } else {
             None
         };
@@ -166,14 +496,248 @@ impl Parser {
         Expr::IfElseExpr(IfElseExpr { condition, if_branch, else_branch })
     }
 
-    fn parse_for_loop(&mut self) -> Stmt {
+    // Parses `valitse` expressions
+    // Calls parse_pattern
+    fn parse_match_expr(&mut self) -> Expr {
+        let line = self.eat().line; // advance past valitse
+        let subject = Box::new(self.parse_expr());
+
+        if self.expect(TokenType::OpenBrace).is_none() {
+            panic!("Odotettu '{{' valitse-lausekkeen arvon jälkeen");
+        }
+
+        let mut arms: Vec<MatchArm> = Vec::new();
+        let mut has_default = false;
+        while self.not_eof() && self.at().token_type != TokenType::CloseBrace {
+            let arm_line = self.at().line;
+            let pattern = match self.eat().token_type {
+                TokenType::Case => Some(self.parse_pattern()),
+                TokenType::Else => None,
+                _ => panic!("Rivi {}: odotettu 'tapaus' tai 'muuten' valitse-lausekkeessa", arm_line),
+            };
+
+            let guard = if pattern.is_some() && self.at().token_type == TokenType::If {
+                self.eat();
+                Some(self.parse_expr())
+            } else {
+                None
+            };
+
+            if self.expect(TokenType::FatArrow).is_none() {
+                panic!("Rivi {}: odotettu '=>' tapauksen jälkeen", arm_line);
+            }
+
+            let body = if self.at().token_type == TokenType::OpenBrace {
+                self.parse_block()
+            } else {
+                let line = self.at().line;
+                Block { statements: vec![Stmt::Expr(ExprStmt { expr: self.parse_expr(), line })] }
+            };
+
+            if has_default {
+                self.warnings.push(format!("Rivi {}: tapaukseen ei voida koskaan päätyä, koska aiempi tapaus täsmää kaikkiin arvoihin", arm_line));
+            }
+            if guard.is_none() && pattern.as_ref().is_none_or(|p| p.is_irrefutable()) {
+                has_default = true;
+            }
+
+            arms.push(MatchArm { pattern, guard, body, line: arm_line });
+
+            if self.at().token_type == TokenType::Comma {
+                self.eat();
+            }
+        }
+
+        if self.expect(TokenType::CloseBrace).is_none() {
+            panic!("Valitse-lausekkeesta puuttuu sulkeva '}}'");
+        }
+
+        if !has_default {
+            let mut covered = Vec::new();
+            for arm in arms.iter().filter(|arm| arm.guard.is_none()) {
+                if let Some(pattern) = &arm.pattern {
+                    covered_variants(pattern, &mut covered);
+                }
+            }
+            self.warnings.push(format!("Rivi {}: valitse-lausekkeesta puuttuu muuten-haara", line));
+            self.unfinished_matches.push((self.warnings.len() - 1, covered));
+        }
+
+        Expr::MatchExpr(MatchExpr { subject, arms })
+    }
+
+    // Parses a pattern with alternatives: 1 | 2 | 3
+    // Calls parse_single_pattern
+    fn parse_pattern(&mut self) -> Pattern {
+        let first = self.parse_single_pattern();
+
+        if !self.at_operator(&["|"]) {
+            return first;
+        }
+
+        let mut alternatives = vec![first];
+        while self.at_operator(&["|"]) {
+            self.eat();
+            alternatives.push(self.parse_single_pattern());
+        }
+        Pattern::Alternatives(alternatives)
+    }
+
+    fn parse_single_pattern(&mut self) -> Pattern {
+        match self.at().token_type {
+            TokenType::Identifier if matches!(self.peek(1), Some(Token { token_type: TokenType::Dot, .. })) => {
+                let enum_name = self.eat().value.into_owned();
+                self.eat(); // advance past dot
+                let variant = match self.expect(TokenType::Identifier) {
+                    Some(token) => token.value.into_owned(),
+                    None => panic!("Odotettu muunnelman nimi kuviossa {}.", enum_name),
+                };
+                let fields = if self.at().token_type == TokenType::OpenParen {
+                    self.eat();
+                    let mut fields = Vec::new();
+                    while self.not_eof() && self.at().token_type != TokenType::CloseParen {
+                        fields.push(self.parse_pattern());
+                        if self.at().token_type != TokenType::CloseParen && self.expect(TokenType::Comma).is_none() {
+                            panic!("Odotettu pilkku tai sulkeva sulku muunnelmakuviossa");
+                        }
+                    }
+                    self.expect(TokenType::CloseParen);
+                    Some(fields)
+                } else {
+                    None
+                };
+                Pattern::Variant(VariantPattern { enum_name, variant, fields })
+            }
+            TokenType::Identifier => {
+                let symbol = self.eat().value.into_owned();
+                match symbol.as_str() {
+                    "_" => Pattern::Wildcard,
+                    "tosi" | "epätosi" | "tyhjä" => Pattern::Literal(Box::new(Expr::Identifier(Identifier { symbol }))),
+                    _ => Pattern::Binding(symbol),
+                }
+            }
+            TokenType::OpenBracket => {
+                self.eat();
+                let mut elements = Vec::new();
+                let mut rest = None;
+                while self.not_eof() && self.at().token_type != TokenType::CloseBracket {
+                    if self.at().token_type == TokenType::Ellipsis {
+                        self.eat();
+                        rest = match self.expect(TokenType::Identifier) {
+                            Some(token) => Some(token.value.into_owned()),
+                            None => panic!("Odotettu tunniste '...'-merkinnän jälkeen"),
+                        };
+                    } else {
+                        if rest.is_some() {
+                            panic!("Loput-kuvion '...' on oltava taulukkokuvion viimeinen alkio");
+                        }
+                        let element = self.parse_pattern();
+                        elements.push(self.parse_pattern_default(element));
+                    }
+                    if self.at().token_type != TokenType::CloseBracket && self.expect(TokenType::Comma).is_none() {
+                        panic!("Odotettu pilkku tai sulkeva hakasulku taulukkokuviossa");
+                    }
+                }
+                self.expect(TokenType::CloseBracket);
+                Pattern::Array(ArrayPattern { elements, rest })
+            }
+            TokenType::OpenBrace => {
+                self.eat();
+                let mut properties = Vec::new();
+                while self.not_eof() && self.at().token_type != TokenType::CloseBrace {
+                    let key = match self.expect(TokenType::Identifier) {
+                        Some(token) => token.value.into_owned(),
+                        None => panic!("Oliokuvio odottaa avainta"),
+                    };
+                    let pattern = if self.at().token_type == TokenType::Colon {
+                        self.eat();
+                        self.parse_pattern()
+                    } else {
+                        Pattern::Binding(key.clone())
+                    };
+                    let pattern = self.parse_pattern_default(pattern);
+                    properties.push(PropertyPattern { key, pattern });
+                    if self.at().token_type != TokenType::CloseBrace && self.expect(TokenType::Comma).is_none() {
+                        panic!("Odotettu pilkku tai sulkeva aaltosulje oliokuviossa");
+                    }
+                }
+                self.expect(TokenType::CloseBrace);
+                Pattern::Object(ObjectPattern { properties })
+            }
+            _ => {
+                let start = self.parse_literal_pattern();
+                if matches!(self.at().token_type, TokenType::DotDot | TokenType::DotDotEqual) {
+                    let inclusive = self.eat().token_type == TokenType::DotDotEqual;
+                    let end = self.parse_literal_pattern();
+                    return Pattern::Range(RangePattern {
+                        start: Box::new(start),
+                        end: Box::new(end),
+                        inclusive,
+                    });
+                }
+                Pattern::Literal(Box::new(start))
+            }
+        }
+    }
+
+    // Parses an optional default value: [a = 1] or { nimi = "tuntematon" }
+    // Calls parse_expr
+    fn parse_pattern_default(&mut self, pattern: Pattern) -> Pattern {
+        if self.at().token_type != TokenType::Assign {
+            return pattern;
+        }
         self.eat();
+        Pattern::Default(DefaultPattern {
+            pattern: Box::new(pattern),
+            default: Box::new(self.parse_expr()),
+        })
+    }
+
+    // Parses the target of a declaration, parameter or loop head: x, [a, b] or { a, b }
+    // Calls parse_single_pattern
+    fn parse_binding_pattern(&mut self, context: &str) -> Pattern {
+        let line = self.at().line;
+        let pattern = self.parse_single_pattern();
+        if !pattern.is_binding_only() {
+            panic!("Rivi {}: {} voi sisältää vain tunnisteita sekä taulukko- ja oliokuvioita", line, context);
+        }
+        pattern
+    }
+
+    fn parse_literal_pattern(&mut self) -> Expr {
+        match self.at().token_type {
+            TokenType::Integer | TokenType::Float | TokenType::TypedInteger | TokenType::StringLiteral =>
+                self.parse_primary_expr(),
+            _ if self.at_operator(&["-"]) => self.parse_unary_expr(),
+            _ => panic!("Rivi {}: odottamaton tunnus kuviossa: {:?}", self.at().line, self.at().value),
+        }
+    }
+
+    // Parses both loop forms: toista (olkoon i = 0; ehto; askel) and toista (olkoon x joukosta lista)
+    // Calls parse_binding_pattern and parse_expr
+    fn parse_for_loop(&mut self) -> Stmt {
+        let line = self.last_line; // the line of toista
         self.expect(TokenType::OpenParen);
-        let identifier = match self.expect(TokenType::Identifier) {
-            Some(token) => token.value,
-            None => panic!("Odotettu tunnisteen nimi avainsanan 'olkoon' jälkeen"),
+        let constant = match self.eat().token_type {
+            TokenType::Let => false,
+            TokenType::Const => true,
+            _ => panic!("Odotettu 'olkoon' tai 'vakio' toista-silmukan alussa"),
         };
-        self.expect(TokenType::Assign);
+        let target = self.parse_binding_pattern("Toista-silmukan muuttuja");
+
+        if self.at().token_type == TokenType::In {
+            self.eat();
+            let iterable = Box::new(self.parse_expr());
+            if self.expect(TokenType::CloseParen).is_none() {
+                panic!("Odotettu sulkeva sulku toista-silmukan otsikon jälkeen");
+            }
+            let body = self.parse_block();
+            return Stmt::ForEachLoop(ForEachLoop { constant, target, iterable, body, line });
+        }
+
+        if self.expect(TokenType::Assign).is_none() {
+            panic!("Odotettu '=' tai 'joukosta' toista-silmukan muuttujan jälkeen");
+        }
         let initialization = Box::new(self.parse_expr());
         self.expect(TokenType::SemiColon);
         let condition = Box::new(self.parse_expr());
@@ -183,13 +747,15 @@ impl Parser {
         let body = self.parse_block(); 
         Stmt::ForLoop(ForLoop {
             initializer: Box::new(Stmt::VarDeclaration(VarDeclaration {
-                identifier: Identifier { symbol: identifier },
-                constant: false,
+                target,
+                constant,
                 value: Some(*initialization),
+                line,
             })),
-            condition: condition,
-            increment: increment,
-            body: body,
+            condition,
+            increment,
+            body,
+            line,
         })
     }
 
@@ -202,7 +768,7 @@ impl Parser {
         }
     
         match self.at().token_type {
-            TokenType::If | TokenType::Else => self.parse_if_else_expr(),
+            TokenType::Else => panic!("'muuten' ilman edeltävää 'jos'-lauseketta"),
             TokenType::OpenBrace => self.parse_object_expr(),
             _ => self.parse_assignment_expr(),
         }
@@ -211,20 +777,21 @@ impl Parser {
     // Parses variable declarations
     // Calls parse_expr
     fn parse_var_declaration(&mut self) -> Stmt {
-        let is_constant = self.eat().token_type == TokenType::Const;
-        let identifier = match self.expect(TokenType::Identifier) {
-            Some(token) =>
-                Identifier {
-                    symbol: token.value,
-                },
-            None => panic!("Odotettu tunnisteen nimi seuraten olkoon | vakio avainsanoja"),
+        let keyword = self.eat();
+        let (is_constant, line) = (keyword.token_type == TokenType::Const, keyword.line);
+        let target = match self.at().token_type {
+            TokenType::Identifier => Pattern::Binding(self.eat().value.into_owned()),
+            TokenType::OpenBracket | TokenType::OpenBrace => self.parse_binding_pattern("Hajottava määrittely"),
+            _ => panic!("Odotettu tunnisteen nimi seuraten olkoon | vakio avainsanoja"),
         };
     
         let value = if self.at().token_type == TokenType::Assign {
             self.expect(TokenType::Assign);
             Some(self.parse_expr())
         } else {
-            if is_constant {
+            if !matches!(target, Pattern::Binding(_)) {
+                panic!("Hajottavalle määrittelylle on annettava arvo");
+            } else if is_constant {
                 panic!("Vakiolausekkeelle on annettava arvo ja arvoa ei ole annettu");
             } else {
                 None
@@ -232,9 +799,10 @@ impl Parser {
         };
     
         Stmt::VarDeclaration(VarDeclaration {
-            identifier,
+            target,
             constant: is_constant,
             value,
+            line,
         })
     }
 
@@ -245,14 +813,42 @@ impl Parser {
     
         if self.at().token_type == TokenType::Assign {
             self.eat(); // advance past equals
+            self.expect_assignable(&left, "=");
             let value = self.parse_assignment_expr();
             return Expr::AssignmentExpr(AssignmentExpr {
                 value: Box::new(value),
                 assignee: Box::new(left),
             });
         }
-    
-        left
+
+        let operator = match self.at().token_type {
+            TokenType::PlusEqual => BinaryOperator::Add,
+            TokenType::MinusEqual => BinaryOperator::Subtract,
+            TokenType::TimesEqual => BinaryOperator::Multiply,
+            TokenType::DivideEqual => BinaryOperator::Divide,
+            TokenType::ModulusEqual => BinaryOperator::Modulus,
+            TokenType::ExponentEqual => BinaryOperator::Exponent,
+            TokenType::NullishEqual => BinaryOperator::NullishCoalescing,
+            _ => return left,
+        };
+        let token = self.eat(); // advance past the compound operator
+        self.expect_assignable(&left, &token.value);
+        let value = self.parse_assignment_expr();
+        Expr::CompoundAssignmentExpr(CompoundAssignmentExpr {
+            assignee: Box::new(left),
+            operator,
+            value: Box::new(value),
+        })
+    }
+
+    fn expect_assignable(&self, target: &Expr, operator: &str) {
+        match target {
+            Expr::Identifier(_) => (),
+            Expr::MemberExpr(member) if member.optional =>
+                panic!("Valinnaista ketjua ei voi käyttää operaattorin '{}' kohteena", operator),
+            Expr::MemberExpr(member) => self.expect_assignable(&member.object, operator),
+            _ => panic!("Operaattorin '{}' kohteen on oltava muuttuja, ominaisuus tai indeksi", operator),
+        }
     }
 
     // Parses array expressions
@@ -286,10 +882,10 @@ impl Parser {
     }
 
     // Parses object expressions
-    // Calls parse_comparison_expr
+    // Calls parse_conditional_expr
     fn parse_object_expr(&mut self) -> Expr {
         if self.at().token_type != TokenType::OpenBrace {
-            return self.parse_comparison_expr();
+            return self.parse_conditional_expr();
         }
 
         self.eat(); // advance past open brace.
@@ -297,7 +893,7 @@ impl Parser {
 
         while self.not_eof() && self.at().token_type != TokenType::CloseBrace {
             let key = match self.expect(TokenType::Identifier) {
-                Some(token) => token.value,
+                Some(token) => token.value.into_owned(),
                 None => panic!("Olio literaali odottaa avainta"),
             };
 
@@ -337,13 +933,90 @@ impl Parser {
         Expr::ObjectLiteral(ObjectLiteral { properties })
     }
 
+    // Parses conditional expressions: ehto ? a : b
+    // Calls parse_nullish_expr
+    fn parse_conditional_expr(&mut self) -> Expr {
+        let condition = self.parse_nullish_expr();
+
+        if self.at().token_type != TokenType::QuestionMark {
+            return condition;
+        }
+
+        self.eat(); // advance past ?
+        let consequent = self.parse_expr();
+        if self.expect(TokenType::Colon).is_none() {
+            panic!("Ehtolausekkeesta puuttuu ':'");
+        }
+        let alternate = self.parse_object_expr();
+
+        Expr::ConditionalExpr(ConditionalExpr {
+            condition: Box::new(condition),
+            consequent: Box::new(consequent),
+            alternate: Box::new(alternate),
+        })
+    }
+
+    // Parses null-coalescing expressions: a ?? b
+    // Calls parse_logical_or_expr
+    fn parse_nullish_expr(&mut self) -> Expr {
+        let mut left = self.parse_logical_or_expr();
+
+        while self.at_operator(&["??"]) {
+            self.eat(); // advance past ??
+            let right = self.parse_logical_or_expr();
+            left = Expr::LogicalExpr(LogicalExpr {
+                left: Box::new(left),
+                right: Box::new(right),
+                operator: BinaryOperator::NullishCoalescing,
+            });
+        }
+
+        left
+    }
+
+    // Parses logical or expressions
+    // Calls parse_logical_and_expr
+    fn parse_logical_or_expr(&mut self) -> Expr {
+        let mut left = self.parse_logical_and_expr();
+
+        while self.at_operator(&["||"]) {
+            self.eat(); // advance past ||
+            let right = self.parse_logical_and_expr();
+            left = Expr::LogicalExpr(LogicalExpr {
+                left: Box::new(left),
+                right: Box::new(right),
+                operator: BinaryOperator::Or,
+            });
+        }
+
+        left
+    }
+
+    // Parses logical and expressions
+    // Calls parse_comparison_expr
+    fn parse_logical_and_expr(&mut self) -> Expr {
+        let mut left = self.parse_comparison_expr();
+
+        while self.at_operator(&["&&"]) {
+            self.eat(); // advance past &&
+            let right = self.parse_comparison_expr();
+            left = Expr::LogicalExpr(LogicalExpr {
+                left: Box::new(left),
+                right: Box::new(right),
+                operator: BinaryOperator::And,
+            });
+        }
+
+        left
+    }
+
     // Parses comparison expressions
-    // Calls parse_logical_expr
+    // Calls parse_bitwise_or_expr
     fn parse_comparison_expr(&mut self) -> Expr {
-        let mut left = self.parse_logical_expr();
+        let mut left = self.parse_bitwise_or_expr();
 
-        while self.at().value == "==" || self.at().value == "!=" || self.at().value == "<" || self.at().value == ">" || self.at().value == "<=" || self.at().value == ">=" {
-            let operator = match self.eat().value.as_str() {
+        while self.at_operator(&["==", "!=", "<", ">", "<=", ">="]) {
+            let operator = match self.eat().value.as_ref() {
                 "==" => BinaryOperator::Equal,
                 "!=" => BinaryOperator::NotEqual,
                 "<" => BinaryOperator::LessThan,
@@ -352,7 +1025,7 @@ impl Parser {
                 ">=" => BinaryOperator::GreaterThanOrEqual,
                 _ => panic!("Odottamaton operaattori"),
             };
-            let right = self.parse_logical_expr();
+            let right = self.parse_bitwise_or_expr();
             left = Expr::BinaryExpr(BinaryExpr {
                 left: Box::new(left),
                 right: Box::new(right),
@@ -363,15 +1036,69 @@ impl Parser {
         left
     }
 
-    // Parses logical expressions
+    // Parses bitwise or expressions
+    // Calls parse_bitwise_xor_expr
+    fn parse_bitwise_or_expr(&mut self) -> Expr {
+        let mut left = self.parse_bitwise_xor_expr();
+
+        while self.at_operator(&["|"]) {
+            self.eat(); // advance past |
+            let right = self.parse_bitwise_xor_expr();
+            left = Expr::BinaryExpr(BinaryExpr {
+                left: Box::new(left),
+                right: Box::new(right),
+                operator: BinaryOperator::BitwiseOr,
+            });
+        }
+
+        left
+    }
+
+    // Parses bitwise xor expressions
+    // Calls parse_bitwise_and_expr
+    fn parse_bitwise_xor_expr(&mut self) -> Expr {
+        let mut left = self.parse_bitwise_and_expr();
+
+        while self.at_operator(&["^"]) {
+            self.eat(); // advance past ^
+            let right = self.parse_bitwise_and_expr();
+            left = Expr::BinaryExpr(BinaryExpr {
+                left: Box::new(left),
+                right: Box::new(right),
+                operator: BinaryOperator::BitwiseXor,
+            });
+        }
+
+        left
+    }
+
+    // Parses bitwise and expressions
+    // Calls parse_shift_expr
+    fn parse_bitwise_and_expr(&mut self) -> Expr {
+        let mut left = self.parse_shift_expr();
+
+        while self.at_operator(&["&"]) {
+            self.eat(); // advance past &
+            let right = self.parse_shift_expr();
+            left = Expr::BinaryExpr(BinaryExpr {
+                left: Box::new(left),
+                right: Box::new(right),
+                operator: BinaryOperator::BitwiseAnd,
+            });
+        }
+
+        left
+    }
+
+    // Parses shift expressions
     // Calls parse_additive_expr
-    fn parse_logical_expr(&mut self) -> Expr {
+    fn parse_shift_expr(&mut self) -> Expr {
         let mut left = self.parse_additive_expr();
-    
-        while self.at().value == "&&" || self.at().value == "||" {
-            let operator = match self.eat().value.as_str() {
-                "&&" => BinaryOperator::And,
-                "||" => BinaryOperator::Or,
+
+        while self.at_operator(&["<<", ">>"]) {
+            let operator = match self.eat().value.as_ref() {
+                "<<" => BinaryOperator::ShiftLeft,
+                ">>" => BinaryOperator::ShiftRight,
                 _ => panic!("Odottamaton operaattori"),
             };
             let right = self.parse_additive_expr();
@@ -381,7 +1108,7 @@ impl Parser {
                 operator,
             });
         }
-    
+
         left
     }
 
@@ -390,39 +1117,18 @@ impl Parser {
     fn parse_additive_expr(&mut self) -> Expr {
         let mut left = self.parse_multiplicative_expr();
     
-        while self.at().value == "+" || self.at().value == "-" || self.at().value == "+=" || self.at().value == "-=" {
-            let operator = match self.eat().value.as_str() {
+        while self.at_operator(&["+", "-"]) {
+            let operator = match self.eat().value.as_ref() {
                 "+" => BinaryOperator::Add,
                 "-" => BinaryOperator::Subtract,
-                "+=" => BinaryOperator::AddEqual,
-                "-=" => BinaryOperator::SubtractEqual,
                 _ => panic!("Odottamaton operaattori"),
             };
-    
             let right = self.parse_multiplicative_expr();
-    
-            if operator == BinaryOperator::Add || operator == BinaryOperator::Subtract {
-                left = Expr::BinaryExpr(BinaryExpr {
-                    left: Box::new(left),
-                    right: Box::new(right),
-                    operator,
-                });
-            } else {
-                let new_value = Expr::BinaryExpr(BinaryExpr {
-                    left: Box::new(left.clone()),
-                    right: Box::new(right.clone()),
-                    operator: if operator == BinaryOperator::AddEqual {
-                        BinaryOperator::Add
-                    } else {
-                        BinaryOperator::Subtract
-                    },
-                });
-    
-                left = Expr::AssignmentExpr(AssignmentExpr {
-                    assignee: Box::new(left),
-                    value: Box::new(new_value),
-                });
-            }
+            left = Expr::BinaryExpr(BinaryExpr {
+                left: Box::new(left),
+                right: Box::new(right),
+                operator,
+            });
         }
     
         left
@@ -433,10 +1139,11 @@ impl Parser {
     fn parse_multiplicative_expr(&mut self) -> Expr {
         let mut left = self.parse_exponentiation_expr();
     
-        while self.at().value == "*" || self.at().value == "/" || self.at().value == "%" {
-            let operator = match self.eat().value.as_str() {
+        while self.at_operator(&["*", "/", "~/", "%"]) {
+            let operator = match self.eat().value.as_ref() {
                 "*" => BinaryOperator::Multiply,
                 "/" => BinaryOperator::Divide,
+                "~/" => BinaryOperator::IntegerDivide,
                 "%" => BinaryOperator::Modulus,
                 _ => panic!("Odottamaton operaattori"),
             };
@@ -456,7 +1163,7 @@ impl Parser {
     fn parse_exponentiation_expr(&mut self) -> Expr {
         let mut left = self.parse_unary_expr();
     
-        while self.at().value == "**" {
+        while self.at_operator(&["**"]) {
             self.eat(); // advance past **
             let right = self.parse_unary_expr();
             left = Expr::BinaryExpr(BinaryExpr {
@@ -472,8 +1179,8 @@ impl Parser {
     // Parses unary expressions
     // Calls parse_call_member_expr
     fn parse_unary_expr(&mut self) -> Expr {
-        if self.at().value == "-" || self.at().value == "!" {
-            let operator = self.eat().value;
+        if self.at_operator(&["-", "!", "~"]) {
+            let operator = self.eat().value.into_owned();
             let operand = self.parse_unary_expr();
             return Expr::UnaryExpr(UnaryExpr {
                 operator,
@@ -481,19 +1188,49 @@ impl Parser {
             });
         }
 
-        self.parse_call_member_expr()
+        if matches!(self.at().token_type, TokenType::Increment | TokenType::Decrement) {
+            let operator = self.eat().value.into_owned();
+            let assignee = self.parse_call_member_expr();
+            self.expect_assignable(&assignee, &operator);
+            return Expr::UpdateExpr(UpdateExpr {
+                operator,
+                prefix: true,
+                assignee: Box::new(assignee),
+            });
+        }
+
+        let expr = self.parse_call_member_expr();
+
+        // Postfix ++/-- must be on the same line as its operand
+        if matches!(self.at().token_type, TokenType::Increment | TokenType::Decrement) && self.at().line == self.last_line {
+            let operator = self.eat().value.into_owned();
+            self.expect_assignable(&expr, &operator);
+            return Expr::UpdateExpr(UpdateExpr {
+                operator,
+                prefix: false,
+                assignee: Box::new(expr),
+            });
+        }
+
+        expr
     }
 
     // Parses call member expressions
-    // Calls parse_member_expression
+    // Calls parse_primary_expr, parse_call_expr and parse_member_expression
     fn parse_call_member_expr(&mut self) -> Expr {
-        let member = self.parse_member_expression();
-
-        if self.at().token_type == TokenType::OpenParen {
-            return self.parse_call_expr(member);
+        let mut expr = self.parse_primary_expr();
+
+        // Calls and member accesses chain freely: olio.metodi(x).kenttä[0]()
+        loop {
+            expr = match self.at().token_type {
+                TokenType::OpenParen => self.parse_call_expr(expr),
+                TokenType::Dot | TokenType::OpenBracket | TokenType::OptionalDot | TokenType::OptionalBracket =>
+                    self.parse_member_expression(expr),
+                _ => break,
+            };
         }
 
-        member
+        expr
     }
 
     // Parses call expressions
@@ -507,81 +1244,112 @@ impl Parser {
         };
         self.expect(TokenType::CloseParen);
 
-        let mut call_expr = Expr::CallExpr(CallExpr {
+        Expr::CallExpr(CallExpr {
             caller: Box::new(caller),
             args,
-        });
+        })
+    }
 
-        while matches!(self.at().token_type, TokenType::OpenParen) {
-            call_expr = self.parse_call_expr(call_expr);
-        }
+    // Parses arguments list
+    // Calls parse_argument
+    fn parse_arguments_list(&mut self) -> Vec<Argument> {
+        let mut args = vec![self.parse_argument()];
 
-        call_expr
-    }
+        while matches!(self.at().token_type, TokenType::Comma) {
+            self.eat();
+            args.push(self.parse_argument());
+        }
 
-    // Parses arguments
-    // Calls parse_arguments_list
-    fn parse_args(&mut self) -> Vec<Expr> {
-        self.expect(TokenType::OpenParen);
-        let args = if matches!(self.at().token_type, TokenType::CloseParen) {
-            Vec::new()
-        } else {
-            self.parse_arguments_list()
-        };
+        let mut seen_named = false;
+        for arg in &args {
+            match arg {
+                Argument::Named(..) => seen_named = true,
+                _ if seen_named => panic!("Rivi {}: nimettyjen argumenttien jälkeen ei voi olla muita argumentteja", self.last_line),
+                _ => (),
+            }
+        }
 
-        self.expect(TokenType::CloseParen);
         args
     }
 
-    // Parses arguments list
+    // Parses a single argument: arvo, nimi: arvo or ...lista
     // Calls parse_assignment_expr
-    fn parse_arguments_list(&mut self) -> Vec<Expr> {
-        let mut args = vec![self.parse_assignment_expr()];
-
-        while matches!(self.at().token_type, TokenType::Comma) {
+    fn parse_argument(&mut self) -> Argument {
+        if self.at().token_type == TokenType::Ellipsis {
             self.eat();
-            args.push(self.parse_assignment_expr());
+            return Argument::Spread(self.parse_assignment_expr());
         }
-
-        args
+        if self.at().token_type == TokenType::Identifier
+            && matches!(self.peek(1), Some(Token { token_type: TokenType::Colon, .. }))
+        {
+            let name = self.eat().value.into_owned();
+            self.eat(); // advance past colon
+            return Argument::Named(name, self.parse_assignment_expr());
+        }
+        Argument::Positional(self.parse_assignment_expr())
     }
 
-    // Parses member expressions
-    // Calls parse_primary_expr
-    fn parse_member_expression(&mut self) -> Expr {
-        let mut object = self.parse_primary_expr();
+    // Parses a single member access: .nimi, ?.nimi, [avain] or ?[avain]
+    // Calls parse_primary_expr and parse_expr
+    fn parse_member_expression(&mut self, object: Expr) -> Expr {
+        let operator = self.eat();
+        let property: Box<Expr>;
+        let computed: bool;
+        let optional = matches!(operator.token_type, TokenType::OptionalDot | TokenType::OptionalBracket);
 
-        while
-            matches!(self.at().token_type, TokenType::Dot) ||
-            matches!(self.at().token_type, TokenType::OpenBracket)
-        {
-            let operator = self.eat();
-            let mut property: Box<Expr>;
-            let mut computed: bool;
+        if operator.token_type == TokenType::Dot || operator.token_type == TokenType::OptionalDot {
+            computed = false;
+            property = Box::new(self.parse_primary_expr());
 
-            if operator.token_type == TokenType::Dot {
-                computed = false;
-                property = Box::new(self.parse_primary_expr());
+            if let Expr::Identifier(_) = *property {
+                // property is an identifier
+            } else {
+                panic!("Odotettu tunniste pisteoperaattorin jälkeen");
+            }
+        } else {
+            computed = true;
+            property = Box::new(self.parse_expr());
+            self.expect(TokenType::CloseBracket);
+        }
 
-                if let Expr::Identifier(_) = *property {
-                    // property is an identifier
-                } else {
-                    panic!("Odotettu tunniste pisteoperaattorin jälkeen");
+        Expr::MemberExpr(MemberExpr {
+            object: Box::new(object),
+            property,
+            computed,
+            optional,
+        })
+    }
+
+    // Parses template strings into their literal and interpolated parts
+    // Calls parse_expr
+    fn parse_template_expr(&mut self) -> Expr {
+        self.eat(); // advance past opening backtick
+        let mut parts = Vec::new();
+
+        while self.not_eof() && self.at().token_type != TokenType::Backtick {
+            match self.at().token_type {
+                TokenType::StringLiteral => {
+                    let value = self.eat().value.into_owned();
+                    if !value.is_empty() {
+                        parts.push(Expr::StringLiteral(StringLiteral { value }));
+                    }
                 }
-            } else {
-                computed = true;
-                property = Box::new(self.parse_expr());
-                self.expect(TokenType::CloseBracket);
+                TokenType::InterpolationStart => {
+                    self.eat();
+                    parts.push(self.parse_expr());
+                    if self.expect(TokenType::CloseBrace).is_none() {
+                        panic!("Mallimerkkijonon lausekkeesta puuttuu sulkeva '}}'");
+                    }
+                }
+                _ => panic!("Odottamaton tunnus mallimerkkijonossa: {:?}", self.at()),
             }
+        }
 
-            object = Expr::MemberExpr(MemberExpr {
-                object: Box::new(object),
-                property,
-                computed,
-            });
+        if self.expect(TokenType::Backtick).is_none() {
+            panic!("Mallimerkkijonosta puuttuu lopetusmerkki '`'");
         }
 
-        object
+        Expr::TemplateLiteral(TemplateLiteral { parts })
     }
 
     // Parses primary expressions
@@ -589,7 +1357,7 @@ impl Parser {
     fn parse_primary_expr(&mut self) -> Expr {
         match self.at().token_type {
             TokenType::Identifier => {
-                let symbol = self.eat().value;
+                let symbol = self.eat().value.into_owned();
                 Expr::Identifier(Identifier { symbol })
             }
             TokenType::Integer => {
@@ -600,6 +1368,10 @@ impl Parser {
                 let value = self.eat().value.parse().unwrap();
                 Expr::FloatLiteral(FloatLiteral { value })
             }
+            TokenType::TypedInteger => {
+                let value = self.eat().value.parse().unwrap();
+                Expr::IntegerLiteral(IntegerLiteral { value })
+            }
             TokenType::OpenBracket => { self.parse_array_expr() }
             TokenType::OpenParen => {
                 self.eat();
@@ -608,13 +1380,27 @@ impl Parser {
                 expr
             }
             TokenType::StringLiteral => {
-                let value = self.eat().value;
+                let value = self.eat().value.into_owned();
                 Expr::StringLiteral(StringLiteral { value })
             }
+            TokenType::Backtick => { self.parse_template_expr() }
+            TokenType::If => { self.parse_if_else_expr() }
+            TokenType::Match => { self.parse_match_expr() }
 
             _ => panic!("Odottamaton tunnus löytyi jäsentämisen aikana! {:?}", self.at()),
         }
     }
 }
 
-// NOTE NEVER DELETE THE ORDER OF PRECEDENCE COMMENTS
\ No newline at end of file
+// NOTE NEVER DELETE THE ORDER OF PRECEDENCE COMMENTS
+// The variants a pattern matches whatever their fields hold
+fn covered_variants(pattern: &Pattern, covered: &mut Vec<(String, String)>) {
+    match pattern {
+        Pattern::Variant(variant) if variant.fields.iter().flatten().all(|field| field.is_irrefutable()) => {
+            covered.push((variant.enum_name.clone(), variant.variant.clone()));
+        }
+        Pattern::Alternatives(alternatives) => alternatives.iter().for_each(|p| covered_variants(p, covered)),
+        Pattern::Default(default) => covered_variants(&default.pattern, covered),
+        _ => {}
+    }
+}
@@ -0,0 +1,582 @@
+use std::any::TypeId;
+use std::collections::{ HashMap, HashSet };
+use std::path::Path;
+use crate::frontend::ast::*;
+
+// Runs after parsing. Every variable use gets a (depth, index) binding: how many functions out
+// the variable lives and its slot in that function's frame. Top-level names stay globals.
+// Undefined names and assignments to vakio are reported here, before anything runs.
+// Only the bytecode compiler uses the slots; the tree-walker still looks variables up by name,
+// and keeps an index of the names in large scopes instead.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    Local { depth: u32, index: u32 },
+    Global,
+}
+
+// The slots of one function, or of the blocks at the top level of a script
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    pub names: Vec<String>,
+    // None when declarations in different jos-branches disagree
+    pub constant: Vec<Option<bool>>,
+    // Read or written by a nested function
+    pub captured: Vec<bool>,
+}
+
+// Nodes are keyed by address, so a Resolution is only valid for the Program it was made from.
+// The type is part of the key, since a node and its first field share an address.
+type Key = (TypeId, usize);
+
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub errors: Vec<String>,
+    pub script: Layout,
+    functions: HashMap<Key, Layout>,
+    scopes: HashMap<Key, (u32, u32)>,
+    declarations: HashMap<Key, u32>,
+    uses: HashMap<Key, Binding>,
+}
+
+impl Resolution {
+    pub fn function(&self, declaration: &FunctionDeclaration) -> &Layout {
+        &self.functions[&key(declaration)]
+    }
+
+    // The slots a loop body, loop header or match arm clears when it is entered
+    pub fn scope<T: 'static>(&self, node: &T) -> (u32, u32) {
+        self.scopes.get(&key(node)).copied().unwrap_or((0, 0))
+    }
+
+    // The slot of a declared name; None for a global
+    pub fn slot(&self, name: &String) -> Option<u32> {
+        self.declarations.get(&key(name)).copied()
+    }
+
+    // Where an identifier, or the name of a shorthand property, is found
+    pub fn binding<T: 'static>(&self, node: &T) -> Binding {
+        self.uses.get(&key(node)).copied().unwrap_or(Binding::Global)
+    }
+}
+
+fn key<T: 'static>(node: &T) -> Key {
+    (TypeId::of::<T>(), node as *const T as usize)
+}
+
+// `globals` tells whether a name already exists outside the program, and whether it is a vakio
+pub fn resolve_program(program: &Program, globals: &dyn Fn(&str) -> Option<bool>) -> Resolution {
+    let mut top_level = HashMap::new();
+    for (name, constant) in declarations(&program.body) {
+        top_level
+            .entry(name)
+            .and_modify(|existing: &mut Option<bool>| if *existing != Some(constant) { *existing = None })
+            .or_insert(Some(constant));
+    }
+
+    let mut resolver = Resolver {
+        resolution: Resolution::default(),
+        functions: vec![FunctionScope::default()],
+        top_level,
+        globals,
+        line: 0,
+    };
+    program.body.iter().for_each(|stmt| resolver.resolve_stmt(stmt));
+
+    let mut resolution = resolver.resolution;
+    resolution.script = resolver.functions.pop().unwrap().layout;
+    let mut seen = HashSet::new();
+    resolution.errors.retain(|error| seen.insert(error.clone()));
+    resolution
+}
+
+struct Slot {
+    index: u32,
+    // Code after the declaration sees the slot; nested functions see it from the start of the scope
+    declared: bool,
+}
+
+#[derive(Default)]
+struct FunctionScope {
+    layout: Layout,
+    // The top level of a script has no scope: its names are globals
+    scopes: Vec<HashMap<String, Slot>>,
+}
+
+struct Resolver<'a> {
+    resolution: Resolution,
+    functions: Vec<FunctionScope>,
+    top_level: HashMap<String, Option<bool>>,
+    globals: &'a dyn Fn(&str) -> Option<bool>,
+    line: usize,
+}
+
+impl Resolver<'_> {
+    fn function(&mut self) -> &mut FunctionScope {
+        self.functions.last_mut().unwrap()
+    }
+
+    // Each name has one slot per scope, however many times it is declared there
+    fn allocate(&mut self, name: &str, constant: bool, declared: bool) -> u32 {
+        let function = self.functions.last_mut().unwrap();
+        let scope = function.scopes.last_mut().unwrap();
+        if let Some(slot) = scope.get_mut(name) {
+            slot.declared |= declared;
+            let existing = &mut function.layout.constant[slot.index as usize];
+            if *existing != Some(constant) {
+                *existing = None;
+            }
+            return slot.index;
+        }
+
+        let layout = &mut function.layout;
+        let index = layout.names.len() as u32;
+        layout.names.push(name.to_string());
+        layout.constant.push(Some(constant));
+        layout.captured.push(false);
+        scope.insert(name.to_string(), Slot { index, declared });
+        index
+    }
+
+    fn begin_scope<T: 'static>(&mut self, node: &T, names: Vec<(String, bool)>) {
+        self.function().scopes.push(HashMap::new());
+        let start = self.function().layout.names.len() as u32;
+        for (name, constant) in names {
+            self.allocate(&name, constant, false);
+        }
+        let end = self.function().layout.names.len() as u32;
+        self.resolution.scopes.insert(key(node), (start, end));
+    }
+
+    fn end_scope(&mut self) {
+        self.function().scopes.pop();
+    }
+
+    fn define(&mut self, name: &String, constant: bool) {
+        if let Some(index) = self.declare(name, constant) {
+            self.resolution.declarations.insert(key(name), index);
+        }
+    }
+
+    fn declare(&mut self, name: &str, constant: bool) -> Option<u32> {
+        if self.function().scopes.is_empty() {
+            return None;
+        }
+        Some(self.allocate(name, constant, true))
+    }
+
+    fn find(&self, depth: usize, name: &str) -> Option<u32> {
+        let positional = depth == self.functions.len() - 1;
+        self.functions[depth].scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).filter(|slot| slot.declared || !positional))
+            .map(|slot| slot.index)
+    }
+
+    // Resolves a use of `name`; `assigned` checks that it is not a vakio
+    fn resolve_name<T: 'static>(&mut self, node: &T, name: &str, assigned: bool) {
+        let current = self.functions.len() - 1;
+        let found = (0..=current).rev().find_map(|depth| self.find(depth, name).map(|index| (depth, index)));
+
+        let constant = match found {
+            Some((depth, index)) => {
+                let layout = &mut self.functions[depth].layout;
+                if depth < current {
+                    layout.captured[index as usize] = true;
+                }
+                let binding = Binding::Local { depth: (current - depth) as u32, index };
+                self.resolution.uses.insert(key(node), binding);
+                layout.constant[index as usize]
+            }
+            None => match self.top_level.get(name).copied().or_else(|| (self.globals)(name).map(Some)) {
+                Some(constant) => constant,
+                None => {
+                    self.error(format!("Kohdetta '{}' ei voida ratkaista, koska sitä ei ole olemassa.", name));
+                    return;
+                }
+            },
+        };
+
+        if assigned && constant == Some(true) {
+            self.error(format!("Ei voida määrittää uudelleen muuttujaa {}, koska se luotiin vakioksi.", name));
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.resolution.errors.push(format!("Rivi {}: {}", self.line, message));
+    }
+
+    fn resolve_block(&mut self, statements: &[Stmt]) {
+        statements.iter().for_each(|stmt| self.resolve_stmt(stmt));
+    }
+
+    // Loop bodies get a fresh scope on every iteration
+    fn resolve_scoped_block(&mut self, block: &Block) {
+        self.begin_scope(block, declarations(&block.statements));
+        self.resolve_block(&block.statements);
+        self.end_scope();
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        // Errors point at the statement they are found in
+        let outer = self.line;
+        if stmt.line() > 0 {
+            self.line = stmt.line();
+        }
+        match stmt {
+            Stmt::Program(program) => self.resolve_block(&program.body),
+            Stmt::VarDeclaration(declaration) => {
+                if let Some(value) = &declaration.value {
+                    self.resolve_expr(value);
+                }
+                self.resolve_pattern(&declaration.target);
+                self.define_pattern(&declaration.target, declaration.constant);
+            }
+            Stmt::FunctionDeclaration(declaration) => {
+                self.resolve_function(declaration, &[]);
+                self.define(&declaration.name, true);
+            }
+            Stmt::Expr(statement) => self.resolve_expr(&statement.expr),
+            Stmt::WhileLoop(while_loop) => {
+                self.resolve_expr(&while_loop.condition);
+                self.resolve_scoped_block(&while_loop.body);
+            }
+            Stmt::ForLoop(for_loop) => {
+                let mut names = declarations(std::slice::from_ref(&*for_loop.initializer));
+                collect_branch_declarations(&for_loop.condition, &mut names);
+                collect_branch_declarations(&for_loop.increment, &mut names);
+                self.begin_scope(for_loop, names);
+                self.resolve_stmt(&for_loop.initializer);
+                self.resolve_expr(&for_loop.condition);
+                self.resolve_scoped_block(&for_loop.body);
+                self.resolve_expr(&for_loop.increment);
+                self.end_scope();
+            }
+            Stmt::ForEachLoop(for_each) => {
+                self.resolve_expr(&for_each.iterable);
+                let mut names: Vec<(String, bool)> = for_each.target
+                    .bound_names()
+                    .into_iter()
+                    .map(|name| (name, for_each.constant))
+                    .collect();
+                names.extend(declarations(&for_each.body.statements));
+                self.begin_scope(for_each, names);
+                self.resolve_pattern(&for_each.target);
+                self.define_pattern(&for_each.target, for_each.constant);
+                self.resolve_block(&for_each.body.statements);
+                self.end_scope();
+            }
+            Stmt::ClassDeclaration(declaration) => {
+                if let Some(parent) = &declaration.parent {
+                    self.resolve_name(parent, parent, false);
+                }
+                for field in declaration.static_fields.iter().chain(&declaration.fields) {
+                    if let Some(value) = &field.value {
+                        self.resolve_expr(value);
+                    }
+                }
+                declaration.static_methods.iter().for_each(|method| self.resolve_function(method, &[]));
+                // Methods run with tämä, and in subclasses yli, declared in their call scope
+                let receiver: &[&str] = if declaration.parent.is_some() { &["tämä", "yli"] } else { &["tämä"] };
+                for method in declaration.constructor.iter().chain(&declaration.methods) {
+                    self.resolve_function(method, receiver);
+                }
+                self.define(&declaration.name, true);
+            }
+            Stmt::EnumDeclaration(declaration) => self.define(&declaration.name, true),
+            Stmt::Import(import) => match &import.names {
+                Some(names) => {
+                    let pattern = Pattern::Object(names.clone());
+                    self.resolve_pattern(&pattern);
+                    for property in &names.properties {
+                        self.define_pattern(&property.pattern, true);
+                    }
+                }
+                None => {
+                    let name = import.alias.clone().unwrap_or_else(|| module_stem(&import.path));
+                    self.declare(&name, true);
+                }
+            },
+            Stmt::Export(export) => match &export.declaration {
+                Some(declaration) => self.resolve_stmt(declaration),
+                None => export.names.iter().for_each(|name| self.resolve_name(name, name, false)),
+            },
+        }
+        self.line = outer;
+    }
+
+    // Parameters come first in the frame, in order, then the rest parameter
+    fn resolve_function(&mut self, declaration: &FunctionDeclaration, receiver: &[&str]) {
+        self.functions.push(FunctionScope::default());
+        self.function().scopes.push(HashMap::new());
+
+        for parameter in &declaration.parameters {
+            for name in parameter.bound_names() {
+                self.allocate(&name, false, false);
+            }
+        }
+        if let Some(rest) = &declaration.rest {
+            self.allocate(rest, false, false);
+        }
+        for name in receiver {
+            self.allocate(name, true, true);
+        }
+        for (name, constant) in declarations(&declaration.body) {
+            self.allocate(&name, constant, false);
+        }
+
+        // Defaults run in order, so they see only the parameters before them
+        for parameter in &declaration.parameters {
+            self.resolve_pattern(parameter);
+            self.define_pattern(parameter, false);
+        }
+        if let Some(rest) = &declaration.rest {
+            self.define(rest, false);
+        }
+        self.resolve_block(&declaration.body);
+
+        let function = self.functions.pop().unwrap();
+        self.resolution.functions.insert(key(declaration), function.layout);
+    }
+
+    fn define_pattern(&mut self, pattern: &Pattern, constant: bool) {
+        match pattern {
+            Pattern::Binding(name) => self.define(name, constant),
+            Pattern::Array(array) => {
+                array.elements.iter().for_each(|element| self.define_pattern(element, constant));
+                if let Some(rest) = array.rest.as_ref().filter(|rest| *rest != "_") {
+                    self.define(rest, constant);
+                }
+            }
+            Pattern::Object(object) => object.properties.iter().for_each(|property| self.define_pattern(&property.pattern, constant)),
+            Pattern::Default(default) => self.define_pattern(&default.pattern, constant),
+            Pattern::Alternatives(alternatives) => {
+                if let Some(first) = alternatives.first() {
+                    self.define_pattern(first, constant);
+                }
+            }
+            Pattern::Variant(variant) => variant.fields.iter().flatten().for_each(|field| self.define_pattern(field, constant)),
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Range(_) => (),
+        }
+    }
+
+    // The expressions inside a pattern run before its names are declared
+    fn resolve_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Literal(expr) => self.resolve_expr(expr),
+            Pattern::Range(range) => {
+                self.resolve_expr(&range.start);
+                self.resolve_expr(&range.end);
+            }
+            Pattern::Array(array) => array.elements.iter().for_each(|element| self.resolve_pattern(element)),
+            Pattern::Object(object) => object.properties.iter().for_each(|property| self.resolve_pattern(&property.pattern)),
+            Pattern::Alternatives(alternatives) => alternatives.iter().for_each(|alternative| self.resolve_pattern(alternative)),
+            Pattern::Default(default) => {
+                self.resolve_pattern(&default.pattern);
+                self.resolve_expr(&default.default);
+            }
+            Pattern::Variant(variant) => {
+                self.resolve_name(variant, &variant.enum_name, false);
+                variant.fields.iter().flatten().for_each(|field| self.resolve_pattern(field));
+            }
+            Pattern::Wildcard | Pattern::Binding(_) => (),
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(identifier) => self.resolve_name(identifier, &identifier.symbol, false),
+            Expr::AssignmentExpr(assignment) => {
+                self.resolve_assignee(&assignment.assignee);
+                self.resolve_expr(&assignment.value);
+            }
+            Expr::CompoundAssignmentExpr(assignment) => {
+                self.resolve_assignee(&assignment.assignee);
+                self.resolve_expr(&assignment.value);
+            }
+            Expr::UpdateExpr(update) => self.resolve_assignee(&update.assignee),
+            Expr::MemberExpr(member) => {
+                self.resolve_expr(&member.object);
+                if member.computed {
+                    self.resolve_expr(&member.property);
+                }
+            }
+            Expr::CallExpr(call) => {
+                for argument in &call.args {
+                    match argument {
+                        Argument::Positional(expr) | Argument::Named(_, expr) | Argument::Spread(expr) => self.resolve_expr(expr),
+                    }
+                }
+                self.resolve_expr(&call.caller);
+            }
+            // The branches share the enclosing scope, so their names were given slots with it
+            Expr::IfElseExpr(if_else) => {
+                self.resolve_expr(&if_else.condition);
+                self.resolve_block(&if_else.if_branch.statements);
+                if let Some(else_branch) = &if_else.else_branch {
+                    self.resolve_block(&else_branch.statements);
+                }
+            }
+            Expr::ConditionalExpr(conditional) => {
+                self.resolve_expr(&conditional.condition);
+                self.resolve_expr(&conditional.consequent);
+                self.resolve_expr(&conditional.alternate);
+            }
+            Expr::MatchExpr(match_expr) => {
+                self.resolve_expr(&match_expr.subject);
+                for arm in &match_expr.arms {
+                    if let Some(pattern) = &arm.pattern {
+                        self.resolve_pattern(pattern);
+                    }
+                    // Bindings live in the arm's scope, shared by the guard and the body
+                    let mut names: Vec<(String, bool)> = arm.pattern
+                        .iter()
+                        .flat_map(|pattern| pattern.bound_names())
+                        .map(|name| (name, false))
+                        .collect();
+                    names.extend(declarations(&arm.body.statements));
+                    self.begin_scope(arm, names);
+                    if let Some(pattern) = &arm.pattern {
+                        self.define_pattern(pattern, false);
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.resolve_expr(guard);
+                    }
+                    self.resolve_block(&arm.body.statements);
+                    self.end_scope();
+                }
+            }
+            Expr::Property(property) => self.resolve_property(property),
+            Expr::ObjectLiteral(object) => object.properties.iter().for_each(|property| self.resolve_property(property)),
+            Expr::ArrayLiteral(array) => array.elements.iter().for_each(|element| self.resolve_expr(element)),
+            Expr::TemplateLiteral(template) => template.parts.iter().for_each(|part| self.resolve_expr(part)),
+            Expr::BinaryExpr(binary) => {
+                self.resolve_expr(&binary.left);
+                self.resolve_expr(&binary.right);
+            }
+            Expr::LogicalExpr(logical) => {
+                self.resolve_expr(&logical.left);
+                self.resolve_expr(&logical.right);
+            }
+            Expr::UnaryExpr(unary) => self.resolve_expr(&unary.operand),
+            Expr::NumericLiteral(_) | Expr::IntegerLiteral(_) | Expr::StringLiteral(_) | Expr::FloatLiteral(_) => (),
+        }
+    }
+
+    // { nimi } reads the variable nimi
+    fn resolve_property(&mut self, property: &Property) {
+        match &property.value {
+            Some(value) => self.resolve_expr(value),
+            None => self.resolve_name(property, &property.key, false),
+        }
+    }
+
+    // Only a bare name is reassigned; a member of a vakio may still change in place
+    fn resolve_assignee(&mut self, assignee: &Expr) {
+        match assignee {
+            Expr::Identifier(identifier) => self.resolve_name(identifier, &identifier.symbol, true),
+            other => self.resolve_expr(other),
+        }
+    }
+}
+
+// Names declared directly in a block, including inside jos-branches, which share the block's scope
+pub(crate) fn declarations(statements: &[Stmt]) -> Vec<(String, bool)> {
+    let mut names = Vec::new();
+    for statement in statements {
+        collect_declarations(statement, &mut names);
+    }
+    names
+}
+
+fn collect_declarations(statement: &Stmt, names: &mut Vec<(String, bool)>) {
+    match statement {
+        Stmt::Program(program) => program.body.iter().for_each(|stmt| collect_declarations(stmt, names)),
+        Stmt::VarDeclaration(declaration) => {
+            if let Some(value) = &declaration.value {
+                collect_branch_declarations(value, names);
+            }
+            names.extend(declaration.target.bound_names().into_iter().map(|name| (name, declaration.constant)));
+        }
+        Stmt::FunctionDeclaration(declaration) => names.push((declaration.name.clone(), true)),
+        Stmt::ClassDeclaration(declaration) => names.push((declaration.name.clone(), true)),
+        Stmt::EnumDeclaration(declaration) => names.push((declaration.name.clone(), true)),
+        Stmt::Import(import) => match (&import.names, &import.alias) {
+            (Some(pattern), _) => names.extend(Pattern::Object(pattern.clone()).bound_names().into_iter().map(|name| (name, true))),
+            (None, Some(alias)) => names.push((alias.clone(), true)),
+            (None, None) => names.push((module_stem(&import.path), true)),
+        },
+        Stmt::Export(export) => {
+            if let Some(declaration) = &export.declaration {
+                collect_declarations(declaration, names);
+            }
+        }
+        Stmt::Expr(statement) => collect_branch_declarations(&statement.expr, names),
+        Stmt::WhileLoop(while_loop) => collect_branch_declarations(&while_loop.condition, names),
+        Stmt::ForEachLoop(for_each) => collect_branch_declarations(&for_each.iterable, names),
+        Stmt::ForLoop(_) => (),
+    }
+}
+
+// `tuo "polku/moduuli.ka"` binds the file name without its extension
+pub(crate) fn module_stem(path: &str) -> String {
+    Path::new(path).file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+fn collect_branch_declarations(expr: &Expr, names: &mut Vec<(String, bool)>) {
+    match expr {
+        Expr::IfElseExpr(if_else) => {
+            collect_branch_declarations(&if_else.condition, names);
+            if_else.if_branch.statements.iter().for_each(|stmt| collect_declarations(stmt, names));
+            if let Some(else_branch) = &if_else.else_branch {
+                else_branch.statements.iter().for_each(|stmt| collect_declarations(stmt, names));
+            }
+        }
+        Expr::AssignmentExpr(assignment) => {
+            collect_branch_declarations(&assignment.assignee, names);
+            collect_branch_declarations(&assignment.value, names);
+        }
+        Expr::CompoundAssignmentExpr(assignment) => {
+            collect_branch_declarations(&assignment.assignee, names);
+            collect_branch_declarations(&assignment.value, names);
+        }
+        Expr::UpdateExpr(update) => collect_branch_declarations(&update.assignee, names),
+        Expr::MemberExpr(member) => {
+            collect_branch_declarations(&member.object, names);
+            collect_branch_declarations(&member.property, names);
+        }
+        Expr::CallExpr(call) => {
+            for argument in &call.args {
+                match argument {
+                    Argument::Positional(expr) | Argument::Named(_, expr) | Argument::Spread(expr) => collect_branch_declarations(expr, names),
+                }
+            }
+            collect_branch_declarations(&call.caller, names);
+        }
+        Expr::ConditionalExpr(conditional) => {
+            collect_branch_declarations(&conditional.condition, names);
+            collect_branch_declarations(&conditional.consequent, names);
+            collect_branch_declarations(&conditional.alternate, names);
+        }
+        Expr::MatchExpr(match_expr) => collect_branch_declarations(&match_expr.subject, names),
+        Expr::ObjectLiteral(object) => object.properties.iter().flat_map(|p| p.value.as_deref()).for_each(|value| collect_branch_declarations(value, names)),
+        Expr::ArrayLiteral(array) => array.elements.iter().for_each(|element| collect_branch_declarations(element, names)),
+        Expr::TemplateLiteral(template) => template.parts.iter().for_each(|part| collect_branch_declarations(part, names)),
+        Expr::BinaryExpr(binary) => {
+            collect_branch_declarations(&binary.left, names);
+            collect_branch_declarations(&binary.right, names);
+        }
+        Expr::LogicalExpr(logical) => {
+            collect_branch_declarations(&logical.left, names);
+            collect_branch_declarations(&logical.right, names);
+        }
+        Expr::UnaryExpr(unary) => collect_branch_declarations(&unary.operand, names),
+        Expr::Property(_) |
+        Expr::NumericLiteral(_) |
+        Expr::IntegerLiteral(_) |
+        Expr::StringLiteral(_) |
+        Expr::FloatLiteral(_) |
+        Expr::Identifier(_) => (),
+    }
+}
@@ -0,0 +1,729 @@
+use std::collections::HashMap;
+use crate::frontend::lexer::*;
+use crate::frontend::resolver::module_stem;
+
+// Declarations and uses of names, found from the tokens alone so that code that does not parse yet
+// can still be navigated. Scopes follow the resolver: functions, loops and match arms have their
+// own, jos-branches share the enclosing one, and top-level names are visible everywhere.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Variable,
+    Constant,
+    Parameter,
+    Function,
+    Class,
+    Method,
+    Constructor,
+    Field,
+    Enum,
+    Variant,
+    Module,
+}
+
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub kind: SymbolKind,
+    // The name, where a rename edits it; an unnamed tuo binds the module's file name at its path
+    pub line: usize,
+    pub column: usize,
+    // How the declaration reads, e.g. "funktio summa(a, b)"
+    pub detail: String,
+    // The function, class or enum the definition is inside
+    pub container: Option<usize>,
+    // From the declaring keyword to the closing brace of the body, or to the end of the name
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    // A definition of the same name in an enclosing scope that this one hides
+    pub shadows: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Declaration,
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone)]
+pub struct Occurrence {
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+    pub access: Access,
+    // None when the name is not declared in the file, e.g. a built-in
+    pub definition: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    pub definitions: Vec<Definition>,
+    pub occurrences: Vec<Occurrence>,
+}
+
+impl SymbolIndex {
+    // The occurrence whose name covers the line and column, counted from 1 like tokens
+    pub fn occurrence_at(&self, line: usize, column: usize) -> Option<&Occurrence> {
+        self.occurrences.iter().find(|occurrence| {
+            occurrence.line == line && (occurrence.column..=occurrence.column + occurrence.name.chars().count()).contains(&column)
+        })
+    }
+
+    pub fn occurrences_of(&self, definition: usize) -> impl Iterator<Item = &Occurrence> {
+        self.occurrences.iter().filter(move |occurrence| occurrence.definition == Some(definition))
+    }
+}
+
+pub fn index_symbols(tokens: &[Token]) -> SymbolIndex {
+    let mut indexer = Indexer {
+        tokens,
+        marks: vec![Mark::None; tokens.len()],
+        scopes: vec![Scope { kind: ScopeKind::Root, parent: None, function: 0, names: HashMap::new(), owner: None }],
+        open: vec![Open { scope: 0, end: End::Brace }],
+        brackets: Vec::new(),
+        branch: None,
+        functions: 0,
+        definitions: Vec::new(),
+        first_tokens: Vec::new(),
+        occurrences: Vec::new(),
+        uses: Vec::new(),
+    };
+    for index in 0..tokens.len() {
+        indexer.visit(index);
+    }
+    indexer.finish()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    None,
+    // A name a pattern binds, with the keyword its hover text starts with
+    Binding(SymbolKind, &'static str),
+    // A class member or enum variant, which is not a variable
+    Member(SymbolKind),
+    // An object key or a variant's field name
+    Key,
+    // The braces of an object pattern, which do not open a scope
+    PatternBrace,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ScopeKind {
+    Root,
+    Block,
+    // A jos-branch: declarations go to the enclosing scope
+    Branch,
+    Function,
+    // Class and enum bodies hold members, which are not visible as variables
+    Members,
+    Arm,
+}
+
+struct Scope {
+    kind: ScopeKind,
+    parent: Option<usize>,
+    function: usize,
+    names: HashMap<String, usize>,
+    // The declaration the scope is the body of
+    owner: Option<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum End {
+    // Closed by the brace that opened it
+    Brace,
+    // Opened before its body, e.g. at `funktio`; the next '{' at this bracket depth becomes the body
+    Header(usize),
+    // A match arm whose body is a bare expression ends at ',' or at the next arm
+    Arm(usize),
+}
+
+struct Open {
+    scope: usize,
+    end: End,
+}
+
+struct Use {
+    occurrence: usize,
+    scope: usize,
+    function: usize,
+    token: usize,
+}
+
+struct Indexer<'t, 'a> {
+    tokens: &'t [Token<'a>],
+    marks: Vec<Mark>,
+    scopes: Vec<Scope>,
+    open: Vec<Open>,
+    // One entry per open bracket: whether its '}' closes a scope
+    brackets: Vec<bool>,
+    // The bracket depth of a `jos` whose branch has not started yet
+    branch: Option<usize>,
+    functions: usize,
+    definitions: Vec<Definition>,
+    // The token each definition was first declared at
+    first_tokens: Vec<usize>,
+    occurrences: Vec<Occurrence>,
+    uses: Vec<Use>,
+}
+
+impl<'t, 'a> Indexer<'t, 'a> {
+    fn token(&self, index: usize) -> Option<&'t Token<'a>> {
+        self.tokens.get(index)
+    }
+
+    fn token_type(&self, index: usize) -> Option<TokenType> {
+        self.token(index).map(|token| token.token_type)
+    }
+
+    fn is_operator(&self, index: usize, operator: &str) -> bool {
+        self.token(index).is_some_and(|token| token.token_type == TokenType::BinaryOperator && token.value == operator)
+    }
+
+    fn top(&self) -> &Open {
+        self.open.last().unwrap()
+    }
+
+    fn open_scope(&mut self, kind: ScopeKind, end: End, owner: Option<usize>) {
+        let parent = self.top().scope;
+        let function = match kind {
+            ScopeKind::Function => {
+                self.functions += 1;
+                self.functions
+            }
+            _ => self.scopes[parent].function,
+        };
+        self.scopes.push(Scope { kind, parent: Some(parent), function, names: HashMap::new(), owner });
+        self.open.push(Open { scope: self.scopes.len() - 1, end });
+    }
+
+    fn close_scope(&mut self, index: usize) {
+        if self.open.len() == 1 {
+            return;
+        }
+        let open = self.open.pop().unwrap();
+        if let (Some(owner), Some(token)) = (self.scopes[open.scope].owner, self.token(index)) {
+            self.definitions[owner].end = (token.line, token.column + 1);
+        }
+    }
+
+    // Bare arm bodies at this depth end here
+    fn close_arms(&mut self, depth: usize, index: usize) {
+        while self.top().end == End::Arm(depth) {
+            self.close_scope(index);
+        }
+    }
+
+    // A closing bracket ends the scopes opened inside it that are still waiting for, or running, a bare body
+    fn close_unfinished(&mut self, depth: usize, index: usize) {
+        while let End::Header(opened) | End::Arm(opened) = self.top().end {
+            if opened < depth || self.open.len() == 1 {
+                break;
+            }
+            self.close_scope(index);
+        }
+    }
+
+    fn in_members(&self) -> bool {
+        self.top().end == End::Brace && self.scopes[self.top().scope].kind == ScopeKind::Members
+    }
+
+    // The scope declarations go to: jos-branches share the one around them
+    fn declaring_scope(&self) -> usize {
+        let mut scope = self.top().scope;
+        while self.scopes[scope].kind == ScopeKind::Branch {
+            scope = self.scopes[scope].parent.unwrap();
+        }
+        scope
+    }
+
+    // The enclosing function, class or enum
+    fn container(&self) -> Option<usize> {
+        self.open.iter().rev().find_map(|open| self.scopes[open.scope].owner)
+    }
+
+    fn define(&mut self, index: usize, name: String, kind: SymbolKind, detail: String, start: usize) -> usize {
+        let token = &self.tokens[index];
+        let (line, column) = (token.line, token.column);
+        let scope = self.declaring_scope();
+        let member = matches!(kind, SymbolKind::Field | SymbolKind::Method | SymbolKind::Constructor | SymbolKind::Variant);
+
+        // Each name has one definition per scope, however many times it is declared there
+        if let Some(&definition) = self.scopes[scope].names.get(&name).filter(|_| !member) {
+            self.occurrences.push(Occurrence { name, line, column, access: Access::Declaration, definition: Some(definition) });
+            return definition;
+        }
+
+        let shadows = if member { None } else { self.outer_definition(scope, &name, index) };
+        let definition = self.definitions.len();
+        let start = &self.tokens[start];
+        self.definitions.push(Definition {
+            end: (line, column + name.chars().count()),
+            start: (start.line, start.column),
+            name: name.clone(),
+            kind,
+            line,
+            column,
+            detail,
+            container: self.container(),
+            shadows,
+        });
+        self.first_tokens.push(index);
+        if !member {
+            self.scopes[scope].names.insert(name.clone(), definition);
+        }
+        self.occurrences.push(Occurrence { name, line, column, access: Access::Declaration, definition: Some(definition) });
+        definition
+    }
+
+    // The definition a name declared in `scope` at `index` would otherwise refer to
+    fn outer_definition(&self, scope: usize, name: &str, index: usize) -> Option<usize> {
+        let function = self.scopes[scope].function;
+        let mut outer = self.scopes[scope].parent;
+        while let Some(id) = outer {
+            let current = &self.scopes[id];
+            if let Some(&definition) = current.names.get(name).filter(|_| current.kind != ScopeKind::Members) {
+                let positional = current.function == function && current.kind != ScopeKind::Root;
+                if !positional || self.first_tokens[definition] < index {
+                    return Some(definition);
+                }
+            }
+            outer = current.parent;
+        }
+        None
+    }
+
+    fn visit(&mut self, index: usize) {
+        let token = &self.tokens[index];
+        let depth = self.brackets.len();
+
+        match self.marks[index] {
+            Mark::Binding(kind, keyword) => {
+                let name = token.value.to_string();
+                let mut detail = format!("{} {}", keyword, name);
+                // A vakio with a single literal shows its value
+                if let (Some(TokenType::Assign), Some(value), Some(TokenType::SemiColon)) =
+                    (self.token_type(index + 1), self.token(index + 2), self.token_type(index + 3))
+                {
+                    if matches!(value.token_type, TokenType::Integer | TokenType::Float | TokenType::TypedInteger | TokenType::StringLiteral) {
+                        detail = format!("{} = {}", detail, render(std::slice::from_ref(value)));
+                    }
+                }
+                self.define(index, name, kind, detail, index);
+                return;
+            }
+            Mark::Member(kind) => {
+                let name = token.value.to_string();
+                let owner = self.container().map(|owner| self.definitions[owner].name.clone()).unwrap_or_default();
+                let detail = match kind {
+                    SymbolKind::Variant => {
+                        let fields = match self.token_type(index + 1) {
+                            Some(TokenType::OpenParen) => render(&self.tokens[index + 1..=self.closing(index + 1).min(self.tokens.len() - 1)]),
+                            _ => String::new(),
+                        };
+                        format!("{}.{}{}", owner, name, fields)
+                    }
+                    _ => format!("olkoon {}.{}", owner, name),
+                };
+                let start = match kind {
+                    SymbolKind::Variant => index,
+                    _ if self.token_type(index.wrapping_sub(2)) == Some(TokenType::Static) => index - 2,
+                    _ => index - 1,
+                };
+                self.define(index, name, kind, detail, start);
+                return;
+            }
+            Mark::Key => return,
+            Mark::PatternBrace => {
+                if token.token_type == TokenType::OpenBrace {
+                    self.brackets.push(false);
+                } else {
+                    self.brackets.pop();
+                }
+                return;
+            }
+            Mark::None => (),
+        }
+
+        match token.token_type {
+            TokenType::Let | TokenType::Const => {
+                if self.in_members() {
+                    if self.token_type(index + 1) == Some(TokenType::Identifier) {
+                        self.marks[index + 1] = Mark::Member(SymbolKind::Field);
+                    }
+                } else if token.token_type == TokenType::Let {
+                    self.mark_pattern(index + 1, SymbolKind::Variable, "olkoon");
+                } else {
+                    self.mark_pattern(index + 1, SymbolKind::Constant, "vakio");
+                }
+            }
+            TokenType::Fn | TokenType::Constructor => self.visit_function(index),
+            TokenType::Class => {
+                let Some(name) = self.token(index + 1).filter(|name| name.token_type == TokenType::Identifier) else { return };
+                let mut detail = format!("luokka {}", name.value);
+                if self.token_type(index + 2) == Some(TokenType::Extends) {
+                    if let Some(parent) = self.token(index + 3) {
+                        detail = format!("{} laajentaa {}", detail, parent.value);
+                    }
+                }
+                self.marks[index + 1] = Mark::Key;
+                let class = self.define(index + 1, name.value.to_string(), SymbolKind::Class, detail, index);
+                self.open_scope(ScopeKind::Members, End::Header(depth), Some(class));
+            }
+            TokenType::Enum => {
+                let Some(name) = self.token(index + 1).filter(|name| name.token_type == TokenType::Identifier) else { return };
+                self.marks[index + 1] = Mark::Key;
+                let detail = format!("luettelo {}", name.value);
+                let enumeration = self.define(index + 1, name.value.to_string(), SymbolKind::Enum, detail, index);
+                self.open_scope(ScopeKind::Members, End::Header(depth), Some(enumeration));
+
+                // Variants are the names directly inside the braces; the names in their parentheses are fields
+                if self.token_type(index + 2) == Some(TokenType::OpenBrace) {
+                    let mut nesting = 0;
+                    for variant in index + 3..self.tokens.len() {
+                        match self.tokens[variant].token_type {
+                            TokenType::OpenParen => nesting += 1,
+                            TokenType::CloseParen => nesting -= 1,
+                            TokenType::CloseBrace => break,
+                            TokenType::Identifier if nesting == 0 => self.marks[variant] = Mark::Member(SymbolKind::Variant),
+                            TokenType::Identifier => self.marks[variant] = Mark::Key,
+                            _ => (),
+                        }
+                    }
+                }
+            }
+            TokenType::Import => match self.token_type(index + 1) {
+                Some(TokenType::OpenBrace) => {
+                    self.mark_pattern(index + 1, SymbolKind::Constant, "tuo");
+                }
+                Some(TokenType::StringLiteral) => {
+                    let path = self.tokens[index + 1].value.to_string();
+                    let detail = format!("tuo \"{}\"", path);
+                    match (self.token_type(index + 2), self.token(index + 3)) {
+                        (Some(TokenType::As), Some(alias)) if alias.token_type == TokenType::Identifier => {
+                            self.marks[index + 3] = Mark::Key;
+                            let detail = format!("{} nimellä {}", detail, alias.value);
+                            self.define(index + 3, alias.value.to_string(), SymbolKind::Module, detail, index);
+                        }
+                        _ => {
+                            self.define(index + 1, module_stem(&path), SymbolKind::Module, detail, index);
+                        }
+                    }
+                }
+                _ => (),
+            },
+            // vie { a, b } lists names, it does not open a scope
+            TokenType::Export if self.token_type(index + 1) == Some(TokenType::OpenBrace) => {
+                let close = self.closing(index + 1);
+                self.marks[index + 1] = Mark::PatternBrace;
+                if close < self.tokens.len() {
+                    self.marks[close] = Mark::PatternBrace;
+                }
+            }
+            TokenType::For => self.open_scope(ScopeKind::Block, End::Header(depth), None),
+            // A jos in a match arm's head is its guard
+            TokenType::If if !matches!(self.top().end, End::Header(_)) || self.scopes[self.top().scope].kind != ScopeKind::Arm => {
+                self.branch = Some(depth);
+            }
+            TokenType::Else => match self.token_type(index + 1) {
+                Some(TokenType::OpenBrace) => self.branch = Some(depth),
+                Some(TokenType::FatArrow) => {
+                    self.close_arms(depth, index);
+                    self.open_scope(ScopeKind::Arm, End::Header(depth), None);
+                }
+                _ => (),
+            },
+            TokenType::Case => {
+                self.close_arms(depth, index);
+                self.open_scope(ScopeKind::Arm, End::Header(depth), None);
+                self.mark_pattern(index + 1, SymbolKind::Variable, "tapaus");
+            }
+            TokenType::FatArrow => {
+                let top = self.top();
+                if top.end == End::Header(depth) && self.scopes[top.scope].kind == ScopeKind::Arm
+                    && self.token_type(index + 1) != Some(TokenType::OpenBrace)
+                {
+                    self.open.last_mut().unwrap().end = End::Arm(depth);
+                }
+            }
+            TokenType::Comma if self.top().end == End::Arm(depth) => self.close_scope(index),
+            TokenType::OpenParen | TokenType::OpenBracket | TokenType::OptionalBracket | TokenType::InterpolationStart => {
+                self.brackets.push(false);
+            }
+            TokenType::CloseParen | TokenType::CloseBracket => {
+                self.close_unfinished(depth, index);
+                self.brackets.pop();
+            }
+            TokenType::OpenBrace => {
+                if self.top().end == End::Header(depth) {
+                    self.open.last_mut().unwrap().end = End::Brace;
+                } else if self.branch == Some(depth) {
+                    self.branch = None;
+                    self.open_scope(ScopeKind::Branch, End::Brace, None);
+                } else {
+                    self.open_scope(ScopeKind::Block, End::Brace, None);
+                }
+                self.brackets.push(true);
+            }
+            TokenType::CloseBrace => {
+                self.close_unfinished(depth, index);
+                if self.brackets.pop() == Some(true) {
+                    self.close_scope(index);
+                }
+            }
+            TokenType::Identifier => self.visit_use(index),
+            _ => (),
+        }
+    }
+
+    // funktio nimi(a, b = 1, ...loput) and rakentaja(a); the parameters live in the function's scope
+    fn visit_function(&mut self, index: usize) {
+        let depth = self.brackets.len();
+        let constructor = self.tokens[index].token_type == TokenType::Constructor;
+        let (name_index, open) = if constructor { (index, index + 1) } else { (index + 1, index + 2) };
+        let Some(name) = self.token(name_index) else { return };
+        if !constructor && name.token_type != TokenType::Identifier {
+            return;
+        }
+        let close = self.closing(open);
+        let parameters = match self.token_type(open) {
+            Some(TokenType::OpenParen) => render(&self.tokens[open..=close.min(self.tokens.len() - 1)]),
+            _ => String::new(),
+        };
+
+        let member = self.in_members();
+        let owner = self.container().map(|owner| self.definitions[owner].name.clone()).unwrap_or_default();
+        let is_static = index > 0 && self.tokens[index - 1].token_type == TokenType::Static;
+        let (kind, detail) = if constructor {
+            (SymbolKind::Constructor, format!("rakentaja {}{}", owner, parameters))
+        } else if member {
+            let prefix = if is_static { "staattinen " } else { "" };
+            (SymbolKind::Method, format!("{}funktio {}.{}{}", prefix, owner, name.value, parameters))
+        } else {
+            (SymbolKind::Function, format!("funktio {}{}", name.value, parameters))
+        };
+        if !constructor {
+            self.marks[name_index] = Mark::Key;
+        }
+        let start = if is_static { index - 1 } else { index };
+        let function = self.define(name_index, name.value.to_string(), kind, detail, start);
+        self.open_scope(ScopeKind::Function, End::Header(depth), Some(function));
+
+        if self.token_type(open) != Some(TokenType::OpenParen) {
+            return;
+        }
+        let mut parameter = open + 1;
+        while parameter < close {
+            if self.token_type(parameter) == Some(TokenType::Ellipsis) {
+                self.mark_binding(parameter + 1, SymbolKind::Parameter, "parametri");
+                parameter += 2;
+            } else {
+                parameter = self.mark_pattern(parameter, SymbolKind::Parameter, "parametri");
+                parameter = self.skip_default(parameter);
+            }
+            if self.token_type(parameter) == Some(TokenType::Comma) {
+                parameter += 1;
+            } else if parameter < close {
+                // Not a parameter list the parser would accept; keep going without bindings
+                break;
+            }
+        }
+    }
+
+    fn visit_use(&mut self, index: usize) {
+        let previous = index.checked_sub(1).and_then(|previous| self.token_type(previous));
+        let next = self.token_type(index + 1);
+        // Properties after '.', and keys of object literals and named arguments
+        if matches!(previous, Some(TokenType::Dot | TokenType::OptionalDot)) {
+            return;
+        }
+        if next == Some(TokenType::Colon) && matches!(previous, Some(TokenType::OpenBrace | TokenType::Comma | TokenType::OpenParen)) {
+            return;
+        }
+
+        let written = matches!(
+            next,
+            Some(
+                TokenType::Assign | TokenType::PlusEqual | TokenType::MinusEqual | TokenType::TimesEqual |
+                TokenType::DivideEqual | TokenType::ModulusEqual | TokenType::ExponentEqual | TokenType::NullishEqual |
+                TokenType::Increment | TokenType::Decrement
+            )
+        ) || matches!(previous, Some(TokenType::Increment | TokenType::Decrement));
+        let token = &self.tokens[index];
+        self.occurrences.push(Occurrence {
+            name: token.value.to_string(),
+            line: token.line,
+            column: token.column,
+            access: if written { Access::Write } else { Access::Read },
+            definition: None,
+        });
+        let scope = self.top().scope;
+        self.uses.push(Use { occurrence: self.occurrences.len() - 1, scope, function: self.scopes[scope].function, token: index });
+    }
+
+    fn mark_binding(&mut self, index: usize, kind: SymbolKind, keyword: &'static str) {
+        if self.token(index).is_some_and(|token| token.token_type == TokenType::Identifier && token.value != "_") {
+            self.marks[index] = Mark::Binding(kind, keyword);
+        }
+    }
+
+    // Marks the names a pattern binds and returns the index after it
+    fn mark_pattern(&mut self, index: usize, kind: SymbolKind, keyword: &'static str) -> usize {
+        let mut end = self.mark_single_pattern(index, kind, keyword);
+        while self.is_operator(end, "|") {
+            end = self.mark_single_pattern(end + 1, kind, keyword);
+        }
+        end
+    }
+
+    fn mark_single_pattern(&mut self, index: usize, kind: SymbolKind, keyword: &'static str) -> usize {
+        match self.token_type(index) {
+            // Muoto.Ympyrä(r): the enum is used, the fields are bound
+            Some(TokenType::Identifier) if self.token_type(index + 1) == Some(TokenType::Dot) => {
+                let mut end = index + 3;
+                if self.token_type(end) == Some(TokenType::OpenParen) {
+                    end += 1;
+                    while end < self.tokens.len() && self.token_type(end) != Some(TokenType::CloseParen) {
+                        let next = self.mark_pattern(end, kind, keyword);
+                        end = if self.token_type(next) == Some(TokenType::Comma) { next + 1 } else { next.max(end + 1) };
+                    }
+                    end += 1;
+                }
+                end
+            }
+            Some(TokenType::Identifier) => {
+                if !matches!(self.tokens[index].value.as_ref(), "tosi" | "epätosi" | "tyhjä") {
+                    self.mark_binding(index, kind, keyword);
+                }
+                index + 1
+            }
+            Some(TokenType::OpenBracket) => {
+                let mut end = index + 1;
+                while end < self.tokens.len() && self.token_type(end) != Some(TokenType::CloseBracket) {
+                    let next = if self.token_type(end) == Some(TokenType::Ellipsis) {
+                        self.mark_binding(end + 1, kind, keyword);
+                        end + 2
+                    } else {
+                        let element = self.mark_pattern(end, kind, keyword);
+                        self.skip_default(element)
+                    };
+                    end = if self.token_type(next) == Some(TokenType::Comma) { next + 1 } else { next.max(end + 1) };
+                }
+                end + 1
+            }
+            Some(TokenType::OpenBrace) => {
+                self.marks[index] = Mark::PatternBrace;
+                let mut end = index + 1;
+                while end < self.tokens.len() && self.token_type(end) != Some(TokenType::CloseBrace) {
+                    let next = if self.token_type(end + 1) == Some(TokenType::Colon) {
+                        self.marks[end] = Mark::Key;
+                        let value = self.mark_pattern(end + 2, kind, keyword);
+                        self.skip_default(value)
+                    } else {
+                        self.mark_binding(end, kind, keyword);
+                        self.skip_default(end + 1)
+                    };
+                    end = if self.token_type(next) == Some(TokenType::Comma) { next + 1 } else { next.max(end + 1) };
+                }
+                if end < self.tokens.len() {
+                    self.marks[end] = Mark::PatternBrace;
+                }
+                end + 1
+            }
+            // Literals and ranges: -1..=5, "a"
+            _ => {
+                let mut end = index;
+                if self.is_operator(end, "-") {
+                    end += 1;
+                }
+                end += 1;
+                if matches!(self.token_type(end), Some(TokenType::DotDot | TokenType::DotDotEqual)) {
+                    end += 1;
+                    if self.is_operator(end, "-") {
+                        end += 1;
+                    }
+                    end += 1;
+                }
+                end
+            }
+        }
+    }
+
+    // Skips `= oletus` after a pattern element, up to the ',' or bracket that ends it
+    fn skip_default(&self, index: usize) -> usize {
+        if self.token_type(index) != Some(TokenType::Assign) {
+            return index;
+        }
+        let mut end = index + 1;
+        let mut nesting = 0;
+        while let Some(token_type) = self.token_type(end) {
+            match token_type {
+                TokenType::OpenParen | TokenType::OpenBracket | TokenType::OpenBrace | TokenType::OptionalBracket | TokenType::InterpolationStart => nesting += 1,
+                TokenType::CloseParen | TokenType::CloseBracket | TokenType::CloseBrace if nesting == 0 => break,
+                TokenType::CloseParen | TokenType::CloseBracket | TokenType::CloseBrace => nesting -= 1,
+                TokenType::Comma | TokenType::SemiColon if nesting == 0 => break,
+                _ => (),
+            }
+            end += 1;
+        }
+        end
+    }
+
+    // The bracket closing the one at `open`, or the end of the tokens
+    fn closing(&self, open: usize) -> usize {
+        let mut nesting = 0;
+        for index in open..self.tokens.len() {
+            match self.tokens[index].token_type {
+                TokenType::OpenParen | TokenType::OpenBracket | TokenType::OpenBrace | TokenType::OptionalBracket | TokenType::InterpolationStart => nesting += 1,
+                TokenType::CloseParen | TokenType::CloseBracket | TokenType::CloseBrace => {
+                    nesting -= 1;
+                    if nesting == 0 {
+                        return index;
+                    }
+                }
+                _ => (),
+            }
+        }
+        self.tokens.len()
+    }
+
+    // Uses resolve once every declaration is known, since top-level names are visible before them
+    fn finish(mut self) -> SymbolIndex {
+        for usage in &self.uses {
+            let name = &self.occurrences[usage.occurrence].name;
+            let mut scope = Some(usage.scope);
+            while let Some(id) = scope {
+                let current = &self.scopes[id];
+                if current.kind != ScopeKind::Members {
+                    if let Some(&definition) = current.names.get(name) {
+                        // Within a function a name is visible after its declaration; from a nested function, everywhere
+                        let positional = current.function == usage.function && current.kind != ScopeKind::Root;
+                        if !positional || self.first_tokens[definition] < usage.token {
+                            self.occurrences[usage.occurrence].definition = Some(definition);
+                            break;
+                        }
+                    }
+                }
+                scope = current.parent;
+            }
+        }
+        SymbolIndex { definitions: self.definitions, occurrences: self.occurrences }
+    }
+}
+
+// Source-like text of a few tokens, for hover texts
+pub fn render(tokens: &[Token]) -> String {
+    let mut text = String::new();
+    for token in tokens {
+        match token.token_type {
+            TokenType::StringLiteral => text.push_str(&format!("\"{}\"", token.value)),
+            TokenType::Assign | TokenType::BinaryOperator | TokenType::FatArrow | TokenType::LogicalAnd | TokenType::LogicalOr |
+            TokenType::Equal | TokenType::NotEqual | TokenType::LessThan | TokenType::GreaterThan |
+            TokenType::LessThanOrEqual | TokenType::GreaterThanOrEqual => text.push_str(&format!(" {} ", token.value)),
+            TokenType::Comma | TokenType::Colon => text.push_str(&format!("{} ", token.value)),
+            _ => text.push_str(&token.value),
+        }
+    }
+    text
+}
@@ -0,0 +1,667 @@
+use std::ops::Range;
+use crate::frontend::lexer::*;
+
+// A comment between two tokens
+pub struct Comment {
+    pub text: String,
+    // A blank line separates it from what came before
+    pub blank_before: bool,
+    // Code continues on the comment's last line, e.g. f(/* x */ 1)
+    pub inline: bool,
+}
+
+// A token as written, with the comments that belong to it. A template string is one item.
+pub struct Item {
+    pub text: String,
+    pub token_type: TokenType,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub blank_before: bool,
+    // Comments on the lines before the token
+    pub leading: Vec<Comment>,
+    // Comments right after the token on its line, written with it
+    pub attached: String,
+    // A comment after the token at the end of its line
+    pub trailing: Option<String>,
+}
+
+// Splits the source into items. Literal text is taken from the source, since token values are
+// normalized (escapes, 0xff -> 255); between tokens there is only whitespace, now that comments are tokens.
+pub fn items(source: &str) -> Vec<Item> {
+    let tokens = tokenize_with_comments(source);
+    let offset = offsets(source);
+
+    let mut items: Vec<Item> = Vec::new();
+    let mut comments: Vec<Comment> = Vec::new();
+    let mut previous_end = 0;
+    let mut index = 0;
+    while index < tokens.len() {
+        let token = &tokens[index];
+        let last = match token.token_type {
+            TokenType::Backtick => template_end(&tokens, index),
+            _ => index,
+        };
+        let start = offset(token);
+        let end = tokens.get(last + 1).map_or(source.len(), &offset).max(start);
+        let text = source[start..end].trim_end();
+        let end_line = token.line + text.matches('\n').count();
+        let blank_before = source[previous_end.min(start)..start].matches('\n').count() >= 2;
+        let spaced = previous_end < start;
+        let next_line = tokens.get(last + 1).map(|next| next.line);
+        let before_closing = tokens.get(last + 1).is_some_and(|next| matches!(next.token_type,
+            TokenType::Comma | TokenType::SemiColon | TokenType::CloseParen | TokenType::CloseBracket | TokenType::CloseBrace));
+        previous_end = start + text.len();
+        index = last + 1;
+
+        match token.token_type {
+            TokenType::SingleLineComment | TokenType::MultiLineComment => {
+                let single = token.token_type == TokenType::SingleLineComment;
+                let after_token = comments.is_empty()
+                    && items.last().is_some_and(|previous| previous.end_line == token.line && previous.trailing.is_none());
+                if after_token && (single || next_line != Some(end_line)) {
+                    items.last_mut().unwrap().trailing = Some(text.to_string());
+                } else if after_token && before_closing {
+                    // `f(1 /* yksi */, 2)` keeps the comment with the 1, and its space before
+                    let attached = &mut items.last_mut().unwrap().attached;
+                    attached.push_str(if spaced { " " } else { "" });
+                    attached.push_str(text);
+                } else {
+                    let inline = !single && next_line == Some(end_line);
+                    comments.push(Comment { text: text.to_string(), blank_before, inline });
+                }
+            }
+            token_type => items.push(Item {
+                text: text.to_string(),
+                token_type,
+                line: token.line,
+                column: token.column,
+                end_line,
+                blank_before,
+                leading: std::mem::take(&mut comments),
+                attached: String::new(),
+                trailing: None,
+            }),
+        }
+    }
+
+    // `a + b // kommentti` before a ';' or ',' is written after it
+    for index in 1..items.len() {
+        let (before, after) = items.split_at_mut(index);
+        let (previous, item) = (before.last_mut().unwrap(), &mut after[0]);
+        if matches!(item.token_type, TokenType::SemiColon | TokenType::Comma) && item.leading.is_empty() && item.trailing.is_none() {
+            item.trailing = previous.trailing.take();
+        }
+    }
+    items
+}
+
+// The byte offset of a token in the source
+fn offsets(source: &str) -> impl Fn(&Token) -> usize + '_ {
+    let line_starts: Vec<usize> = std::iter::once(0).chain(source.match_indices('\n').map(|(at, _)| at + 1)).collect();
+    move |token: &Token| {
+        let start = line_starts.get(token.line.saturating_sub(1)).copied().unwrap_or(source.len());
+        let line = &source[start..];
+        start + line.char_indices().nth(token.column.saturating_sub(1)).map_or(line.len(), |(at, _)| at)
+    }
+}
+
+// Where the code of each ${ } of a template item is in its text; templates nested inside are left out
+pub fn interpolations(template: &str) -> Vec<Range<usize>> {
+    let tokens = tokenize_with_comments(template);
+    let offset = offsets(template);
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut open_templates: Vec<Option<usize>> = Vec::new();
+    for token in &tokens {
+        match (token.token_type, open_templates.last_mut()) {
+            (TokenType::Backtick, Some(None)) => {
+                open_templates.pop();
+            }
+            (TokenType::Backtick, _) => open_templates.push(None),
+            (TokenType::InterpolationStart, _) => {
+                open_templates.push(Some(0));
+                if open_templates.len() == 2 {
+                    start = offset(token) + "${".len();
+                }
+            }
+            (TokenType::OpenBrace, Some(Some(depth))) => *depth += 1,
+            (TokenType::CloseBrace, Some(Some(0))) => {
+                if open_templates.len() == 2 {
+                    ranges.push(start..offset(token));
+                }
+                open_templates.pop();
+            }
+            (TokenType::CloseBrace, Some(Some(depth))) => *depth -= 1,
+            _ => (),
+        }
+    }
+    ranges
+}
+
+// The closing backtick of the template that opens at `open`; templates nest inside ${ }
+fn template_end(tokens: &[Token], open: usize) -> usize {
+    // One entry per open template or interpolation; an interpolation counts its own braces
+    let mut open_templates: Vec<Option<usize>> = Vec::new();
+    for (index, token) in tokens.iter().enumerate().skip(open) {
+        match (token.token_type, open_templates.last_mut()) {
+            (TokenType::Backtick, Some(None)) => {
+                open_templates.pop();
+                if open_templates.is_empty() {
+                    return index;
+                }
+            }
+            (TokenType::Backtick, _) => open_templates.push(None),
+            (TokenType::InterpolationStart, _) => open_templates.push(Some(0)),
+            (TokenType::OpenBrace, Some(Some(depth))) => *depth += 1,
+            (TokenType::CloseBrace, Some(Some(0))) => {
+                open_templates.pop();
+            }
+            (TokenType::CloseBrace, Some(Some(depth))) => *depth -= 1,
+            _ => (),
+        }
+    }
+    tokens.len() - 1
+}
+
+// A syntax tree that keeps every token, so printing it loses nothing but whitespace
+pub enum Node {
+    Token(usize),
+    Tree(Kind, Vec<Node>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    // Statements followed by the end of the file
+    Program,
+    // '{', statements or class members, '}'
+    Block,
+    // '{', match arms, '}'
+    Arms,
+    // The head, the body and the comma of a match arm
+    Arm,
+    // Children separated by spaces
+    Seq,
+    // Children written together, e.g. calls, member access and unary operators
+    Tight,
+    // A bracket, items separated by commas and the closing bracket; true puts each item on its own line
+    List(bool),
+    // A chain of operators of one precedence level
+    Binary(u8),
+    // ehto ? a : b
+    Conditional,
+}
+
+// Binary operators from the loosest to the tightest, as the parser reads them
+const LEVELS: [&[&str]; 11] = [
+    &["??"],
+    &["||"],
+    &["&&"],
+    &["==", "!=", "<", ">", "<=", ">="],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "~/", "%"],
+    &["**"],
+];
+
+type Parsed = Result<Node, String>;
+
+// Follows the grammar of frontend::parser, but keeps the tokens instead of building the AST
+pub struct TreeParser<'i> {
+    items: &'i [Item],
+    position: usize,
+}
+
+impl<'i> TreeParser<'i> {
+    pub fn new(items: &'i [Item]) -> Self {
+        Self { items, position: 0 }
+    }
+
+    fn at(&self) -> &'i Item {
+        &self.items[self.position.min(self.items.len() - 1)]
+    }
+
+    fn at_type(&self) -> TokenType {
+        self.at().token_type
+    }
+
+    fn peek_type(&self, offset: usize) -> Option<TokenType> {
+        self.items.get(self.position + offset).map(|item| item.token_type)
+    }
+
+    fn at_operator(&self, operators: &[&str]) -> bool {
+        self.at_type() != TokenType::StringLiteral && operators.contains(&self.at().text.as_str())
+    }
+
+    fn eat(&mut self) -> Result<Node, String> {
+        if self.at_type() == TokenType::EOF {
+            return Err(format!("Rivi {}: lähdekoodi päättyi kesken", self.at().line));
+        }
+        self.position += 1;
+        Ok(Node::Token(self.position - 1))
+    }
+
+    fn expect(&mut self, token_type: TokenType) -> Parsed {
+        if self.at_type() != token_type {
+            return Err(format!("Rivi {}: odottamaton '{}'", self.at().line, self.at().text));
+        }
+        self.eat()
+    }
+
+    pub fn program(&mut self) -> Parsed {
+        let mut children = Vec::new();
+        while self.at_type() != TokenType::EOF {
+            children.push(self.stmt()?);
+        }
+        children.push(Node::Token(self.position));
+        Ok(Node::Tree(Kind::Program, children))
+    }
+
+    fn stmt(&mut self) -> Parsed {
+        match self.at_type() {
+            TokenType::If => self.if_expr(),
+            TokenType::While => Ok(Node::Tree(Kind::Seq, vec![self.eat()?, self.expr()?, self.block()?])),
+            TokenType::For => self.for_loop(),
+            TokenType::Let | TokenType::Const => {
+                let declaration = self.var_declaration()?;
+                Ok(Node::Tree(Kind::Tight, vec![declaration, self.expect(TokenType::SemiColon)?]))
+            }
+            TokenType::Fn => self.function(Vec::new()),
+            TokenType::Class => self.class(),
+            TokenType::Enum => self.enumeration(),
+            TokenType::Import => self.import(),
+            TokenType::Export => self.export(),
+            _ => self.expr(),
+        }
+    }
+
+    fn block(&mut self) -> Parsed {
+        let mut children = vec![self.expect(TokenType::OpenBrace)?];
+        while self.at_type() != TokenType::CloseBrace {
+            if self.at_type() == TokenType::EOF {
+                return Err(format!("Rivi {}: lohkosta puuttuu sulkeva '}}'", self.at().line));
+            }
+            children.push(self.stmt()?);
+        }
+        children.push(self.eat()?);
+        Ok(Node::Tree(Kind::Block, children))
+    }
+
+    // Items separated by commas up to the closing bracket, which may follow a comma
+    fn list(&mut self, close: TokenType, broken: bool, mut item: impl FnMut(&mut Self) -> Parsed) -> Parsed {
+        let mut children = vec![self.eat()?];
+        while self.at_type() != close {
+            children.push(item(self)?);
+            if self.at_type() != close {
+                children.push(self.expect(TokenType::Comma)?);
+            }
+        }
+        children.push(self.eat()?);
+        Ok(Node::Tree(Kind::List(broken), children))
+    }
+
+    fn var_declaration(&mut self) -> Parsed {
+        let mut children = vec![self.eat()?];
+        children.push(match self.at_type() {
+            TokenType::Identifier => self.eat()?,
+            _ => self.single_pattern()?,
+        });
+        if self.at_type() == TokenType::Assign {
+            children.push(self.eat()?);
+            children.push(self.expr()?);
+        }
+        Ok(Node::Tree(Kind::Seq, children))
+    }
+
+    // funktio nimi(parametrit) { }, after an optional staattinen
+    fn function(&mut self, mut children: Vec<Node>) -> Parsed {
+        children.push(self.eat()?);
+        let name = self.expect(TokenType::Identifier)?;
+        children.push(Node::Tree(Kind::Tight, vec![name, self.params()?]));
+        children.push(self.block()?);
+        Ok(Node::Tree(Kind::Seq, children))
+    }
+
+    fn params(&mut self) -> Parsed {
+        if self.at_type() != TokenType::OpenParen {
+            return Err(format!("Rivi {}: odotettu '('", self.at().line));
+        }
+        self.list(TokenType::CloseParen, false, |parser| match parser.at_type() {
+            TokenType::Ellipsis => Ok(Node::Tree(Kind::Tight, vec![parser.eat()?, parser.expect(TokenType::Identifier)?])),
+            _ => {
+                let pattern = parser.single_pattern()?;
+                parser.pattern_default(pattern)
+            }
+        })
+    }
+
+    fn class(&mut self) -> Parsed {
+        let mut children = vec![self.eat()?, self.expect(TokenType::Identifier)?];
+        if self.at_type() == TokenType::Extends {
+            children.push(self.eat()?);
+            children.push(self.expect(TokenType::Identifier)?);
+        }
+
+        let mut members = vec![self.expect(TokenType::OpenBrace)?];
+        while !matches!(self.at_type(), TokenType::CloseBrace | TokenType::EOF) {
+            let mut member = Vec::new();
+            if self.at_type() == TokenType::Static {
+                member.push(self.eat()?);
+            }
+            members.push(match self.at_type() {
+                TokenType::Let => {
+                    member.push(self.eat()?);
+                    member.push(self.expect(TokenType::Identifier)?);
+                    if self.at_type() == TokenType::Assign {
+                        member.push(self.eat()?);
+                        member.push(self.expr()?);
+                    }
+                    let field = Node::Tree(Kind::Seq, member);
+                    Node::Tree(Kind::Tight, vec![field, self.expect(TokenType::SemiColon)?])
+                }
+                TokenType::Fn => self.function(member)?,
+                TokenType::Constructor => {
+                    let constructor = Node::Tree(Kind::Tight, vec![self.eat()?, self.params()?]);
+                    Node::Tree(Kind::Seq, vec![constructor, self.block()?])
+                }
+                _ => return Err(format!("Rivi {}: odottamaton '{}' luokan rungossa", self.at().line, self.at().text)),
+            });
+        }
+        members.push(self.expect(TokenType::CloseBrace)?);
+        children.push(Node::Tree(Kind::Block, members));
+        Ok(Node::Tree(Kind::Seq, children))
+    }
+
+    fn enumeration(&mut self) -> Parsed {
+        let mut children = vec![self.eat()?, self.expect(TokenType::Identifier)?];
+        if self.at_type() != TokenType::OpenBrace {
+            return Err(format!("Rivi {}: odotettu '{{'", self.at().line));
+        }
+        children.push(self.list(TokenType::CloseBrace, true, |parser| {
+            let name = parser.expect(TokenType::Identifier)?;
+            if parser.at_type() != TokenType::OpenParen {
+                return Ok(name);
+            }
+            let fields = parser.list(TokenType::CloseParen, false, |parser| parser.expect(TokenType::Identifier))?;
+            Ok(Node::Tree(Kind::Tight, vec![name, fields]))
+        })?);
+        Ok(Node::Tree(Kind::Seq, children))
+    }
+
+    fn import(&mut self) -> Parsed {
+        let mut children = vec![self.eat()?];
+        if self.at_type() == TokenType::OpenBrace {
+            children.push(self.single_pattern()?);
+            children.push(self.expect(TokenType::From)?);
+        }
+        children.push(self.expect(TokenType::StringLiteral)?);
+        if self.at_type() == TokenType::As {
+            children.push(self.eat()?);
+            children.push(self.expect(TokenType::Identifier)?);
+        }
+        Ok(Node::Tree(Kind::Seq, children))
+    }
+
+    fn export(&mut self) -> Parsed {
+        let export = self.eat()?;
+        let exported = match self.at_type() {
+            TokenType::OpenBrace => self.list(TokenType::CloseBrace, false, |parser| parser.expect(TokenType::Identifier))?,
+            _ => self.stmt()?,
+        };
+        Ok(Node::Tree(Kind::Seq, vec![export, exported]))
+    }
+
+    fn if_expr(&mut self) -> Parsed {
+        let mut children = vec![self.eat()?, self.expr()?, self.block()?];
+        if self.at_type() == TokenType::Else {
+            children.push(self.eat()?);
+            children.push(match self.at_type() {
+                TokenType::If => self.if_expr()?,
+                _ => self.block()?,
+            });
+        }
+        Ok(Node::Tree(Kind::Seq, children))
+    }
+
+    fn match_expr(&mut self) -> Parsed {
+        let keyword = self.eat()?;
+        let subject = self.expr()?;
+        let mut arms = vec![self.expect(TokenType::OpenBrace)?];
+        while !matches!(self.at_type(), TokenType::CloseBrace | TokenType::EOF) {
+            let mut head = Vec::new();
+            match self.at_type() {
+                TokenType::Case => {
+                    head.push(self.eat()?);
+                    head.push(self.pattern()?);
+                    if self.at_type() == TokenType::If {
+                        head.push(self.eat()?);
+                        head.push(self.expr()?);
+                    }
+                }
+                _ => head.push(self.expect(TokenType::Else)?),
+            }
+            head.push(self.expect(TokenType::FatArrow)?);
+            let body = match self.at_type() {
+                TokenType::OpenBrace => self.block()?,
+                _ => self.expr()?,
+            };
+            let mut arm = vec![Node::Tree(Kind::Seq, head), body];
+            if self.at_type() == TokenType::Comma {
+                arm.push(self.eat()?);
+            }
+            arms.push(Node::Tree(Kind::Arm, arm));
+        }
+        arms.push(self.expect(TokenType::CloseBrace)?);
+        Ok(Node::Tree(Kind::Seq, vec![keyword, subject, Node::Tree(Kind::Arms, arms)]))
+    }
+
+    fn for_loop(&mut self) -> Parsed {
+        let keyword = self.eat()?;
+        let open = self.expect(TokenType::OpenParen)?;
+        let mut declaration = match self.at_type() {
+            TokenType::Let | TokenType::Const => vec![self.eat()?],
+            _ => return Err(format!("Rivi {}: odotettu 'olkoon' tai 'vakio'", self.at().line)),
+        };
+        declaration.push(self.single_pattern()?);
+
+        let head = if self.at_type() == TokenType::In {
+            declaration.push(self.eat()?);
+            declaration.push(self.expr()?);
+            Node::Tree(Kind::Seq, declaration)
+        } else {
+            declaration.push(self.expect(TokenType::Assign)?);
+            declaration.push(self.expr()?);
+            let initializer = Node::Tree(Kind::Tight, vec![Node::Tree(Kind::Seq, declaration), self.expect(TokenType::SemiColon)?]);
+            let condition = Node::Tree(Kind::Tight, vec![self.expr()?, self.expect(TokenType::SemiColon)?]);
+            Node::Tree(Kind::Seq, vec![initializer, condition, self.expr()?])
+        };
+        let header = Node::Tree(Kind::Tight, vec![open, head, self.expect(TokenType::CloseParen)?]);
+        Ok(Node::Tree(Kind::Seq, vec![keyword, header, self.block()?]))
+    }
+
+    fn pattern(&mut self) -> Parsed {
+        let first = self.single_pattern()?;
+        if !self.at_operator(&["|"]) {
+            return Ok(first);
+        }
+        let mut alternatives = vec![first];
+        while self.at_operator(&["|"]) {
+            alternatives.push(self.eat()?);
+            alternatives.push(self.single_pattern()?);
+        }
+        Ok(Node::Tree(Kind::Seq, alternatives))
+    }
+
+    fn single_pattern(&mut self) -> Parsed {
+        match self.at_type() {
+            TokenType::Identifier if self.peek_type(1) == Some(TokenType::Dot) => {
+                let mut children = vec![self.eat()?, self.eat()?, self.expect(TokenType::Identifier)?];
+                if self.at_type() == TokenType::OpenParen {
+                    children.push(self.list(TokenType::CloseParen, false, Self::pattern)?);
+                }
+                Ok(Node::Tree(Kind::Tight, children))
+            }
+            TokenType::Identifier => self.eat(),
+            TokenType::OpenBracket => self.list(TokenType::CloseBracket, false, |parser| match parser.at_type() {
+                TokenType::Ellipsis => Ok(Node::Tree(Kind::Tight, vec![parser.eat()?, parser.expect(TokenType::Identifier)?])),
+                _ => {
+                    let element = parser.pattern()?;
+                    parser.pattern_default(element)
+                }
+            }),
+            TokenType::OpenBrace => self.list(TokenType::CloseBrace, false, |parser| {
+                let key = parser.expect(TokenType::Identifier)?;
+                let property = match parser.at_type() {
+                    TokenType::Colon => {
+                        let key = Node::Tree(Kind::Tight, vec![key, parser.eat()?]);
+                        Node::Tree(Kind::Seq, vec![key, parser.pattern()?])
+                    }
+                    _ => key,
+                };
+                parser.pattern_default(property)
+            }),
+            _ => {
+                let start = self.literal_pattern()?;
+                if !matches!(self.at_type(), TokenType::DotDot | TokenType::DotDotEqual) {
+                    return Ok(start);
+                }
+                Ok(Node::Tree(Kind::Tight, vec![start, self.eat()?, self.literal_pattern()?]))
+            }
+        }
+    }
+
+    fn pattern_default(&mut self, pattern: Node) -> Parsed {
+        if self.at_type() != TokenType::Assign {
+            return Ok(pattern);
+        }
+        Ok(Node::Tree(Kind::Seq, vec![pattern, self.eat()?, self.expr()?]))
+    }
+
+    fn literal_pattern(&mut self) -> Parsed {
+        match self.at_type() {
+            TokenType::Integer | TokenType::Float | TokenType::TypedInteger | TokenType::StringLiteral => self.eat(),
+            _ if self.at_operator(&["-"]) => self.unary(),
+            _ => Err(format!("Rivi {}: odottamaton '{}' kuviossa", self.at().line, self.at().text)),
+        }
+    }
+
+    fn expr(&mut self) -> Parsed {
+        match self.at_type() {
+            TokenType::OpenBrace => self.object(),
+            _ => self.assignment(),
+        }
+    }
+
+    fn assignment(&mut self) -> Parsed {
+        let left = match self.at_type() {
+            TokenType::OpenBrace => self.object()?,
+            _ => self.conditional()?,
+        };
+        match self.at_type() {
+            TokenType::Assign | TokenType::PlusEqual | TokenType::MinusEqual | TokenType::TimesEqual | TokenType::DivideEqual
+            | TokenType::ModulusEqual | TokenType::ExponentEqual | TokenType::NullishEqual => {
+                let operator = self.eat()?;
+                Ok(Node::Tree(Kind::Seq, vec![left, operator, self.assignment()?]))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn object(&mut self) -> Parsed {
+        self.list(TokenType::CloseBrace, false, |parser| {
+            let key = parser.expect(TokenType::Identifier)?;
+            if parser.at_type() != TokenType::Colon {
+                return Ok(key);
+            }
+            let key = Node::Tree(Kind::Tight, vec![key, parser.eat()?]);
+            Ok(Node::Tree(Kind::Seq, vec![key, parser.expr()?]))
+        })
+    }
+
+    fn conditional(&mut self) -> Parsed {
+        let condition = self.binary(0)?;
+        if self.at_type() != TokenType::QuestionMark {
+            return Ok(condition);
+        }
+        let question = self.eat()?;
+        let consequent = self.expr()?;
+        let colon = self.expect(TokenType::Colon)?;
+        let alternate = match self.at_type() {
+            TokenType::OpenBrace => self.object()?,
+            _ => self.conditional()?,
+        };
+        Ok(Node::Tree(Kind::Conditional, vec![condition, question, consequent, colon, alternate]))
+    }
+
+    fn binary(&mut self, level: usize) -> Parsed {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while self.at_operator(LEVELS[level]) {
+            let operator = self.eat()?;
+            left = Node::Tree(Kind::Binary(level as u8), vec![left, operator, self.binary(level + 1)?]);
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Parsed {
+        if self.at_operator(&["-", "!", "~"]) {
+            return Ok(Node::Tree(Kind::Tight, vec![self.eat()?, self.unary()?]));
+        }
+        if matches!(self.at_type(), TokenType::Increment | TokenType::Decrement) {
+            return Ok(Node::Tree(Kind::Tight, vec![self.eat()?, self.call_member()?]));
+        }
+        let expr = self.call_member()?;
+        // Postfix ++/-- must be on the same line as its operand
+        if matches!(self.at_type(), TokenType::Increment | TokenType::Decrement) && self.at().line == self.items[self.position - 1].line {
+            return Ok(Node::Tree(Kind::Tight, vec![expr, self.eat()?]));
+        }
+        Ok(expr)
+    }
+
+    fn call_member(&mut self) -> Parsed {
+        let mut expr = self.primary()?;
+        loop {
+            expr = match self.at_type() {
+                TokenType::OpenParen => {
+                    let args = self.list(TokenType::CloseParen, false, Self::argument)?;
+                    Node::Tree(Kind::Tight, vec![expr, args])
+                }
+                TokenType::Dot | TokenType::OptionalDot => Node::Tree(Kind::Tight, vec![expr, self.eat()?, self.primary()?]),
+                TokenType::OpenBracket | TokenType::OptionalBracket => {
+                    let open = self.eat()?;
+                    let index = self.expr()?;
+                    Node::Tree(Kind::Tight, vec![expr, open, index, self.expect(TokenType::CloseBracket)?])
+                }
+                _ => return Ok(expr),
+            };
+        }
+    }
+
+    fn argument(&mut self) -> Parsed {
+        if self.at_type() == TokenType::Ellipsis {
+            return Ok(Node::Tree(Kind::Tight, vec![self.eat()?, self.assignment()?]));
+        }
+        if self.at_type() == TokenType::Identifier && self.peek_type(1) == Some(TokenType::Colon) {
+            let name = Node::Tree(Kind::Tight, vec![self.eat()?, self.eat()?]);
+            return Ok(Node::Tree(Kind::Seq, vec![name, self.assignment()?]));
+        }
+        self.assignment()
+    }
+
+    fn primary(&mut self) -> Parsed {
+        match self.at_type() {
+            TokenType::Identifier | TokenType::Integer | TokenType::Float | TokenType::TypedInteger | TokenType::StringLiteral
+            | TokenType::Backtick => self.eat(),
+            TokenType::OpenBracket => self.list(TokenType::CloseBracket, false, Self::expr),
+            TokenType::OpenParen => Ok(Node::Tree(Kind::Tight, vec![self.eat()?, self.expr()?, self.expect(TokenType::CloseParen)?])),
+            TokenType::If => self.if_expr(),
+            TokenType::Match => self.match_expr(),
+            _ => Err(format!("Rivi {}: odottamaton '{}'", self.at().line, self.at().text)),
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::any::Any;
+use std::cell::{ Ref, RefCell, RefMut };
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use crate::conversions::*;
+use crate::embed::{ catch_panic, KaulinError };
+use crate::runtime::environment::Environment;
+use crate::runtime::eval::expressions::call_value;
+use crate::runtime::values::*;
+
+// Given to host functions that take it as their first parameter, for calling back into Kaulin
+pub struct Context {
+    env: Environment,
+}
+
+impl Context {
+    pub fn call<R: FromKaulin>(&self, function: &RuntimeVal, args: Vec<RuntimeVal>) -> Result<R, KaulinError> {
+        let result = catch_panic(|| call_value(function.clone(), args, Vec::new(), &self.env))?;
+        R::from_kaulin(result)
+    }
+
+    // Calls a function by the name it has where the host function was called
+    pub fn call_function<R: FromKaulin>(&self, name: &str, args: Vec<RuntimeVal>) -> Result<R, KaulinError> {
+        if !self.env.has_var(name) {
+            return Err(KaulinError::Runtime(format!("Nimeä '{}' ei ole määritelty", name)));
+        }
+        let function = self.env.clone().lookup_var(name);
+        self.call(&function, args)
+    }
+}
+
+// What a host function may return: a value, or a Result whose error becomes a Kaulin error
+pub trait HostResult {
+    fn into_value(self, name: &str) -> RuntimeVal;
+}
+
+impl<T: IntoKaulin> HostResult for T {
+    fn into_value(self, _name: &str) -> RuntimeVal {
+        self.into_kaulin()
+    }
+}
+
+impl<T: IntoKaulin, E: Display> HostResult for Result<T, E> {
+    fn into_value(self, name: &str) -> RuntimeVal {
+        match self {
+            Ok(value) => value.into_kaulin(),
+            Err(error) => panic!("{}-funktio: {}", name, error),
+        }
+    }
+}
+
+// Rust closures that can be registered as Kaulin functions.
+// `Marker` tells apart closures with and without a leading `&Context`.
+pub trait HostFunction<Marker> {
+    fn into_native(self, name: &str) -> NativeFn;
+}
+
+pub struct Plain<Args>(PhantomData<Args>);
+pub struct WithContext<Args>(PhantomData<Args>);
+
+// Checks the argument count once, then hands out converted arguments in order
+struct Arguments<'a> {
+    name: &'a str,
+    values: std::vec::IntoIter<RuntimeVal>,
+    position: usize,
+}
+
+impl<'a> Arguments<'a> {
+    fn new(name: &'a str, values: Vec<RuntimeVal>, optional: &[bool]) -> Self {
+        let total = optional.len();
+        let required = total - optional.iter().rev().take_while(|optional| **optional).count();
+        if values.len() < required || values.len() > total {
+            panic!("{}-funktio {}, saatiin {}", name, describe_arity(required, total), values.len());
+        }
+        Self { name, values: values.into_iter(), position: 0 }
+    }
+
+    fn next<T: FromKaulin>(&mut self) -> T {
+        self.position += 1;
+        let value = self.values.next().unwrap_or(RuntimeVal::Null);
+        match T::from_kaulin(value) {
+            Ok(value) => value,
+            Err(error) => panic!("{}-funktion {}. argumentti: {}", self.name, self.position, error),
+        }
+    }
+}
+
+fn describe_arity(required: usize, total: usize) -> String {
+    match (required, total) {
+        (0, 0) => "ei ota argumentteja".to_string(),
+        (1, 1) => "ottaa täsmälleen yhden argumentin".to_string(),
+        (required, total) if required == total => format!("ottaa täsmälleen {} argumenttia", total),
+        (required, total) => format!("ottaa {}–{} argumenttia", required, total),
+    }
+}
+
+macro_rules! impl_host_function {
+    ($($arg:ident),*) => {
+        #[allow(non_snake_case, unused_mut, unused_variables)]
+        impl<F, R, $($arg),*> HostFunction<Plain<($($arg,)*)>> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: HostResult,
+            $($arg: FromKaulin),*
+        {
+            fn into_native(self, name: &str) -> NativeFn {
+                let name = name.to_string();
+                Rc::new(move |values, _env| {
+                    let mut args = Arguments::new(&name, values, &[$($arg::is_optional()),*]);
+                    $(let $arg = args.next::<$arg>();)*
+                    self($($arg),*).into_value(&name)
+                })
+            }
+        }
+
+        #[allow(non_snake_case, unused_mut, unused_variables)]
+        impl<F, R, $($arg),*> HostFunction<WithContext<($($arg,)*)>> for F
+        where
+            F: Fn(&Context, $($arg),*) -> R + 'static,
+            R: HostResult,
+            $($arg: FromKaulin),*
+        {
+            fn into_native(self, name: &str) -> NativeFn {
+                let name = name.to_string();
+                Rc::new(move |values, env| {
+                    let mut args = Arguments::new(&name, values, &[$($arg::is_optional()),*]);
+                    $(let $arg = args.next::<$arg>();)*
+                    let context = Context { env: env.clone() };
+                    self(&context, $($arg),*).into_value(&name)
+                })
+            }
+        }
+    };
+}
+
+impl_host_function!();
+impl_host_function!(A);
+impl_host_function!(A, B);
+impl_host_function!(A, B, C);
+impl_host_function!(A, B, C, D);
+impl_host_function!(A, B, C, D, E);
+impl_host_function!(A, B, C, D, E, G);
+
+// Rust values handed to scripts as opaque objects; member access and method calls dispatch here
+pub trait HostObject: Any {
+    fn type_name(&self) -> &str;
+
+    fn get(&self, _property: &str) -> Option<RuntimeVal> {
+        None
+    }
+
+    fn set(&mut self, property: &str, _value: RuntimeVal) -> Result<(), String> {
+        Err(format!("ominaisuutta '{}' ei voi asettaa", property))
+    }
+
+    fn has_method(&self, _method: &str) -> bool {
+        false
+    }
+
+    fn call_method(&mut self, method: &str, _args: Vec<RuntimeVal>, _context: &Context) -> Result<RuntimeVal, String> {
+        Err(format!("metodia '{}' ei ole", method))
+    }
+
+    fn display(&self) -> String {
+        format!("<{}>", self.type_name())
+    }
+
+    // Called only for distinct objects; the same object always equals itself
+    fn equals(&self, _other: &dyn Any) -> bool {
+        false
+    }
+}
+
+// A shared handle to a host object; copies in Kaulin refer to the same Rust value
+#[derive(Clone)]
+pub struct HostRef(Rc<RefCell<dyn HostObject>>);
+
+impl HostRef {
+    pub fn new(object: impl HostObject) -> Self {
+        HostRef(Rc::new(RefCell::new(object)))
+    }
+
+    pub fn borrow<T: HostObject>(&self) -> Option<Ref<'_, T>> {
+        Ref::filter_map(self.0.borrow(), |object| (object as &dyn Any).downcast_ref::<T>()).ok()
+    }
+
+    pub fn borrow_mut<T: HostObject>(&self) -> Option<RefMut<'_, T>> {
+        RefMut::filter_map(self.0.borrow_mut(), |object| (object as &mut dyn Any).downcast_mut::<T>()).ok()
+    }
+
+    pub fn type_name(&self) -> String {
+        self.0.borrow().type_name().to_string()
+    }
+
+    pub fn equals(&self, other: &HostRef) -> bool {
+        Rc::ptr_eq(&self.0, &other.0) || self.0.borrow().equals(&*other.0.borrow() as &dyn Any)
+    }
+
+    // Methods are returned as natives bound to this object, like enum constructors
+    pub fn get_member(&self, property: &str) -> Option<RuntimeVal> {
+        let object = self.0.borrow();
+        if !object.has_method(property) {
+            return object.get(property);
+        }
+
+        let target = self.clone();
+        let method = property.to_string();
+        Some(MK_NATIVE_FN(Rc::new(move |args, env| target.call_method(&method, args, env))))
+    }
+
+    pub fn set_member(&self, property: &str, value: RuntimeVal) {
+        let mut object = self.borrow_object_mut();
+        if let Err(error) = object.set(property, value) {
+            panic!("{}: {}", object.type_name(), error);
+        }
+    }
+
+    fn call_method(&self, method: &str, args: Vec<RuntimeVal>, env: &Environment) -> RuntimeVal {
+        let context = Context { env: env.clone() };
+        let mut object = self.borrow_object_mut();
+        match object.call_method(method, args, &context) {
+            Ok(value) => value,
+            Err(error) => panic!("{}.{}: {}", object.type_name(), method, error),
+        }
+    }
+
+    // A method that calls back into Kaulin may reach the same object again
+    fn borrow_object_mut(&self) -> RefMut<'_, dyn HostObject> {
+        match self.0.try_borrow_mut() {
+            Ok(object) => object,
+            Err(_) => panic!("Isäntäoliota ei voida muuttaa, koska sen metodi on vielä kesken"),
+        }
+    }
+}
+
+impl std::fmt::Debug for HostRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.borrow().display())
+    }
+}
+
+impl FromKaulin for HostRef {
+    fn from_kaulin(value: RuntimeVal) -> Result<Self, KaulinError> {
+        match value {
+            RuntimeVal::Host(object) => Ok(object),
+            other => Err(KaulinError::Conversion(format!("Odotettiin isäntäoliota, saatiin {} {}", other.type_name(), other))),
+        }
+    }
+}
+
+impl IntoKaulin for HostRef {
+    fn into_kaulin(self) -> RuntimeVal {
+        RuntimeVal::Host(self)
+    }
+}
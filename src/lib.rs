@@ -0,0 +1,17 @@
+pub mod frontend;
+pub mod runtime;
+pub mod conversions;
+pub mod embed;
+pub mod host;
+pub mod vm;
+pub mod json;
+pub mod lsp;
+pub mod formatter;
+pub mod linter;
+pub mod testing;
+pub mod debugger;
+
+pub use crate::conversions::{ FromKaulin, IntoKaulin };
+pub use crate::embed::{ Backend, Interpreter, KaulinError };
+pub use crate::host::{ Context, HostFunction, HostObject, HostRef };
+pub use crate::runtime::values::RuntimeVal;
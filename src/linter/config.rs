@@ -0,0 +1,85 @@
+use std::collections::{ HashMap, HashSet };
+use std::fs;
+use std::path::{ Path, PathBuf };
+use super::Rule;
+
+// kaulin.toml in the project directory or any directory above it turns rules on and off:
+//
+//   [tarkista]
+//   varjostus = false       # comments start with #
+//
+// Other sections are left for other tools.
+
+pub const FILE_NAME: &str = "kaulin.toml";
+
+#[derive(Clone, Default)]
+pub struct Config {
+    pub disabled: HashSet<Rule>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut section = String::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let Some(name) = header.strip_suffix(']') else {
+                    return Err(format!("rivi {}: osion otsikosta puuttuu ']'", number + 1));
+                };
+                section = name.trim().to_string();
+                continue;
+            }
+            if section != "tarkista" {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("rivi {}: odotettu 'sääntö = tosi/epätosi'", number + 1));
+            };
+            let (key, value) = (key.trim().trim_matches('"'), value.trim());
+            let Some(rule) = Rule::from_name(key) else {
+                return Err(format!("rivi {}: tuntematon sääntö '{}'", number + 1, key));
+            };
+            match value {
+                "true" | "tosi" => config.disabled.remove(&rule),
+                "false" | "epätosi" => config.disabled.insert(rule),
+                _ => return Err(format!("rivi {}: säännön '{}' arvon on oltava true tai false", number + 1, key)),
+            };
+        }
+        Ok(config)
+    }
+}
+
+// Configs by directory, so each is read once however many files share it
+#[derive(Default)]
+pub struct Configs {
+    found: HashMap<PathBuf, Result<Config, String>>,
+}
+
+impl Configs {
+    // The config nearest to the file, or the defaults when there is none
+    pub fn for_file(&mut self, file: &Path) -> Result<Config, String> {
+        let directory = file.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let directory = directory.canonicalize().unwrap_or_else(|_| directory.to_path_buf());
+        self.for_directory(&directory)
+    }
+
+    fn for_directory(&mut self, directory: &Path) -> Result<Config, String> {
+        if let Some(config) = self.found.get(directory) {
+            return config.clone();
+        }
+        let path = directory.join(FILE_NAME);
+        let config = match fs::read_to_string(&path) {
+            Ok(text) => Config::parse(&text).map_err(|error| format!("{}: {}", path.display(), error)),
+            Err(_) => match directory.parent() {
+                Some(parent) => self.for_directory(parent),
+                None => Ok(Config::default()),
+            },
+        };
+        self.found.insert(directory.to_path_buf(), config.clone());
+        config
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::{ HashMap, HashSet };
+use std::fs;
+use std::path::PathBuf;
+use crate::embed::catch_panic;
+use crate::formatter::collect_files;
+use crate::frontend::lexer::tokenize;
+use crate::frontend::parser::Parser;
+use crate::frontend::symbols::index_symbols;
+use crate::frontend::syntax::{ self, Item };
+
+mod config;
+mod rules;
+
+pub use config::Config;
+
+// `kaulin tarkista` reports code that runs but is probably wrong, without running it. Each rule can
+// be turned off in kaulin.toml, and a comment turns rules off for one line:
+//
+//   olkoon _väliaikainen = 1; // tarkista: salli käyttämätön
+//   // tarkista: salli               <- every rule, on the next line
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    Unused,
+    Shadowing,
+    ConstantAssignment,
+    Arity,
+    Unreachable,
+    ConstantCondition,
+    ComparisonTypes,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 7] = [
+        Rule::Unused,
+        Rule::Shadowing,
+        Rule::ConstantAssignment,
+        Rule::Arity,
+        Rule::Unreachable,
+        Rule::ConstantCondition,
+        Rule::ComparisonTypes,
+    ];
+
+    // The name used in kaulin.toml, suppression comments and the output
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::Unused => "käyttämätön",
+            Rule::Shadowing => "varjostus",
+            Rule::ConstantAssignment => "vakion_muutos",
+            Rule::Arity => "argumenttien_määrä",
+            Rule::Unreachable => "saavuttamaton",
+            Rule::ConstantCondition => "vakioehto",
+            Rule::ComparisonTypes => "vertailun_tyypit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub rule: Rule,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+// Warnings in line order; fails with the parse error if the source does not parse
+pub fn check_source(source: &str, config: &Config) -> Result<Vec<Warning>, String> {
+    let tokens = catch_panic(|| {
+        let tokens = tokenize(source);
+        Parser::new(tokens.clone()).produce_ast();
+        tokens
+    }).map_err(|error| error.to_string())?;
+    let items = syntax::items(source);
+    let tree = syntax::TreeParser::new(&items).program()?;
+    let symbols = index_symbols(&tokens);
+    let suppressed = suppressions(&items)?;
+
+    let mut warnings = rules::check(&tree, &items, &tokens, &symbols);
+    warnings.retain(|warning| {
+        !config.disabled.contains(&warning.rule) && !suppressed.get(&warning.line).is_some_and(|rules| {
+            rules.is_empty() || rules.contains(&warning.rule)
+        })
+    });
+    warnings.sort_by_key(|warning| (warning.line, warning.column));
+    Ok(warnings)
+}
+
+// Lines and the rules allowed on them; an empty set allows every rule
+fn suppressions(items: &[Item]) -> Result<HashMap<usize, HashSet<Rule>>, String> {
+    let mut suppressed: HashMap<usize, HashSet<Rule>> = HashMap::new();
+    for item in items {
+        // A comment after code covers its own line, a comment on a line of its own the next line of code
+        let comments = item.leading.iter().map(|comment| (comment.text.as_str(), item.line))
+            .chain(Some(item.attached.trim()).filter(|text| !text.is_empty()).map(|text| (text, item.line)))
+            .chain(item.trailing.iter().map(|text| (text.as_str(), item.end_line)));
+        for (text, line) in comments {
+            let text = text.trim_start_matches("//").trim_start_matches("/*").trim_end_matches("*/").trim();
+            let Some(allowed) = text.strip_prefix("tarkista:").map(str::trim).and_then(|text| text.strip_prefix("salli")) else {
+                continue;
+            };
+            let rules = suppressed.entry(line).or_default();
+            for name in allowed.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+                match Rule::from_name(name) {
+                    Some(rule) => rules.insert(rule),
+                    None => return Err(format!("Rivi {}: tuntematon sääntö '{}'", line, name)),
+                };
+            }
+            if allowed.trim().is_empty() {
+                rules.clear();
+            }
+        }
+    }
+    Ok(suppressed)
+}
+
+// kaulin tarkista [polut...]; returns the exit code
+pub fn run(args: &[String]) -> i32 {
+    let mut paths = Vec::new();
+    for arg in args {
+        if arg.starts_with("--") {
+            eprintln!("Virhe: Tuntematon valitsin {}", arg);
+            return 2;
+        }
+        paths.push(PathBuf::from(arg));
+    }
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+    let mut files = Vec::new();
+    for path in &paths {
+        collect_files(path, &mut files);
+    }
+
+    let mut configs = config::Configs::default();
+    let (mut count, mut warned, mut failed) = (0, 0, 0);
+    for file in &files {
+        let checked = configs.for_file(file).and_then(|config| {
+            let source = fs::read_to_string(file).map_err(|error| error.to_string())?;
+            check_source(&source, &config)
+        });
+        match checked {
+            Ok(warnings) => {
+                for warning in &warnings {
+                    println!("{}:{}:{}: {}: {}", file.display(), warning.line, warning.column, warning.rule.name(), warning.message);
+                }
+                count += warnings.len();
+                warned += !warnings.is_empty() as usize;
+            }
+            Err(error) => {
+                eprintln!("Virhe: {}: {}", file.display(), error);
+                failed += 1;
+            }
+        }
+    }
+
+    if count > 0 {
+        eprintln!("{} varoitusta {} tiedostossa", count, warned);
+    }
+    match (failed, count) {
+        (0, 0) => 0,
+        (0, _) => 1,
+        _ => 2,
+    }
+}
@@ -0,0 +1,595 @@
+use std::collections::{ HashMap, HashSet };
+use crate::frontend::lexer::*;
+use crate::frontend::symbols::*;
+use crate::frontend::syntax::*;
+use crate::runtime::environment::{ create_global_env, Environment };
+use crate::runtime::formatting::placeholder_names;
+use super::{ Rule, Warning };
+
+// What a call to a known function may pass
+struct Signature {
+    // As the runtime calls it in its errors, e.g. "Funktio summa" or "Muoto.Ympyrä"
+    name: String,
+    // The parameter's name, unless it is destructured, and whether it has a default
+    parameters: Vec<(Option<String>, bool)>,
+    rest: bool,
+    named: bool,
+}
+
+// The built-ins that check how many arguments they get, or read ones that are not there
+fn builtin_arity(name: &str) -> Option<(usize, Option<usize>)> {
+    Some(match name {
+        "aika" | "syöte" => (0, Some(0)),
+        "sekunnit" | "itseisarvo" | "neliöjuuri" | "pituus" | "järjestä" | "käänteinen" | "kluku" | "lluku" | "mjono" => (1, Some(1)),
+        "pyöristä" => (1, Some(2)),
+        "satunnainen" => (2, Some(3)),
+        "maksimi" | "minimi" | "muotoile" => (1, None),
+        "tulosta" => (0, None),
+        "väitä" => (1, Some(2)),
+        "väitä_yhtä" | "väitä_eri" => (2, Some(3)),
+        "väitä_virhe" => (1, None),
+        _ => return None,
+    })
+}
+
+// The type an expression has whatever the variables hold
+#[derive(Clone, Copy, PartialEq)]
+enum Type {
+    Number,
+    Integer,
+    String,
+    Bool,
+    Array,
+}
+
+impl Type {
+    fn name(self) -> &'static str {
+        match self {
+            Type::Number => "luku",
+            Type::Integer => "kokonaisluku",
+            Type::String => "merkkijono",
+            Type::Bool => "totuusarvo",
+            Type::Array => "taulukko",
+        }
+    }
+
+    fn is_number(self) -> bool {
+        matches!(self, Type::Number | Type::Integer)
+    }
+}
+
+// The value of an expression made of literals
+#[derive(PartialEq)]
+enum Value {
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Null,
+}
+
+struct Checker<'a, 't> {
+    items: &'a [Item],
+    tokens: HashMap<(usize, usize), &'a Token<'t>>,
+    symbols: &'a SymbolIndex,
+    occurrences: HashMap<(usize, usize), usize>,
+    globals: Environment,
+    // Keyed by where the function, class or variant is named
+    signatures: HashMap<(usize, usize), Signature>,
+    // vakio nimi = literaali;
+    literal_constants: HashMap<(usize, usize), Type>,
+    // From the first to the last token of each vie-statement
+    exported: Vec<((usize, usize), (usize, usize))>,
+    warnings: Vec<Warning>,
+}
+
+pub fn check(tree: &Node, items: &[Item], tokens: &[Token], symbols: &SymbolIndex) -> Vec<Warning> {
+    let mut checker = Checker {
+        items,
+        tokens: tokens.iter().map(|token| ((token.line, token.column), token)).collect(),
+        symbols,
+        occurrences: symbols.occurrences.iter().enumerate()
+            .map(|(index, occurrence)| ((occurrence.line, occurrence.column), index))
+            .collect(),
+        globals: create_global_env(),
+        signatures: HashMap::new(),
+        literal_constants: HashMap::new(),
+        exported: Vec::new(),
+        warnings: Vec::new(),
+    };
+    checker.collect(tree);
+    checker.check_names(tokens);
+    checker.visit(tree);
+    checker.warnings
+}
+
+fn position(item: &Item) -> (usize, usize) {
+    (item.line, item.column)
+}
+
+// The items of a bracketed list, without the brackets and commas
+fn list_items(children: &[Node]) -> impl Iterator<Item = &Node> {
+    children[1..children.len() - 1].iter().step_by(2)
+}
+
+impl<'a, 't> Checker<'a, 't> {
+    fn item(&self, node: &Node) -> Option<&'a Item> {
+        match node {
+            Node::Token(index) => Some(&self.items[*index]),
+            Node::Tree(..) => None,
+        }
+    }
+
+    fn is(&self, node: &Node, token_type: TokenType) -> bool {
+        self.item(node).is_some_and(|item| item.token_type == token_type)
+    }
+
+    fn is_operator(&self, node: &Node, operator: &str) -> bool {
+        self.item(node).is_some_and(|item| item.token_type != TokenType::StringLiteral && item.text == operator)
+    }
+
+    fn first(&self, node: &Node) -> &'a Item {
+        match node {
+            Node::Token(index) => &self.items[*index],
+            Node::Tree(_, children) => self.first(&children[0]),
+        }
+    }
+
+    fn last(&self, node: &Node) -> &'a Item {
+        match node {
+            Node::Token(index) => &self.items[*index],
+            Node::Tree(_, children) => self.last(children.last().unwrap()),
+        }
+    }
+
+    fn warn(&mut self, rule: Rule, (line, column): (usize, usize), message: String) {
+        self.warnings.push(Warning { rule, line, column, message });
+    }
+
+    // The definition the name at the node refers to, with its index
+    fn definition(&self, node: &Node) -> Option<(usize, &'a Definition)> {
+        let item = self.item(node).filter(|item| item.token_type == TokenType::Identifier)?;
+        let occurrence = &self.symbols.occurrences[*self.occurrences.get(&position(item))?];
+        occurrence.definition.map(|definition| (definition, &self.symbols.definitions[definition]))
+    }
+
+    // A built-in name the file does not declare again, e.g. tosi
+    fn builtin(&self, node: &Node) -> Option<&'a str> {
+        let item = self.item(node).filter(|item| item.token_type == TokenType::Identifier)?;
+        if self.definition(node).is_some() || self.globals.constness(&item.text).is_none() {
+            return None;
+        }
+        Some(&item.text)
+    }
+
+    // Signatures, literal constants and exports, which later checks look up by position
+    fn collect(&mut self, node: &Node) {
+        let Node::Tree(kind, children) = node else { return };
+        if *kind == Kind::Seq {
+            match self.item(&children[0]).map(|item| item.token_type) {
+                Some(TokenType::Class) => self.collect_class(children),
+                Some(TokenType::Enum) => self.collect_enum(children),
+                Some(TokenType::Const) if children.len() == 4 && self.is(&children[2], TokenType::Assign) => {
+                    if let (Some(name), Some(value)) = (self.item(&children[1]), self.type_of(&children[3])) {
+                        self.literal_constants.insert(position(name), value);
+                    }
+                }
+                Some(TokenType::Export) => {
+                    let exported = (position(self.first(&children[1])), position(self.last(&children[1])));
+                    self.exported.push(exported);
+                }
+                _ => (),
+            }
+            if let Some(at) = children.iter().position(|child| self.is(child, TokenType::Fn)) {
+                if let Some(Node::Tree(_, header)) = children.get(at + 1) {
+                    let name = self.first(&header[0]);
+                    let signature = self.signature(format!("Funktio {}", name.text), &header[1]);
+                    self.signatures.insert(position(name), signature);
+                }
+            }
+        }
+        for child in children {
+            self.collect(child);
+        }
+    }
+
+    fn collect_class(&mut self, children: &[Node]) {
+        let name = self.first(&children[1]);
+        let extends = self.is(&children[2], TokenType::Extends);
+        let Some(Node::Tree(_, members)) = children.last() else { return };
+
+        let mut fields = Vec::new();
+        for member in members {
+            let Node::Tree(_, parts) = member else { continue };
+            // rakentaja(parametrit) { }
+            if let Node::Tree(_, header) = &parts[0] {
+                if self.is(&header[0], TokenType::Constructor) {
+                    let signature = self.signature(format!("Luokka {}", name.text), &header[1]);
+                    self.signatures.insert(position(name), signature);
+                    return;
+                }
+                // olkoon kenttä = arvo;
+                if self.is(&header[0], TokenType::Let) {
+                    fields.push((Some(self.first(&header[1]).text.clone()), true));
+                }
+            }
+        }
+        // Without a constructor of its own a subclass runs its parent's
+        if !extends {
+            let signature = Signature { name: format!("Luokka {}", name.text), parameters: fields, rest: false, named: true };
+            self.signatures.insert(position(name), signature);
+        }
+    }
+
+    fn collect_enum(&mut self, children: &[Node]) {
+        let name = self.first(&children[1]);
+        let Some(Node::Tree(_, variants)) = children.get(2) else { return };
+        for variant in list_items(variants) {
+            // Variants without fields are values, not functions
+            let Node::Tree(_, parts) = variant else { continue };
+            let Node::Tree(_, fields) = &parts[1] else { continue };
+            let variant = self.first(&parts[0]);
+            let parameters = list_items(fields).map(|field| (Some(self.first(field).text.clone()), false)).collect();
+            let signature = Signature { name: format!("{}.{}", name.text, variant.text), parameters, rest: false, named: true };
+            self.signatures.insert(position(variant), signature);
+        }
+    }
+
+    fn signature(&self, name: String, parameters: &Node) -> Signature {
+        let mut signature = Signature { name, parameters: Vec::new(), rest: false, named: true };
+        let Node::Tree(_, list) = parameters else { return signature };
+        for parameter in list_items(list) {
+            match parameter {
+                Node::Tree(Kind::Tight, parts) if self.is(&parts[0], TokenType::Ellipsis) => signature.rest = true,
+                Node::Tree(Kind::Seq, parts) if parts.len() == 3 && self.is(&parts[1], TokenType::Assign) => {
+                    let name = self.item(&parts[0]).map(|item| item.text.clone());
+                    signature.parameters.push((name, true));
+                }
+                Node::Token(index) => signature.parameters.push((Some(self.items[*index].text.clone()), false)),
+                _ => signature.parameters.push((None, false)),
+            }
+        }
+        signature
+    }
+
+    // käyttämätön, varjostus and vakion_muutos, which only need the names
+    fn check_names(&mut self, tokens: &[Token]) {
+        let symbols = self.symbols;
+        let mut read = vec![false; symbols.definitions.len()];
+        for occurrence in &symbols.occurrences {
+            if let (Access::Read, Some(definition)) = (occurrence.access, occurrence.definition) {
+                read[definition] = true;
+            }
+        }
+        // tulosta("%{nimi}") reads nimi from the caller's scope
+        let formatted: HashSet<String> = tokens.iter()
+            .filter(|token| token.token_type == TokenType::StringLiteral)
+            .flat_map(|token| placeholder_names(&token.value))
+            .collect();
+
+        for (index, definition) in symbols.definitions.iter().enumerate() {
+            let name = &definition.name;
+            let at = (definition.line, definition.column);
+            let unused = match definition.kind {
+                SymbolKind::Parameter => Some(format!("parametria '{}' ei käytetä", name)),
+                SymbolKind::Variable => Some(format!("muuttujaa '{}' ei käytetä", name)),
+                SymbolKind::Constant if definition.detail.starts_with("tuo") => Some(format!("tuotua nimeä '{}' ei käytetä", name)),
+                SymbolKind::Constant => Some(format!("vakiota '{}' ei käytetä", name)),
+                _ => None,
+            };
+            let exported = definition.container.is_none() && self.exported.iter().any(|(start, end)| (*start..=*end).contains(&at));
+            if let Some(message) = unused {
+                if !read[index] && !name.starts_with('_') && !formatted.contains(name) && !exported {
+                    self.warn(Rule::Unused, at, message);
+                }
+            }
+
+            if matches!(definition.kind, SymbolKind::Field | SymbolKind::Method | SymbolKind::Constructor | SymbolKind::Variant) {
+                continue;
+            }
+            if let Some(outer) = definition.shadows {
+                let message = format!("'{}' peittää rivillä {} määritellyn nimen", name, symbols.definitions[outer].line);
+                self.warn(Rule::Shadowing, at, message);
+            } else if self.globals.constness(name).is_some() {
+                self.warn(Rule::Shadowing, at, format!("'{}' peittää sisäänrakennetun nimen", name));
+            }
+        }
+
+        for occurrence in symbols.occurrences.iter().filter(|occurrence| occurrence.access == Access::Write) {
+            let at = (occurrence.line, occurrence.column);
+            let message = match occurrence.definition.map(|definition| &symbols.definitions[definition]) {
+                Some(definition) => match definition.kind {
+                    SymbolKind::Constant | SymbolKind::Function | SymbolKind::Class | SymbolKind::Enum | SymbolKind::Module => {
+                        format!("'{}' on määritelty vakioksi rivillä {}, eikä sitä voi muuttaa", occurrence.name, definition.line)
+                    }
+                    _ => continue,
+                },
+                None if self.globals.constness(&occurrence.name) == Some(true) => {
+                    format!("'{}' on sisäänrakennettu vakio, eikä sitä voi muuttaa", occurrence.name)
+                }
+                None => continue,
+            };
+            self.warn(Rule::ConstantAssignment, at, message);
+        }
+    }
+
+    fn visit(&mut self, node: &Node) {
+        let Node::Tree(kind, children) = node else { return };
+        match kind {
+            Kind::Program | Kind::Block => self.check_statements(children),
+            Kind::Arms => self.check_arms(children),
+            Kind::Conditional => self.check_condition(&children[0]),
+            Kind::Binary(3) => self.check_comparison(children),
+            Kind::Seq => {
+                match self.item(&children[0]).map(|item| item.token_type) {
+                    // Variants with fields look like calls
+                    Some(TokenType::Enum) => return,
+                    // `kun tosi` is a loop that is meant to end from inside
+                    Some(TokenType::While) if matches!(children[1], Node::Token(_)) && self.builtin(&children[1]) == Some("tosi") => (),
+                    Some(TokenType::If | TokenType::While) => self.check_condition(&children[1]),
+                    // tapaus kuvio jos vartija =>
+                    Some(TokenType::Case) if children.len() == 5 => self.check_condition(&children[3]),
+                    // toista (alustus; ehto; päivitys)
+                    Some(TokenType::For) => {
+                        if let Node::Tree(_, header) = &children[1] {
+                            if let Node::Tree(Kind::Seq, head) = &header[1] {
+                                if let [Node::Tree(Kind::Tight, _), Node::Tree(Kind::Tight, condition), _] = head.as_slice() {
+                                    self.check_condition(&condition[0]);
+                                }
+                            }
+                        }
+                    }
+                    _ => (),
+                }
+                // A function's name and parameters are not a call
+                if let Some(at) = children.iter().position(|child| self.is(child, TokenType::Fn)) {
+                    for (index, child) in children.iter().enumerate() {
+                        match child {
+                            Node::Tree(_, header) if index == at + 1 => self.visit(&header[1]),
+                            _ => self.visit(child),
+                        }
+                    }
+                    return;
+                }
+            }
+            Kind::Tight => {
+                if let [callee, Node::Tree(Kind::List(_), arguments)] = children.as_slice() {
+                    if self.is(&arguments[0], TokenType::OpenParen) && !self.is(callee, TokenType::Constructor) {
+                        self.check_call(callee, arguments);
+                    }
+                }
+            }
+            _ => (),
+        }
+        for child in children {
+            self.visit(child);
+        }
+    }
+
+    // Nothing runs after a kun-loop whose condition is always true, since loops only end when it is false
+    fn check_statements(&mut self, children: &[Node]) {
+        let statements: Vec<&Node> = children.iter()
+            .filter(|child| !matches!(self.item(child).map(|item| item.token_type), Some(TokenType::OpenBrace | TokenType::CloseBrace | TokenType::EOF)))
+            .collect();
+        for pair in statements.windows(2) {
+            let Node::Tree(Kind::Seq, parts) = pair[0] else { continue };
+            if self.is(&parts[0], TokenType::While) && self.constant(&parts[1]) == Some(Value::Bool(true)) {
+                let message = format!("tähän ei koskaan päästä, koska rivin {} kun-silmukka ei pääty", self.first(pair[0]).line);
+                self.warn(Rule::Unreachable, position(self.first(pair[1])), message);
+                return;
+            }
+        }
+    }
+
+    // Arms after `muuten` or an unguarded `tapaus nimi` never match
+    fn check_arms(&mut self, children: &[Node]) {
+        let mut catch_all = None;
+        for arm in children {
+            let Node::Tree(Kind::Arm, parts) = arm else { continue };
+            if let Some(line) = catch_all {
+                let message = format!("tähän haaraan ei koskaan päästä, koska rivin {} haara sopii kaikkiin arvoihin", line);
+                self.warn(Rule::Unreachable, position(self.first(arm)), message);
+                return;
+            }
+            let Node::Tree(_, head) = &parts[0] else { continue };
+            let binds_anything = head.len() == 3 && self.item(&head[1]).is_some_and(|item| {
+                item.token_type == TokenType::Identifier && !matches!(item.text.as_str(), "tosi" | "epätosi" | "tyhjä")
+            });
+            if self.is(&head[0], TokenType::Else) || binds_anything {
+                catch_all = Some(self.first(arm).line);
+            }
+        }
+    }
+
+    fn check_condition(&mut self, condition: &Node) {
+        if let Some(Value::Bool(value)) = self.constant(condition) {
+            let message = format!("ehto on aina {}", if value { "tosi" } else { "epätosi" });
+            self.warn(Rule::ConstantCondition, position(self.first(condition)), message);
+        }
+    }
+
+    fn check_comparison(&mut self, children: &[Node]) {
+        let (Some(left), Some(right)) = (self.type_of(&children[0]), self.type_of(&children[2])) else { return };
+        let operator = self.first(&children[1]);
+        let message = match operator.text.as_str() {
+            "==" | "!=" if left == right || left.is_number() && right.is_number() => return,
+            "==" | "!=" => format!(
+                "'{}' vertaa eri tyyppejä ({} ja {}), joten se on aina {}",
+                operator.text, left.name(), right.name(), if operator.text == "==" { "epätosi" } else { "tosi" }
+            ),
+            // Ordering needs two numbers of the same kind
+            _ if left == right && left.is_number() => return,
+            _ if left == right => format!("'{}' ei toimi tyypille {}", operator.text, left.name()),
+            _ => format!("'{}' ei voi verrata tyyppejä {} ja {}", operator.text, left.name(), right.name()),
+        };
+        self.warn(Rule::ComparisonTypes, position(operator), message);
+    }
+
+    fn check_call(&mut self, callee: &Node, arguments: &[Node]) {
+        let builtin;
+        let signature = match callee {
+            Node::Token(_) => match (self.definition(callee), self.builtin(callee)) {
+                (Some((_, definition)), _) => self.signatures.get(&(definition.line, definition.column)),
+                (None, Some(name)) => {
+                    let Some((min, max)) = builtin_arity(name) else { return };
+                    let mut parameters = vec![(None, false); min];
+                    parameters.resize(max.unwrap_or(min), (None, true));
+                    builtin = Signature { name: format!("Funktio {}", name), parameters, rest: max.is_none(), named: false };
+                    Some(&builtin)
+                }
+                _ => None,
+            },
+            // Luettelo.Muunnelma(kentät)
+            Node::Tree(Kind::Tight, parts) if parts.len() == 3 && self.is(&parts[1], TokenType::Dot) => {
+                let Some((enumeration, SymbolKind::Enum)) = self.definition(&parts[0]).map(|(index, definition)| (index, definition.kind)) else { return };
+                let variant = &self.first(&parts[2]).text;
+                self.symbols.definitions.iter()
+                    .find(|definition| definition.kind == SymbolKind::Variant && definition.container == Some(enumeration) && &definition.name == variant)
+                    .and_then(|definition| self.signatures.get(&(definition.line, definition.column)))
+            }
+            _ => None,
+        };
+        let Some(signature) = signature else { return };
+
+        let mut positional = 0;
+        let mut named = Vec::new();
+        for argument in list_items(arguments) {
+            match argument {
+                // f(...lista) passes any number
+                Node::Tree(Kind::Tight, parts) if self.is(&parts[0], TokenType::Ellipsis) => return,
+                Node::Tree(Kind::Seq, parts) if matches!(&parts[0], Node::Tree(Kind::Tight, key) if key.len() == 2 && self.is(&key[1], TokenType::Colon)) => {
+                    named.push(self.first(&parts[0]));
+                }
+                _ => positional += 1,
+            }
+        }
+
+        let at = position(self.first(callee));
+        let parameters = &signature.parameters;
+        let mut problems = Vec::new();
+        let fixed = !signature.rest && parameters.iter().all(|(_, default)| !default);
+        if positional > parameters.len() && !signature.rest {
+            let limit = if fixed { "" } else { "enintään " };
+            problems.push(format!("{} ottaa {}{} argumenttia, mutta sille annetaan {}", signature.name, limit, parameters.len(), positional));
+        }
+        if !named.is_empty() && !signature.named {
+            problems.push(format!("{} ei ota nimettyjä argumentteja", signature.name));
+        } else {
+            for name in &named {
+                match parameters.iter().position(|(parameter, _)| parameter.as_ref() == Some(&name.text)) {
+                    None => problems.push(format!("{}: parametria '{}' ei ole", signature.name, name.text)),
+                    Some(index) if index < positional => {
+                        problems.push(format!("{}: parametrille '{}' annetaan arvo kahdesti", signature.name, name.text));
+                    }
+                    _ => (),
+                }
+            }
+        }
+        let missing = parameters.iter().skip(positional).find(|(parameter, default)| {
+            !default && !parameter.as_ref().is_some_and(|parameter| named.iter().any(|name| &name.text == parameter))
+        });
+        match missing {
+            Some((Some(parameter), _)) if signature.named => {
+                problems.push(format!("{}: kutsusta puuttuu argumentti parametrille '{}'", signature.name, parameter));
+            }
+            Some(_) => {
+                let required = parameters.iter().filter(|(_, default)| !default).count();
+                let limit = if fixed { "" } else { "vähintään " };
+                problems.push(format!("{} ottaa {}{} argumenttia, mutta sille annetaan {}", signature.name, limit, required, positional + named.len()));
+            }
+            None => (),
+        }
+        for message in problems {
+            self.warn(Rule::Arity, at, message);
+        }
+    }
+
+    fn literal(&self, node: &Node) -> Option<&'a Token<'t>> {
+        self.item(node).and_then(|item| self.tokens.get(&position(item)).copied())
+    }
+
+    // The value of an expression of literals, tosi, epätosi and tyhjä
+    fn constant(&self, node: &Node) -> Option<Value> {
+        match node {
+            Node::Token(_) => {
+                let token = self.literal(node)?;
+                match token.token_type {
+                    TokenType::Integer | TokenType::Float => token.value.parse().ok().map(Value::Number),
+                    TokenType::StringLiteral => Some(Value::String(token.value.to_string())),
+                    TokenType::Identifier => match self.builtin(node)? {
+                        "tosi" => Some(Value::Bool(true)),
+                        "epätosi" => Some(Value::Bool(false)),
+                        "tyhjä" => Some(Value::Null),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }
+            Node::Tree(Kind::Tight, parts) => match parts.as_slice() {
+                [open, inner, _] if self.is(open, TokenType::OpenParen) => self.constant(inner),
+                [operator, operand] if self.is_operator(operator, "!") => match self.constant(operand)? {
+                    Value::Bool(value) => Some(Value::Bool(!value)),
+                    _ => None,
+                },
+                [operator, operand] if self.is_operator(operator, "-") => match self.constant(operand)? {
+                    Value::Number(value) => Some(Value::Number(-value)),
+                    _ => None,
+                },
+                _ => None,
+            },
+            Node::Tree(Kind::Binary(_), parts) => {
+                let operator = self.item(&parts[1])?.text.as_str();
+                let left = self.constant(&parts[0]);
+                // && and || decide on the left alone when they can
+                match (operator, &left) {
+                    ("&&", Some(Value::Bool(false))) => return Some(Value::Bool(false)),
+                    ("||", Some(Value::Bool(true))) => return Some(Value::Bool(true)),
+                    _ => (),
+                }
+                let (left, right) = (left?, self.constant(&parts[2])?);
+                match (operator, &left, &right) {
+                    ("&&" | "||", _, Value::Bool(_)) => Some(right),
+                    ("==", ..) => Some(Value::Bool(left == right)),
+                    ("!=", ..) => Some(Value::Bool(left != right)),
+                    (_, Value::Number(a), Value::Number(b)) => match operator {
+                        "<" => Some(Value::Bool(a < b)),
+                        ">" => Some(Value::Bool(a > b)),
+                        "<=" => Some(Value::Bool(a <= b)),
+                        ">=" => Some(Value::Bool(a >= b)),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }
+            Node::Tree(..) => None,
+        }
+    }
+
+    // The type of a literal, of a vakio bound to one, or of an operator that always gives a totuusarvo
+    fn type_of(&self, node: &Node) -> Option<Type> {
+        match node {
+            Node::Token(index) => match self.items[*index].token_type {
+                TokenType::Integer | TokenType::Float => Some(Type::Number),
+                TokenType::TypedInteger => Some(Type::Integer),
+                TokenType::StringLiteral | TokenType::Backtick => Some(Type::String),
+                TokenType::Identifier => match self.builtin(node) {
+                    Some("tosi" | "epätosi") => Some(Type::Bool),
+                    Some(_) => None,
+                    None => {
+                        let (_, definition) = self.definition(node)?;
+                        self.literal_constants.get(&(definition.line, definition.column)).copied()
+                    }
+                },
+                _ => None,
+            },
+            Node::Tree(Kind::Tight, parts) => match parts.as_slice() {
+                [open, inner, _] if self.is(open, TokenType::OpenParen) => self.type_of(inner),
+                [operator, _] if self.is_operator(operator, "!") => Some(Type::Bool),
+                [operator, operand] if self.is_operator(operator, "-") => self.type_of(operand).filter(|value| value.is_number()),
+                _ => None,
+            },
+            Node::Tree(Kind::Binary(3), _) => Some(Type::Bool),
+            Node::Tree(Kind::List(_), parts) if self.is(&parts[0], TokenType::OpenBracket) => Some(Type::Array),
+            Node::Tree(..) => None,
+        }
+    }
+}
@@ -0,0 +1,60 @@
+// Editor help for keywords and the built-ins of create_global_env: (name, how it is written, what it does)
+
+pub const KEYWORDS: [(&str, &str, &str); 21] = [
+    ("olkoon", "olkoon nimi = arvo;", "Määrittelee muuttujan. Arvon voi hajottaa: `olkoon [a, b] = lista;` tai `olkoon { nimi } = olio;`."),
+    ("vakio", "vakio nimi = arvo;", "Määrittelee vakion, jolle ei voi antaa uutta arvoa."),
+    ("funktio", "funktio nimi(a, b = 2, ...loput) { }", "Määrittelee funktion. Funktio palauttaa viimeisen lauseensa arvon."),
+    ("jos", "jos ehto { } muuten { }", "Suorittaa haaran, jos ehto on tosi. Haarat jakavat ympäröivän näkyvyysalueen."),
+    ("muuten", "muuten { } / muuten jos ehto { }", "Haara, joka suoritetaan, kun jos-lauseen ehto ei täyty. Valitse-lausekkeessa tapaus, joka täsmää kaikkiin arvoihin."),
+    ("kun", "kun ehto { }", "Toistaa runkoa niin kauan kuin ehto on tosi."),
+    ("toista", "toista (olkoon i = 0; i < n; i++) { } / toista (olkoon x joukosta lista) { }", "Silmukka laskurilla tai kokoelman läpi."),
+    ("valitse", "valitse arvo { tapaus kuvio => tulos, muuten => tulos }", "Vertaa arvoa tapausten kuvioihin järjestyksessä ja palauttaa ensimmäisen täsmäävän tuloksen."),
+    ("tapaus", "tapaus kuvio jos ehto => tulos", "Valitse-lausekkeen tapaus. Kuvio voi olla arvo, väli `1..=5`, taulukko, olio tai luettelon muunnelma."),
+    ("joukosta", "toista (olkoon x joukosta lista) { }", "Käy läpi taulukon, merkkijonon tai olion alkiot."),
+    ("luokka", "luokka Nimi laajentaa Isä { }", "Määrittelee luokan kenttineen, rakentajineen ja metodeineen."),
+    ("laajentaa", "luokka Nimi laajentaa Isä { }", "Perii toisen luokan kentät ja metodit."),
+    ("rakentaja", "rakentaja(a, b) { }", "Luokan rakentaja, joka suoritetaan, kun luokasta luodaan olio."),
+    ("staattinen", "staattinen funktio nimi() { }", "Kenttä tai metodi, joka kuuluu luokalle eikä sen olioille."),
+    ("luettelo", "luettelo Nimi { Muunnelma(kenttä), Toinen }", "Määrittelee luettelotyypin muunnelmineen."),
+    ("tuo", "tuo \"polku.ka\" nimellä m / tuo { a, b } moduulista \"polku.ka\"", "Tuo moduulin tai sen viemät nimet."),
+    ("moduulista", "tuo { a, b } moduulista \"polku.ka\"", "Kertoo, mistä moduulista nimet tuodaan."),
+    ("vie", "vie funktio f() { } / vie { a, b }", "Vie nimet moduulista muiden tuotaviksi."),
+    ("nimellä", "tuo \"polku.ka\" nimellä m", "Antaa tuodulle moduulille nimen."),
+    ("tämä", "tämä.kenttä", "Olio, jonka metodia kutsutaan."),
+    ("yli", "yli.metodi()", "Yliluokan metodit aliluokan metodissa."),
+];
+
+pub const BUILTINS: [(&str, &str, &str); 24] = [
+    ("tosi", "tosi", "Totuusarvo tosi."),
+    ("epätosi", "epätosi", "Totuusarvo epätosi."),
+    ("tyhjä", "tyhjä", "Arvo, joka tarkoittaa arvon puuttumista."),
+    ("tulosta", "tulosta(arvo, ...)", "Tulostaa arvot välilyönnein erotettuina. Jos ensimmäinen argumentti on merkkijono, se muotoillaan: `%{}` ja `%{0}` korvataan seuraavilla argumenteilla ja `%{nimi}` muuttujan arvolla."),
+    ("muotoile", "muotoile(malli, ...)", "Palauttaa muotoillun merkkijonon tulostamatta sitä. Malli toimii kuten tulosta-funktiossa, esim. `%{:>8.2f}`."),
+    ("aika", "aika()", "Palauttaa nykyhetken millisekunteina vuoden 1970 alusta."),
+    ("sekunnit", "sekunnit(millisekunnit)", "Muuntaa millisekunnit kokonaisiksi sekunneiksi."),
+    ("itseisarvo", "itseisarvo(luku)", "Palauttaa luvun itseisarvon."),
+    ("pyöristä", "pyöristä(luku, desimaalit = 0)", "Pyöristää luvun annettuun määrään desimaaleja."),
+    ("neliöjuuri", "neliöjuuri(luku)", "Palauttaa luvun neliöjuuren. Negatiivinen luku on virhe."),
+    ("syöte", "syöte()", "Lukee rivin käyttäjältä ja palauttaa sen merkkijonona ilman rivinvaihtoa."),
+    ("satunnainen", "satunnainen(alku, loppu, tyyppi)", "Palauttaa satunnaisen luvun väliltä alku–loppu, loppu pois lukien. Tyyppi on \"kokonaisluku\" (oletus) tai \"liukuluku\"."),
+    ("maksimi", "maksimi(lista)", "Palauttaa taulukon suurimman luvun."),
+    ("minimi", "minimi(lista)", "Palauttaa taulukon pienimmän luvun."),
+    ("pituus", "pituus(lista)", "Palauttaa taulukon alkioiden määrän."),
+    ("järjestä", "järjestä(lista)", "Palauttaa taulukon luvut tai merkkijonot nousevassa järjestyksessä."),
+    ("käänteinen", "käänteinen(lista)", "Palauttaa taulukon alkiot käänteisessä järjestyksessä."),
+    ("kluku", "kluku(arvo)", "Muuntaa luvun tai merkkijonon kokonaisluvuksi pyöristäen alaspäin."),
+    ("lluku", "lluku(arvo)", "Muuntaa luvun tai merkkijonon liukuluvuksi."),
+    ("mjono", "mjono(arvo)", "Muuntaa arvon merkkijonoksi."),
+    ("väitä", "väitä(ehto, viesti)", "Virhe, jos ehto on epätosi. Viesti kertoo, mikä väite epäonnistui."),
+    ("väitä_yhtä", "väitä_yhtä(saatu, odotettu, viesti)", "Virhe, jos arvot eroavat. Virheilmoitus näyttää odotetun ja saadun arvon erot."),
+    ("väitä_eri", "väitä_eri(saatu, odottamaton, viesti)", "Virhe, jos arvot ovat yhtä suuret."),
+    ("väitä_virhe", "väitä_virhe(funktio, ...argumentit)", "Kutsuu funktiota ja palauttaa sen virheilmoituksen. Virhe, jos funktio ei aiheuta virhettä."),
+];
+
+pub fn keyword(name: &str) -> Option<(&'static str, &'static str)> {
+    KEYWORDS.iter().find(|(keyword, _, _)| *keyword == name).map(|&(_, usage, doc)| (usage, doc))
+}
+
+pub fn builtin(name: &str) -> Option<(&'static str, &'static str)> {
+    BUILTINS.iter().find(|(builtin, _, _)| *builtin == name).map(|&(_, usage, doc)| (usage, doc))
+}
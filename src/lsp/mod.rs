@@ -0,0 +1,512 @@
+use std::collections::{ HashMap, HashSet };
+use std::io::{ self, Write };
+use crate::embed::catch_panic;
+use crate::frontend::lexer::*;
+use crate::frontend::parser::Parser;
+use crate::frontend::resolver::resolve_program;
+use crate::frontend::symbols::*;
+use crate::json::*;
+use crate::runtime::environment::create_global_env;
+use crate::runtime::values::RuntimeVal;
+
+mod docs;
+
+// A language server over stdin and stdout, started with `kaulin lsp`. Editors send whole documents
+// on every change; each is lexed, parsed and resolved again for diagnostics, and navigation uses
+// the symbol index of its tokens, so it keeps working while the code does not parse.
+
+const ERROR: u8 = 1;
+const WARNING: u8 = 2;
+
+// Returns the exit code: 0 when the editor asked the server to shut down before exiting
+pub fn run() -> i32 {
+    let mut server = Server { documents: HashMap::new(), shutdown: false };
+    match server.serve(&mut io::stdin().lock(), &mut io::stdout().lock()) {
+        Ok(()) if server.shutdown => 0,
+        Ok(()) => 1,
+        Err(error) => {
+            eprintln!("Virhe: kielipalvelimen yhteys katkesi: {}", error);
+            1
+        }
+    }
+}
+
+struct Server {
+    documents: HashMap<String, String>,
+    shutdown: bool,
+}
+
+type Response = Result<Json, (i64, String)>;
+
+impl Server {
+    fn serve(&mut self, input: &mut impl io::BufRead, output: &mut impl Write) -> io::Result<()> {
+        while let Some(message) = read_message(input)? {
+            let method = message.get("method").as_str().unwrap_or_default().to_string();
+            let params = message.get("params");
+            let id = message.get("id");
+            if method == "exit" {
+                return Ok(());
+            }
+            // Replies to requests of our own; the server sends none
+            if method.is_empty() {
+                continue;
+            }
+
+            if id.is_null() {
+                for notification in self.notify(&method, params) {
+                    write_message(output, &notification)?;
+                }
+                continue;
+            }
+            let result = if self.shutdown {
+                Err((-32600, "Palvelin on sammumassa".to_string()))
+            } else {
+                self.request(&method, params)
+            };
+            let response = match result {
+                Ok(result) => Json::object(vec![("jsonrpc", "2.0".into()), ("id", id.clone()), ("result", result)]),
+                Err((code, message)) => Json::object(vec![
+                    ("jsonrpc", "2.0".into()),
+                    ("id", id.clone()),
+                    ("error", Json::object(vec![("code", code.into()), ("message", message.into())])),
+                ]),
+            };
+            write_message(output, &response)?;
+        }
+        Ok(())
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Response {
+        if method == "initialize" {
+            return Ok(initialize());
+        }
+        if method == "shutdown" {
+            self.shutdown = true;
+            return Ok(Json::Null);
+        }
+
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or_default();
+        let Some(text) = self.documents.get(uri) else {
+            return match method {
+                "textDocument/completion" | "textDocument/references" | "textDocument/documentSymbol" => Ok(Json::Array(Vec::new())),
+                "textDocument/hover" | "textDocument/definition" => Ok(Json::Null),
+                _ => Err((-32601, format!("Tuntematon metodi {}", method))),
+            };
+        };
+        let (line, column) = from_position(text, params.get("position"));
+        match method {
+            "textDocument/completion" => Ok(completion(text)),
+            "textDocument/hover" => Ok(hover(text, line, column)),
+            "textDocument/definition" => Ok(definition(uri, text, line, column)),
+            "textDocument/references" => {
+                let declarations = params.get("context").get("includeDeclaration").as_bool().unwrap_or(true);
+                Ok(references(uri, text, line, column, declarations))
+            }
+            "textDocument/rename" => rename(uri, text, line, column, params.get("newName").as_str().unwrap_or_default()),
+            "textDocument/documentSymbol" => Ok(document_symbols(text)),
+            _ => Err((-32601, format!("Tuntematon metodi {}", method))),
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or_default().to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").get("text").as_str().unwrap_or_default().to_string();
+                self.documents.insert(uri.clone(), text);
+            }
+            // Documents are synced whole, so the last change is the new text
+            "textDocument/didChange" => match params.get("contentChanges").as_array().last().and_then(|change| change.get("text").as_str()) {
+                Some(text) => {
+                    self.documents.insert(uri.clone(), text.to_string());
+                }
+                None => return Vec::new(),
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![publish(&uri, Vec::new())];
+            }
+            _ => return Vec::new(),
+        }
+        let text = &self.documents[&uri];
+        vec![publish(&uri, diagnostics(text))]
+    }
+}
+
+fn initialize() -> Json {
+    Json::object(vec![
+        ("capabilities", Json::object(vec![
+            ("textDocumentSync", Json::object(vec![("openClose", true.into()), ("change", 1usize.into())])),
+            ("completionProvider", Json::object(Vec::new())),
+            ("hoverProvider", true.into()),
+            ("definitionProvider", true.into()),
+            ("referencesProvider", true.into()),
+            ("renameProvider", true.into()),
+            ("documentSymbolProvider", true.into()),
+        ])),
+        ("serverInfo", Json::object(vec![("name", "kaulin".into()), ("version", env!("CARGO_PKG_VERSION").into())])),
+    ])
+}
+
+fn analyze(text: &str) -> Option<(Vec<Token<'_>>, SymbolIndex)> {
+    let tokens = catch_panic(|| tokenize(text)).ok()?;
+    let index = index_symbols(&tokens);
+    Some((tokens, index))
+}
+
+// LSP counts lines and characters from 0, characters in UTF-16 units; tokens count both from 1 in chars
+fn to_position(text: &str, line: usize, column: usize) -> Json {
+    let source = text.split('\n').nth(line.saturating_sub(1)).unwrap_or_default();
+    let character: usize = source.chars().take(column.saturating_sub(1)).map(char::len_utf16).sum();
+    Json::object(vec![("line", line.saturating_sub(1).into()), ("character", character.into())])
+}
+
+fn from_position(text: &str, position: &Json) -> (usize, usize) {
+    let line = position.get("line").as_u64().unwrap_or(0) as usize;
+    let character = position.get("character").as_u64().unwrap_or(0) as usize;
+    let source = text.split('\n').nth(line).unwrap_or_default();
+    let mut units = 0;
+    let mut column = 1;
+    for c in source.chars() {
+        if units >= character {
+            break;
+        }
+        units += c.len_utf16();
+        column += 1;
+    }
+    (line + 1, column)
+}
+
+fn range(text: &str, start: (usize, usize), end: (usize, usize)) -> Json {
+    Json::object(vec![("start", to_position(text, start.0, start.1)), ("end", to_position(text, end.0, end.1))])
+}
+
+fn name_range(text: &str, line: usize, column: usize, name: &str) -> Json {
+    range(text, (line, column), (line, column + name.chars().count()))
+}
+
+fn location(uri: &str, range: Json) -> Json {
+    Json::object(vec![("uri", uri.into()), ("range", range)])
+}
+
+fn markdown(value: String) -> Json {
+    Json::object(vec![("kind", "markdown".into()), ("value", value.into())])
+}
+
+fn code(source: &str) -> String {
+    format!("```kaulin\n{}\n```", source)
+}
+
+fn help(usage: &str, doc: &str) -> String {
+    format!("{}\n\n{}", code(usage), doc)
+}
+
+fn hover(text: &str, line: usize, column: usize) -> Json {
+    let Some((tokens, index)) = analyze(text) else { return Json::Null };
+    let value = match index.occurrence_at(line, column) {
+        Some(Occurrence { definition: Some(definition), .. }) => code(&index.definitions[*definition].detail),
+        Some(occurrence) => match docs::builtin(&occurrence.name).or_else(|| docs::keyword(&occurrence.name)) {
+            Some((usage, doc)) => help(usage, doc),
+            None => return Json::Null,
+        },
+        None => {
+            let keyword = tokens.iter().find(|token| {
+                token.token_type != TokenType::Identifier && token.line == line
+                    && (token.column..=token.column + token.value.chars().count()).contains(&column)
+            });
+            match keyword.and_then(|token| docs::keyword(&token.value)) {
+                Some((usage, doc)) => help(usage, doc),
+                None => return Json::Null,
+            }
+        }
+    };
+    Json::object(vec![("contents", markdown(value))])
+}
+
+fn completion_kind(kind: SymbolKind) -> usize {
+    match kind {
+        SymbolKind::Variable | SymbolKind::Parameter => 6,
+        SymbolKind::Constant => 21,
+        SymbolKind::Function => 3,
+        SymbolKind::Class => 7,
+        SymbolKind::Method => 2,
+        SymbolKind::Constructor => 4,
+        SymbolKind::Field => 5,
+        SymbolKind::Enum => 13,
+        SymbolKind::Variant => 20,
+        SymbolKind::Module => 9,
+    }
+}
+
+fn completion_item(label: &str, kind: usize, detail: &str, documentation: Option<&str>) -> Json {
+    let mut item = vec![("label", label.into()), ("kind", kind.into()), ("detail", detail.into())];
+    if let Some(documentation) = documentation {
+        item.push(("documentation", markdown(documentation.to_string())));
+    }
+    Json::object(item)
+}
+
+// Names of the document, then built-ins and keywords; the editor filters them by what has been typed
+fn completion(text: &str) -> Json {
+    let mut items = Vec::new();
+    let mut seen = HashSet::new();
+    if let Some((_, index)) = analyze(text) {
+        for definition in &index.definitions {
+            let member = matches!(definition.kind, SymbolKind::Field | SymbolKind::Method | SymbolKind::Constructor | SymbolKind::Variant);
+            if !member && seen.insert(definition.name.clone()) {
+                items.push(completion_item(&definition.name, completion_kind(definition.kind), &definition.detail, None));
+            }
+        }
+    }
+    for (name, value) in create_global_env().visible_variables() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let kind = match value {
+            RuntimeVal::NativeFunction(_) | RuntimeVal::Function(_) => 3,
+            _ => 21,
+        };
+        let (usage, doc) = docs::builtin(&name).unwrap_or(("", ""));
+        items.push(completion_item(&name, kind, usage, Some(doc).filter(|doc| !doc.is_empty())));
+    }
+    for (keyword, usage, doc) in docs::KEYWORDS {
+        if seen.insert(keyword.to_string()) {
+            items.push(completion_item(keyword, 14, usage, Some(doc)));
+        }
+    }
+    Json::Array(items)
+}
+
+// The definition of the name under the cursor
+fn resolve_at(text: &str, line: usize, column: usize) -> Option<(Vec<Token<'_>>, SymbolIndex, usize)> {
+    let (tokens, index) = analyze(text)?;
+    let definition = index.occurrence_at(line, column)?.definition?;
+    Some((tokens, index, definition))
+}
+
+fn definition(uri: &str, text: &str, line: usize, column: usize) -> Json {
+    match resolve_at(text, line, column) {
+        Some((_, index, definition)) => {
+            let definition = &index.definitions[definition];
+            location(uri, name_range(text, definition.line, definition.column, &definition.name))
+        }
+        None => Json::Null,
+    }
+}
+
+fn references(uri: &str, text: &str, line: usize, column: usize, declarations: bool) -> Json {
+    let Some((_, index, definition)) = resolve_at(text, line, column) else { return Json::Array(Vec::new()) };
+    let references = index
+        .occurrences_of(definition)
+        .filter(|occurrence| declarations || occurrence.access != Access::Declaration)
+        .map(|occurrence| location(uri, name_range(text, occurrence.line, occurrence.column, &occurrence.name)))
+        .collect();
+    Json::Array(references)
+}
+
+fn rename(uri: &str, text: &str, line: usize, column: usize, new_name: &str) -> Response {
+    let tokens = catch_panic(|| tokenize(new_name)).unwrap_or_default();
+    if !matches!(tokens.as_slice(), [name, _] if name.token_type == TokenType::Identifier && name.value == new_name) {
+        return Err((-32602, format!("'{}' ei kelpaa nimeksi", new_name)));
+    }
+
+    let Some((tokens, index, definition)) = resolve_at(text, line, column) else {
+        return Err((-32602, "Kohdassa ei ole tässä tiedostossa määriteltyä nimeä".to_string()));
+    };
+    let target = &index.definitions[definition];
+    if matches!(target.kind, SymbolKind::Field | SymbolKind::Method | SymbolKind::Constructor | SymbolKind::Variant) {
+        return Err((-32602, "Luokan jäseniä ja luettelon muunnelmia ei voi vielä nimetä uudelleen".to_string()));
+    }
+    let named = tokens.iter().any(|token| {
+        token.line == target.line && token.column == target.column && token.token_type == TokenType::Identifier
+    });
+    if !named {
+        return Err((-32602, format!("Nimi '{}' tulee moduulin tiedostonimestä; anna sille nimi nimellä-avainsanalla", target.name)));
+    }
+
+    let edits = index
+        .occurrences_of(definition)
+        .map(|occurrence| Json::object(vec![
+            ("range", name_range(text, occurrence.line, occurrence.column, &occurrence.name)),
+            ("newText", new_name.into()),
+        ]))
+        .collect();
+    Ok(Json::object(vec![("changes", Json::Object(vec![(uri.to_string(), Json::Array(edits))]))]))
+}
+
+fn symbol_kind(kind: SymbolKind) -> usize {
+    match kind {
+        SymbolKind::Variable | SymbolKind::Parameter => 13,
+        SymbolKind::Constant => 14,
+        SymbolKind::Function => 12,
+        SymbolKind::Class => 5,
+        SymbolKind::Method => 6,
+        SymbolKind::Constructor => 9,
+        SymbolKind::Field => 8,
+        SymbolKind::Enum => 10,
+        SymbolKind::Variant => 22,
+        SymbolKind::Module => 2,
+    }
+}
+
+// An outline of functions, classes, enums and top-level names; the locals of functions are left out
+fn document_symbols(text: &str) -> Json {
+    match analyze(text) {
+        Some((_, index)) => Json::Array(symbols_in(text, &index, None)),
+        None => Json::Array(Vec::new()),
+    }
+}
+
+fn symbols_in(text: &str, index: &SymbolIndex, container: Option<usize>) -> Vec<Json> {
+    let local = container.is_some_and(|container| {
+        matches!(index.definitions[container].kind, SymbolKind::Function | SymbolKind::Method | SymbolKind::Constructor)
+    });
+    let mut symbols = Vec::new();
+    for (id, definition) in index.definitions.iter().enumerate() {
+        let shown = match definition.kind {
+            SymbolKind::Parameter => false,
+            SymbolKind::Variable | SymbolKind::Constant | SymbolKind::Module => !local,
+            _ => true,
+        };
+        if definition.container != container || !shown {
+            continue;
+        }
+        let selection = name_range(text, definition.line, definition.column, &definition.name);
+        let name_end = (definition.line, definition.column + definition.name.chars().count());
+        let whole = range(text, definition.start.min((definition.line, definition.column)), definition.end.max(name_end));
+        symbols.push(Json::object(vec![
+            ("name", definition.name.as_str().into()),
+            ("detail", definition.detail.as_str().into()),
+            ("kind", symbol_kind(definition.kind).into()),
+            ("range", whole),
+            ("selectionRange", selection),
+            ("children", Json::Array(symbols_in(text, index, Some(id)))),
+        ]));
+    }
+    symbols
+}
+
+struct Problem {
+    line: usize,
+    // None marks the whole line
+    column: Option<usize>,
+    severity: u8,
+    message: String,
+}
+
+// Parse errors and warnings carry their line, or the parser's position tells where it stopped;
+// resolver errors carry their line and name the variable, which the symbol index finds on it
+fn check(text: &str) -> Vec<Problem> {
+    let tokens = match catch_panic(|| tokenize(text)) {
+        Ok(tokens) => tokens,
+        Err(error) => return vec![problem(&error.to_string(), ERROR, (1, 1))],
+    };
+    let index = index_symbols(&tokens);
+    let mut parser = Parser::new(tokens);
+    let program = catch_panic(|| parser.produce_ast());
+
+    let mut problems: Vec<Problem> = parser.warnings.iter().map(|warning| problem(warning, WARNING, (1, 1))).collect();
+    match program {
+        Err(error) => problems.push(problem(&error.to_string(), ERROR, parser.last_position())),
+        Ok(program) => {
+            let globals = create_global_env();
+            let resolution = resolve_program(&program, &|name| globals.constness(name));
+            for error in &resolution.errors {
+                problems.extend(resolver_problems(error, &index));
+            }
+        }
+    }
+    problems
+}
+
+// "Rivi 3, sarake 5: ...", "Rivi 3: ..." or a token printed as "line: 3, column: 5"
+fn problem(message: &str, severity: u8, fallback: (usize, usize)) -> Problem {
+    let number = |text: &str| -> Option<(usize, usize)> {
+        let digits = text.chars().take_while(char::is_ascii_digit).count();
+        Some((text[..digits].parse().ok()?, digits))
+    };
+
+    if let Some(rest) = message.strip_prefix("Rivi ") {
+        if let Some((line, digits)) = number(rest) {
+            let rest = &rest[digits..];
+            if let Some(rest) = rest.strip_prefix(", sarake ") {
+                if let Some((column, digits)) = number(rest) {
+                    let message = rest[digits..].trim_start_matches(':').trim().to_string();
+                    return Problem { line, column: Some(column), severity, message };
+                }
+            }
+            let message = rest.trim_start_matches(':').trim().to_string();
+            let column = token_position(&message).map(|(_, column)| column);
+            return Problem { line, column, severity, message };
+        }
+    }
+    let (line, column) = token_position(message).unwrap_or(fallback);
+    Problem { line, column: Some(column), severity, message: message.to_string() }
+}
+
+fn token_position(message: &str) -> Option<(usize, usize)> {
+    let line = message.split("line: ").nth(1)?.split(|c: char| !c.is_ascii_digit()).next()?.parse().ok()?;
+    let column = message.split("column: ").nth(1)?.split(|c: char| !c.is_ascii_digit()).next()?.parse().ok()?;
+    Some((line, column))
+}
+
+fn resolver_problems(error: &str, index: &SymbolIndex) -> Vec<Problem> {
+    let mut problem = problem(error, ERROR, (1, 1));
+    let message = &problem.message;
+    let quoted = message.split('\'').nth(1);
+    let name = quoted.or_else(|| message.split_once("muuttujaa ").and_then(|(_, rest)| rest.split(',').next()));
+    let Some(name) = name else {
+        return vec![problem];
+    };
+
+    // The line is where the statement starts, and the name can be on a later line of it
+    let uses: Vec<&Occurrence> = index.occurrences.iter()
+        .filter(|occurrence| occurrence.name == name && occurrence.line >= problem.line && occurrence.access != Access::Declaration)
+        .collect();
+    let located = match quoted {
+        Some(_) => uses.iter().find(|occurrence| occurrence.definition.is_none()),
+        None => uses.iter().find(|occurrence| occurrence.access == Access::Write),
+    };
+    if let Some(occurrence) = located.or(uses.first()) {
+        problem.line = occurrence.line;
+        problem.column = Some(occurrence.column);
+    }
+    vec![problem]
+}
+
+fn diagnostics(text: &str) -> Vec<Json> {
+    check(text)
+        .into_iter()
+        .map(|problem| {
+            let source = text.split('\n').nth(problem.line.saturating_sub(1)).unwrap_or_default();
+            let line_end = source.trim_end_matches('\r').chars().count() + 1;
+            let (start, end) = match problem.column {
+                // A word, or a single character when the position is not on one
+                Some(column) => {
+                    let word = source
+                        .chars()
+                        .skip(column.saturating_sub(1))
+                        .take_while(|&c| c.is_alphanumeric() || c == '_')
+                        .count()
+                        .max(1);
+                    (column, (column + word).min(line_end.max(column + 1)))
+                }
+                None => (source.chars().take_while(|c| c.is_whitespace()).count() + 1, line_end),
+            };
+            Json::object(vec![
+                ("range", range(text, (problem.line, start), (problem.line, end))),
+                ("severity", (problem.severity as usize).into()),
+                ("source", "kaulin".into()),
+                ("message", problem.message.into()),
+            ])
+        })
+        .collect()
+}
+
+fn publish(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        ("params", Json::object(vec![("uri", uri.into()), ("diagnostics", Json::Array(diagnostics))])),
+    ])
+}
@@ -1,48 +1,18 @@
-#![allow(warnings)]
-
-mod runtime;
-mod frontend;
-
-use crate::frontend::ast::*;
-use crate::frontend::lexer::*;
-use crate::frontend::parser::*;
-use crate::runtime::environment::*;
-use crate::runtime::interpreter::*;
-use crate::runtime::values::*;
+use kaulin::{ debugger, formatter, linter, lsp, testing, Backend, Interpreter };
 use std::io::{ self, Write };
-use std::fs;
 use std::env;
-use std::fmt;
-
-impl fmt::Display for RuntimeVal {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            RuntimeVal::Null => write!(f, "null"),
-            RuntimeVal::Bool(b) => write!(f, "{}", b),
-            RuntimeVal::Integer(i) => write!(f, "{}", i), 
-            RuntimeVal::Number(n) => write!(f, "{}", n),
-            RuntimeVal::String(s) => write!(f, "{}", s),
-            RuntimeVal::Object(obj) => {
-                let properties: Vec<String> = obj.iter()
-                    .map(|(key, value)| format!("{}: {}", key, value))
-                    .collect();
-                write!(f, "{{{}}}", properties.join(", "))
-            },
-            RuntimeVal::Array(arr) => {
-                let elements: Vec<String> = arr.iter()
-                    .map(|value| format!("{}", value))
-                    .collect();
-                write!(f, "[{}]", elements.join(", "))
-            },
-            RuntimeVal::NativeFunction(_) => write!(f, "NativeFunction"),
-            RuntimeVal::Function(func) => write!(f, "Function({})", func.parameters.join(", ")),
-        }
-    }
+use std::process;
+
+fn new_interpreter(backend: Backend, optimization: u8) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_backend(backend);
+    interpreter.set_optimization_level(optimization);
+    interpreter.set_warning_handler(|warning| eprintln!("Varoitus: {}", warning));
+    interpreter
 }
 
-fn repl() {
-    let global_env = create_global_env();
-    let mut env = Environment::new(Some(Box::new(global_env)));
+fn repl(backend: Backend, optimization: u8) {
+    let mut interpreter = new_interpreter(backend, optimization);
 
     // INITIALIZE REPL
     println!("\nKaulin Repl v0.1!\nKirjoita 'exit' tai ' ' poistuaksesi repl:stä.");
@@ -60,43 +30,66 @@ fn repl() {
             break;
         }
 
-        // Produce AST From source-code
-        let tokens = tokenize(&input);
-        let program = Parser::new(tokens).produce_ast();
-        let stmt = Stmt::Program(program);
-
-        let result = evaluate(&stmt, &mut env);
-        println!("{:?}", result);
-
+        // An error ends the line, not the session
+        let result = interpreter.eval_str(&input);
+        match result {
+            Ok(value) => println!("{:?}", value),
+            Err(error) => eprintln!("Virhe: {}", error),
+        }
     }
 }
 
-fn run(filename: &str) {
+fn run(filename: &str, backend: Backend, optimization: u8) {
     if !filename.ends_with(".ka") {
         println!("Virhe: Virheellinen tiedostomuoto. Anna .ka-tiedosto.");
         return;
     }
 
-    let global_env = create_global_env();
-    let mut env = Environment::new(Some(Box::new(global_env)));
-
-    let input = fs::read_to_string(filename).expect("Tiedostoa ei voitu lukea");
-    let tokens = tokenize(&input);
-    let program = Parser::new(tokens).produce_ast();
-    let stmt = Stmt::Program(program);
-
-    let _ = evaluate(&stmt, &mut env);
+    let mut interpreter = new_interpreter(backend, optimization);
+    let result = interpreter.eval_file(filename);
+    if let Err(error) = result {
+        eprintln!("Virhe: {}", error);
+        process::exit(1);
+    }
 }
 
 fn main() {
+    // Editor and project tools are subcommands; anything else runs a file or the REPL
     let args: Vec<String> = env::args().collect();
-    match args.len() {
-        2 => {
-            let filename = &args[1];
-            run(filename);
-        },
-        _ => {
-            repl();
+    match args.get(1).map(String::as_str) {
+        Some("lsp") => process::exit(lsp::run()),
+        Some("muotoile") => process::exit(formatter::run(&args[2..])),
+        Some("tarkista") => process::exit(linter::run(&args[2..])),
+        Some("testaa") => process::exit(testing::run(&args[2..])),
+        Some("debug") => process::exit(debugger::run(&args[2..])),
+        _ => (),
+    }
+
+    let mut backend = Backend::TreeWalker;
+    let mut optimization = 0;
+    let mut files = Vec::new();
+    for arg in args.into_iter().skip(1) {
+        match arg.as_str() {
+            // Runs scripts on the bytecode VM while it replaces the tree-walker
+            "--vm" => backend = Backend::Bytecode,
+            // -O is the same as -O1
+            "-O0" => optimization = 0,
+            "-O" | "-O1" => optimization = 1,
+            "-O2" => optimization = 2,
+            flag if flag.starts_with("-O") => {
+                eprintln!("Virhe: Tuntematon optimointitaso {}, käytä -O0, -O1 tai -O2", flag);
+                process::exit(1);
+            }
+            flag if flag.starts_with("--") => {
+                eprintln!("Virhe: Tuntematon valitsin {}", flag);
+                process::exit(1);
+            }
+            _ => files.push(arg),
         }
     }
-}
\ No newline at end of file
+
+    match files.as_slice() {
+        [filename] => run(filename, backend, optimization),
+        _ => repl(backend, optimization),
+    }
+}
@@ -0,0 +1,121 @@
+use crate::embed::catch_panic;
+use crate::runtime::environment::Environment;
+use crate::runtime::eval::expressions::call_value;
+use crate::runtime::values::*;
+
+// Assertions for `kaulin testaa`. A failed assertion is an ordinary error whose message says what
+// was expected, so it can be used in any program.
+
+// väitä(ehto, viesti)
+pub fn assert_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
+    match args.first() {
+        Some(RuntimeVal::Bool(true)) => MK_NULL(),
+        Some(RuntimeVal::Bool(false)) => panic!("{}", failure("väitä", args.get(1))),
+        _ => panic!("väitä-funktio odottaa totuusarvoa ensimmäisenä argumenttina"),
+    }
+}
+
+// väitä_yhtä(saatu, odotettu, viesti)
+pub fn assert_equal_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
+    let [actual, expected] = two_arguments("väitä_yhtä", &args);
+    if !actual.equals(expected) {
+        panic!("{}\n{}", failure("väitä_yhtä", args.get(2)), difference(expected, actual));
+    }
+    MK_NULL()
+}
+
+// väitä_eri(saatu, odottamaton, viesti)
+pub fn assert_not_equal_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
+    let [actual, unexpected] = two_arguments("väitä_eri", &args);
+    if actual.equals(unexpected) {
+        panic!("{}\nmolemmat arvot ovat {}", failure("väitä_eri", args.get(2)), shown(actual));
+    }
+    MK_NULL()
+}
+
+// väitä_virhe(funktio, ...argumentit) calls the function and returns the message of the error it must raise
+pub fn assert_error_function(args: Vec<RuntimeVal>, env: &Environment) -> RuntimeVal {
+    let Some(function) = args.first().cloned() else {
+        panic!("väitä_virhe-funktio odottaa funktiota ensimmäisenä argumenttina");
+    };
+    match catch_panic(|| call_value(function, args[1..].to_vec(), Vec::new(), env)) {
+        Ok(value) => panic!("väitä_virhe epäonnistui: funktio palautti arvon {} ilman virhettä", shown(&value)),
+        Err(error) => MK_STRING(error.to_string()),
+    }
+}
+
+fn two_arguments<'a>(name: &str, args: &'a [RuntimeVal]) -> [&'a RuntimeVal; 2] {
+    match args {
+        [first, second, ..] => [first, second],
+        _ => panic!("{}-funktio odottaa kahta argumenttia, mutta saatiin {}", name, args.len()),
+    }
+}
+
+fn failure(name: &str, message: Option<&RuntimeVal>) -> String {
+    match message {
+        Some(message) => format!("{} epäonnistui: {}", name, message),
+        None => format!("{} epäonnistui", name),
+    }
+}
+
+// A value as it would be written in code, so "1" and 1 look different
+pub(crate) fn shown(value: &RuntimeVal) -> String {
+    match value {
+        RuntimeVal::String(text) => format!("{:?}", text),
+        RuntimeVal::Array(elements) => format!("[{}]", elements.iter().map(shown).collect::<Vec<_>>().join(", ")),
+        RuntimeVal::Object(properties) => {
+            let properties: Vec<String> = properties.iter().map(|(key, value)| format!("{}: {}", key, shown(value))).collect();
+            format!("{{{}}}", properties.join(", "))
+        }
+        other => other.to_string(),
+    }
+}
+
+// The expected value with '-', what was got with '+'; multi-line strings are compared line by line
+fn difference(expected: &RuntimeVal, actual: &RuntimeVal) -> String {
+    let mut lines = vec!["- odotettu".to_string(), "+ saatu".to_string()];
+    match (expected, actual) {
+        (RuntimeVal::String(expected), RuntimeVal::String(actual)) if expected.contains('\n') || actual.contains('\n') => {
+            let expected: Vec<&str> = expected.split('\n').collect();
+            let actual: Vec<&str> = actual.split('\n').collect();
+            lines.extend(line_difference(&expected, &actual));
+        }
+        _ => {
+            lines.push(format!("- {}", shown(expected)));
+            lines.push(format!("+ {}", shown(actual)));
+        }
+    }
+    lines.join("\n")
+}
+
+// Lines common to both are kept, using the longest common subsequence of the lines
+fn line_difference(expected: &[&str], actual: &[&str]) -> Vec<String> {
+    let (n, m) = (expected.len(), actual.len());
+    if n * m > 1_000_000 {
+        return expected.iter().map(|line| format!("- {}", line)).chain(actual.iter().map(|line| format!("+ {}", line))).collect();
+    }
+    // common[i][j]: how many lines expected[i..] and actual[j..] have in common
+    let mut common = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i][j] = if expected[i] == actual[j] { common[i + 1][j + 1] + 1 } else { common[i + 1][j].max(common[i][j + 1]) };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            lines.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(format!("- {}", expected[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", actual[j]));
+            j += 1;
+        }
+    }
+    lines
+}
@@ -1,18 +1,29 @@
 use crate::runtime::values::*;
 use crate::runtime::native_functions::*;
+use crate::runtime::assertions::*;
+use crate::runtime::modules::ModuleRegistry;
+use crate::host::HostFunction;
 use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// Scopes with more variables than this also keep an index from name to position
+const INDEXED_SCOPE_SIZE: usize = 32;
 
 pub fn create_global_env() -> Environment {
     let mut env = Environment::new(None);
+    env.scope.borrow_mut().modules = Some(Rc::new(RefCell::new(ModuleRegistry::default())));
     env.declare_var("tosi".to_string(), MK_BOOL(true), true);
     env.declare_var("epätosi".to_string(), MK_BOOL(false), true);
     env.declare_var("tyhjä".to_string(), MK_NULL(), true);
     env.declare_var("tulosta".to_string(), MK_NATIVE_FN(Rc::new(print_function)), true);
+    env.declare_var("muotoile".to_string(), MK_NATIVE_FN(Rc::new(format_function)), true);
     env.declare_var("aika".to_string(), MK_NATIVE_FN(Rc::new(time_function)), true);
     env.declare_var("sekunnit".to_string(), MK_NATIVE_FN(Rc::new(millis_to_seconds_function)), true);
     env.declare_var("itseisarvo".to_string(), MK_NATIVE_FN(Rc::new(abs_function)), true);
-    env.declare_var("pyöristä".to_string(), MK_NATIVE_FN(Rc::new(round_function)), true);
-    env.declare_var("neliöjuuri".to_string(), MK_NATIVE_FN(Rc::new(sqrt_function)), true);
+    env.register_function("pyöristä", round_function);
+    env.register_function("neliöjuuri", sqrt_function);
     env.declare_var("syöte".to_string(), MK_NATIVE_FN(Rc::new(input_function)), true);
     env.declare_var("satunnainen".to_string(), MK_NATIVE_FN(Rc::new(random_function)), true);
     env.declare_var("maksimi".to_string(), MK_NATIVE_FN(Rc::new(max_function)), true);
@@ -23,73 +34,256 @@ pub fn create_global_env() -> Environment {
     env.declare_var("kluku".to_string(), MK_NATIVE_FN(Rc::new(kluku_function)), true);
     env.declare_var("lluku".to_string(), MK_NATIVE_FN(Rc::new(lluku_function)), true);
     env.declare_var("mjono".to_string(), MK_NATIVE_FN(Rc::new(mjono_function)), true);
+    env.declare_var("väitä".to_string(), MK_NATIVE_FN(Rc::new(assert_function)), true);
+    env.declare_var("väitä_yhtä".to_string(), MK_NATIVE_FN(Rc::new(assert_equal_function)), true);
+    env.declare_var("väitä_eri".to_string(), MK_NATIVE_FN(Rc::new(assert_not_equal_function)), true);
+    env.declare_var("väitä_virhe".to_string(), MK_NATIVE_FN(Rc::new(assert_error_function)), true);
     env
 }
 
-#[derive(Debug, Clone)]
+// A handle to a scope; clones share the same variables, so closures see later changes
+#[derive(Clone)]
 pub struct Environment {
-    pub parent: Option<Box<Environment>>,
+    scope: Rc<RefCell<Scope>>,
+}
+
+// A variable found by name once and then used by its position, as the VM does for globals.
+// Scopes only grow, so it stays valid while the scopes searched before its own keep their size.
+pub struct VarRef {
+    scope: Environment,
+    position: usize,
+    shadowing: usize,
+}
+
+pub struct Scope {
+    pub parent: Option<Environment>,
     pub variables: Vec<(String, RuntimeVal)>,
-    pub constants: Vec<String>,
+    // Whether each variable is a vakio, in the same order
+    pub constants: Vec<bool>,
+    index: Option<HashMap<String, usize>>,
+    // Set on the top scope of a file, so imports resolve relative to it
+    pub module_path: Option<PathBuf>,
+    // Set on the global scope only
+    pub modules: Option<Rc<RefCell<ModuleRegistry>>>,
+}
+
+impl std::fmt::Debug for Environment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Environment")
+    }
 }
 
 impl Environment {
-    pub fn new(parent_env: Option<Box<Environment>>) -> Self {
+    pub fn new(parent_env: Option<Environment>) -> Self {
         Self {
-            parent: parent_env,
-            variables: Vec::new(),
-            constants: Vec::new(),
+            scope: Rc::new(RefCell::new(Scope {
+                parent: parent_env,
+                variables: Vec::new(),
+                constants: Vec::new(),
+                index: None,
+                module_path: None,
+                modules: None,
+            })),
         }
     }
 
+    // Enters a nested scope in place; the current scope becomes its parent
+    pub fn push_scope(&mut self) {
+        *self = Environment::new(Some(self.clone()));
+    }
+
+    pub fn pop_scope(&mut self) {
+        let parent = self.scope.borrow().parent.clone().expect("Ei avointa näkyvyysaluetta suljettavaksi");
+        *self = parent;
+    }
+
     pub fn declare_var(
         &mut self,
         varname: String,
         value: RuntimeVal,
         constant: bool
     ) -> RuntimeVal {
-        if self.variables.iter().any(|(name, _)| name == &varname) {
+        let mut scope = self.scope.borrow_mut();
+        if scope.position(&varname).is_some() {
             panic!("Ei voida määrittää muuttujaa {}, sillä se on jo määritelty.", varname);
         }
 
-        self.variables.push((varname.clone(), value.clone()));
-        if constant {
-            self.constants.push(varname.clone());
+        let position = scope.variables.len();
+        match &mut scope.index {
+            Some(index) => {
+                index.insert(varname.clone(), position);
+            }
+            None if position == INDEXED_SCOPE_SIZE => {
+                let mut index: HashMap<String, usize> =
+                    scope.variables.iter().enumerate().map(|(position, (name, _))| (name.clone(), position)).collect();
+                index.insert(varname.clone(), position);
+                scope.index = Some(index);
+            }
+            None => (),
         }
+        scope.variables.push((varname, value.clone()));
+        scope.constants.push(constant);
         value
     }
 
+    // Declares a Rust closure as a constant native function
+    pub fn register_function<M>(&mut self, name: &str, function: impl HostFunction<M>) {
+        self.declare_var(name.to_string(), MK_NATIVE_FN(function.into_native(name)), true);
+    }
+
     pub fn assign_var(&mut self, varname: &String, value: &RuntimeVal) {
-        let env = self.resolve(varname);
-    
-        // Cannot assign to constant
-        if env.constants.contains(varname) {
-            panic!("Ei voida määrittää uudelleen muuttujaa {}, koska se luotiin vakioksi.", varname);
+        self.with_scope_of(varname, |scope, position| {
+            // Cannot assign to constant
+            if scope.constants[position] {
+                panic!("Ei voida määrittää uudelleen muuttujaa {}, koska se luotiin vakioksi.", varname);
+            }
+            scope.variables[position].1 = value.clone();
+        })
+    }
+
+    pub fn lookup_var(&mut self, varname: &str) -> RuntimeVal {
+        self.with_scope_of(varname, |scope, position| scope.variables[position].1.clone())
+    }
+
+    pub fn has_var(&self, varname: &str) -> bool {
+        self.constness(varname).is_some()
+    }
+
+    // Whether the innermost `varname` is a vakio; None if it is not declared
+    pub fn constness(&self, varname: &str) -> Option<bool> {
+        let scope = self.scope.borrow();
+        if let Some(position) = scope.position(varname) {
+            return Some(scope.constants[position]);
         }
-    
-        if let Some((_, val)) = env.variables.iter_mut().find(|(name, _)| *name == *varname) {
-            *val = value.clone();
+        scope.parent.as_ref().and_then(|parent| parent.constness(varname))
+    }
+
+    pub fn find_var(&self, varname: &str) -> VarRef {
+        let mut shadowing = 0;
+        let mut current = self.clone();
+        loop {
+            let parent = {
+                let scope = current.scope.borrow();
+                if let Some(position) = scope.position(varname) {
+                    drop(scope);
+                    return VarRef { scope: current, position, shadowing };
+                }
+                shadowing += scope.variables.len();
+                scope.parent.clone()
+            };
+            match parent {
+                Some(parent) => current = parent,
+                None => panic!("Kohdetta '{}' ei voida ratkaista, koska sitä ei ole olemassa.", varname),
+            }
         }
-        value.clone();
     }
 
-    pub fn lookup_var(&mut self, varname: &str) -> RuntimeVal {
-        let env = self.resolve(varname);
-        env.variables
-            .iter()
-            .find(|(name, _)| name == &varname)
-            .unwrap()
-            .1.clone()
+    // Whether `var` is still the variable its name refers to from this scope
+    pub fn is_current(&self, var: &VarRef) -> bool {
+        self.declared_before(&var.scope) == Some(var.shadowing)
     }
 
-    pub fn resolve(&mut self, varname: &str) -> &mut Self {
-        if self.variables.iter().any(|(name, _)| name == &varname) {
-            return self;
+    fn declared_before(&self, target: &Environment) -> Option<usize> {
+        if Rc::ptr_eq(&self.scope, &target.scope) {
+            return Some(0);
         }
+        let scope = self.scope.borrow();
+        let parent = scope.parent.as_ref()?;
+        Some(scope.variables.len() + parent.declared_before(target)?)
+    }
 
-        match &mut self.parent {
-            Some(parent) => parent.resolve(varname),
+    // Runs `f` on the innermost scope that declares `varname`, with the variable's position in it
+    fn with_scope_of<R>(&self, varname: &str, f: impl FnOnce(&mut Scope, usize) -> R) -> R {
+        let mut scope = self.scope.borrow_mut();
+        if let Some(position) = scope.position(varname) {
+            return f(&mut scope, position);
+        }
+
+        match &scope.parent {
+            Some(parent) => parent.with_scope_of(varname, f),
             None => panic!("Kohdetta '{}' ei voida ratkaista, koska sitä ei ole olemassa.", varname),
         }
     }
+
+    // The outermost scope, holding the built-in functions
+    pub fn global(&self) -> Environment {
+        let mut current = self.clone();
+        loop {
+            let parent = current.scope.borrow().parent.clone();
+            match parent {
+                Some(parent) => current = parent,
+                None => return current,
+            }
+        }
+    }
+
+    pub fn module_registry(&self) -> Rc<RefCell<ModuleRegistry>> {
+        match &self.global().scope.borrow().modules {
+            Some(registry) => Rc::clone(registry),
+            None => panic!("Moduuleja ei voida ladata ilman globaalia ympäristöä"),
+        }
+    }
+
+    pub fn set_module_path(&mut self, path: PathBuf) {
+        self.scope.borrow_mut().module_path = Some(path);
+    }
+
+    // The file whose code is running in this scope, if any
+    pub fn module_path(&self) -> Option<PathBuf> {
+        let mut current = Some(self.clone());
+        while let Some(env) = current {
+            let scope = env.scope.borrow();
+            if let Some(path) = &scope.module_path {
+                return Some(path.clone());
+            }
+            current = scope.parent.clone();
+        }
+        None
+    }
+
+    // The enclosing scope; None for the global scope
+    pub fn parent(&self) -> Option<Environment> {
+        self.scope.borrow().parent.clone()
+    }
+
+    // The variables declared in this scope only, in declaration order
+    pub fn local_variables(&self) -> Vec<(String, RuntimeVal)> {
+        self.scope.borrow().variables.clone()
+    }
+
+    // All visible variables, innermost first, for natives that look names up by string
+    pub fn visible_variables(&self) -> Vec<(String, RuntimeVal)> {
+        let mut variables = Vec::new();
+        let mut current = Some(self.clone());
+        while let Some(env) = current {
+            let scope = env.scope.borrow();
+            variables.extend(scope.variables.iter().rev().cloned());
+            current = scope.parent.clone();
+        }
+        variables
+    }
+}
+
+impl VarRef {
+    pub fn get(&self) -> RuntimeVal {
+        self.scope.scope.borrow().variables[self.position].1.clone()
+    }
+
+    pub fn set(&self, value: RuntimeVal) {
+        let mut scope = self.scope.scope.borrow_mut();
+        if scope.constants[self.position] {
+            panic!("Ei voida määrittää uudelleen muuttujaa {}, koska se luotiin vakioksi.", scope.variables[self.position].0);
+        }
+        scope.variables[self.position].1 = value;
+    }
+}
+
+impl Scope {
+    fn position(&self, varname: &str) -> Option<usize> {
+        match &self.index {
+            Some(index) => index.get(varname).copied(),
+            // Names are unique in a scope, and the newest ones are looked up most
+            None => self.variables.iter().rposition(|(name, _)| name == varname),
+        }
+    }
 }
@@ -0,0 +1,142 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::frontend::ast::*;
+use crate::runtime::environment::*;
+use crate::runtime::interpreter::*;
+use crate::runtime::values::*;
+use crate::runtime::eval::expressions::*;
+use crate::runtime::eval::statements::*;
+
+pub fn eval_class_declaration(declaration: &ClassDeclaration, env: &mut Environment) -> RuntimeVal {
+    let parent = match &declaration.parent {
+        Some(name) => match env.lookup_var(name) {
+            RuntimeVal::Class(class) => Some(class),
+            other => panic!("Luokka {} voi laajentaa vain luokkaa, mutta {} on {}", declaration.name, name, other),
+        },
+        None => None,
+    };
+
+    let mut statics = Vec::new();
+    for field in &declaration.static_fields {
+        let value = match &field.value {
+            Some(expr) => eval_expr(expr, env),
+            None => MK_NULL(),
+        };
+        statics.push((field.name.clone(), value));
+    }
+    for method in &declaration.static_methods {
+        statics.push((method.name.clone(), RuntimeVal::Function(Rc::new(make_function(method, env)))));
+    }
+
+    let class = Class {
+        name: declaration.name.clone(),
+        parent,
+        fields: declaration.fields.clone(),
+        constructor: declaration.constructor.as_ref().map(|constructor| Rc::new(make_function(constructor, env))),
+        methods: declaration.methods.iter().map(|method| Rc::new(make_function(method, env))).collect(),
+        statics: RefCell::new(statics),
+        declaration_env: env.clone(),
+    };
+
+    env.declare_var(declaration.name.clone(), RuntimeVal::Class(Rc::new(class)), true)
+}
+
+// Calling a class creates an instance and runs the nearest constructor
+pub fn instantiate(
+    class: &Rc<Class>,
+    args: Vec<RuntimeVal>,
+    named_args: Vec<(String, RuntimeVal)>
+) -> RuntimeVal {
+    let instance = Rc::new(RefCell::new(Instance { class: Rc::clone(class), fields: Vec::new() }));
+
+    // Fields are initialised from the root class down, so subclasses can override defaults
+    for ancestor in class.ancestors().iter().rev() {
+        let mut env = ancestor.declaration_env.clone();
+        for field in &ancestor.fields {
+            let value = match &field.value {
+                Some(expr) => eval_expr(expr, &mut env),
+                None => MK_NULL(),
+            };
+            let mut instance = instance.borrow_mut();
+            match instance.fields.iter_mut().find(|(name, _)| name == &field.name) {
+                Some(slot) => slot.1 = value,
+                None => instance.fields.push((field.name.clone(), value)),
+            }
+        }
+    }
+
+    match class.find_constructor() {
+        Some((constructor, home)) => {
+            call_function(&constructor, args, named_args, Some((&instance, &home)));
+        }
+        None => init_fields_from_args(class, &instance, args, named_args),
+    }
+
+    RuntimeVal::Instance(instance)
+}
+
+// `yli(...)` inside a constructor runs the parent's constructor on the same instance
+pub fn call_super_constructor(
+    super_ref: &SuperRef,
+    args: Vec<RuntimeVal>,
+    named_args: Vec<(String, RuntimeVal)>
+) -> RuntimeVal {
+    match super_ref.class.find_constructor() {
+        Some((constructor, home)) => call_function(&constructor, args, named_args, Some((&super_ref.receiver, &home))),
+        None => {
+            init_fields_from_args(&super_ref.class, &super_ref.receiver, args, named_args);
+            MK_NULL()
+        }
+    }
+}
+
+// Without a constructor a class acts as a record: Piste(1, 2) or Piste(y: 2, x: 1)
+fn init_fields_from_args(
+    class: &Rc<Class>,
+    instance: &Rc<RefCell<Instance>>,
+    args: Vec<RuntimeVal>,
+    named_args: Vec<(String, RuntimeVal)>
+) {
+    let mut instance = instance.borrow_mut();
+    if args.len() > instance.fields.len() {
+        panic!(
+            "Luokalla {} on {} kenttää eikä rakentajaa, mutta sille annettiin {} argumenttia",
+            class.name, instance.fields.len(), args.len()
+        );
+    }
+    for (slot, arg) in instance.fields.iter_mut().zip(args) {
+        slot.1 = arg;
+    }
+    for (name, value) in named_args {
+        match instance.fields.iter_mut().find(|(field, _)| field == &name) {
+            Some(slot) => slot.1 = value,
+            None => panic!("Luokalla {} ei ole kenttää '{}'", class.name, name),
+        }
+    }
+}
+
+// Fields shadow methods; methods are bound to the instance they were read from
+pub fn get_instance_member(instance: &Rc<RefCell<Instance>>, name: &str) -> Option<RuntimeVal> {
+    let class = {
+        let borrowed = instance.borrow();
+        if let Some((_, value)) = borrowed.fields.iter().find(|(field, _)| field == name) {
+            return Some(value.clone());
+        }
+        Rc::clone(&borrowed.class)
+    };
+    class.find_method(name).map(|(method, home)| {
+        RuntimeVal::BoundMethod(BoundMethod { receiver: Rc::clone(instance), method, home })
+    })
+}
+
+pub fn get_super_member(super_ref: &SuperRef, name: &str) -> Option<RuntimeVal> {
+    super_ref.class.find_method(name).map(|(method, home)| {
+        RuntimeVal::BoundMethod(BoundMethod { receiver: Rc::clone(&super_ref.receiver), method, home })
+    })
+}
+
+pub fn get_static_member(class: &Rc<Class>, name: &str) -> Option<RuntimeVal> {
+    let owner = class.find_static(name)?;
+    let statics = owner.statics.borrow();
+    statics.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone())
+}
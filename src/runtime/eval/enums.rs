@@ -0,0 +1,65 @@
+use std::rc::Rc;
+use crate::frontend::ast::*;
+use crate::runtime::environment::*;
+use crate::runtime::values::*;
+
+pub fn eval_enum_declaration(declaration: &EnumDeclaration, env: &mut Environment) -> RuntimeVal {
+    let enum_type = EnumType {
+        name: declaration.name.clone(),
+        variants: declaration.variants.clone(),
+    };
+
+    env.declare_var(declaration.name.clone(), RuntimeVal::Enum(Rc::new(enum_type)), true)
+}
+
+// Muoto.Tyhjä is a value, Muoto.Ympyrä a constructor
+pub fn get_enum_member(enum_type: &Rc<EnumType>, name: &str) -> Option<RuntimeVal> {
+    let index = enum_type.variants.iter().position(|variant| variant.name == name)?;
+    let variant = &enum_type.variants[index];
+
+    if variant.fields.is_empty() {
+        return Some(RuntimeVal::EnumValue(EnumValue { enum_type: Rc::clone(enum_type), variant: index, values: Vec::new() }));
+    }
+
+    Some(RuntimeVal::Variant(VariantConstructor { enum_type: Rc::clone(enum_type), variant: index }))
+}
+
+// Muoto.Ympyrä(1) or Muoto.Suorakulmio(leveys: 2, korkeus: 3); every field must be given once
+pub fn construct_variant(
+    constructor: &VariantConstructor,
+    args: Vec<RuntimeVal>,
+    named_args: Vec<(String, RuntimeVal)>
+) -> RuntimeVal {
+    let enum_type = &constructor.enum_type;
+    let variant = &enum_type.variants[constructor.variant];
+    let given = args.len() + named_args.len();
+    if args.len() > variant.fields.len() {
+        panic!("{}.{} odottaa {} argumenttia, mutta saatiin {}", enum_type.name, variant.name, variant.fields.len(), given);
+    }
+
+    let mut values: Vec<Option<RuntimeVal>> = args.into_iter().map(Some).collect();
+    values.resize(variant.fields.len(), None);
+    for (name, value) in named_args {
+        let Some(index) = variant.fields.iter().position(|field| field == &name) else {
+            panic!("Muunnelmalla {}.{} ei ole kenttää '{}'", enum_type.name, variant.name, name);
+        };
+        if values[index].replace(value).is_some() {
+            panic!("{}.{}: kentälle '{}' annettiin arvo kahdesti", enum_type.name, variant.name, name);
+        }
+    }
+
+    let values = values.into_iter().zip(&variant.fields).map(|(value, field)| match value {
+        Some(value) => value,
+        None => panic!(
+            "{}.{} odottaa {} argumenttia, mutta saatiin {} (puuttuu '{}')",
+            enum_type.name, variant.name, variant.fields.len(), given, field
+        ),
+    }).collect();
+    RuntimeVal::EnumValue(EnumValue { enum_type: Rc::clone(enum_type), variant: constructor.variant, values })
+}
+
+// Payload fields are read by name: ympyrä.säde
+pub fn get_enum_value_member(value: &EnumValue, name: &str) -> Option<RuntimeVal> {
+    let index = value.variant().fields.iter().position(|field| field == name)?;
+    Some(value.values[index].clone())
+}
@@ -2,103 +2,91 @@ use crate::frontend::ast::*;
 use crate::runtime::environment::*;
 use crate::runtime::interpreter::*;
 use crate::runtime::values::*;
-use crate::runtime::eval::statements::*;
+use crate::runtime::eval::patterns::*;
+use crate::runtime::eval::classes::*;
+use crate::runtime::eval::enums::*;
+use crate::vm::machine::call_closure;
+use std::rc::Rc;
 use std::cell::RefCell;
+use crate::debugger;
 
-fn eval_numeric_binary_expr(lhs: f64, rhs: f64, operator: &BinaryOperator) -> RuntimeVal {
-    match operator {
-        BinaryOperator::Add => MK_NUMBER(lhs + rhs),
-        BinaryOperator::Subtract => MK_NUMBER(lhs - rhs),
-        BinaryOperator::Multiply => MK_NUMBER(lhs * rhs),
-        BinaryOperator::Divide => {
-            if rhs == 0.0 {
-                panic!("Jako nollavirheellä");
-            } else {
-                MK_NUMBER(lhs / rhs)
-            }
-        }
-        BinaryOperator::Modulus => {
-            if rhs == 0.0 {
-                panic!("Jako nollavirheellä");
-            } else {
-                MK_NUMBER(lhs % rhs)
-            }
-        }
-        BinaryOperator::And => {
-            if lhs != 0.0 && rhs != 0.0 { MK_BOOL(true) } else { MK_BOOL(false) }
-        }
-        BinaryOperator::Or => {
-            if lhs != 0.0 || rhs != 0.0 { MK_BOOL(true) } else { MK_BOOL(false) }
+pub fn eval_binary_expr(binop: &BinaryExpr, env: &mut Environment) -> RuntimeVal {
+    let lhs = eval_expr(&binop.left, env);
+    let rhs = eval_expr(&binop.right, env);
+    apply_binary_operator(lhs, rhs, &binop.operator)
+}
+
+pub fn apply_binary_operator(lhs: RuntimeVal, rhs: RuntimeVal, operator: &BinaryOperator) -> RuntimeVal {
+    // Liukuluku arithmetic and comparisons are the common case, and skip the general code below
+    if let (RuntimeVal::Number(a), RuntimeVal::Number(b)) = (&lhs, &rhs) {
+        match operator {
+            BinaryOperator::Add => return RuntimeVal::Number(a + b),
+            BinaryOperator::Subtract => return RuntimeVal::Number(a - b),
+            BinaryOperator::Multiply => return RuntimeVal::Number(a * b),
+            BinaryOperator::LessThan => return RuntimeVal::Bool(a < b),
+            BinaryOperator::GreaterThan => return RuntimeVal::Bool(a > b),
+            BinaryOperator::LessThanOrEqual => return RuntimeVal::Bool(a <= b),
+            BinaryOperator::GreaterThanOrEqual => return RuntimeVal::Bool(a >= b),
+            _ => (),
         }
-        BinaryOperator::Exponent => MK_NUMBER(lhs.powf(rhs)),
-        BinaryOperator::Equal => MK_BOOL(lhs == rhs),
-        BinaryOperator::NotEqual => MK_BOOL(lhs != rhs),
-        BinaryOperator::LessThan => MK_BOOL(lhs < rhs),
-        BinaryOperator::GreaterThan => MK_BOOL(lhs > rhs),
-        BinaryOperator::LessThanOrEqual => MK_BOOL(lhs <= rhs),
-        BinaryOperator::GreaterThanOrEqual => MK_BOOL(lhs >= rhs),
-        _ => panic!("Odottamaton operaattori"),
     }
-}
 
-pub fn eval_binary_expr(binop: &BinaryExpr, env: &mut Environment) -> RuntimeVal {
-    let lhs = eval_expr(&*binop.left, env);
-    let rhs = eval_expr(&*binop.right, env);
+    // Mixed kokonaisluku and liukuluku operands are promoted to liukuluku
+    let (lhs, rhs) = match (lhs, rhs) {
+        (RuntimeVal::Integer(i), RuntimeVal::Number(n)) => (MK_NUMBER(i as f64), MK_NUMBER(n)),
+        (RuntimeVal::Number(n), RuntimeVal::Integer(i)) => (MK_NUMBER(n), MK_NUMBER(i as f64)),
+        operands => operands,
+    };
 
-    match binop.operator {
+    match operator {
         BinaryOperator::Add => match (&lhs, &rhs) {
             (RuntimeVal::Number(n1), RuntimeVal::Number(n2)) => RuntimeVal::Number(n1 + n2),
-            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => RuntimeVal::Integer(i1 + i2),
+            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => checked_integer(i1.checked_add(*i2), "yhteenlaskussa"),
             (RuntimeVal::String(s1), RuntimeVal::String(s2)) => RuntimeVal::String(s1.clone() + s2),
             _ => panic!("Tukematon operandityyppi yhteenlaskuun"),
         },
         BinaryOperator::Subtract => match (&lhs, &rhs) {
             (RuntimeVal::Number(n1), RuntimeVal::Number(n2)) => RuntimeVal::Number(n1 - n2),
-            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => RuntimeVal::Integer(i1 - i2),
+            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => checked_integer(i1.checked_sub(*i2), "vähennyslaskussa"),
             _ => panic!("Tukematon operandityyppi vähennyslaskuun"),
         },
         BinaryOperator::Multiply => match (&lhs, &rhs) {
             (RuntimeVal::Number(n1), RuntimeVal::Number(n2)) => RuntimeVal::Number(n1 * n2),
-            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => RuntimeVal::Integer(i1 * i2),
+            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => checked_integer(i1.checked_mul(*i2), "kertolaskussa"),
             _ => panic!("Tukematon operandityyppi kertolaskuun"),
         },
         BinaryOperator::Divide => match (&lhs, &rhs) {
             (RuntimeVal::Number(n1), RuntimeVal::Number(n2)) => RuntimeVal::Number(n1 / n2),
-            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => RuntimeVal::Integer(i1 / i2),
+            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => {
+                if *i2 == 0 {
+                    panic!("Jako nollavirheellä");
+                }
+                checked_integer(i1.checked_div(*i2), "jakolaskussa")
+            }
             _ => panic!("Tukematon operandityyppi jakolaskuun"),
         },
         BinaryOperator::Exponent => match (&lhs, &rhs) {
             (RuntimeVal::Number(n1), RuntimeVal::Number(n2)) => RuntimeVal::Number(n1.powf(*n2)),
-            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => RuntimeVal::Integer(i1.pow(*i2 as u32)),
+            // A negative exponent gives a fraction, so the result is a liukuluku
+            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) if *i2 < 0 => RuntimeVal::Number((*i1 as f64).powf(*i2 as f64)),
+            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) =>
+                checked_integer(u32::try_from(*i2).ok().and_then(|exponent| i1.checked_pow(exponent)), "potenssiin korotuksessa"),
             _ => panic!("Tukematon operandityyppi potenssiin"),
         },
         BinaryOperator::Modulus => match (&lhs, &rhs) {
             (RuntimeVal::Number(n1), RuntimeVal::Number(n2)) => RuntimeVal::Number(n1 % n2),
-            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => RuntimeVal::Integer(i1 % i2),
+            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => {
+                if *i2 == 0 {
+                    panic!("Jako nollavirheellä");
+                }
+                // Only i64::MIN % -1 overflows, and its remainder is 0
+                MK_INTEGER(i1.checked_rem(*i2).unwrap_or(0))
+            }
             _ => panic!("Tukematon operandityyppi jakojäännökseen"),
         },
-        BinaryOperator::AddEqual => match (&lhs, &rhs) {
-            (RuntimeVal::Number(n1), RuntimeVal::Number(n2)) => RuntimeVal::Number(*n1 + *n2),
-            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => RuntimeVal::Integer(*i1 + *i2),
-            _ => panic!("Tukematon operandityyppi yhteenlaskuun ja sijoitukseen"),
-        },
-        BinaryOperator::SubtractEqual => match (&lhs, &rhs) {
-            (RuntimeVal::Number(n1), RuntimeVal::Number(n2)) => RuntimeVal::Number(*n1 - *n2),
-            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => RuntimeVal::Integer(*i1 - *i2),
-            _ => panic!("Tukematon operandityyppi vähennyslaskuun ja sijoitukseen"),
-        },
-        BinaryOperator::Equal => match (&lhs, &rhs) {
-            (RuntimeVal::Number(n1), RuntimeVal::Number(n2)) => RuntimeVal::Bool(n1 == n2),
-            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => RuntimeVal::Bool(i1 == i2),
-            (RuntimeVal::String(s1), RuntimeVal::String(s2)) => RuntimeVal::Bool(s1 == s2),
-            _ => panic!("Tukematon operandityyppi yhtäsuuruuden tarkistukseen"),
-        },
-        BinaryOperator::NotEqual => match (&lhs, &rhs) {
-            (RuntimeVal::Number(n1), RuntimeVal::Number(n2)) => RuntimeVal::Bool(n1 != n2),
-            (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => RuntimeVal::Bool(i1 != i2),
-            (RuntimeVal::String(s1), RuntimeVal::String(s2)) => RuntimeVal::Bool(s1 != s2),
-            _ => panic!("Tukematon operandityyppi erisuuruuden tarkistukseen"),
-        },
+        // Values of different types are never equal; instances compare by identity
+        BinaryOperator::Equal => MK_BOOL(lhs.equals(&rhs)),
+        BinaryOperator::NotEqual => MK_BOOL(!lhs.equals(&rhs)),
         BinaryOperator::LessThan => match (&lhs, &rhs) {
             (RuntimeVal::Number(n1), RuntimeVal::Number(n2)) => RuntimeVal::Bool(n1 < n2),
             (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => RuntimeVal::Bool(i1 < i2),
@@ -119,23 +107,333 @@ pub fn eval_binary_expr(binop: &BinaryExpr, env: &mut Environment) -> RuntimeVal
             (RuntimeVal::Integer(i1), RuntimeVal::Integer(i2)) => RuntimeVal::Bool(i1 >= i2),
             _ => panic!("Tukematon operandityyppi suurempi tai yhtä suuri kuin -tarkistukseen"),
         },
+        BinaryOperator::IntegerDivide => match (&lhs, &rhs) {
+            (RuntimeVal::Integer(a), RuntimeVal::Integer(b)) => {
+                if *b == 0 {
+                    panic!("Jako nollavirheellä");
+                }
+                // Rounds towards negative infinity, like the liukuluku case below
+                let Some(quotient) = a.checked_div(*b) else {
+                    panic!("Kokonaisluvun ylivuoto kokonaislukujaossa");
+                };
+                if a % b != 0 && (*a < 0) != (*b < 0) {
+                    MK_INTEGER(quotient - 1)
+                } else {
+                    MK_INTEGER(quotient)
+                }
+            }
+            (RuntimeVal::Number(a), RuntimeVal::Number(b)) => {
+                if *b == 0.0 {
+                    panic!("Jako nollavirheellä");
+                }
+                MK_INTEGER(float_to_integer((a / b).floor()))
+            }
+            _ => panic!("Tukematon operandityyppi kokonaislukujakoon"),
+        },
+        BinaryOperator::BitwiseAnd => MK_INTEGER(as_integer(&lhs, "bittitason ja-operaatioon") & as_integer(&rhs, "bittitason ja-operaatioon")),
+        BinaryOperator::BitwiseOr => MK_INTEGER(as_integer(&lhs, "bittitason tai-operaatioon") | as_integer(&rhs, "bittitason tai-operaatioon")),
+        BinaryOperator::BitwiseXor => MK_INTEGER(as_integer(&lhs, "bittitason xor-operaatioon") ^ as_integer(&rhs, "bittitason xor-operaatioon")),
+        BinaryOperator::ShiftLeft => {
+            let value = as_integer(&lhs, "bittisiirtoon");
+            MK_INTEGER(value << shift_amount(&rhs))
+        }
+        BinaryOperator::ShiftRight => {
+            let value = as_integer(&lhs, "bittisiirtoon");
+            MK_INTEGER(value >> shift_amount(&rhs))
+        }
         _ => panic!("Tukematon operaattori"),
     }
 }
 
+// Kokonaisluku arithmetic fails instead of wrapping around
+pub(crate) fn checked_integer(result: Option<i64>, operation: &str) -> RuntimeVal {
+    match result {
+        Some(value) => MK_INTEGER(value),
+        None => panic!("Kokonaisluvun ylivuoto {}", operation),
+    }
+}
+
+// A whole liukuluku as a kokonaisluku; one out of its range, inf or NaN fails like overflowing arithmetic
+pub(crate) fn float_to_integer(n: f64) -> i64 {
+    // -2^63 is the smallest kokonaisluku, and 2^63 the first liukuluku above the largest
+    if !(-9_223_372_036_854_775_808.0..9_223_372_036_854_775_808.0).contains(&n) {
+        panic!("Kokonaisluvun ylivuoto: {} ei mahdu kokonaisluvuksi", n);
+    }
+    n as i64
+}
+
+// Bitwise and integer operators accept kokonaisluvut and whole liukuluvut
+fn as_integer(value: &RuntimeVal, operation: &str) -> i64 {
+    match value {
+        RuntimeVal::Integer(i) => *i,
+        RuntimeVal::Number(n) if n.fract() == 0.0 || !n.is_finite() => float_to_integer(*n),
+        _ => panic!("Tukematon operandityyppi {}: {:?}", operation, value),
+    }
+}
+
+fn shift_amount(value: &RuntimeVal) -> i64 {
+    let amount = as_integer(value, "bittisiirtoon");
+    if !(0..=63).contains(&amount) {
+        panic!("Bittisiirron määrän on oltava välillä 0-63, saatiin {}", amount);
+    }
+    amount
+}
+
+pub fn eval_logical_expr(logical_expr: &LogicalExpr, env: &mut Environment) -> RuntimeVal {
+    let lhs = eval_expr(&logical_expr.left, env);
+
+    // The right-hand side is only evaluated when it can change the result
+    match logical_expr.operator {
+        BinaryOperator::And => MK_BOOL(lhs.is_truthy() && eval_expr(&logical_expr.right, env).is_truthy()),
+        BinaryOperator::Or => MK_BOOL(lhs.is_truthy() || eval_expr(&logical_expr.right, env).is_truthy()),
+        BinaryOperator::NullishCoalescing => match lhs {
+            RuntimeVal::Null => eval_expr(&logical_expr.right, env),
+            _ => lhs,
+        },
+        _ => panic!("Odottamaton looginen operaattori {:?}", logical_expr.operator),
+    }
+}
+
 pub fn eval_identifier(ident: &Identifier, env: &mut Environment) -> RuntimeVal {
-    let val = env.lookup_var(&ident.symbol);
-    val
+    
+    env.lookup_var(&ident.symbol)
 }
 
 pub fn eval_assignment(assignment_expr: &AssignmentExpr, env: &mut Environment) -> RuntimeVal {
-    match &*assignment_expr.assignee {
-        Expr::Identifier(ident) => {
-            let value = eval_expr(&assignment_expr.value, env);
+    // Plain variables skip building a place
+    if let Expr::Identifier(ident) = &*assignment_expr.assignee {
+        let value = eval_expr(&assignment_expr.value, env);
+        env.assign_var(&ident.symbol, &value);
+        return value;
+    }
+    let place = eval_place(&assignment_expr.assignee, env);
+    let value = eval_expr(&assignment_expr.value, env);
+    write_place(&place, value.clone(), env);
+    value
+}
+
+pub fn eval_compound_assignment(assignment_expr: &CompoundAssignmentExpr, env: &mut Environment) -> RuntimeVal {
+    let operator = &assignment_expr.operator;
+    if let Expr::Identifier(ident) = &*assignment_expr.assignee {
+        if *operator != BinaryOperator::NullishCoalescing {
+            let current = env.lookup_var(&ident.symbol);
+            let rhs = eval_expr(&assignment_expr.value, env);
+            let value = apply_binary_operator(current, rhs, operator);
             env.assign_var(&ident.symbol, &value);
-            value
+            return value;
         }
-        _ => panic!("Virheellinen vasen puoli lausekkeen sisällä {:?}", assignment_expr.assignee),
+    }
+
+    // The target is resolved once, so `a[f()] += 1` calls f only once
+    let place = eval_place(&assignment_expr.assignee, env);
+    let current = read_place(&place, env);
+
+    let value = match assignment_expr.operator {
+        BinaryOperator::NullishCoalescing => {
+            if !matches!(current, RuntimeVal::Null) {
+                return current;
+            }
+            eval_expr(&assignment_expr.value, env)
+        }
+        _ => {
+            let rhs = eval_expr(&assignment_expr.value, env);
+            apply_binary_operator(current, rhs, operator)
+        }
+    };
+
+    write_place(&place, value.clone(), env);
+    value
+}
+
+pub fn eval_update_expr(update_expr: &UpdateExpr, env: &mut Environment) -> RuntimeVal {
+    let place = eval_place(&update_expr.assignee, env);
+    let current = read_place(&place, env);
+
+    let updated = match (&current, update_expr.operator.as_str()) {
+        (RuntimeVal::Integer(i), "++") => checked_integer(i.checked_add(1), "operaattorissa '++'"),
+        (RuntimeVal::Integer(i), "--") => checked_integer(i.checked_sub(1), "operaattorissa '--'"),
+        (RuntimeVal::Number(n), "++") => MK_NUMBER(n + 1.0),
+        (RuntimeVal::Number(n), "--") => MK_NUMBER(n - 1.0),
+        _ => panic!("Operaattori '{}' vaatii luvun, saatiin {:?}", update_expr.operator, current),
+    };
+
+    write_place(&place, updated.clone(), env);
+    if update_expr.prefix { updated } else { current }
+}
+
+// An assignable location: a variable and the keys leading into it, e.g. a[0].b -> ("a", [0, "b"])
+pub struct Place<'a> {
+    pub variable: &'a String,
+    pub path: Vec<RuntimeVal>,
+}
+
+pub fn eval_place<'a>(expr: &'a Expr, env: &mut Environment) -> Place<'a> {
+    match expr {
+        Expr::Identifier(ident) => Place { variable: &ident.symbol, path: Vec::new() },
+        Expr::MemberExpr(member) if member.optional =>
+            panic!("Valinnaista ketjua ei voi käyttää sijoituksen kohteena"),
+        Expr::MemberExpr(member) => {
+            let mut place = eval_place(&member.object, env);
+            place.path.push(eval_member_key(member, env));
+            place
+        }
+        _ => panic!("Virheellinen vasen puoli lausekkeen sisällä {:?}", expr),
+    }
+}
+
+pub fn read_place(place: &Place, env: &mut Environment) -> RuntimeVal {
+    let mut value = env.lookup_var(place.variable);
+    for key in &place.path {
+        value = get_member(&value, key);
+    }
+    value
+}
+
+pub fn write_place(place: &Place, value: RuntimeVal, env: &mut Environment) {
+    if place.path.is_empty() {
+        env.assign_var(place.variable, &value);
+        return;
+    }
+
+    let mut root = env.lookup_var(place.variable);
+    set_member(&mut root, &place.path, value);
+    // Instances and classes were changed in place, and may be held in a vakio such as tämä
+    if !root.is_reference() {
+        env.assign_var(place.variable, &root);
+    }
+}
+
+pub fn set_member(target: &mut RuntimeVal, path: &[RuntimeVal], value: RuntimeVal) {
+    let key = &path[0];
+    match (target, key) {
+        (RuntimeVal::Object(properties), RuntimeVal::String(name)) => {
+            let slot = match properties.iter().position(|(k, _)| k == name) {
+                Some(index) => &mut properties[index].1,
+                None => {
+                    properties.push((name.clone(), MK_NULL()));
+                    &mut properties.last_mut().unwrap().1
+                }
+            };
+            assign_slot(slot, path, value);
+        }
+        (RuntimeVal::Array(elements), _) => {
+            let index = array_index(key, elements.len());
+            assign_slot(&mut elements[index], path, value);
+        }
+        (RuntimeVal::Instance(instance), RuntimeVal::String(name)) => {
+            let mut instance = instance.borrow_mut();
+            let class_name = instance.class.name.clone();
+            match instance.fields.iter_mut().find(|(field, _)| field == name) {
+                Some((_, slot)) => assign_slot(slot, path, value),
+                None => panic!("Luokan {} oliolla ei ole kenttää '{}'", class_name, name),
+            }
+        }
+        (RuntimeVal::Class(class), RuntimeVal::String(name)) => {
+            let owner = match class.find_static(name) {
+                Some(owner) => owner,
+                None => panic!("Luokalla {} ei ole staattista jäsentä '{}'", class.name, name),
+            };
+            let mut statics = owner.statics.borrow_mut();
+            let slot = &mut statics.iter_mut().find(|(key, _)| key == name).unwrap().1;
+            assign_slot(slot, path, value);
+        }
+        (RuntimeVal::Host(object), RuntimeVal::String(name)) => {
+            if path.len() == 1 {
+                object.set_member(name, value);
+                return;
+            }
+            // Nested paths change a copy of the property and hand it back to the host
+            let mut property = get_member(&RuntimeVal::Host(object.clone()), key);
+            set_member(&mut property, &path[1..], value);
+            if !property.is_reference() {
+                object.set_member(name, property);
+            }
+        }
+        (target, _) => panic!("Arvoon {:?} ei voida sijoittaa avaimella {:?}", target, key),
+    }
+}
+
+fn assign_slot(slot: &mut RuntimeVal, path: &[RuntimeVal], value: RuntimeVal) {
+    if path.len() == 1 {
+        *slot = value;
+    } else {
+        set_member(slot, &path[1..], value);
+    }
+}
+
+fn array_index(key: &RuntimeVal, len: usize) -> usize {
+    let index = match key {
+        RuntimeVal::Integer(i) => *i,
+        RuntimeVal::Number(n) if n.fract() == 0.0 => *n as i64,
+        _ => panic!("Taulukon indeksin on oltava kokonaisluku, saatiin {:?}", key),
+    };
+    if index < 0 || index as usize >= len {
+        panic!("Indeksi {} on taulukon rajojen ulkopuolella (pituus {})", index, len);
+    }
+    index as usize
+}
+
+fn eval_member_key(expr: &MemberExpr, env: &mut Environment) -> RuntimeVal {
+    if expr.computed {
+        return eval_expr(&expr.property, env);
+    }
+    match &*expr.property {
+        Expr::Identifier(ident) => MK_STRING(ident.symbol.clone()),
+        _ => panic!("Omaisuuden on oltava tunniste"),
+    }
+}
+
+pub fn get_member(object: &RuntimeVal, key: &RuntimeVal) -> RuntimeVal {
+    match try_get_member(object, key) {
+        Some(value) => value,
+        None => match (object, key) {
+            (RuntimeVal::Object(_), RuntimeVal::String(property)) =>
+                panic!("Oliossa ei ole ominaisuutta {}", property),
+            (RuntimeVal::Array(elements), _) => elements[array_index(key, elements.len())].clone(),
+            (RuntimeVal::String(s), _) => {
+                let chars: Vec<char> = s.chars().collect();
+                MK_STRING(chars[array_index(key, chars.len())].to_string())
+            }
+            (RuntimeVal::Object(_), _) => panic!("Olion avaimen on oltava merkkijono, saatiin {:?}", key),
+            (RuntimeVal::Instance(instance), RuntimeVal::String(property)) =>
+                panic!("Luokan {} oliolla ei ole kenttää tai metodia '{}'", instance.borrow().class.name, property),
+            (RuntimeVal::Class(class), RuntimeVal::String(property)) =>
+                panic!("Luokalla {} ei ole staattista jäsentä '{}'", class.name, property),
+            (RuntimeVal::Super(super_ref), RuntimeVal::String(property)) =>
+                panic!("Yliluokalla {} ei ole metodia '{}'", super_ref.class.name, property),
+            (RuntimeVal::Enum(enum_type), RuntimeVal::String(property)) =>
+                panic!("Luettelossa {} ei ole muunnelmaa '{}'", enum_type.name, property),
+            (RuntimeVal::EnumValue(value), RuntimeVal::String(property)) =>
+                panic!("Muunnelmalla {}.{} ei ole kenttää '{}'", value.enum_type.name, value.variant().name, property),
+            (RuntimeVal::Host(object), RuntimeVal::String(property)) =>
+                panic!("Isäntäoliolla {} ei ole ominaisuutta tai metodia '{}'", object.type_name(), property),
+            _ => panic!("Vain olioilla on ominaisuuksia"),
+        },
+    }
+}
+
+// Like get_member, but a missing property or index gives None instead of an error
+pub fn try_get_member(object: &RuntimeVal, key: &RuntimeVal) -> Option<RuntimeVal> {
+    let index = match key {
+        RuntimeVal::Integer(i) => Some(*i),
+        RuntimeVal::Number(n) if n.fract() == 0.0 => Some(*n as i64),
+        _ => None,
+    };
+
+    match (object, key) {
+        (RuntimeVal::Object(obj), RuntimeVal::String(property)) =>
+            obj.iter().find(|(key, _)| key == property).map(|(_, val)| val.clone()),
+        (RuntimeVal::Array(elements), _) =>
+            index.and_then(|i| usize::try_from(i).ok()).and_then(|i| elements.get(i)).cloned(),
+        (RuntimeVal::String(s), _) =>
+            index.and_then(|i| usize::try_from(i).ok()).and_then(|i| s.chars().nth(i)).map(|c| MK_STRING(c.to_string())),
+        (RuntimeVal::Instance(instance), RuntimeVal::String(property)) => get_instance_member(instance, property),
+        (RuntimeVal::Class(class), RuntimeVal::String(property)) => get_static_member(class, property),
+        (RuntimeVal::Super(super_ref), RuntimeVal::String(property)) => get_super_member(super_ref, property),
+        (RuntimeVal::Enum(enum_type), RuntimeVal::String(property)) => get_enum_member(enum_type, property),
+        (RuntimeVal::EnumValue(value), RuntimeVal::String(property)) => get_enum_value_member(value, property),
+        (RuntimeVal::Host(object), RuntimeVal::String(property)) => object.get_member(property),
+        _ => None,
     }
 }
 
@@ -160,72 +458,185 @@ pub fn eval_array_expr(array_literal: &ArrayLiteral, env: &mut Environment) -> R
 }
 
 pub fn eval_call_expr(expr: &CallExpr, env: &mut Environment) -> RuntimeVal {
-    let args: Vec<RuntimeVal> = expr.args.iter().map(|arg| eval_expr(arg, env)).collect();
+    if expr.caller.is_optional_chain() {
+        return eval_optional_call(expr, env).unwrap_or(MK_NULL());
+    }
+    let (args, named_args) = eval_arguments(&expr.args, env);
     let fn_val = eval_expr(&expr.caller, env);
+    call_value(fn_val, args, named_args, env)
+}
 
+// The callee of n?.f() comes first, so that tyhjä can skip the arguments too
+fn eval_optional_call(expr: &CallExpr, env: &mut Environment) -> Option<RuntimeVal> {
+    let fn_val = eval_chain(&expr.caller, env)?;
+    let (args, named_args) = eval_arguments(&expr.args, env);
+    Some(call_value(fn_val, args, named_args, env))
+}
+
+fn eval_arguments(arguments: &[Argument], env: &mut Environment) -> (Vec<RuntimeVal>, Vec<(String, RuntimeVal)>) {
+    let mut args: Vec<RuntimeVal> = Vec::new();
+    let mut named_args: Vec<(String, RuntimeVal)> = Vec::new();
+    for arg in arguments {
+        match arg {
+            Argument::Positional(arg) => args.push(eval_expr(arg, env)),
+            Argument::Named(name, arg) => {
+                let value = eval_expr(arg, env);
+                named_args.push((name.clone(), value));
+            }
+            Argument::Spread(arg) => match eval_expr(arg, env) {
+                RuntimeVal::Array(elements) => args.extend(elements),
+                other => panic!("Vain taulukon voi levittää argumenteiksi, saatiin {}", other),
+            },
+        }
+    }
+    (args, named_args)
+}
 
+// Calls anything callable: functions, natives, bound methods, classes and `yli`
+pub fn call_value(
+    fn_val: RuntimeVal,
+    args: Vec<RuntimeVal>,
+    named_args: Vec<(String, RuntimeVal)>,
+    env: &Environment
+) -> RuntimeVal {
     match fn_val {
         RuntimeVal::NativeFunction(native_fn) => {
-            let result = native_fn.get_fn()(args, env.variables.clone());
-            result
+            if let Some((name, _)) = named_args.first() {
+                panic!("Sisäänrakennettu funktio ei tue nimettyjä argumentteja, saatiin '{}'", name);
+            }
+
+            native_fn.get_fn()(args, env)
         }
-        RuntimeVal::Function(func) => {
-            let mut scope = Environment::new(
-                Some(Box::new(RefCell::borrow(&*func.declaration_env).clone()))
-            );
+        RuntimeVal::Function(func) => call_function(&func, args, named_args, None),
+        RuntimeVal::BoundMethod(bound) =>
+            call_function(&bound.method, args, named_args, Some((&bound.receiver, &bound.home))),
+        RuntimeVal::Class(class) => instantiate(&class, args, named_args),
+        RuntimeVal::Super(super_ref) => call_super_constructor(&super_ref, args, named_args),
+        RuntimeVal::Variant(constructor) => construct_variant(&constructor, args, named_args),
+        RuntimeVal::Closure(closure) => call_closure(&closure, args, named_args),
+        _ => panic!("Ei voida kutsua arvoa, joka ei ole funktio: {:?}", fn_val),
+    }
+}
 
-            // Check the bounds here.
-            // Verify arity of function
-            if func.parameters.len() != args.len() {
-                panic!("Väärä argumenttien määrä. Odotettu {}, saatu {}", func.parameters.len(), args.len());
-            }
+// Runs a user function; methods also get `tämä` and, in subclasses, `yli`
+pub fn call_function(
+    func: &Function,
+    args: Vec<RuntimeVal>,
+    named_args: Vec<(String, RuntimeVal)>,
+    receiver: Option<(&Rc<RefCell<Instance>>, &Rc<Class>)>
+) -> RuntimeVal {
+    let mut scope = Environment::new(Some(func.declaration_env.clone()));
 
-            // Create the variables for the parameters list
-            for (i, varname) in func.parameters.iter().enumerate() {
-                scope.declare_var(varname.clone(), args[i].clone(), false);
-            }
+    if let Some((instance, home)) = receiver {
+        scope.declare_var("tämä".to_string(), RuntimeVal::Instance(Rc::clone(instance)), true);
+        if let Some(parent) = &home.parent {
+            let super_ref = SuperRef { receiver: Rc::clone(instance), class: Rc::clone(parent) };
+            scope.declare_var("yli".to_string(), RuntimeVal::Super(super_ref), true);
+        }
+    }
 
-            let mut result = MK_NULL();
-            // Evaluate the function body line by line
-            for stmt in &func.body {
-                result = evaluate(&stmt.clone(), &mut scope);
-            }
+    bind_arguments(func, args, named_args, &mut scope);
+    let _frame = debugger::enter_function(&func.name, &scope);
+
+    let mut result = MK_NULL();
+    // Evaluate the function body line by line
+    for stmt in &func.body {
+        result = evaluate(stmt, &mut scope);
+    }
+
+    result
+}
+
+// Matches positional and named arguments to the parameters and declares them in the call scope
+fn bind_arguments(
+    func: &Function,
+    args: Vec<RuntimeVal>,
+    named_args: Vec<(String, RuntimeVal)>,
+    scope: &mut Environment
+) {
+    let mut slots: Vec<Option<RuntimeVal>> = vec![None; func.parameters.len()];
+    let mut extra = Vec::new();
 
-            result
+    for (i, arg) in args.into_iter().enumerate() {
+        if i < slots.len() {
+            slots[i] = Some(arg);
+        } else if func.rest.is_some() {
+            extra.push(arg);
+        } else {
+            panic!(
+                "Funktio {} ottaa enintään {} argumenttia, mutta sille annettiin ylimääräinen argumentti {}",
+                func.name, func.parameters.len(), arg
+            );
         }
-        _ => panic!("Ei voida kutsua arvoa, joka ei ole funktio: {:?}", fn_val),
+    }
+
+    for (name, value) in named_args {
+        let index = match func.parameters.iter().position(|p| p.parameter_name() == Some(&name)) {
+            Some(index) => index,
+            None => panic!("Funktiolla {} ei ole parametria '{}'", func.name, name),
+        };
+        if slots[index].is_some() {
+            panic!("Funktion {} parametrille '{}' annettiin arvo useaan kertaan", func.name, name);
+        }
+        slots[index] = Some(value);
+    }
+
+    // Defaults are evaluated in order, so they can refer to earlier parameters
+    for (parameter, slot) in func.parameters.iter().zip(slots) {
+        let value = match (slot, parameter) {
+            (Some(value), _) => value,
+            (None, Pattern::Default(default)) => eval_expr(&default.default, scope),
+            (None, _) => panic!("Funktion {} kutsusta puuttuu argumentti parametrille '{}'", func.name, parameter),
+        };
+        declare_pattern(parameter, value, false, scope);
+    }
+
+    if let Some(rest) = &func.rest {
+        scope.declare_var(rest.clone(), MK_ARRAY(extra), false);
     }
 }
 
 pub fn eval_member_expr(expr: &MemberExpr, env: &mut Environment) -> RuntimeVal {
-    let object = eval_expr(&expr.object, env);
-    let property = match &*expr.property {
-        Expr::Identifier(ident) => ident.symbol.clone(),
-        _ => panic!("Omaisuuden on oltava tunniste"),
-    };
+    eval_member_chain(expr, env).unwrap_or(MK_NULL())
+}
+
+// Returns None when an optional link (?. or ?[]) short-circuits the rest of the chain
+fn eval_chain(expr: &Expr, env: &mut Environment) -> Option<RuntimeVal> {
+    match expr {
+        Expr::MemberExpr(member) => eval_member_chain(member, env),
+        Expr::CallExpr(call) if call.caller.is_optional_chain() => eval_optional_call(call, env),
+        other => Some(eval_expr(other, env)),
+    }
+}
+
+fn eval_member_chain(expr: &MemberExpr, env: &mut Environment) -> Option<RuntimeVal> {
+    let object = eval_chain(&expr.object, env)?;
 
-    match object {
-        RuntimeVal::Object(obj) => {
-            obj.iter()
-                .find_map(|(key, val)| {
-                    if key == &property { Some(val.clone()) } else { None }
-                })
-                .unwrap_or_else(|| panic!("Oliossa ei ole ominaisuutta {}", property))
+    if expr.optional {
+        if let RuntimeVal::Null = object {
+            return None;
         }
-        _ => panic!("Vain olioilla on ominaisuuksia"),
+        let key = eval_member_key(expr, env);
+        return Some(try_get_member(&object, &key).unwrap_or(MK_NULL()));
     }
+
+    let key = eval_member_key(expr, env);
+    Some(get_member(&object, &key))
 }
 
 pub fn eval_unary_expr(unary_expr: &UnaryExpr, env: &mut Environment) -> RuntimeVal {
     let operand = eval_expr(&unary_expr.operand, env);
-    match unary_expr.operator.as_str() {
-        "-" => {
-            if let RuntimeVal::Number(n) = operand {
-                MK_NUMBER(-n)
-            } else {
-                panic!("Operandin on oltava unaarisen '-'-operaattorin numero");
-            }
-        }
+    apply_unary_operator(&unary_expr.operator, operand)
+}
+
+pub fn apply_unary_operator(operator: &str, operand: RuntimeVal) -> RuntimeVal {
+    match operator {
+        "-" => match operand {
+            RuntimeVal::Number(n) => MK_NUMBER(-n),
+            RuntimeVal::Integer(i) => checked_integer(i.checked_neg(), "vastaluvussa"),
+            _ => panic!("Operandin on oltava unaarisen '-'-operaattorin numero"),
+        },
+        "~" => MK_INTEGER(!as_integer(&operand, "bittitason komplementtiin")),
         "!" => {
             if let RuntimeVal::Bool(b) = operand {
                 MK_BOOL(!b)
@@ -244,7 +655,7 @@ pub fn eval_if_else_expr(if_else_expr: &IfElseExpr, env: &mut Environment) -> Ru
             if b {
                 let mut result = MK_NULL();
                 for stmt in &if_else_expr.if_branch.statements {
-                    result = evaluate(&stmt.clone(), env);
+                    result = evaluate(stmt, env);
                 }
                 result
             } else {
@@ -252,7 +663,7 @@ pub fn eval_if_else_expr(if_else_expr: &IfElseExpr, env: &mut Environment) -> Ru
                     Some(else_branch) => {
                         let mut result = MK_NULL();
                         for stmt in &else_branch.statements {
-                            result = evaluate(&stmt.clone(), env);
+                            result = evaluate(stmt, env);
                         }
                         result
                     }
@@ -264,10 +675,14 @@ pub fn eval_if_else_expr(if_else_expr: &IfElseExpr, env: &mut Environment) -> Ru
     }
 }
 
-pub fn eval_string_literal(string_literal: &StringLiteral, env: &mut Environment, args: &[RuntimeVal]) -> RuntimeVal {
-    let mut output = string_literal.value.clone();
-    for arg in args {
-        output = output.replace("%{}", &format!("{}", arg)); // Replace first occurrence of "%{}" with the argument
+pub fn eval_conditional_expr(conditional_expr: &ConditionalExpr, env: &mut Environment) -> RuntimeVal {
+    if eval_expr(&conditional_expr.condition, env).is_truthy() {
+        eval_expr(&conditional_expr.consequent, env)
+    } else {
+        eval_expr(&conditional_expr.alternate, env)
     }
-    MK_STRING(output)
+}
+
+pub fn eval_string_literal(string_literal: &StringLiteral, _env: &mut Environment) -> RuntimeVal {
+    MK_STRING(string_literal.value.clone())
 }
\ No newline at end of file
@@ -1,2 +1,5 @@
 pub mod expressions;
 pub mod statements;
+pub mod patterns;
+pub mod classes;
+pub mod enums;
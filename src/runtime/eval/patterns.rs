@@ -0,0 +1,262 @@
+use std::rc::Rc;
+use std::borrow::Cow;
+use crate::frontend::ast::*;
+use crate::runtime::environment::*;
+use crate::runtime::interpreter::*;
+use crate::runtime::values::*;
+
+// Tests a value against a pattern, collecting the variables it binds
+pub fn match_pattern(
+    pattern: &Pattern,
+    value: &RuntimeVal,
+    env: &mut Environment,
+    bindings: &mut Vec<(String, RuntimeVal)>
+) -> bool {
+    match pattern {
+        Pattern::Wildcard => true,
+        Pattern::Binding(name) => {
+            bindings.push((name.clone(), value.clone()));
+            true
+        }
+        Pattern::Literal(expr) => eval_expr(expr, env).equals(value),
+        Pattern::Range(range) => {
+            let start = eval_expr(&range.start, env);
+            let end = eval_expr(&range.end, env);
+            match (as_number(&start), as_number(&end), as_number(value)) {
+                (Some(start), Some(end), Some(n)) =>
+                    n >= start && (if range.inclusive { n <= end } else { n < end }),
+                (None, _, _) | (_, None, _) => panic!("Välikuvion rajojen on oltava lukuja"),
+                _ => false,
+            }
+        }
+        Pattern::Array(array) => {
+            let elements = match value {
+                RuntimeVal::Array(elements) => elements,
+                _ => return false,
+            };
+            // Trailing elements with defaults may be missing
+            let required = array.elements
+                .iter()
+                .rposition(|p| !matches!(p, Pattern::Default(_)))
+                .map_or(0, |i| i + 1);
+            if elements.len() < required || (array.rest.is_none() && elements.len() > array.elements.len()) {
+                return false;
+            }
+            for (i, pattern) in array.elements.iter().enumerate() {
+                let matched = match elements.get(i) {
+                    Some(element) => match_pattern(pattern, element, env, bindings),
+                    None => match_missing(pattern, env, bindings),
+                };
+                if !matched {
+                    return false;
+                }
+            }
+            if let Some(rest) = &array.rest {
+                if rest != "_" {
+                    let rest_elements = elements.get(array.elements.len()..).unwrap_or(&[]).to_vec();
+                    bindings.push((rest.clone(), MK_ARRAY(rest_elements)));
+                }
+            }
+            true
+        }
+        Pattern::Object(object) => {
+            let properties = match object_properties(value) {
+                Some(properties) => properties,
+                None => return false,
+            };
+            for property in &object.properties {
+                let matched = match properties.iter().find(|(key, _)| key == &property.key) {
+                    Some((_, value)) => match_pattern(&property.pattern, value, env, bindings),
+                    None => match_missing(&property.pattern, env, bindings),
+                };
+                if !matched {
+                    return false;
+                }
+            }
+            true
+        }
+        Pattern::Alternatives(alternatives) => {
+            for alternative in alternatives {
+                let mark = bindings.len();
+                if match_pattern(alternative, value, env, bindings) {
+                    return true;
+                }
+                bindings.truncate(mark);
+            }
+            false
+        }
+        Pattern::Default(default) => match_pattern(&default.pattern, value, env, bindings),
+        Pattern::Variant(pattern) => {
+            let enum_type = match env.lookup_var(&pattern.enum_name) {
+                RuntimeVal::Enum(enum_type) => enum_type,
+                other => panic!("Kuviossa {}.{} odotettiin luetteloa, mutta {} on {}", pattern.enum_name, pattern.variant, pattern.enum_name, other),
+            };
+            let index = match enum_type.variants.iter().position(|variant| variant.name == pattern.variant) {
+                Some(index) => index,
+                None => panic!("Luettelossa {} ei ole muunnelmaa '{}'", enum_type.name, pattern.variant),
+            };
+            let fields = pattern.fields.as_ref();
+            if let Some(fields) = fields {
+                let expected = enum_type.variants[index].fields.len();
+                if fields.len() != expected {
+                    panic!(
+                        "Muunnelmalla {}.{} on {} kenttää, mutta kuviossa on {}",
+                        enum_type.name, pattern.variant, expected, fields.len()
+                    );
+                }
+            }
+
+            let value = match value {
+                RuntimeVal::EnumValue(value) if Rc::ptr_eq(&value.enum_type, &enum_type) && value.variant == index => value,
+                _ => return false,
+            };
+            match fields {
+                Some(fields) => fields
+                    .iter()
+                    .zip(&value.values)
+                    .all(|(field, element)| match_pattern(field, element, env, bindings)),
+                None => true,
+            }
+        }
+    }
+}
+
+// A missing array element or object key only matches a pattern with a default
+fn match_missing(pattern: &Pattern, env: &mut Environment, bindings: &mut Vec<(String, RuntimeVal)>) -> bool {
+    match pattern {
+        Pattern::Default(default) => {
+            let value = eval_expr(&default.default, env);
+            match_pattern(&default.pattern, &value, env, bindings)
+        }
+        _ => false,
+    }
+}
+
+// Binds a declaration, parameter or loop target, panicking when the value does not have the expected shape
+pub fn destructure(
+    pattern: &Pattern,
+    value: &RuntimeVal,
+    env: &mut Environment,
+    bindings: &mut Vec<(String, RuntimeVal)>
+) {
+    match pattern {
+        Pattern::Wildcard => (),
+        Pattern::Binding(name) => bindings.push((name.clone(), value.clone())),
+        Pattern::Default(default) => destructure(&default.pattern, value, env, bindings),
+        Pattern::Array(array) => {
+            let elements = match value {
+                RuntimeVal::Array(elements) => elements,
+                _ => panic!("Ei voida hajottaa arvoa {} taulukkokuvioon {}, koska se ei ole taulukko", value, pattern),
+            };
+            for (i, element_pattern) in array.elements.iter().enumerate() {
+                match elements.get(i) {
+                    Some(element) => destructure(element_pattern, element, env, bindings),
+                    None => match element_pattern {
+                        Pattern::Default(default) => {
+                            let value = eval_expr(&default.default, env);
+                            destructure(&default.pattern, &value, env, bindings);
+                        }
+                        _ => panic!(
+                            "Taulukossa on {} alkiota, mutta kuvio {} odottaa vähintään {}",
+                            elements.len(), pattern, i + 1
+                        ),
+                    },
+                }
+            }
+            if let Some(rest) = &array.rest {
+                if rest != "_" {
+                    let rest_elements = elements.get(array.elements.len()..).unwrap_or(&[]).to_vec();
+                    bindings.push((rest.clone(), MK_ARRAY(rest_elements)));
+                }
+            }
+        }
+        Pattern::Object(object) => {
+            let properties = match object_properties(value) {
+                Some(properties) => properties,
+                None => panic!("Ei voida hajottaa arvoa {} oliokuvioon {}, koska se ei ole olio", value, pattern),
+            };
+            for property in &object.properties {
+                match properties.iter().find(|(key, _)| key == &property.key) {
+                    Some((_, value)) => destructure(&property.pattern, value, env, bindings),
+                    None => match &property.pattern {
+                        Pattern::Default(default) => {
+                            let value = eval_expr(&default.default, env);
+                            destructure(&default.pattern, &value, env, bindings);
+                        }
+                        _ => panic!("Oliosta {} puuttuu avain '{}'", value, property.key),
+                    },
+                }
+            }
+        }
+        Pattern::Literal(_) | Pattern::Range(_) | Pattern::Alternatives(_) | Pattern::Variant(_) => {
+            if !match_pattern(pattern, value, env, bindings) {
+                panic!("Arvo {} ei täsmää kuvioon {}", value, pattern);
+            }
+        }
+    }
+}
+
+// Declares every variable bound by a pattern in the current scope
+pub fn declare_pattern(pattern: &Pattern, value: RuntimeVal, constant: bool, env: &mut Environment) {
+    if let Pattern::Binding(name) = pattern {
+        env.declare_var(name.clone(), value, constant);
+        return;
+    }
+    let mut bindings = Vec::new();
+    destructure(pattern, &value, env, &mut bindings);
+    for (name, value) in bindings {
+        env.declare_var(name, value, constant);
+    }
+}
+
+// Object patterns also look into the fields of class instances
+fn object_properties(value: &RuntimeVal) -> Option<Cow<'_, [(String, RuntimeVal)]>> {
+    match value {
+        RuntimeVal::Object(properties) => Some(Cow::Borrowed(properties.as_slice())),
+        RuntimeVal::Instance(instance) => Some(Cow::Owned(instance.borrow().fields.clone())),
+        _ => None,
+    }
+}
+
+fn as_number(value: &RuntimeVal) -> Option<f64> {
+    match value {
+        RuntimeVal::Integer(i) => Some(*i as f64),
+        RuntimeVal::Number(n) => Some(*n),
+        _ => None,
+    }
+}
+
+pub fn eval_match_expr(match_expr: &MatchExpr, env: &mut Environment) -> RuntimeVal {
+    let subject = eval_expr(&match_expr.subject, env);
+
+    for arm in &match_expr.arms {
+        let mut bindings = Vec::new();
+        if let Some(pattern) = &arm.pattern {
+            if !match_pattern(pattern, &subject, env, &mut bindings) {
+                continue;
+            }
+        }
+
+        // Bindings live in their own scope, shared by the guard and the body
+        env.push_scope();
+        for (name, value) in bindings {
+            env.declare_var(name, value, false);
+        }
+
+        if let Some(guard) = &arm.guard {
+            if !eval_expr(guard, env).is_truthy() {
+                env.pop_scope();
+                continue;
+            }
+        }
+
+        let mut result = MK_NULL();
+        for stmt in &arm.body.statements {
+            result = evaluate(stmt, env);
+        }
+        env.pop_scope();
+        return result;
+    }
+
+    MK_NULL()
+}
@@ -2,10 +2,10 @@ use crate::frontend::ast::*;
 use crate::runtime::environment::*;
 use crate::runtime::interpreter::*;
 use crate::runtime::values::*;
-
-
+use crate::runtime::eval::patterns::*;
+use crate::frontend::resolver::declarations;
 use std::rc::Rc;
-use std::cell::RefCell;
+
 
 pub fn eval_program(program: &Program, env: &mut Environment) -> RuntimeVal {
     let mut last_evaluated = MK_NULL();
@@ -16,51 +16,103 @@ pub fn eval_program(program: &Program, env: &mut Environment) -> RuntimeVal {
 }
 
 pub fn eval_var_declaration(var_declaration: &VarDeclaration, env: &mut Environment) -> RuntimeVal {
-    let VarDeclaration { constant, identifier, value } = var_declaration;
+    let VarDeclaration { constant, target, value, .. } = var_declaration;
     let value = match value {
-        Some(expr) => evaluate(&Stmt::Expr(expr.clone()), env),
+        Some(expr) => eval_expr(expr, env),
         None => MK_NULL(),
     };
 
-    env.declare_var(identifier.symbol.clone(), value, *constant)
+    declare_pattern(target, value.clone(), *constant, env);
+    value
 }
 
 pub fn eval_function_declaration(
     declaration: &FunctionDeclaration,
     env: &mut Environment
 ) -> RuntimeVal {
-    let function = Function {
-        parameters: declaration.parameters.clone(),
-        declaration_env: Rc::new(RefCell::new(env.clone())),
-        body: declaration.body.clone(),
-    };
-
-    let function_val = RuntimeVal::Function(function);
+    let function_val = RuntimeVal::Function(Rc::new(make_function(declaration, env)));
 
     env.declare_var(declaration.name.clone(), function_val, true)
 }
 
+// Functions share their declaration scope, so they see later declarations and themselves
+pub fn make_function(declaration: &FunctionDeclaration, env: &Environment) -> Function {
+    Function {
+        name: declaration.name.clone(),
+        parameters: declaration.parameters.clone(),
+        rest: declaration.rest.clone(),
+        declaration_env: env.clone(),
+        body: declaration.body.clone(),
+    }
+}
+
 pub fn eval_while_loop(while_loop: &WhileLoop, env: &mut Environment) -> RuntimeVal {
     let mut result = RuntimeVal::Null;
+    let scoped = declares_names(&while_loop.body);
     while eval_expr(&while_loop.condition, env).is_truthy() {
-        result = eval_block(&while_loop.body.clone(), env);
+        result = eval_loop_body(&while_loop.body, scoped, env);
     }
     result
 }
 
 pub fn eval_for_loop(for_loop: &ForLoop, env: &mut Environment) -> RuntimeVal {
-    let ForLoop { initializer, condition, increment, body } = for_loop;
-    evaluate(initializer, env);
+    let ForLoop { initializer, condition, increment, body, .. } = for_loop;
+    // The loop variable lives in its own scope around the body
+    env.push_scope();
+    eval_stmt(initializer, env);
+    let scoped = declares_names(body);
     while match eval_expr(condition, env) {
         RuntimeVal::Bool(b) => b,
         _ => false,
     } {
-        eval_block(body, env);
+        eval_loop_body(body, scoped, env);
         eval_expr(increment, env);
     }
+    env.pop_scope();
     MK_NULL()
 }
 
+pub fn eval_for_each_loop(for_each: &ForEachLoop, env: &mut Environment) -> RuntimeVal {
+    let items = iteration_items(eval_expr(&for_each.iterable, env));
+    for item in items {
+        env.push_scope();
+        declare_pattern(&for_each.target, item, for_each.constant, env);
+        eval_block(&for_each.body, env);
+        env.pop_scope();
+    }
+    MK_NULL()
+}
+
+// The values a for-each loop visits
+pub fn iteration_items(iterable: RuntimeVal) -> Vec<RuntimeVal> {
+    match iterable {
+        RuntimeVal::Array(elements) => elements,
+        RuntimeVal::String(s) => s.chars().map(|c| MK_STRING(c.to_string())).collect(),
+        // Objects are iterated as [avain, arvo] pairs
+        RuntimeVal::Object(properties) => properties
+            .into_iter()
+            .map(|(key, value)| MK_ARRAY(vec![MK_STRING(key), value]))
+            .collect(),
+        other => panic!("toista-silmukka voi käydä läpi vain taulukon, merkkijonon tai olion, saatiin {}", other),
+    }
+}
+
+// A body that declares names runs in a fresh scope each iteration; one that does not
+// would only allocate an empty scope
+fn eval_loop_body(block: &Block, scoped: bool, env: &mut Environment) -> RuntimeVal {
+    if !scoped {
+        return eval_block(block, env);
+    }
+    env.push_scope();
+    let result = eval_block(block, env);
+    env.pop_scope();
+    result
+}
+
+fn declares_names(block: &Block) -> bool {
+    !declarations(&block.statements).is_empty()
+}
+
 fn eval_block(block: &Block, env: &mut Environment) -> RuntimeVal {
     let mut result = RuntimeVal::Null;
     for stmt in &block.statements {
@@ -0,0 +1,443 @@
+use crate::embed::catch_panic;
+use crate::runtime::environment::Environment;
+use crate::runtime::eval::expressions::float_to_integer;
+use crate::runtime::values::*;
+
+// Formatting mini-language shared by tulosta() and muotoile(). muotoile always formats its first
+// argument; tulosta only when it gets more arguments, and prints a string alone as it is.
+//
+//   %{}            next positional argument
+//   %{0}           argument by index
+//   %{nimi}        named value, from a trailing object argument or the caller's scope
+//   %{:spec}       format spec: [[täyte]tasaus][+][#][0][leveys][,|_][.tarkkuus][tyyppi]
+//   %%{            literal "%{"
+//
+// Alignment is one of < > ^, and the type is one of d f e E x X b o s.
+
+#[derive(Debug, Clone, PartialEq)]
+enum ArgRef {
+    Next,
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone)]
+struct FormatSpec {
+    fill: char,
+    align: Option<Align>,
+    plus_sign: bool,
+    alternate: bool,
+    zero_pad: bool,
+    width: Option<usize>,
+    thousands: Option<char>,
+    precision: Option<usize>,
+    ty: Option<char>,
+}
+
+impl FormatSpec {
+    fn new() -> Self {
+        Self {
+            fill: ' ',
+            align: None,
+            plus_sign: false,
+            alternate: false,
+            zero_pad: false,
+            width: None,
+            thousands: None,
+            precision: None,
+            ty: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Placeholder(ArgRef, FormatSpec),
+}
+
+pub fn format_string(
+    caller: &str,
+    template: &str,
+    args: &[RuntimeVal],
+    env: &Environment
+) -> String {
+    let segments = parse_template(caller, template);
+
+    let mut implicit_count = 0;
+    let mut required = 0;
+    let mut has_named = false;
+    for segment in &segments {
+        match segment {
+            Segment::Placeholder(ArgRef::Next, _) => {
+                implicit_count += 1;
+                required = required.max(implicit_count);
+            }
+            Segment::Placeholder(ArgRef::Index(i), _) => {
+                required = required.max(i + 1);
+            }
+            Segment::Placeholder(ArgRef::Name(_), _) => {
+                has_named = true;
+            }
+            Segment::Text(_) => (),
+        }
+    }
+
+    // A trailing object argument beyond the positional ones supplies the named values
+    let (positional, named) = match args.last() {
+        Some(RuntimeVal::Object(properties)) if has_named && args.len() == required + 1 =>
+            (&args[..args.len() - 1], Some(properties)),
+        _ => (args, None),
+    };
+
+    if positional.len() != required {
+        panic!(
+            "{}: muotoilumerkkijono odottaa {} argumenttia, mutta saatiin {}",
+            caller,
+            required,
+            positional.len()
+        );
+    }
+
+    let mut output = String::new();
+    let mut next = 0;
+    for segment in &segments {
+        match segment {
+            Segment::Text(text) => output.push_str(text),
+            Segment::Placeholder(arg_ref, spec) => {
+                let found;
+                let value = match arg_ref {
+                    ArgRef::Next => {
+                        next += 1;
+                        &positional[next - 1]
+                    }
+                    ArgRef::Index(i) => &positional[*i],
+                    ArgRef::Name(name) => {
+                        found = lookup_named(caller, name, named, env);
+                        &found
+                    }
+                };
+                output.push_str(&format_value(caller, value, spec));
+            }
+        }
+    }
+
+    output
+}
+
+// Only the names a template uses are looked up, since the caller's scope can hold large values
+fn lookup_named(
+    caller: &str,
+    name: &str,
+    named: Option<&Vec<(String, RuntimeVal)>>,
+    env: &Environment
+) -> RuntimeVal {
+    if let Some((_, value)) = named.and_then(|properties| properties.iter().find(|(key, _)| key == name)) {
+        return value.clone();
+    }
+    if !env.has_var(name) {
+        panic!("{}: nimettyä arvoa '{}' ei löytynyt", caller, name);
+    }
+    env.find_var(name).get()
+}
+
+// The names a template reads from the caller's scope, or nothing if it is not a valid template
+pub fn placeholder_names(template: &str) -> Vec<String> {
+    if !template.contains("%{") {
+        return Vec::new();
+    }
+    let segments = catch_panic(|| parse_template("", template)).unwrap_or_default();
+    segments.into_iter().filter_map(|segment| match segment {
+        Segment::Placeholder(ArgRef::Name(name), _) => Some(name),
+        _ => None,
+    }).collect()
+}
+
+fn parse_template(caller: &str, template: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+
+        match chars.peek() {
+            Some(&'%') => {
+                chars.next();
+                if chars.peek() == Some(&'{') {
+                    chars.next();
+                    text.push_str("%{");
+                } else {
+                    text.push_str("%%");
+                }
+            }
+            Some(&'{') => {
+                chars.next();
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => inner.push(c),
+                        None => panic!("{}: muotoilumerkkijonosta puuttuu sulkeva '}}'", caller),
+                    }
+                }
+                if !text.is_empty() {
+                    segments.push(Segment::Text(text.clone()));
+                    text.clear();
+                }
+                segments.push(parse_placeholder(caller, &inner));
+            }
+            _ => text.push(c),
+        }
+    }
+
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    segments
+}
+
+fn parse_placeholder(caller: &str, inner: &str) -> Segment {
+    let (reference, spec) = match inner.find(':') {
+        Some(index) => (&inner[..index], Some(&inner[index + 1..])),
+        None => (inner, None),
+    };
+
+    let reference = reference.trim();
+    let arg_ref = if reference.is_empty() {
+        ArgRef::Next
+    } else if reference.chars().all(|c| c.is_ascii_digit()) {
+        ArgRef::Index(reference.parse().unwrap())
+    } else if reference.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        ArgRef::Name(reference.to_string())
+    } else {
+        panic!("{}: virheellinen paikkamerkki '%{{{}}}'", caller, inner);
+    };
+
+    let spec = match spec {
+        Some(spec) => parse_spec(caller, spec),
+        None => FormatSpec::new(),
+    };
+
+    Segment::Placeholder(arg_ref, spec)
+}
+
+fn parse_align(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    }
+}
+
+fn parse_spec(caller: &str, spec: &str) -> FormatSpec {
+    let mut result = FormatSpec::new();
+    let chars: Vec<char> = spec.chars().collect();
+    let mut i = 0;
+
+    // [[fill]align]
+    if chars.len() >= 2 && parse_align(chars[1]).is_some() {
+        result.fill = chars[0];
+        result.align = parse_align(chars[1]);
+        i = 2;
+    } else if !chars.is_empty() && parse_align(chars[0]).is_some() {
+        result.align = parse_align(chars[0]);
+        i = 1;
+    }
+
+    if i < chars.len() && chars[i] == '+' {
+        result.plus_sign = true;
+        i += 1;
+    }
+
+    if i < chars.len() && chars[i] == '#' {
+        result.alternate = true;
+        i += 1;
+    }
+
+    if i < chars.len() && chars[i] == '0' {
+        result.zero_pad = true;
+        i += 1;
+    }
+
+    let start = i;
+    while i < chars.len() && chars[i].is_ascii_digit() {
+        i += 1;
+    }
+    if i > start {
+        result.width = Some(chars[start..i].iter().collect::<String>().parse().unwrap());
+    }
+
+    if i < chars.len() && (chars[i] == ',' || chars[i] == '_') {
+        result.thousands = Some(chars[i]);
+        i += 1;
+    }
+
+    if i < chars.len() && chars[i] == '.' {
+        i += 1;
+        let start = i;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+        if i == start {
+            panic!("{}: tarkkuus puuttuu pisteen jälkeen muotoilussa '{}'", caller, spec);
+        }
+        result.precision = Some(chars[start..i].iter().collect::<String>().parse().unwrap());
+    }
+
+    if i < chars.len() {
+        match chars[i] {
+            'd' | 'f' | 'e' | 'E' | 'x' | 'X' | 'b' | 'o' | 's' => {
+                result.ty = Some(chars[i]);
+                i += 1;
+            }
+            c => panic!("{}: tuntematon muotoilutyyppi '{}'", caller, c),
+        }
+    }
+
+    if i < chars.len() {
+        panic!("{}: virheellinen muotoilu '{}'", caller, spec);
+    }
+
+    result
+}
+
+fn format_value(caller: &str, value: &RuntimeVal, spec: &FormatSpec) -> String {
+    match spec.ty {
+        Some('x') | Some('X') | Some('b') | Some('o') | Some('d') => {
+            let int = match value {
+                RuntimeVal::Integer(i) => *i,
+                RuntimeVal::Number(n) if n.fract() == 0.0 => float_to_integer(*n),
+                _ => panic!("{}: muotoilu '{}' vaatii kokonaisluvun, saatiin {}", caller, spec.ty.unwrap(), value),
+            };
+            format_integer(int, spec)
+        }
+        Some('f') | Some('e') | Some('E') => {
+            let number = match value {
+                RuntimeVal::Integer(i) => *i as f64,
+                RuntimeVal::Number(n) => *n,
+                _ => panic!("{}: muotoilu '{}' vaatii luvun, saatiin {}", caller, spec.ty.unwrap(), value),
+            };
+            format_float(number, spec)
+        }
+        _ => match value {
+            RuntimeVal::Integer(i) if spec.ty.is_none() => format_integer(*i, spec),
+            RuntimeVal::Number(n) if spec.ty.is_none() => format_float(*n, spec),
+            _ => {
+                let mut text = value.to_string();
+                if let Some(precision) = spec.precision {
+                    text = text.chars().take(precision).collect();
+                }
+                pad(text, String::new(), spec, Align::Left)
+            }
+        },
+    }
+}
+
+fn format_integer(value: i64, spec: &FormatSpec) -> String {
+    let magnitude = value.unsigned_abs();
+    let (digits, prefix) = match spec.ty {
+        Some('x') => (format!("{:x}", magnitude), "0x"),
+        Some('X') => (format!("{:X}", magnitude), "0x"),
+        Some('b') => (format!("{:b}", magnitude), "0b"),
+        Some('o') => (format!("{:o}", magnitude), "0o"),
+        _ => (magnitude.to_string(), ""),
+    };
+
+    let digits = match spec.thousands {
+        Some(separator) => group_digits(&digits, separator),
+        None => digits,
+    };
+
+    let mut sign = sign_of(value < 0, spec);
+    if spec.alternate {
+        sign.push_str(prefix);
+    }
+    pad(digits, sign, spec, Align::Right)
+}
+
+fn format_float(value: f64, spec: &FormatSpec) -> String {
+    let magnitude = value.abs();
+    let body = match (spec.ty, spec.precision) {
+        (Some('e'), Some(p)) => format!("{:.*e}", p, magnitude),
+        (Some('e'), None) => format!("{:e}", magnitude),
+        (Some('E'), Some(p)) => format!("{:.*E}", p, magnitude),
+        (Some('E'), None) => format!("{:E}", magnitude),
+        (_, Some(p)) => format!("{:.*}", p, magnitude),
+        (_, None) => format!("{}", magnitude),
+    };
+
+    let body = match spec.thousands {
+        Some(separator) if !matches!(spec.ty, Some('e') | Some('E')) => {
+            let (int_part, rest) = match body.find('.') {
+                Some(index) => body.split_at(index),
+                None => (body.as_str(), ""),
+            };
+            format!("{}{}", group_digits(int_part, separator), rest)
+        }
+        _ => body,
+    };
+
+    let sign = sign_of(value.is_sign_negative() && value != 0.0, spec);
+    pad(body, sign, spec, Align::Right)
+}
+
+fn sign_of(negative: bool, spec: &FormatSpec) -> String {
+    if negative {
+        "-".to_string()
+    } else if spec.plus_sign {
+        "+".to_string()
+    } else {
+        String::new()
+    }
+}
+
+fn group_digits(digits: &str, separator: char) -> String {
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+fn pad(body: String, sign: String, spec: &FormatSpec, default_align: Align) -> String {
+    let width = match spec.width {
+        Some(width) => width,
+        None => return format!("{}{}", sign, body),
+    };
+
+    let len = sign.chars().count() + body.chars().count();
+    if len >= width {
+        return format!("{}{}", sign, body);
+    }
+    let padding = width - len;
+
+    // Zero padding goes between the sign and the digits
+    if spec.zero_pad && spec.align.is_none() {
+        return format!("{}{}{}", sign, "0".repeat(padding), body);
+    }
+
+    let fill = spec.fill.to_string();
+    match spec.align.clone().unwrap_or(default_align) {
+        Align::Left => format!("{}{}{}", sign, body, fill.repeat(padding)),
+        Align::Right => format!("{}{}{}", fill.repeat(padding), sign, body),
+        Align::Center => {
+            let left = padding / 2;
+            format!("{}{}{}{}", fill.repeat(left), sign, body, fill.repeat(padding - left))
+        }
+    }
+}
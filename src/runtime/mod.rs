@@ -2,4 +2,7 @@ pub mod interpreter;
 pub mod values;
 pub mod environment;
 pub mod eval;
-pub mod native_functions;
\ No newline at end of file
+pub mod native_functions;
+pub mod assertions;
+pub mod formatting;
+pub mod modules;
@@ -0,0 +1,195 @@
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use crate::frontend::ast::*;
+use crate::frontend::cache::parse_cached;
+use crate::frontend::lexer::*;
+use crate::frontend::parser::*;
+use crate::frontend::resolver::*;
+use crate::runtime::environment::*;
+use crate::runtime::interpreter::*;
+use crate::runtime::values::*;
+use crate::runtime::eval::patterns::*;
+
+// Modules that are loaded, and the chain of files currently being loaded
+#[derive(Default)]
+pub struct ModuleRegistry {
+    cache: Vec<(PathBuf, RuntimeVal)>,
+    loading: Vec<PathBuf>,
+}
+
+impl ModuleRegistry {
+    // Forgets the files left half-loaded by a failed run
+    pub fn clear_loading(&mut self) {
+        self.loading.clear();
+    }
+}
+
+pub fn eval_import(import: &ImportDeclaration, env: &mut Environment) -> RuntimeVal {
+    let path = resolve_module_path(&import.path, env, import.line);
+    let exports = load_module(&path, env);
+
+    match &import.names {
+        Some(names) => {
+            let properties = match &exports {
+                RuntimeVal::Object(properties) => properties,
+                _ => unreachable!(),
+            };
+            for property in &names.properties {
+                let value = match properties.iter().find(|(key, _)| key == &property.key) {
+                    Some((_, value)) => value.clone(),
+                    None => panic!("Rivi {}: moduuli \"{}\" ei vie nimeä '{}'", import.line, import.path, property.key),
+                };
+                declare_pattern(&property.pattern, value, true, env);
+            }
+        }
+        None => {
+            let name = match &import.alias {
+                Some(alias) => alias.clone(),
+                None => module_name(&path, import),
+            };
+            env.declare_var(name, exports.clone(), true);
+        }
+    }
+
+    exports
+}
+
+// The binding for `tuo "polku/moduuli.ka"` is the file name without its extension
+fn module_name(path: &Path, import: &ImportDeclaration) -> String {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let valid = stem.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_') &&
+        stem.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if !valid {
+        panic!(
+            "Rivi {}: moduulin \"{}\" nimi ei kelpaa tunnisteeksi, anna nimi: tuo \"{}\" nimellä nimi",
+            import.line, import.path, import.path
+        );
+    }
+    stem
+}
+
+// Looks next to the importing file first, then in each directory of KAULIN_PATH
+fn resolve_module_path(path: &str, env: &Environment, line: usize) -> PathBuf {
+    let mut relative = PathBuf::from(path);
+    if relative.extension().is_none() {
+        relative.set_extension("ka");
+    }
+    if relative.is_absolute() {
+        return canonical(&relative, path, line);
+    }
+
+    let base = env
+        .module_path()
+        .and_then(|file| file.parent().map(Path::to_path_buf))
+        .unwrap_or_else(|| PathBuf::from("."));
+    let mut candidates = vec![base.join(&relative)];
+    if let Some(search_path) = env::var_os("KAULIN_PATH") {
+        candidates.extend(env::split_paths(&search_path).map(|dir| dir.join(&relative)));
+    }
+
+    match candidates.iter().find(|candidate| candidate.is_file()) {
+        Some(found) => canonical(found, path, line),
+        None => {
+            let searched: Vec<String> = candidates.iter().map(|c| c.display().to_string()).collect();
+            panic!("Rivi {}: moduulia \"{}\" ei löytynyt. Etsittiin: {}", line, path, searched.join(", "))
+        }
+    }
+}
+
+fn canonical(path: &Path, original: &str, line: usize) -> PathBuf {
+    match fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(_) => panic!("Rivi {}: moduulia \"{}\" ei löytynyt", line, original),
+    }
+}
+
+// Evaluates a module once and returns its exports as an object
+fn load_module(path: &Path, env: &Environment) -> RuntimeVal {
+    let registry = env.module_registry();
+    if let Some((_, exports)) = registry.borrow().cache.iter().find(|(loaded, _)| loaded == path) {
+        return exports.clone();
+    }
+
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(_) => panic!("Moduulia {} ei voitu lukea", path.display()),
+    };
+    let (program, warnings) = parse_cached(path, &source, || {
+        let mut parser = Parser::new(tokenize(&source));
+        let program = parser.produce_ast();
+        (program, parser.warnings)
+    });
+    for warning in &warnings {
+        eprintln!("Varoitus: {}: {}", path.display(), warning);
+    }
+    let global = env.global();
+    let resolution = resolve_program(&program, &|name| global.constness(name));
+    if !resolution.errors.is_empty() {
+        panic!("{}", resolution.errors.join("\n"));
+    }
+
+    let mut module_env = Environment::new(Some(env.global()));
+    eval_file_program(&program, path, &mut module_env);
+
+    let mut exports = Vec::new();
+    for stmt in &program.body {
+        if let Stmt::Export(export) = stmt {
+            for name in export.exported_names() {
+                let value = module_env.lookup_var(&name);
+                exports.push((name, value));
+            }
+        }
+    }
+
+    let exports = MK_OBJECT(exports);
+    registry.borrow_mut().cache.push((path.to_path_buf(), exports.clone()));
+    exports
+}
+
+// Runs the top level of a file, refusing to enter a file that is already being loaded
+pub fn eval_file_program(program: &Program, path: &Path, env: &mut Environment) -> RuntimeVal {
+    run_file(path, env, |env| {
+        let mut result = MK_NULL();
+        for stmt in &program.body {
+            result = evaluate(stmt, env);
+        }
+        result
+    })
+}
+
+// Sets up the module path and the import cycle check around `run`, whichever backend it uses
+pub fn run_file(path: &Path, env: &mut Environment, run: impl FnOnce(&mut Environment) -> RuntimeVal) -> RuntimeVal {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let registry = env.module_registry();
+
+    if let Some(start) = registry.borrow().loading.iter().position(|loading| loading == &path) {
+        let chain: Vec<String> = registry.borrow().loading[start..]
+            .iter()
+            .chain(std::iter::once(&path))
+            .map(|file| file.file_name().map_or(file.display().to_string(), |name| name.to_string_lossy().to_string()))
+            .collect();
+        panic!("Kehäviittaus moduulien välillä: {}", chain.join(" -> "));
+    }
+
+    registry.borrow_mut().loading.push(path.clone());
+    env.set_module_path(path);
+    let result = run(env);
+    registry.borrow_mut().loading.pop();
+    result
+}
+
+pub fn eval_export(export: &ExportDeclaration, env: &mut Environment) -> RuntimeVal {
+    match &export.declaration {
+        Some(declaration) => eval_stmt(declaration, env),
+        None => {
+            // Names listed in `vie { a, b }` must exist when the statement runs
+            for name in &export.names {
+                if !env.has_var(name) {
+                    panic!("Ei voida viedä nimeä '{}', koska sitä ei ole määritelty", name);
+                }
+            }
+            MK_NULL()
+        }
+    }
+}
@@ -1,21 +1,21 @@
 use std::time::SystemTime;
-use std::rc::Rc;
 use std::f64;
-use std::io::{ self, Write };
-use std::convert::TryInto;
+use std::io::{ self };
 use rand::Rng;
-use strfmt::strfmt;
 use crate::runtime::values::*;
+use crate::runtime::formatting::*;
+use crate::runtime::environment::Environment;
+use crate::debugger;
 
-pub fn time_function(_args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>) -> RuntimeVal {
+pub fn time_function(_args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
     let now = SystemTime::now();
     let duration_since_epoch = now.duration_since(SystemTime::UNIX_EPOCH).unwrap();
     let millis = duration_since_epoch.as_millis();
     MK_INTEGER(millis as i64)
 }
 
-pub fn millis_to_seconds_function(args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>) -> RuntimeVal {
-    match args.get(0) {
+pub fn millis_to_seconds_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
+    match args.first() {
         Some(RuntimeVal::Integer(n)) => {
             let seconds = *n / 1000;
             MK_INTEGER(seconds)
@@ -24,59 +24,41 @@ pub fn millis_to_seconds_function(args: Vec<RuntimeVal>, _scope: Vec<(String, Ru
     }
 }
 
-pub fn abs_function(args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>) -> RuntimeVal {
-    match args.get(0) {
+pub fn abs_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
+    match args.first() {
         Some(RuntimeVal::Number(n)) => MK_NUMBER(n.abs()),
-        Some(RuntimeVal::Integer(i)) => MK_INTEGER(i.abs()),
+        Some(RuntimeVal::Integer(i)) => match i.checked_abs() {
+            Some(value) => MK_INTEGER(value),
+            None => panic!("Kokonaisluvun ylivuoto itseisarvossa"),
+        },
         _ => panic!("itseisarvo-funktio odottaa numeroa argumenttina"),
     }
 }
 
-pub fn round_function(args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>) -> RuntimeVal {
-    if args.len() > 2 {
-        panic!("pyöristä-funktio ottaa enintään kaksi argumenttia");
-    }
-    let number = match args.get(0) {
-        Some(RuntimeVal::Number(n)) => *n,
-        Some(RuntimeVal::Integer(i)) => *i as f64,
-        _ => panic!("pyöristä-funktio ottaa luvun ensimmäisenä argumenttina"),
-    };
-    let ndigits = if args.len() == 2 {
-        match args.get(1) {
-            Some(RuntimeVal::Number(n)) => *n as i32,
-            _ => panic!("pyöristä-funktio ottaa kokonaisluvun toiseksi argumentiksi"),
-        }
-    } else {
-        0
-    };
-    let multiplier = (10f64).powi(ndigits);
-    let mut result = (number * multiplier).round() / multiplier;
+pub fn round_function(number: f64, ndigits: Option<i64>) -> f64 {
+    let multiplier = (10f64).powi(ndigits.unwrap_or(0) as i32);
+    let result = (number * multiplier).round() / multiplier;
     if result == -0.0 {
-        result = 0.0;
+        0.0
+    } else {
+        result
     }
-    MK_NUMBER(result)
 }
 
-pub fn sqrt_function(args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>) -> RuntimeVal {
-    if args.len() != 1 {
-        panic!("neliöjuuri-funktio ottaa täsmälleen yhden argumentin");
-    }
-    let number = match args.get(0) {
-        Some(RuntimeVal::Number(n)) => *n,
-        Some(RuntimeVal::Integer(i)) => *i as f64,
-        _ => panic!("neliöjuuri-funktio ottaa luvun argumenttina"),
-    };
+pub fn sqrt_function(number: f64) -> Result<f64, &'static str> {
     if number < 0.0 {
-        panic!("neliöjuuri-funktio ei voi ottaa negatiivista lukua argumenttina");
+        return Err("ei voi ottaa negatiivista lukua argumenttina");
     }
-    let result = number.sqrt();
-    MK_NUMBER(result)
+    Ok(number.sqrt())
 }
 
-pub fn input_function(args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>) -> RuntimeVal {
+pub fn input_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
     if !args.is_empty() {
         panic!("syöttötoiminto ei ota argumentteja");
     }
+    if debugger::console_taken() {
+        panic!("syöte ei ole käytettävissä, kun virheenjäljitin käyttää syötettä ja tulostetta");
+    }
     let mut input = String::new();
     io::stdin().read_line(&mut input).expect("Rivin lukeminen epäonnistui");
     if let Some('\n') = input.chars().next_back() {
@@ -88,11 +70,11 @@ pub fn input_function(args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>)
     MK_STRING(input)
 }
 
-pub fn random_function(args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>) -> RuntimeVal {
+pub fn random_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
     if args.len() < 2 || args.len() > 3 {
         panic!("satunnainen-funktio ottaa kaksi tai kolme argumenttia");
     }
-    let min = match args.get(0) {
+    let min = match args.first() {
         Some(RuntimeVal::Number(n)) => *n,
         Some(RuntimeVal::Integer(i)) => *i as f64,
         _ => panic!("satunnainen-funktio ottaa luvun ensimmäiseksi argumentiksi"),
@@ -119,30 +101,29 @@ pub fn random_function(args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>)
     MK_NUMBER(result)
 }
 
-pub fn print_function(args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>) -> RuntimeVal {
-    let mut args_iter = args.iter();
-
-    let format_args = args_iter
-        .next()
-        .map(|arg| match arg {
-            RuntimeVal::String(s) => {
-                let parts: Vec<&str> = s.split("%{}").collect();
-                let mut result = parts[0].to_string();
-                for (part, arg) in parts[1..].iter().zip(&args[1..]) {
-                    result.push_str(&arg.to_string());
-                    result.push_str(part);
-                }
-                result
-            },
-            _ => arg.to_string(), // Convert non-string arguments into strings
-        })
-        .unwrap_or_else(|| String::new());
+// A string alone is printed as it is, so text made at runtime, e.g. by muotoile, is never formatted twice
+pub fn print_function(args: Vec<RuntimeVal>, env: &Environment) -> RuntimeVal {
+    let output = match args.first() {
+        Some(RuntimeVal::String(template)) if args.len() > 1 => format_string("tulosta-funktio", template, &args[1..], env),
+        Some(RuntimeVal::String(text)) => text.clone(),
+        // Convert non-string arguments into strings
+        Some(_) => args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>().join(" "),
+        None => String::new(),
+    };
 
-    println!("{}", format_args);
+    debugger::print(&output);
     MK_NULL()
 }
 
-pub fn max_function(args: Vec<RuntimeVal>, _: Vec<(String, RuntimeVal)>) -> RuntimeVal {
+pub fn format_function(args: Vec<RuntimeVal>, env: &Environment) -> RuntimeVal {
+    match args.first() {
+        Some(RuntimeVal::String(template)) =>
+            MK_STRING(format_string("muotoile-funktio", template, &args[1..], env)),
+        _ => panic!("muotoile-funktio odottaa muotoilumerkkijonoa ensimmäisenä argumenttina"),
+    }
+}
+
+pub fn max_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
     let numbers = match &args[0] {
         RuntimeVal::Array(arr) => arr,
         _ => panic!("maksimi-funktio odottaa numero joukkoa argumenttina"),
@@ -178,7 +159,7 @@ pub fn max_function(args: Vec<RuntimeVal>, _: Vec<(String, RuntimeVal)>) -> Runt
     RuntimeVal::Number(max_val)
 }
 
-pub fn min_function(args: Vec<RuntimeVal>, _: Vec<(String, RuntimeVal)>) -> RuntimeVal {
+pub fn min_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
     let numbers = match &args[0] {
         RuntimeVal::Array(arr) => arr,
         _ => panic!("minimi-funktio odottaa numero joukkoa argumenttina"),
@@ -214,7 +195,7 @@ pub fn min_function(args: Vec<RuntimeVal>, _: Vec<(String, RuntimeVal)>) -> Runt
     RuntimeVal::Number(min_val)
 }
 
-pub fn length_function(args: Vec<RuntimeVal>, _: Vec<(String, RuntimeVal)>) -> RuntimeVal {
+pub fn length_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
     let array = match &args[0] {
         RuntimeVal::Array(arr) => arr,
         _ => panic!("pituus-funktio odottaa taulukon argumenttina"),
@@ -223,7 +204,7 @@ pub fn length_function(args: Vec<RuntimeVal>, _: Vec<(String, RuntimeVal)>) -> R
     RuntimeVal::Number(array.len() as f64)
 }
 
-pub fn sort_function(args: Vec<RuntimeVal>, _: Vec<(String, RuntimeVal)>) -> RuntimeVal {
+pub fn sort_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
     let mut array = match &args[0] {
         RuntimeVal::Array(arr) => arr.clone(),
         _ => panic!("järjestä-funktio odottaa taulukon argumenttina"),
@@ -239,7 +220,7 @@ pub fn sort_function(args: Vec<RuntimeVal>, _: Vec<(String, RuntimeVal)>) -> Run
     RuntimeVal::Array(array)
 }
 
-pub fn reverse_function(args: Vec<RuntimeVal>, _: Vec<(String, RuntimeVal)>) -> RuntimeVal {
+pub fn reverse_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
     let mut array = match &args[0] {
         RuntimeVal::Array(arr) => arr.clone(),
         _ => panic!("käänteinen-funktio odottaa taulukkoa argumenttina"),
@@ -250,8 +231,8 @@ pub fn reverse_function(args: Vec<RuntimeVal>, _: Vec<(String, RuntimeVal)>) ->
     RuntimeVal::Array(array)
 }
 
-pub fn kluku_function(args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>) -> RuntimeVal {
-    match args.get(0) {
+pub fn kluku_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
+    match args.first() {
         Some(RuntimeVal::String(s)) => {
             match s.parse::<f64>() {
                 Ok(n) => MK_INTEGER(n.floor() as i64),
@@ -264,8 +245,8 @@ pub fn kluku_function(args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>)
     }
 }
 
-pub fn lluku_function(args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>) -> RuntimeVal {
-    match args.get(0) {
+pub fn lluku_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
+    match args.first() {
         Some(RuntimeVal::String(s)) => {
             match s.parse::<f64>() {
                 Ok(n) => MK_NUMBER(n),
@@ -278,8 +259,8 @@ pub fn lluku_function(args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>)
     }
 }
 
-pub fn mjono_function(args: Vec<RuntimeVal>, _scope: Vec<(String, RuntimeVal)>) -> RuntimeVal {
-    match args.get(0) {
+pub fn mjono_function(args: Vec<RuntimeVal>, _env: &Environment) -> RuntimeVal {
+    match args.first() {
         Some(val) => MK_STRING(val.to_string()),
         _ => panic!("mjono-funktio odottaa argumenttia"),
     }
@@ -1,7 +1,12 @@
+// The MK_ value constructors are named in upper case by convention
+#![allow(non_snake_case)]
+
 use std::rc::Rc;
 use std::cell::RefCell;
 use crate::frontend::ast::*;
 use crate::runtime::environment::*;
+use crate::host::{ HostObject, HostRef };
+use crate::vm::chunk::Closure;
 
 #[derive(Clone, Debug)]
 pub enum RuntimeVal {
@@ -13,7 +18,20 @@ pub enum RuntimeVal {
     Object(Vec<(String, RuntimeVal)>),
     Array(Vec<RuntimeVal>),
     NativeFunction(NativeFunction),
-    Function(Function),
+    Function(Rc<Function>),
+    Class(Rc<Class>),
+    // Instances are shared: copies refer to the same fields
+    Instance(Rc<RefCell<Instance>>),
+    BoundMethod(BoundMethod),
+    Super(SuperRef),
+    Enum(Rc<EnumType>),
+    // Muoto.Ympyrä: builds the variant from positional or named fields
+    Variant(VariantConstructor),
+    EnumValue(EnumValue),
+    // A Rust value owned by the embedding program
+    Host(HostRef),
+    // A function compiled to bytecode
+    Closure(Rc<Closure>),
 }
 
 impl RuntimeVal {
@@ -27,22 +45,80 @@ impl RuntimeVal {
             RuntimeVal::Object(_) => true,
             RuntimeVal::Array(a) => !a.is_empty(),
             RuntimeVal::NativeFunction(_) => true,
-            RuntimeVal::Function(_) => true,
+            RuntimeVal::Function(_) | RuntimeVal::Closure(_) => true,
+            RuntimeVal::Class(_) | RuntimeVal::Instance(_) | RuntimeVal::BoundMethod(_) | RuntimeVal::Super(_) => true,
+            RuntimeVal::Enum(_) | RuntimeVal::Variant(_) | RuntimeVal::EnumValue(_) | RuntimeVal::Host(_) => true,
+        }
+    }
+    // Structural equality; kokonaisluvut and liukuluvut compare by value
+    pub fn equals(&self, other: &RuntimeVal) -> bool {
+        match (self, other) {
+            (RuntimeVal::Null, RuntimeVal::Null) => true,
+            (RuntimeVal::Bool(a), RuntimeVal::Bool(b)) => a == b,
+            (RuntimeVal::Integer(a), RuntimeVal::Integer(b)) => a == b,
+            (RuntimeVal::Number(a), RuntimeVal::Number(b)) => a == b,
+            (RuntimeVal::Integer(a), RuntimeVal::Number(b)) | (RuntimeVal::Number(b), RuntimeVal::Integer(a)) =>
+                (*a as f64) == *b,
+            (RuntimeVal::String(a), RuntimeVal::String(b)) => a == b,
+            (RuntimeVal::Array(a), RuntimeVal::Array(b)) =>
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.equals(y)),
+            (RuntimeVal::Object(a), RuntimeVal::Object(b)) =>
+                a.len() == b.len() &&
+                    a.iter().all(|(key, x)| b.iter().any(|(other_key, y)| key == other_key && x.equals(y))),
+            (RuntimeVal::Class(a), RuntimeVal::Class(b)) => Rc::ptr_eq(a, b),
+            (RuntimeVal::Instance(a), RuntimeVal::Instance(b)) => Rc::ptr_eq(a, b),
+            (RuntimeVal::Enum(a), RuntimeVal::Enum(b)) => Rc::ptr_eq(a, b),
+            (RuntimeVal::Variant(a), RuntimeVal::Variant(b)) => Rc::ptr_eq(&a.enum_type, &b.enum_type) && a.variant == b.variant,
+            (RuntimeVal::EnumValue(a), RuntimeVal::EnumValue(b)) =>
+                Rc::ptr_eq(&a.enum_type, &b.enum_type) &&
+                    a.variant == b.variant &&
+                    a.values.iter().zip(&b.values).all(|(x, y)| x.equals(y)),
+            (RuntimeVal::Host(a), RuntimeVal::Host(b)) => a.equals(b),
+            _ => false,
         }
     }
+
+    // Values whose members are changed in place rather than by reassigning the variable
+    pub fn is_reference(&self) -> bool {
+        matches!(self, RuntimeVal::Class(_) | RuntimeVal::Instance(_) | RuntimeVal::Host(_))
+    }
+
     pub fn as_bool(&self) -> Option<bool> {
         match self {
             RuntimeVal::Bool(b) => Some(*b),
             _ => None,
         }
     }
+
+    // The Finnish name of the value's type, for error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RuntimeVal::Null => "tyhjä",
+            RuntimeVal::Bool(_) => "totuusarvo",
+            RuntimeVal::Integer(_) => "kokonaisluku",
+            RuntimeVal::Number(_) => "liukuluku",
+            RuntimeVal::String(_) => "merkkijono",
+            RuntimeVal::Object(_) => "objekti",
+            RuntimeVal::Array(_) => "taulukko",
+            RuntimeVal::NativeFunction(_) | RuntimeVal::Function(_) | RuntimeVal::BoundMethod(_) | RuntimeVal::Closure(_)
+                | RuntimeVal::Variant(_) => "funktio",
+            RuntimeVal::Class(_) | RuntimeVal::Super(_) => "luokka",
+            RuntimeVal::Instance(_) => "olio",
+            RuntimeVal::Enum(_) => "luettelo",
+            RuntimeVal::EnumValue(_) => "luettelon arvo",
+            RuntimeVal::Host(_) => "isäntäolio",
+        }
+    }
 }
 
 
-pub struct NativeFunction(Rc<dyn Fn(Vec<RuntimeVal>, Vec<(String, RuntimeVal)>) -> RuntimeVal>);
+// Receives the arguments and the scope of the call
+pub type NativeFn = Rc<dyn Fn(Vec<RuntimeVal>, &Environment) -> RuntimeVal>;
+
+pub struct NativeFunction(NativeFn);
 
 impl NativeFunction {
-    pub fn get_fn(&self) -> Rc<dyn Fn(Vec<RuntimeVal>, Vec<(String, RuntimeVal)>) -> RuntimeVal> {
+    pub fn get_fn(&self) -> NativeFn {
         Rc::clone(&self.0)
     }
 }
@@ -61,11 +137,102 @@ impl Clone for NativeFunction {
 
 #[derive(Clone, Debug)]
 pub struct Function {
-    pub parameters: Vec<String>,
-    pub declaration_env: Rc<RefCell<Environment>>,
+    pub name: String,
+    pub parameters: Vec<Pattern>,
+    pub rest: Option<String>,
+    pub declaration_env: Environment,
     pub body: Vec<Stmt>,
 }
 
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    pub parent: Option<Rc<Class>>,
+    pub fields: Vec<FieldDeclaration>,
+    pub constructor: Option<Rc<Function>>,
+    pub methods: Vec<Rc<Function>>,
+    pub statics: RefCell<Vec<(String, RuntimeVal)>>,
+    pub declaration_env: Environment,
+}
+
+impl Class {
+    // Walks from this class towards the root of the hierarchy
+    pub fn ancestors(self: &Rc<Self>) -> Vec<Rc<Class>> {
+        let mut classes = vec![Rc::clone(self)];
+        while let Some(parent) = classes.last().unwrap().parent.clone() {
+            classes.push(parent);
+        }
+        classes
+    }
+
+    // Finds a method and the class that defines it, so that `yli` resolves from there
+    pub fn find_method(self: &Rc<Self>, name: &str) -> Option<(Rc<Function>, Rc<Class>)> {
+        self.ancestors()
+            .into_iter()
+            .find_map(|class| class.methods.iter().find(|m| m.name == name).cloned().map(|m| (m, class)))
+    }
+
+    pub fn find_constructor(self: &Rc<Self>) -> Option<(Rc<Function>, Rc<Class>)> {
+        self.ancestors()
+            .into_iter()
+            .find_map(|class| class.constructor.clone().map(|c| (c, class)))
+    }
+
+    // The class in the hierarchy that owns a static member
+    pub fn find_static(self: &Rc<Self>, name: &str) -> Option<Rc<Class>> {
+        self.ancestors()
+            .into_iter()
+            .find(|class| class.statics.borrow().iter().any(|(key, _)| key == name))
+    }
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: Vec<(String, RuntimeVal)>,
+}
+
+// A method together with the receiver it was read from
+#[derive(Clone, Debug)]
+pub struct BoundMethod {
+    pub receiver: Rc<RefCell<Instance>>,
+    pub method: Rc<Function>,
+    pub home: Rc<Class>,
+}
+
+// The `yli` value inside a method: the receiver seen as an instance of the parent class
+#[derive(Clone, Debug)]
+pub struct SuperRef {
+    pub receiver: Rc<RefCell<Instance>>,
+    pub class: Rc<Class>,
+}
+
+#[derive(Debug)]
+pub struct EnumType {
+    pub name: String,
+    pub variants: Vec<VariantDeclaration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct VariantConstructor {
+    pub enum_type: Rc<EnumType>,
+    pub variant: usize,
+}
+
+// A variant of a luettelo with its payload in declaration order
+#[derive(Clone, Debug)]
+pub struct EnumValue {
+    pub enum_type: Rc<EnumType>,
+    pub variant: usize,
+    pub values: Vec<RuntimeVal>,
+}
+
+impl EnumValue {
+    pub fn variant(&self) -> &VariantDeclaration {
+        &self.enum_type.variants[self.variant]
+    }
+}
+
 pub fn MK_BOOL(value: bool) -> RuntimeVal {
     RuntimeVal::Bool(value)
 }
@@ -87,7 +254,7 @@ pub fn MK_OBJECT(properties: Vec<(String, RuntimeVal)>) -> RuntimeVal {
 }
 
 pub fn MK_NATIVE_FN(
-    call: Rc<dyn Fn(Vec<RuntimeVal>, Vec<(String, RuntimeVal)>) -> RuntimeVal>
+    call: NativeFn
 ) -> RuntimeVal {
     RuntimeVal::NativeFunction(NativeFunction(call))
 }
@@ -96,6 +263,69 @@ pub fn MK_STRING(value: String) -> RuntimeVal {
     RuntimeVal::String(value)
 }
 
+pub fn MK_HOST(object: impl HostObject) -> RuntimeVal {
+    RuntimeVal::Host(HostRef::new(object))
+}
+
 pub fn MK_ARRAY(elements: Vec<RuntimeVal>) -> RuntimeVal {
     RuntimeVal::Array(elements)
-}
\ No newline at end of file
+}
+
+impl std::fmt::Display for RuntimeVal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RuntimeVal::Null => write!(f, "null"),
+            RuntimeVal::Bool(b) => write!(f, "{}", b),
+            RuntimeVal::Integer(i) => write!(f, "{}", i), 
+            RuntimeVal::Number(n) => write!(f, "{}", n),
+            RuntimeVal::String(s) => write!(f, "{}", s),
+            RuntimeVal::Object(obj) => {
+                let properties: Vec<String> = obj.iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect();
+                write!(f, "{{{}}}", properties.join(", "))
+            },
+            RuntimeVal::Array(arr) => {
+                let elements: Vec<String> = arr.iter()
+                    .map(|value| format!("{}", value))
+                    .collect();
+                write!(f, "[{}]", elements.join(", "))
+            },
+            RuntimeVal::NativeFunction(_) => write!(f, "NativeFunction"),
+            RuntimeVal::Function(func) => write_function(f, &func.parameters, &func.rest),
+            RuntimeVal::Closure(closure) => write_function(f, &closure.proto.parameters, &closure.proto.rest),
+            RuntimeVal::Class(class) => write!(f, "luokka {}", class.name),
+            RuntimeVal::Instance(instance) => {
+                let instance = instance.borrow();
+                let fields: Vec<String> = instance.fields.iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect();
+                write!(f, "{} {{{}}}", instance.class.name, fields.join(", "))
+            },
+            RuntimeVal::BoundMethod(bound) => write!(f, "Method({}.{})", bound.home.name, bound.method.name),
+            RuntimeVal::Super(super_ref) => write!(f, "yli({})", super_ref.class.name),
+            RuntimeVal::Enum(enum_type) => write!(f, "luettelo {}", enum_type.name),
+            RuntimeVal::Variant(constructor) => {
+                let variant = &constructor.enum_type.variants[constructor.variant];
+                write!(f, "{}.{}({})", constructor.enum_type.name, variant.name, variant.fields.join(", "))
+            },
+            RuntimeVal::EnumValue(value) => {
+                write!(f, "{}.{}", value.enum_type.name, value.variant().name)?;
+                if !value.values.is_empty() {
+                    let values: Vec<String> = value.values.iter().map(|v| v.to_string()).collect();
+                    write!(f, "({})", values.join(", "))?;
+                }
+                Ok(())
+            },
+            RuntimeVal::Host(object) => write!(f, "{:?}", object),
+        }
+    }
+}
+
+fn write_function(f: &mut std::fmt::Formatter, parameters: &[Pattern], rest: &Option<String>) -> std::fmt::Result {
+    let mut parameters: Vec<String> = parameters.iter().map(|p| p.to_string()).collect();
+    if let Some(rest) = rest {
+        parameters.push(format!("...{}", rest));
+    }
+    write!(f, "Function({})", parameters.join(", "))
+}
@@ -0,0 +1,127 @@
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::time::Instant;
+use crate::embed::{ catch_panic, Interpreter };
+use crate::formatter::collect_files;
+use crate::frontend::ast::Stmt;
+use crate::frontend::lexer::tokenize;
+use crate::frontend::parser::Parser;
+
+// `kaulin testaa` runs the tests of *_testi.ka files. A test is a top-level function whose name
+// starts with testi_; it passes if it returns without an error. Each test gets a fresh interpreter,
+// so the file's top-level code runs again before every test and tests cannot affect each other.
+
+pub const FILE_SUFFIX: &str = "_testi.ka";
+pub const TEST_PREFIX: &str = "testi_";
+
+// Test function names in the order they are written
+fn test_names(source: &str) -> Result<Vec<String>, String> {
+    let program = catch_panic(|| Parser::new(tokenize(source)).produce_ast()).map_err(|error| error.to_string())?;
+    let names = program.body.iter().filter_map(|stmt| match stmt {
+        Stmt::FunctionDeclaration(function) => Some(&function.name),
+        Stmt::Export(export) => match export.declaration.as_deref() {
+            Some(Stmt::FunctionDeclaration(function)) => Some(&function.name),
+            _ => None,
+        },
+        _ => None,
+    });
+    Ok(names.filter(|name| name.starts_with(TEST_PREFIX)).cloned().collect())
+}
+
+fn run_test(file: &Path, name: &str) -> Result<(), String> {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_file(file).map_err(|error| error.to_string())?;
+    interpreter.call_function(name, Vec::new()).map_err(|error| error.to_string())?;
+    Ok(())
+}
+
+// kaulin testaa [--filter teksti] [polut...]; returns the exit code
+pub fn run(args: &[String]) -> i32 {
+    let mut filters = Vec::new();
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // Runs the tests whose name or file::name contains the text
+            "--filter" => match args.next() {
+                Some(filter) => filters.push(filter.clone()),
+                None => {
+                    eprintln!("Virhe: --filter odottaa tekstin");
+                    return 2;
+                }
+            },
+            flag if flag.starts_with("--") => {
+                eprintln!("Virhe: Tuntematon valitsin {}", flag);
+                return 2;
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+
+    // Directories are searched for test files; files named on the command line are run as they are
+    let mut files = Vec::new();
+    for path in &paths {
+        let mut found = Vec::new();
+        collect_files(path, &mut found);
+        if path.is_dir() {
+            found.retain(|file| file.to_string_lossy().ends_with(FILE_SUFFIX));
+        }
+        files.extend(found);
+    }
+
+    let start = Instant::now();
+    let (mut passed, mut filtered, mut broken) = (0, 0, 0);
+    let mut failures = Vec::new();
+    for file in &files {
+        let names = fs::read_to_string(file).map_err(|error| error.to_string()).and_then(|source| test_names(&source));
+        let names = match names {
+            Ok(names) => names,
+            Err(error) => {
+                eprintln!("Virhe: {}: {}", file.display(), error);
+                broken += 1;
+                continue;
+            }
+        };
+        for name in names {
+            let id = format!("{}::{}", file.display(), name);
+            if !filters.is_empty() && !filters.iter().any(|filter| id.contains(filter.as_str())) {
+                filtered += 1;
+                continue;
+            }
+            match run_test(file, &name) {
+                Ok(()) => {
+                    println!("testi {} ... ok", id);
+                    passed += 1;
+                }
+                Err(error) => {
+                    println!("testi {} ... EPÄONNISTUI", id);
+                    failures.push((id, error));
+                }
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        println!("\nEpäonnistuneet:");
+        for (id, error) in &failures {
+            println!("\n---- {} ----\n{}", id, error);
+        }
+    }
+    if passed + failures.len() + filtered == 0 && broken == 0 {
+        println!("Testejä ei löytynyt: testitiedostojen nimet päättyvät {} ja testifunktioiden nimet alkavat {}", FILE_SUFFIX, TEST_PREFIX);
+        return 0;
+    }
+    let verdict = if failures.is_empty() && broken == 0 { "ok" } else { "EPÄONNISTUI" };
+    println!(
+        "\ntestien tulos: {}. {} onnistui, {} epäonnistui, {} suodatettu pois; kesto {:.2} s",
+        verdict, passed, failures.len(), filtered, start.elapsed().as_secs_f64()
+    );
+    match (broken, failures.len()) {
+        (0, 0) => 0,
+        (0, _) => 1,
+        _ => 2,
+    }
+}
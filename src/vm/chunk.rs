@@ -0,0 +1,146 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::frontend::ast::*;
+use crate::runtime::environment::{ Environment, VarRef };
+use crate::runtime::values::*;
+
+// Jump targets are instruction indices; other operands index the tables of the Proto
+#[derive(Debug, Clone)]
+pub enum Op {
+    Constant(u32),
+    Null,
+    True,
+    False,
+    Pop,
+    // Keeps the top value and removes the n values below it
+    Squash(u32),
+    // Moves the value below the top n values to the top
+    Rotate(u32),
+    Return,
+
+    GetLocal(u32),
+    SetLocal(u32),
+    DefineLocal(u32),
+    GetUpvalue(u32),
+    SetUpvalue(u32),
+    GetGlobal(u32),
+    SetGlobal(u32),
+    DefineGlobal(u32, bool),
+    // Assignment to a vakio found at compile time; fails when run, like the tree-walker
+    ConstantAssign(u32),
+    // Clears the slots of a scope, giving captured ones a fresh cell
+    EnterScope(u32, u32),
+
+    Binary(BinaryOperator),
+    Unary(&'static str),
+    // Replaces the top value with its truthiness
+    Truthy,
+
+    Jump(u32),
+    JumpIfFalsy(u32),
+    JumpIfTruthy(u32),
+    // Like JumpIfFalsy, but the value must be a totuusarvo (jos-muuten)
+    JumpIfFalseStrict(u32),
+    // Anything but tosi jumps (toista)
+    JumpUnlessTrue(u32),
+    // Leave the value in place when jumping
+    JumpIfNull(u32),
+    JumpIfNotNull(u32),
+
+    Array(u32),
+    // Joins the values as strings (mallimerkkijono)
+    Template(u32),
+    Object(u32),
+    GetMember,
+    TryGetMember,
+
+    // Places are a root variable and the n member keys on the stack
+    ReadPlace(Root, u32),
+    WritePlace(Root, u32),
+    UpdatePlace(Root, u32, bool, bool),
+
+    // Checks that a spread argument is a taulukko
+    CheckSpread,
+    Call(u32),
+    CallWith(u32),
+    Closure(u32),
+
+    // Iteration keeps the items and the next index on the stack
+    IterStart,
+    IterNext(u32),
+    IterEnd,
+
+    // Parameter prologue: skip the default if the caller gave a value
+    JumpIfBound(u32, u32),
+    RequireArg(u32),
+
+    // Fails with a message from the name table
+    Fail(u32),
+    // Runs a top-level statement the compiler does not support on the tree-walker
+    TreeWalk(u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Root {
+    // Slot or upvalue index, name index, vakio
+    Local(u32, u32, bool),
+    Upvalue(u32, u32, bool),
+    Global(u32),
+}
+
+#[derive(Debug, Clone)]
+pub enum ArgKind {
+    Positional,
+    Named(String),
+    Spread,
+}
+
+// Where a closure finds a captured variable when it is created
+#[derive(Debug, Clone, PartialEq)]
+pub enum Capture {
+    Local(u32),
+    Upvalue(u32),
+}
+
+// A compiled function, or the top level of a script
+#[derive(Debug, Default)]
+pub struct Proto {
+    pub name: String,
+    pub parameters: Vec<Pattern>,
+    pub rest: Option<String>,
+    pub code: Vec<Op>,
+    pub constants: Vec<RuntimeVal>,
+    pub names: Vec<String>,
+    pub key_lists: Vec<Vec<String>>,
+    pub call_specs: Vec<Vec<ArgKind>>,
+    pub protos: Vec<Rc<Proto>>,
+    pub captures: Vec<Capture>,
+    pub capture_names: Vec<String>,
+    pub slot_names: Vec<String>,
+    pub captured: Vec<bool>,
+    // The statements run by TreeWalk
+    pub statements: Vec<Stmt>,
+}
+
+pub type Cell = Rc<RefCell<Option<RuntimeVal>>>;
+
+pub struct Closure {
+    pub proto: Rc<Proto>,
+    pub upvalues: Vec<Cell>,
+    pub globals: Environment,
+    // The globals this closure has used, by name index
+    pub global_slots: RefCell<Vec<Option<VarRef>>>,
+}
+
+impl Closure {
+    pub fn new(proto: Rc<Proto>, upvalues: Vec<Cell>, globals: Environment) -> Self {
+        let global_slots = RefCell::new(proto.names.iter().map(|_| None).collect());
+        Closure { proto, upvalues, globals, global_slots }
+    }
+}
+
+impl std::fmt::Debug for Closure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Closure({})", self.proto.name)
+    }
+}
@@ -0,0 +1,632 @@
+use std::rc::Rc;
+use crate::frontend::ast::*;
+use crate::frontend::resolver::*;
+use crate::runtime::values::*;
+use crate::vm::chunk::*;
+
+// Compiles a resolved program to bytecode. Top-level names stay in the Environment, so they are
+// looked up by name; everything declared inside a function or a loop has a slot from the resolver.
+//
+// A top-level statement that uses a construct the compiler does not support yet, anywhere inside
+// it, runs on the tree-walker instead. Both see the same top-level names, so only that statement
+// (a function, a loop, ...) leaves the VM. The names of those constructs are returned with the code.
+pub fn compile_program(program: &Program, resolution: &Resolution) -> (Rc<Proto>, Vec<String>) {
+    let mut compiler = Compiler { resolution, functions: Vec::new() };
+    compiler.begin_function(Proto::default(), &resolution.script);
+    let mut unsupported = Vec::new();
+    if program.body.is_empty() {
+        compiler.emit(Op::Null);
+    }
+    for (i, statement) in program.body.iter().enumerate() {
+        if let Err(feature) = compiler.compile_top_level(statement) {
+            unsupported.push(feature);
+        }
+        if i + 1 < program.body.len() {
+            compiler.emit(Op::Pop);
+        }
+    }
+    compiler.emit(Op::Return);
+    let state = compiler.functions.pop().unwrap();
+    (Rc::new(state.proto), unsupported)
+}
+
+struct FunctionState {
+    proto: Proto,
+    // None when declarations in different jos-branches disagree
+    constant: Vec<Option<bool>>,
+}
+
+enum Var {
+    Local(u32, bool),
+    Upvalue(u32, bool),
+    Global,
+}
+
+struct Compiler<'a> {
+    resolution: &'a Resolution,
+    functions: Vec<FunctionState>,
+}
+
+impl Compiler<'_> {
+    fn state(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let code = &mut self.state().proto.code;
+        code.push(op);
+        code.len() - 1
+    }
+
+    fn here(&mut self) -> u32 {
+        self.state().proto.code.len() as u32
+    }
+
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+        match &mut self.state().proto.code[at] {
+            Op::Jump(t) |
+            Op::JumpIfFalsy(t) |
+            Op::JumpIfTruthy(t) |
+            Op::JumpIfFalseStrict(t) |
+            Op::JumpUnlessTrue(t) |
+            Op::JumpIfNull(t) |
+            Op::JumpIfNotNull(t) |
+            Op::IterNext(t) |
+            Op::JumpIfBound(_, t) => *t = target,
+            op => unreachable!("{:?} ei ole hyppy", op),
+        }
+    }
+
+    fn constant(&mut self, value: RuntimeVal) -> u32 {
+        let constants = &mut self.state().proto.constants;
+        constants.push(value);
+        (constants.len() - 1) as u32
+    }
+
+    fn name(&mut self, name: &str) -> u32 {
+        let names = &mut self.state().proto.names;
+        match names.iter().position(|existing| existing == name) {
+            Some(index) => index as u32,
+            None => {
+                names.push(name.to_string());
+                (names.len() - 1) as u32
+            }
+        }
+    }
+
+    fn fail(&mut self, message: String) {
+        let index = self.name(&message);
+        self.emit(Op::Fail(index));
+    }
+
+    fn begin_function(&mut self, mut proto: Proto, layout: &Layout) {
+        proto.slot_names = layout.names.clone();
+        proto.captured = layout.captured.clone();
+        self.functions.push(FunctionState { proto, constant: layout.constant.clone() });
+    }
+
+    // Clears the slots of a scope the resolver opened at `node`
+    fn begin_scope<T: 'static>(&mut self, node: &T) {
+        let (start, end) = self.resolution.scope(node);
+        if start < end {
+            self.emit(Op::EnterScope(start, end));
+        }
+    }
+
+    // Declares the value on top of the stack, leaving it there
+    fn define(&mut self, name: &String, constant: bool) {
+        let op = match self.resolution.slot(name) {
+            Some(slot) => Op::DefineLocal(slot),
+            None => Op::DefineGlobal(self.name(name), constant),
+        };
+        self.emit(op);
+    }
+
+    fn resolve<T: 'static>(&mut self, node: &T) -> Result<Var, String> {
+        let level = self.functions.len() - 1;
+        let (depth, index) = match self.resolution.binding(node) {
+            Binding::Local { depth, index } => (depth, index),
+            Binding::Global => return Ok(Var::Global),
+        };
+        let Some(constant) = self.functions[level - depth as usize].constant[index as usize] else {
+            return Err("saman nimen muuttujaa ja vakiota samassa lohkossa".to_string());
+        };
+        match depth {
+            0 => Ok(Var::Local(index, constant)),
+            _ => Ok(Var::Upvalue(self.upvalue(level, depth, index), constant)),
+        }
+    }
+
+    // The upvalue of function `level` that reaches slot `index` of the function `depth` levels out
+    fn upvalue(&mut self, level: usize, depth: u32, index: u32) -> u32 {
+        let capture = match depth {
+            1 => Capture::Local(index),
+            _ => Capture::Upvalue(self.upvalue(level - 1, depth - 1, index)),
+        };
+        let name = self.functions[level - depth as usize].proto.slot_names[index as usize].clone();
+        let proto = &mut self.functions[level].proto;
+        match proto.captures.iter().position(|existing| *existing == capture) {
+            Some(position) => position as u32,
+            None => {
+                proto.captures.push(capture);
+                proto.capture_names.push(name);
+                (proto.captures.len() - 1) as u32
+            }
+        }
+    }
+
+    // Anything emitted for a statement that fails to compile is replaced by a call to the tree-walker
+    fn compile_top_level(&mut self, statement: &Stmt) -> Result<(), String> {
+        let start = self.here() as usize;
+        let result = self.compile_stmt(statement);
+        if result.is_err() {
+            self.functions.truncate(1);
+            let proto = &mut self.state().proto;
+            proto.code.truncate(start);
+            proto.statements.push(statement.clone());
+            let index = (proto.statements.len() - 1) as u32;
+            self.emit(Op::TreeWalk(index));
+        }
+        result
+    }
+
+    // Leaves the value of the last statement on the stack, or tyhjä for an empty block
+    fn compile_block(&mut self, statements: &[Stmt]) -> Result<(), String> {
+        if statements.is_empty() {
+            self.emit(Op::Null);
+        }
+        for (i, statement) in statements.iter().enumerate() {
+            self.compile_stmt(statement)?;
+            if i + 1 < statements.len() {
+                self.emit(Op::Pop);
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_stmt(&mut self, stmt: &Stmt) -> Result<(), String> {
+        match stmt {
+            Stmt::Program(program) => self.compile_block(&program.body)?,
+            Stmt::VarDeclaration(declaration) => {
+                match &declaration.value {
+                    Some(value) => self.compile_expr(value)?,
+                    None => {
+                        self.emit(Op::Null);
+                    }
+                }
+                match &declaration.target {
+                    Pattern::Binding(name) => self.define(name, declaration.constant),
+                    Pattern::Wildcard => (),
+                    _ => return Err("hajauttavia määrittelyjä".to_string()),
+                }
+            }
+            Stmt::FunctionDeclaration(declaration) => {
+                self.compile_function(declaration)?;
+                self.define(&declaration.name, true);
+            }
+            Stmt::Expr(statement) => self.compile_expr(&statement.expr)?,
+            Stmt::WhileLoop(while_loop) => {
+                self.emit(Op::Null);
+                let start = self.here();
+                self.compile_expr(&while_loop.condition)?;
+                let exit = self.emit(Op::JumpIfFalsy(0));
+                self.emit(Op::Pop);
+                self.compile_scoped_block(&while_loop.body)?;
+                self.emit(Op::Jump(start));
+                self.patch(exit);
+            }
+            Stmt::ForLoop(for_loop) => {
+                self.begin_scope(for_loop);
+                self.compile_stmt(&for_loop.initializer)?;
+                self.emit(Op::Pop);
+                let start = self.here();
+                self.compile_expr(&for_loop.condition)?;
+                let exit = self.emit(Op::JumpUnlessTrue(0));
+                self.begin_scope(&for_loop.body);
+                self.compile_discarded(&for_loop.body.statements)?;
+                self.compile_expr(&for_loop.increment)?;
+                self.emit(Op::Pop);
+                self.emit(Op::Jump(start));
+                self.patch(exit);
+                self.emit(Op::Null);
+            }
+            Stmt::ForEachLoop(for_each) => {
+                let target = match &for_each.target {
+                    Pattern::Binding(name) => Some(name),
+                    Pattern::Wildcard => None,
+                    _ => return Err("hajauttavia silmukoita".to_string()),
+                };
+                self.compile_expr(&for_each.iterable)?;
+                self.emit(Op::IterStart);
+                let start = self.here();
+                let exit = self.emit(Op::IterNext(0));
+
+                self.begin_scope(for_each);
+                if let Some(name) = target {
+                    self.define(name, for_each.constant);
+                }
+                self.emit(Op::Pop);
+                self.compile_discarded(&for_each.body.statements)?;
+
+                self.emit(Op::Jump(start));
+                self.patch(exit);
+                self.emit(Op::IterEnd);
+                self.emit(Op::Null);
+            }
+            Stmt::ClassDeclaration(_) => return Err("luokkia".to_string()),
+            Stmt::EnumDeclaration(_) => return Err("luetteloita".to_string()),
+            Stmt::Import(_) | Stmt::Export(_) => return Err("moduuleja".to_string()),
+        }
+        Ok(())
+    }
+
+    // Loop bodies get a fresh scope on every iteration
+    fn compile_scoped_block(&mut self, block: &Block) -> Result<(), String> {
+        self.begin_scope(block);
+        self.compile_block(&block.statements)
+    }
+
+    // Runs the statements for their effects only, leaving nothing on the stack
+    fn compile_discarded(&mut self, statements: &[Stmt]) -> Result<(), String> {
+        for statement in statements {
+            self.compile_stmt(statement)?;
+            self.emit(Op::Pop);
+        }
+        Ok(())
+    }
+
+    fn compile_function(&mut self, declaration: &FunctionDeclaration) -> Result<(), String> {
+        for parameter in &declaration.parameters {
+            if parameter.parameter_name().is_none() {
+                return Err("hajauttavia parametreja".to_string());
+            }
+        }
+
+        let proto = Proto {
+            name: declaration.name.clone(),
+            parameters: declaration.parameters.clone(),
+            rest: declaration.rest.clone(),
+            ..Proto::default()
+        };
+        self.begin_function(proto, self.resolution.function(declaration));
+
+        // Defaults run in order, so they can use the parameters before them
+        for (index, parameter) in declaration.parameters.iter().enumerate() {
+            match parameter {
+                Pattern::Default(default) => {
+                    let skip = self.emit(Op::JumpIfBound(index as u32, 0));
+                    self.compile_expr(&default.default)?;
+                    self.emit(Op::DefineLocal(index as u32));
+                    self.emit(Op::Pop);
+                    self.patch(skip);
+                }
+                _ => {
+                    self.emit(Op::RequireArg(index as u32));
+                }
+            }
+        }
+        self.compile_block(&declaration.body)?;
+        self.emit(Op::Return);
+
+        let state = self.functions.pop().unwrap();
+        let protos = &mut self.state().proto.protos;
+        protos.push(Rc::new(state.proto));
+        let index = (protos.len() - 1) as u32;
+        self.emit(Op::Closure(index));
+        Ok(())
+    }
+
+    fn compile_expr(&mut self, expr: &Expr) -> Result<(), String> {
+        match expr {
+            Expr::NumericLiteral(literal) => self.compile_constant(MK_NUMBER(literal.value)),
+            Expr::FloatLiteral(literal) => self.compile_constant(MK_NUMBER(literal.value)),
+            Expr::IntegerLiteral(literal) => self.compile_constant(MK_INTEGER(literal.value)),
+            Expr::StringLiteral(literal) => self.compile_constant(MK_STRING(literal.value.clone())),
+            Expr::Identifier(identifier) => self.compile_variable(identifier, &identifier.symbol)?,
+            Expr::ObjectLiteral(object) => {
+                let mut keys = Vec::new();
+                for property in &object.properties {
+                    match &property.value {
+                        Some(value) => self.compile_expr(value)?,
+                        None => self.compile_variable(property, &property.key)?,
+                    }
+                    keys.push(property.key.clone());
+                }
+                let key_lists = &mut self.state().proto.key_lists;
+                key_lists.push(keys);
+                let index = (key_lists.len() - 1) as u32;
+                self.emit(Op::Object(index));
+            }
+            Expr::ArrayLiteral(array) => {
+                for element in &array.elements {
+                    self.compile_expr(element)?;
+                }
+                self.emit(Op::Array(array.elements.len() as u32));
+            }
+            Expr::TemplateLiteral(template) => {
+                for part in &template.parts {
+                    self.compile_expr(part)?;
+                }
+                self.emit(Op::Template(template.parts.len() as u32));
+            }
+            Expr::CallExpr(call) if call.caller.is_optional_chain() => {
+                let mut exits = Vec::new();
+                self.compile_chain(expr, &mut exits)?;
+                for exit in exits {
+                    self.patch(exit);
+                }
+            }
+            Expr::CallExpr(call) => self.compile_call(call)?,
+            Expr::AssignmentExpr(assignment) => match &*assignment.assignee {
+                Expr::Identifier(identifier) => {
+                    self.compile_expr(&assignment.value)?;
+                    self.compile_set(identifier)?;
+                }
+                assignee => {
+                    let Some((root, depth)) = self.compile_place(assignee)? else {
+                        return Ok(());
+                    };
+                    self.compile_expr(&assignment.value)?;
+                    self.emit(Op::WritePlace(root, depth));
+                }
+            },
+            Expr::CompoundAssignmentExpr(assignment) => match &*assignment.assignee {
+                // A plain variable is read and written directly, without a place
+                Expr::Identifier(identifier) if assignment.operator != BinaryOperator::NullishCoalescing => {
+                    self.compile_variable(identifier, &identifier.symbol)?;
+                    self.compile_expr(&assignment.value)?;
+                    self.emit(Op::Binary(assignment.operator.clone()));
+                    self.compile_set(identifier)?;
+                }
+                assignee => {
+                    let Some((root, depth)) = self.compile_place(assignee)? else {
+                        return Ok(());
+                    };
+                    self.emit(Op::ReadPlace(root, depth));
+                    if assignment.operator == BinaryOperator::NullishCoalescing {
+                        let skip = self.emit(Op::JumpIfNotNull(0));
+                        self.emit(Op::Pop);
+                        self.compile_expr(&assignment.value)?;
+                        self.emit(Op::WritePlace(root, depth));
+                        let end = self.emit(Op::Jump(0));
+                        self.patch(skip);
+                        self.emit(Op::Squash(depth));
+                        self.patch(end);
+                    } else {
+                        self.compile_expr(&assignment.value)?;
+                        self.emit(Op::Binary(assignment.operator.clone()));
+                        self.emit(Op::WritePlace(root, depth));
+                    }
+                }
+            },
+            Expr::UpdateExpr(update) => {
+                let Some((root, depth)) = self.compile_place(&update.assignee)? else {
+                    return Ok(());
+                };
+                self.emit(Op::UpdatePlace(root, depth, update.operator == "++", update.prefix));
+            }
+            Expr::MemberExpr(_) => {
+                let mut exits = Vec::new();
+                self.compile_chain(expr, &mut exits)?;
+                for exit in exits {
+                    self.patch(exit);
+                }
+            }
+            Expr::IfElseExpr(if_else) => {
+                // The branches share the enclosing scope, as in the tree-walker
+                self.compile_expr(&if_else.condition)?;
+                let otherwise = self.emit(Op::JumpIfFalseStrict(0));
+                self.compile_block(&if_else.if_branch.statements)?;
+                let end = self.emit(Op::Jump(0));
+                self.patch(otherwise);
+                match &if_else.else_branch {
+                    Some(else_branch) => self.compile_block(&else_branch.statements)?,
+                    None => {
+                        self.emit(Op::Null);
+                    }
+                }
+                self.patch(end);
+            }
+            Expr::ConditionalExpr(conditional) => {
+                self.compile_expr(&conditional.condition)?;
+                let otherwise = self.emit(Op::JumpIfFalsy(0));
+                self.compile_expr(&conditional.consequent)?;
+                let end = self.emit(Op::Jump(0));
+                self.patch(otherwise);
+                self.compile_expr(&conditional.alternate)?;
+                self.patch(end);
+            }
+            Expr::BinaryExpr(binary) => {
+                self.compile_expr(&binary.left)?;
+                self.compile_expr(&binary.right)?;
+                self.emit(Op::Binary(binary.operator.clone()));
+            }
+            Expr::LogicalExpr(logical) => self.compile_logical(logical)?,
+            Expr::UnaryExpr(unary) => {
+                self.compile_expr(&unary.operand)?;
+                let operator = match unary.operator.as_str() {
+                    "-" => "-",
+                    "~" => "~",
+                    "!" => "!",
+                    _ => "?",
+                };
+                self.emit(Op::Unary(operator));
+            }
+            Expr::MatchExpr(_) => return Err("valitse-lausekkeita".to_string()),
+            Expr::Property(_) => return Err("irrallisia ominaisuuksia".to_string()),
+        }
+        Ok(())
+    }
+
+    fn compile_constant(&mut self, value: RuntimeVal) {
+        let index = self.constant(value);
+        self.emit(Op::Constant(index));
+    }
+
+    fn compile_variable<T: 'static>(&mut self, node: &T, name: &str) -> Result<(), String> {
+        let op = match self.resolve(node)? {
+            Var::Local(index, _) => Op::GetLocal(index),
+            Var::Upvalue(index, _) => Op::GetUpvalue(index),
+            Var::Global => Op::GetGlobal(self.name(name)),
+        };
+        self.emit(op);
+        Ok(())
+    }
+
+    // Assigns the value on top of the stack, leaving it there
+    fn compile_set(&mut self, identifier: &Identifier) -> Result<(), String> {
+        let name = &identifier.symbol;
+        let op = match self.resolve(identifier)? {
+            Var::Local(_, true) | Var::Upvalue(_, true) => Op::ConstantAssign(self.name(name)),
+            Var::Local(index, false) => Op::SetLocal(index),
+            Var::Upvalue(index, false) => Op::SetUpvalue(index),
+            Var::Global => Op::SetGlobal(self.name(name)),
+        };
+        self.emit(op);
+        Ok(())
+    }
+
+    // Pushes the member keys of an assignment target. None means the target is invalid
+    // and code that fails has been emitted instead, as eval_place would fail.
+    fn compile_place(&mut self, expr: &Expr) -> Result<Option<(Root, u32)>, String> {
+        let mut members = Vec::new();
+        let mut current = expr;
+        while let Expr::MemberExpr(member) = current {
+            if member.optional {
+                self.fail("Valinnaista ketjua ei voi käyttää sijoituksen kohteena".to_string());
+                return Ok(None);
+            }
+            members.push(member);
+            current = &member.object;
+        }
+        let identifier = match current {
+            Expr::Identifier(identifier) => identifier,
+            other => {
+                self.fail(format!("Virheellinen vasen puoli lausekkeen sisällä {:?}", other));
+                return Ok(None);
+            }
+        };
+
+        for member in members.iter().rev() {
+            self.compile_member_key(member)?;
+        }
+        let name_index = self.name(&identifier.symbol);
+        let root = match self.resolve(identifier)? {
+            Var::Local(index, constant) => Root::Local(index, name_index, constant),
+            Var::Upvalue(index, constant) => Root::Upvalue(index, name_index, constant),
+            Var::Global => Root::Global(name_index),
+        };
+        Ok(Some((root, members.len() as u32)))
+    }
+
+    fn compile_member_key(&mut self, member: &MemberExpr) -> Result<(), String> {
+        if member.computed {
+            return self.compile_expr(&member.property);
+        }
+        match &*member.property {
+            Expr::Identifier(identifier) => self.compile_constant(MK_STRING(identifier.symbol.clone())),
+            _ => self.fail("Omaisuuden on oltava tunniste".to_string()),
+        }
+        Ok(())
+    }
+
+    // An optional link that finds tyhjä skips the rest of the chain
+    fn compile_chain(&mut self, expr: &Expr, exits: &mut Vec<usize>) -> Result<(), String> {
+        match expr {
+            Expr::MemberExpr(member) => self.compile_member(member, exits),
+            // The callee comes first, as in eval_optional_call, and is moved above the arguments
+            Expr::CallExpr(call) if call.caller.is_optional_chain() => {
+                self.compile_chain(&call.caller, exits)?;
+                let kinds = self.compile_arguments(&call.args)?;
+                self.emit(Op::Rotate(kinds.len() as u32));
+                self.emit_call(kinds);
+                Ok(())
+            }
+            other => self.compile_expr(other),
+        }
+    }
+
+    fn compile_member(&mut self, member: &MemberExpr, exits: &mut Vec<usize>) -> Result<(), String> {
+        self.compile_chain(&member.object, exits)?;
+        if member.optional {
+            exits.push(self.emit(Op::JumpIfNull(0)));
+            self.compile_member_key(member)?;
+            self.emit(Op::TryGetMember);
+        } else {
+            self.compile_member_key(member)?;
+            self.emit(Op::GetMember);
+        }
+        Ok(())
+    }
+
+    // Arguments are evaluated before the callee, as in eval_call_expr
+    fn compile_call(&mut self, call: &CallExpr) -> Result<(), String> {
+        let kinds = self.compile_arguments(&call.args)?;
+        self.compile_expr(&call.caller)?;
+        self.emit_call(kinds);
+        Ok(())
+    }
+
+    fn compile_arguments(&mut self, arguments: &[Argument]) -> Result<Vec<ArgKind>, String> {
+        let mut kinds = Vec::new();
+        for argument in arguments {
+            match argument {
+                Argument::Positional(expr) => {
+                    self.compile_expr(expr)?;
+                    kinds.push(ArgKind::Positional);
+                }
+                Argument::Named(name, expr) => {
+                    self.compile_expr(expr)?;
+                    kinds.push(ArgKind::Named(name.clone()));
+                }
+                Argument::Spread(expr) => {
+                    self.compile_expr(expr)?;
+                    self.emit(Op::CheckSpread);
+                    kinds.push(ArgKind::Spread);
+                }
+            }
+        }
+        Ok(kinds)
+    }
+
+    fn emit_call(&mut self, kinds: Vec<ArgKind>) {
+        if kinds.iter().all(|kind| matches!(kind, ArgKind::Positional)) {
+            self.emit(Op::Call(kinds.len() as u32));
+        } else {
+            let call_specs = &mut self.state().proto.call_specs;
+            call_specs.push(kinds);
+            let index = (call_specs.len() - 1) as u32;
+            self.emit(Op::CallWith(index));
+        }
+    }
+
+    fn compile_logical(&mut self, logical: &LogicalExpr) -> Result<(), String> {
+        self.compile_expr(&logical.left)?;
+        match logical.operator {
+            BinaryOperator::And | BinaryOperator::Or => {
+                let short_circuit = match logical.operator {
+                    BinaryOperator::And => self.emit(Op::JumpIfFalsy(0)),
+                    _ => self.emit(Op::JumpIfTruthy(0)),
+                };
+                self.compile_expr(&logical.right)?;
+                self.emit(Op::Truthy);
+                let end = self.emit(Op::Jump(0));
+                self.patch(short_circuit);
+                match logical.operator {
+                    BinaryOperator::And => self.emit(Op::False),
+                    _ => self.emit(Op::True),
+                };
+                self.patch(end);
+            }
+            BinaryOperator::NullishCoalescing => {
+                let end = self.emit(Op::JumpIfNotNull(0));
+                self.emit(Op::Pop);
+                self.compile_expr(&logical.right)?;
+                self.patch(end);
+            }
+            _ => self.fail(format!("Odottamaton looginen operaattori {:?}", logical.operator)),
+        }
+        Ok(())
+    }
+}
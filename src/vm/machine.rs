@@ -0,0 +1,553 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use crate::frontend::ast::*;
+use crate::runtime::environment::{ Environment, VarRef };
+use crate::runtime::eval::expressions::*;
+use crate::runtime::eval::statements::iteration_items;
+use crate::runtime::formatting::placeholder_names;
+use crate::runtime::interpreter::evaluate;
+use crate::runtime::values::*;
+use crate::vm::chunk::*;
+
+#[derive(Clone)]
+enum Local {
+    // Not declared yet, or not in this iteration
+    Empty,
+    Value(RuntimeVal),
+    // Captured by a closure
+    Cell(Cell),
+}
+
+// Runs compiled top-level code; its declarations go into `env`
+pub fn run_script(proto: Rc<Proto>, env: &Environment) -> RuntimeVal {
+    let closure = Rc::new(Closure::new(proto, Vec::new(), env.clone()));
+    let mut locals = vec![Local::Empty; closure.proto.slot_names.len()];
+    capture_slots(&closure.proto, &mut locals);
+    execute(&closure, locals)
+}
+
+pub fn call_closure(closure: &Rc<Closure>, args: Vec<RuntimeVal>, named_args: Vec<(String, RuntimeVal)>) -> RuntimeVal {
+    let locals = bind_arguments(&closure.proto, args, named_args);
+    execute(closure, locals)
+}
+
+// Positional and named arguments go into the parameter slots; defaults run in the function's prologue
+fn bind_arguments(proto: &Proto, args: Vec<RuntimeVal>, named_args: Vec<(String, RuntimeVal)>) -> Vec<Local> {
+    let count = proto.parameters.len();
+    let mut locals = vec![Local::Empty; proto.slot_names.len()];
+    let mut extra = Vec::new();
+
+    for (i, arg) in args.into_iter().enumerate() {
+        if i < count {
+            locals[i] = Local::Value(arg);
+        } else if proto.rest.is_some() {
+            extra.push(arg);
+        } else {
+            panic!(
+                "Funktio {} ottaa enintään {} argumenttia, mutta sille annettiin ylimääräinen argumentti {}",
+                proto.name, count, arg
+            );
+        }
+    }
+
+    for (name, value) in named_args {
+        let index = match proto.parameters.iter().position(|p| p.parameter_name() == Some(&name)) {
+            Some(index) => index,
+            None => panic!("Funktiolla {} ei ole parametria '{}'", proto.name, name),
+        };
+        if !matches!(locals[index], Local::Empty) {
+            panic!("Funktion {} parametrille '{}' annettiin arvo useaan kertaan", proto.name, name);
+        }
+        locals[index] = Local::Value(value);
+    }
+
+    if proto.rest.is_some() {
+        locals[count] = Local::Value(MK_ARRAY(extra));
+    }
+    capture_slots(proto, &mut locals);
+    locals
+}
+
+fn capture_slots(proto: &Proto, locals: &mut [Local]) {
+    for (slot, captured) in proto.captured.iter().enumerate() {
+        if *captured {
+            let value = match std::mem::replace(&mut locals[slot], Local::Empty) {
+                Local::Value(value) => Some(value),
+                _ => None,
+            };
+            locals[slot] = Local::Cell(Rc::new(RefCell::new(value)));
+        }
+    }
+}
+
+// Scalars are copied without going through the derived Clone of the whole enum
+fn copy(value: &RuntimeVal) -> RuntimeVal {
+    match value {
+        RuntimeVal::Number(n) => RuntimeVal::Number(*n),
+        RuntimeVal::Integer(i) => RuntimeVal::Integer(*i),
+        RuntimeVal::Bool(b) => RuntimeVal::Bool(*b),
+        RuntimeVal::Null => RuntimeVal::Null,
+        other => other.clone(),
+    }
+}
+
+fn unresolved(name: &str) -> ! {
+    panic!("Kohdetta '{}' ei voida ratkaista, koska sitä ei ole olemassa.", name)
+}
+
+fn constant_assignment(name: &str) -> ! {
+    panic!("Ei voida määrittää uudelleen muuttujaa {}, koska se luotiin vakioksi.", name)
+}
+
+fn read_local(local: &Local, name: &str) -> RuntimeVal {
+    match local {
+        Local::Value(value) => copy(value),
+        Local::Cell(cell) => cell.borrow().as_ref().map(copy).unwrap_or_else(|| unresolved(name)),
+        Local::Empty => unresolved(name),
+    }
+}
+
+fn write_local(local: &mut Local, value: RuntimeVal, name: &str) {
+    match local {
+        Local::Value(slot) => *slot = value,
+        Local::Cell(cell) => {
+            let mut cell = cell.borrow_mut();
+            if cell.is_none() {
+                unresolved(name);
+            }
+            *cell = Some(value);
+        }
+        Local::Empty => unresolved(name),
+    }
+}
+
+fn define_local(local: &mut Local, value: RuntimeVal, name: &str) {
+    match local {
+        Local::Empty => *local = Local::Value(value),
+        Local::Cell(cell) if cell.borrow().is_none() => *cell.borrow_mut() = Some(value),
+        _ => panic!("Ei voida määrittää muuttujaa {}, sillä se on jo määritelty.", name),
+    }
+}
+
+fn read_cell(cell: &Cell, name: &str) -> RuntimeVal {
+    cell.borrow().clone().unwrap_or_else(|| unresolved(name))
+}
+
+// Arithmetic and comparisons on two numbers of the same kind skip the general operator code.
+// The operands are read where they are on the stack, which is much faster than popping them.
+fn binary(stack: &mut Vec<RuntimeVal>, operator: &BinaryOperator) {
+    let len = stack.len();
+    let result = match (&stack[len - 2], &stack[len - 1]) {
+        (RuntimeVal::Number(a), RuntimeVal::Number(b)) => match operator {
+            BinaryOperator::Add => Some(RuntimeVal::Number(a + b)),
+            BinaryOperator::Subtract => Some(RuntimeVal::Number(a - b)),
+            BinaryOperator::Multiply => Some(RuntimeVal::Number(a * b)),
+            BinaryOperator::LessThan => Some(RuntimeVal::Bool(a < b)),
+            BinaryOperator::GreaterThan => Some(RuntimeVal::Bool(a > b)),
+            BinaryOperator::LessThanOrEqual => Some(RuntimeVal::Bool(a <= b)),
+            BinaryOperator::GreaterThanOrEqual => Some(RuntimeVal::Bool(a >= b)),
+            _ => None,
+        },
+        (RuntimeVal::Integer(a), RuntimeVal::Integer(b)) => match operator {
+            BinaryOperator::Add => Some(checked_integer(a.checked_add(*b), "yhteenlaskussa")),
+            BinaryOperator::Subtract => Some(checked_integer(a.checked_sub(*b), "vähennyslaskussa")),
+            BinaryOperator::LessThan => Some(RuntimeVal::Bool(a < b)),
+            BinaryOperator::GreaterThan => Some(RuntimeVal::Bool(a > b)),
+            _ => None,
+        },
+        _ => None,
+    };
+    match result {
+        Some(result) => {
+            stack.truncate(len - 1);
+            stack[len - 2] = result;
+        }
+        None => {
+            let rhs = stack.pop().unwrap();
+            let lhs = stack.pop().unwrap();
+            stack.push(apply_binary_operator(lhs, rhs, operator));
+        }
+    }
+}
+
+// Drops the top value in place
+fn discard(stack: &mut Vec<RuntimeVal>) {
+    stack.truncate(stack.len() - 1);
+}
+
+struct Frame<'a> {
+    closure: &'a Rc<Closure>,
+    locals: Vec<Local>,
+    globals: Environment,
+}
+
+impl Frame<'_> {
+    fn read_root(&mut self, root: Root) -> RuntimeVal {
+        let proto = &self.closure.proto;
+        match root {
+            Root::Local(slot, _, _) => read_local(&self.locals[slot as usize], &proto.slot_names[slot as usize]),
+            Root::Upvalue(index, name, _) => read_cell(&self.closure.upvalues[index as usize], &proto.names[name as usize]),
+            Root::Global(name) => self.with_global(name, VarRef::get),
+        }
+    }
+
+    fn write_root(&mut self, root: Root, value: RuntimeVal) {
+        let proto = &self.closure.proto;
+        match root {
+            Root::Local(_, name, true) | Root::Upvalue(_, name, true) => constant_assignment(&proto.names[name as usize]),
+            Root::Local(slot, name, false) => write_local(&mut self.locals[slot as usize], value, &proto.names[name as usize]),
+            Root::Upvalue(index, name, false) => {
+                let name = &proto.names[name as usize];
+                let mut cell = self.closure.upvalues[index as usize].borrow_mut();
+                if cell.is_none() {
+                    unresolved(name);
+                }
+                *cell = Some(value);
+            }
+            Root::Global(name) => self.with_global(name, |var| var.set(value)),
+        }
+    }
+
+    // A global is found by name on its first use, and by position after that
+    fn with_global<R>(&self, name: u32, f: impl FnOnce(&VarRef) -> R) -> R {
+        let mut slots = self.closure.global_slots.borrow_mut();
+        let slot = &mut slots[name as usize];
+        if !slot.as_ref().is_some_and(|var| self.globals.is_current(var)) {
+            *slot = Some(self.globals.find_var(&self.closure.proto.names[name as usize]));
+        }
+        f(slot.as_ref().unwrap())
+    }
+
+    // Same steps as write_place: change the root value, then store it unless it changed in place
+    fn write_place(&mut self, root: Root, keys: &[RuntimeVal], value: RuntimeVal) {
+        if keys.is_empty() {
+            self.write_root(root, value);
+            return;
+        }
+        let mut target = self.read_root(root);
+        set_member(&mut target, keys, value);
+        if !target.is_reference() {
+            self.write_root(root, target);
+        }
+    }
+
+    fn read_place(&mut self, root: Root, keys: &[RuntimeVal]) -> RuntimeVal {
+        let mut value = self.read_root(root);
+        for key in keys {
+            value = get_member(&value, key);
+        }
+        value
+    }
+
+    fn call(&self, callee: RuntimeVal, args: Vec<RuntimeVal>, named_args: Vec<(String, RuntimeVal)>) -> RuntimeVal {
+        match callee {
+            RuntimeVal::Closure(closure) => call_closure(&closure, args, named_args),
+            RuntimeVal::NativeFunction(_) => {
+                let names = match args.first() {
+                    Some(RuntimeVal::String(template)) => placeholder_names(template),
+                    _ => Vec::new(),
+                };
+                call_value(callee, args, named_args, &self.native_env(&names))
+            }
+            other => call_value(other, args, named_args, &self.globals),
+        }
+    }
+
+    // A format string passed to tulosta or muotoile can name variables of the caller, so the slots
+    // with those names are given back their names. Other natives only see the globals.
+    fn native_env(&self, names: &[String]) -> Environment {
+        let mut env = Environment::new(Some(self.globals.clone()));
+        let proto = &self.closure.proto;
+        let mut seen = HashSet::new();
+        for name in names.iter().filter(|name| seen.insert(*name)) {
+            let local = self.locals.iter().enumerate().rev().find_map(|(slot, local)| match local {
+                _ if &proto.slot_names[slot] != name => None,
+                Local::Value(value) => Some(value.clone()),
+                Local::Cell(cell) => cell.borrow().clone(),
+                Local::Empty => None,
+            });
+            let upvalue = || self.closure.upvalues.iter().enumerate()
+                .find(|(index, _)| &proto.capture_names[*index] == name)
+                .and_then(|(_, cell)| cell.borrow().clone());
+            if let Some(value) = local.or_else(upvalue) {
+                env.declare_var(name.clone(), value, false);
+            }
+        }
+        env
+    }
+}
+
+fn execute(closure: &Rc<Closure>, locals: Vec<Local>) -> RuntimeVal {
+    let proto = &closure.proto;
+    let code = &proto.code;
+    let mut frame = Frame { closure, locals, globals: closure.globals.clone() };
+    let mut stack: Vec<RuntimeVal> = Vec::with_capacity(16);
+    let mut ip = 0;
+
+    loop {
+        let op = &code[ip];
+        ip += 1;
+        match op {
+            Op::Constant(index) => stack.push(copy(&proto.constants[*index as usize])),
+            Op::Null => stack.push(RuntimeVal::Null),
+            Op::True => stack.push(RuntimeVal::Bool(true)),
+            Op::False => stack.push(RuntimeVal::Bool(false)),
+            Op::Pop => discard(&mut stack),
+            Op::Squash(count) => {
+                let top = stack.pop().unwrap();
+                stack.truncate(stack.len() - *count as usize);
+                stack.push(top);
+            }
+            Op::Rotate(count) => {
+                let value = stack.remove(stack.len() - 1 - *count as usize);
+                stack.push(value);
+            }
+            Op::Return => return stack.pop().unwrap_or(RuntimeVal::Null),
+
+            Op::GetLocal(slot) => {
+                let slot = *slot as usize;
+                stack.push(read_local(&frame.locals[slot], &proto.slot_names[slot]));
+            }
+            Op::SetLocal(slot) => {
+                let slot = *slot as usize;
+                let value = copy(stack.last().unwrap());
+                write_local(&mut frame.locals[slot], value, &proto.slot_names[slot]);
+            }
+            Op::DefineLocal(slot) => {
+                let slot = *slot as usize;
+                let value = copy(stack.last().unwrap());
+                define_local(&mut frame.locals[slot], value, &proto.slot_names[slot]);
+            }
+            Op::GetUpvalue(index) => {
+                let index = *index as usize;
+                stack.push(read_cell(&closure.upvalues[index], &proto.capture_names[index]));
+            }
+            Op::SetUpvalue(index) => {
+                let index = *index as usize;
+                let mut cell = closure.upvalues[index].borrow_mut();
+                if cell.is_none() {
+                    unresolved(&proto.capture_names[index]);
+                }
+                *cell = Some(stack.last().unwrap().clone());
+            }
+            Op::GetGlobal(name) => stack.push(frame.with_global(*name, VarRef::get)),
+            Op::SetGlobal(name) => {
+                let value = copy(stack.last().unwrap());
+                frame.with_global(*name, |var| var.set(value));
+            }
+            Op::DefineGlobal(name, constant) => {
+                let value = stack.last().unwrap().clone();
+                frame.globals.declare_var(proto.names[*name as usize].clone(), value, *constant);
+            }
+            Op::ConstantAssign(name) => constant_assignment(&proto.names[*name as usize]),
+            Op::EnterScope(start, end) => {
+                for slot in *start as usize..*end as usize {
+                    frame.locals[slot] = if proto.captured[slot] {
+                        Local::Cell(Rc::new(RefCell::new(None)))
+                    } else {
+                        Local::Empty
+                    };
+                }
+            }
+
+            Op::Binary(operator) => binary(&mut stack, operator),
+            Op::Unary(operator) => {
+                let operand = stack.pop().unwrap();
+                stack.push(apply_unary_operator(operator, operand));
+            }
+            Op::Truthy => {
+                let value = stack.pop().unwrap();
+                stack.push(MK_BOOL(value.is_truthy()));
+            }
+
+            Op::Jump(target) => ip = *target as usize,
+            Op::JumpIfFalsy(target) => {
+                if !stack.last().unwrap().is_truthy() {
+                    ip = *target as usize;
+                }
+                discard(&mut stack);
+            }
+            Op::JumpIfTruthy(target) => {
+                if stack.last().unwrap().is_truthy() {
+                    ip = *target as usize;
+                }
+                discard(&mut stack);
+            }
+            Op::JumpIfFalseStrict(target) => match stack.pop().unwrap() {
+                RuntimeVal::Bool(true) => (),
+                RuntimeVal::Bool(false) => ip = *target as usize,
+                _ => panic!("jos-muuten-lausekkeen ehto on arvioitava totuusarvoksi"),
+            },
+            Op::JumpUnlessTrue(target) => {
+                if !matches!(stack.last(), Some(RuntimeVal::Bool(true))) {
+                    ip = *target as usize;
+                }
+                discard(&mut stack);
+            }
+            Op::JumpIfNull(target) => {
+                if matches!(stack.last(), Some(RuntimeVal::Null)) {
+                    ip = *target as usize;
+                }
+            }
+            Op::JumpIfNotNull(target) => {
+                if !matches!(stack.last(), Some(RuntimeVal::Null)) {
+                    ip = *target as usize;
+                }
+            }
+
+            Op::Array(count) => {
+                let elements = stack.split_off(stack.len() - *count as usize);
+                stack.push(MK_ARRAY(elements));
+            }
+            Op::Template(count) => {
+                let parts = stack.split_off(stack.len() - *count as usize);
+                stack.push(MK_STRING(parts.iter().map(|part| part.to_string()).collect()));
+            }
+            Op::Object(index) => {
+                let keys = &proto.key_lists[*index as usize];
+                let values = stack.split_off(stack.len() - keys.len());
+                stack.push(MK_OBJECT(keys.iter().cloned().zip(values).collect()));
+            }
+            Op::GetMember => {
+                let key = stack.pop().unwrap();
+                let object = stack.pop().unwrap();
+                stack.push(get_member(&object, &key));
+            }
+            Op::TryGetMember => {
+                let key = stack.pop().unwrap();
+                let object = stack.pop().unwrap();
+                stack.push(try_get_member(&object, &key).unwrap_or(MK_NULL()));
+            }
+
+            Op::ReadPlace(root, depth) => {
+                let value = if *depth == 0 {
+                    frame.read_root(*root)
+                } else {
+                    let keys = &stack[stack.len() - *depth as usize..];
+                    frame.read_place(*root, keys)
+                };
+                stack.push(value);
+            }
+            Op::WritePlace(root, depth) => {
+                let value = stack.pop().unwrap();
+                let keys = stack.split_off(stack.len() - *depth as usize);
+                frame.write_place(*root, &keys, copy(&value));
+                stack.push(value);
+            }
+            Op::UpdatePlace(root, depth, increment, prefix) => {
+                let keys = stack.split_off(stack.len() - *depth as usize);
+                let current = frame.read_place(*root, &keys);
+                let operator = if *increment { "++" } else { "--" };
+                let updated = match (&current, increment) {
+                    (RuntimeVal::Integer(i), true) => checked_integer(i.checked_add(1), "operaattorissa '++'"),
+                    (RuntimeVal::Integer(i), false) => checked_integer(i.checked_sub(1), "operaattorissa '--'"),
+                    (RuntimeVal::Number(n), true) => MK_NUMBER(n + 1.0),
+                    (RuntimeVal::Number(n), false) => MK_NUMBER(n - 1.0),
+                    _ => panic!("Operaattori '{}' vaatii luvun, saatiin {:?}", operator, current),
+                };
+                frame.write_place(*root, &keys, updated.clone());
+                stack.push(if *prefix { updated } else { current });
+            }
+
+            Op::CheckSpread => {
+                if !matches!(stack.last(), Some(RuntimeVal::Array(_))) {
+                    panic!("Vain taulukon voi levittää argumenteiksi, saatiin {}", stack.last().unwrap());
+                }
+            }
+            Op::Call(count) => {
+                let callee = stack.pop().unwrap();
+                let args = stack.split_off(stack.len() - *count as usize);
+                stack.push(frame.call(callee, args, Vec::new()));
+            }
+            Op::CallWith(index) => {
+                let callee = stack.pop().unwrap();
+                let kinds = &proto.call_specs[*index as usize];
+                let values = stack.split_off(stack.len() - kinds.len());
+                let mut args = Vec::new();
+                let mut named_args = Vec::new();
+                for (kind, value) in kinds.iter().zip(values) {
+                    match (kind, value) {
+                        (ArgKind::Positional, value) => args.push(value),
+                        (ArgKind::Named(name), value) => named_args.push((name.clone(), value)),
+                        (ArgKind::Spread, RuntimeVal::Array(elements)) => args.extend(elements),
+                        (ArgKind::Spread, _) => unreachable!(),
+                    }
+                }
+                stack.push(frame.call(callee, args, named_args));
+            }
+            Op::Closure(index) => {
+                let function = &proto.protos[*index as usize];
+                let upvalues = function.captures
+                    .iter()
+                    .map(|capture| match capture {
+                        Capture::Local(slot) => match &frame.locals[*slot as usize] {
+                            Local::Cell(cell) => Rc::clone(cell),
+                            _ => unreachable!("Siepattu paikka ilman solua"),
+                        },
+                        Capture::Upvalue(index) => Rc::clone(&closure.upvalues[*index as usize]),
+                    })
+                    .collect();
+                let created = Closure::new(Rc::clone(function), upvalues, closure.globals.clone());
+                stack.push(RuntimeVal::Closure(Rc::new(created)));
+            }
+
+            Op::IterStart => {
+                let iterable = stack.pop().unwrap();
+                stack.push(MK_ARRAY(iteration_items(iterable)));
+                stack.push(MK_INTEGER(0));
+            }
+            Op::IterNext(target) => {
+                let len = stack.len();
+                let index = match stack[len - 1] {
+                    RuntimeVal::Integer(index) => index as usize,
+                    _ => unreachable!(),
+                };
+                let item = match &stack[len - 2] {
+                    RuntimeVal::Array(items) => items.get(index).cloned(),
+                    _ => unreachable!(),
+                };
+                match item {
+                    Some(item) => {
+                        stack[len - 1] = MK_INTEGER(index as i64 + 1);
+                        stack.push(item);
+                    }
+                    None => ip = *target as usize,
+                }
+            }
+            Op::IterEnd => {
+                stack.truncate(stack.len() - 2);
+            }
+
+            Op::JumpIfBound(slot, target) => {
+                let bound = match &frame.locals[*slot as usize] {
+                    Local::Value(_) => true,
+                    Local::Cell(cell) => cell.borrow().is_some(),
+                    Local::Empty => false,
+                };
+                if bound {
+                    ip = *target as usize;
+                }
+            }
+            Op::RequireArg(slot) => {
+                let bound = match &frame.locals[*slot as usize] {
+                    Local::Value(_) => true,
+                    Local::Cell(cell) => cell.borrow().is_some(),
+                    Local::Empty => false,
+                };
+                if !bound {
+                    panic!(
+                        "Funktion {} kutsusta puuttuu argumentti parametrille '{}'",
+                        proto.name, proto.parameters[*slot as usize]
+                    );
+                }
+            }
+
+            Op::Fail(message) => panic!("{}", proto.names[*message as usize]),
+            Op::TreeWalk(index) => {
+                let mut env = frame.globals.clone();
+                stack.push(evaluate(&proto.statements[*index as usize], &mut env));
+            }
+        }
+    }
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use common::{ corpus, run };
+
+// Every program in tests/backends and tests/optimizer must print the same output and fail with the
+// same error on the tree-walker and the bytecode VM. The VM's warnings about the statements it
+// leaves to the tree-walker are the only difference allowed.
+
+fn without_warnings(stderr: &[u8]) -> String {
+    String::from_utf8_lossy(stderr)
+        .lines()
+        .filter(|line| !line.starts_with("Varoitus: Tavukoodikääntäjä"))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+#[test]
+fn vm_matches_tree_walker() {
+    for program in corpus("backends").into_iter().chain(corpus("optimizer")) {
+        for level in ["-O0", "-O2"] {
+            let tree_walker = run(&program, &[level]);
+            let vm = run(&program, &["--vm", level]);
+            let name = format!("{} {}", program.display(), level);
+            assert!(!tree_walker.stdout.is_empty(), "{} ei tulostanut mitään", name);
+            assert_eq!(String::from_utf8_lossy(&vm.stdout), String::from_utf8_lossy(&tree_walker.stdout), "{}", name);
+            assert_eq!(without_warnings(&vm.stderr), without_warnings(&tree_walker.stderr), "{}", name);
+            assert_eq!(vm.status.code(), tree_walker.status.code(), "{}", name);
+        }
+    }
+}
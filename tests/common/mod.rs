@@ -0,0 +1,27 @@
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::process::{ Command, Output };
+
+pub fn run(program: &Path, flags: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_Kaulin"))
+        .args(flags)
+        .arg(program)
+        // Keeps the parse cache out of the source tree
+        .env("KAULIN_CACHE", env::temp_dir().join("kaulin-tests"))
+        .output()
+        .expect("Kaulin ei käynnistynyt")
+}
+
+// The .ka programs in a directory under tests
+pub fn corpus(name: &str) -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join(name);
+    let mut programs: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ka"))
+        .collect();
+    programs.sort();
+    assert!(!programs.is_empty());
+    programs
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use common::{ corpus, run };
+
+// Every program in tests/optimizer must print the same output and fail with the same error
+// at every optimization level, on both backends.
+
+fn assert_same_at_every_level(backend: &[&str]) {
+    for program in corpus("optimizer") {
+        let baseline = run(&program, &[backend, &["-O0"]].concat());
+        assert!(!baseline.stdout.is_empty(), "{} ei tulostanut mitään", program.display());
+
+        for level in ["-O1", "-O2"] {
+            let optimized = run(&program, &[backend, &[level]].concat());
+            let name = format!("{} {}", program.display(), level);
+            assert_eq!(String::from_utf8_lossy(&optimized.stdout), String::from_utf8_lossy(&baseline.stdout), "{}", name);
+            assert_eq!(String::from_utf8_lossy(&optimized.stderr), String::from_utf8_lossy(&baseline.stderr), "{}", name);
+            assert_eq!(optimized.status.code(), baseline.status.code(), "{}", name);
+        }
+    }
+}
+
+#[test]
+fn tree_walker_output_does_not_depend_on_optimization() {
+    assert_same_at_every_level(&[]);
+}
+
+#[test]
+fn vm_output_does_not_depend_on_optimization() {
+    assert_same_at_every_level(&["--vm"]);
+}